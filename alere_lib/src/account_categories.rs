@@ -2,7 +2,7 @@
 /// These broadly match how we present things in the GUI, though the actual
 /// accounts have finer grained flags
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, serde::Serialize, serde::Deserialize)]
 pub enum AccountCategory {
     EXPENSE = 0,
     INCOME = 1,
@@ -22,6 +22,14 @@ impl Default for AccountKindCollection {
                 AccountCategory::INCOME,
             )
             .set_is_passive_income(true),
+            AccountKind::new(
+                "Interest Income",
+                "Expense",
+                "Income",
+                AccountCategory::INCOME,
+            )
+            .set_is_passive_income(true)
+            .set_is_interest(true),
             AccountKind::new(
                 "Work Income",
                 "Expense",
@@ -142,6 +150,15 @@ impl AccountKindCollection {
     pub fn lookup(&self, name: &str) -> Option<&AccountKind> {
         self.kinds.get(name)
     }
+
+    /// Register a new kind, replacing any existing kind with the same name.
+    pub fn add(&mut self, kind: AccountKind) {
+        self.kinds.insert(kind.get_name(), kind);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &AccountKind> {
+        self.kinds.values()
+    }
 }
 
 #[derive(Debug)]
@@ -150,10 +167,10 @@ struct AccountKindDetails {
     name: String,
 
     // credit / increase / ...
-    _name_when_positive: String,
+    name_when_positive: String,
 
     // debit / decrease / ...
-    _name_when_negative: String,
+    name_when_negative: String,
 
     category: AccountCategory,
 
@@ -169,6 +186,10 @@ struct AccountKindDetails {
     // like dividends, rents,...
     is_passive_income: bool,
 
+    // Whether this is an income category for the interests paid on savings
+    // accounts, bonds,...
+    is_interest: bool,
+
     // Whether this is a potential income or expense, i.e. the amount might
     // change later. This includes stock price changes, real-estate until you
     // actually sell, and so on. This is the result of your assets' value
@@ -213,11 +234,12 @@ impl AccountKindDetails {
     ) -> Self {
         AccountKindDetails {
             name: name.into(),
-            _name_when_positive: name_when_positive.into(),
-            _name_when_negative: name_when_negative.into(),
+            name_when_positive: name_when_positive.into(),
+            name_when_negative: name_when_negative.into(),
             category,
             is_work_income: false,
             is_passive_income: false,
+            is_interest: false,
             is_unrealized: false,
             is_networth: false,
             is_trading: false,
@@ -257,6 +279,11 @@ impl AccountKind {
         self
     }
     #[must_use]
+    pub fn set_is_interest(self, is_interest: bool) -> Self {
+        self.0.borrow_mut().is_interest = is_interest;
+        self
+    }
+    #[must_use]
     pub fn set_is_unrealized(self, is_unrealized: bool) -> Self {
         self.0.borrow_mut().is_unrealized = is_unrealized;
         self
@@ -320,6 +347,21 @@ impl AccountKind {
         self.0.borrow().name.clone()
     }
 
+    #[must_use]
+    pub fn get_name_when_positive(&self) -> String {
+        self.0.borrow().name_when_positive.clone()
+    }
+
+    #[must_use]
+    pub fn get_name_when_negative(&self) -> String {
+        self.0.borrow().name_when_negative.clone()
+    }
+
+    #[must_use]
+    pub fn get_category(&self) -> AccountCategory {
+        self.0.borrow().category
+    }
+
     #[must_use]
     pub fn cmp_name(&self, right: &AccountKind) -> std::cmp::Ordering {
         self.0.borrow().name.cmp(&right.0.borrow().name)
@@ -370,6 +412,11 @@ impl AccountKind {
     pub fn is_passive_income(&self) -> bool {
         self.0.borrow().is_passive_income
     }
+
+    #[must_use]
+    pub fn is_interest(&self) -> bool {
+        self.0.borrow().is_interest
+    }
 }
 
 impl PartialEq for AccountKind {
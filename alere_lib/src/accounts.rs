@@ -1,11 +1,12 @@
 use crate::{
     account_kinds::AccountKind,
     institutions::Institution,
+    lots::CostMethod,
     multi_values::MultiValue,
     transactions::{Split, Transaction},
 };
 use chrono::{DateTime, Local};
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, collections::BTreeMap, rc::Rc};
 
 /// How to display account name.
 /// This includes the basename for the account (level 1), its parent (level 2),
@@ -75,11 +76,13 @@ impl AccountCollection {
             kind,
             parent,
             institution,
-            _description: description.map(str::to_string),
+            description: description.map(str::to_string),
             iban: iban.map(str::to_string),
-            _number: number.map(str::to_string),
+            number: number.map(str::to_string),
             closed,
-            _opened_on: opened_on,
+            opened_on,
+            cost_method: CostMethod::default(),
+            asset_classes: BTreeMap::new(),
             transactions: Vec::new(),
             reconciliations: Vec::new(),
         })));
@@ -96,6 +99,27 @@ impl AccountCollection {
         self.accounts.iter().cloned()
     }
 
+    /// Find an account from its full name (like "Expenses:Food"), or from
+    /// its short name if that is unique.  This is case-insensitive.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<Account> {
+        let full = self.accounts.iter().find(|a| {
+            a.name(AccountNameDepth::unlimited())
+                .eq_ignore_ascii_case(name)
+        });
+        if full.is_some() {
+            return full.cloned();
+        }
+        let mut short = self.accounts.iter().filter(|a| {
+            a.name(AccountNameDepth::basename())
+                .eq_ignore_ascii_case(name)
+        });
+        match (short.next(), short.next()) {
+            (Some(a), None) => Some(a.clone()),
+            _ => None,
+        }
+    }
+
     /// Return the parent accounts of acc (not including acc itself).  The last
     /// element returned is the toplevel account, like Asset.
     pub fn iter_parents(
@@ -123,6 +147,9 @@ impl AccountCollection {
     }
 }
 
+/// Name of the account used to balance imported transactions
+pub(crate) const IMBALANCE_ACCOUNT: &str = "Imbalance";
+
 #[derive(Debug, Eq, PartialEq, Hash, Clone, Copy, Default, PartialOrd, Ord)]
 pub struct AccountId(u16);
 
@@ -157,21 +184,28 @@ struct AccountDetails {
 
     institution: Option<Institution>,
     parent: Option<Account>,
-    _description: Option<String>,
+    description: Option<String>,
 
     // Only for actual IBAN, not free-form
     iban: Option<String>,
 
     // Any code used by the bank to identify the account
-    _number: Option<String>,
+    number: Option<String>,
 
     closed: bool,
 
     // When the account was opened
-    _opened_on: Option<DateTime<Local>>,
+    opened_on: Option<DateTime<Local>>,
 
     kind: AccountKind,
 
+    // How to compute the cost of shares sold from this account
+    cost_method: CostMethod,
+
+    // User-defined asset classes, which override those of the commodities
+    // held in the account.
+    asset_classes: BTreeMap<String, String>,
+
     // The chronologically sorted list of transactions for which at least one
     // split applies to the account.
     transactions: Vec<Transaction>,
@@ -301,6 +335,32 @@ impl Account {
         self.0.borrow().kind.clone()
     }
 
+    #[must_use]
+    pub fn get_cost_method(&self) -> CostMethod {
+        self.0.borrow().cost_method
+    }
+
+    pub fn set_cost_method(&mut self, method: CostMethod) {
+        self.0.borrow_mut().cost_method = method;
+    }
+
+    #[must_use]
+    pub fn get_asset_class(&self, dimension: &str) -> Option<String> {
+        self.0.borrow().asset_classes.get(dimension).cloned()
+    }
+
+    #[must_use]
+    pub fn get_asset_classes(&self) -> BTreeMap<String, String> {
+        self.0.borrow().asset_classes.clone()
+    }
+
+    pub fn set_asset_class(&mut self, dimension: &str, class: &str) {
+        self.0
+            .borrow_mut()
+            .asset_classes
+            .insert(dimension.to_string(), class.to_string());
+    }
+
     pub fn set_id(&mut self, id: AccountId) {
         self.0.borrow_mut().id = id;
     }
@@ -314,6 +374,33 @@ impl Account {
         self.0.borrow_mut().iban = Some(iban.to_string());
     }
 
+    #[must_use]
+    pub fn get_iban(&self) -> Option<String> {
+        self.0.borrow().iban.clone()
+    }
+
+    #[must_use]
+    pub fn get_number(&self) -> Option<String> {
+        self.0.borrow().number.clone()
+    }
+
+    #[must_use]
+    pub fn get_description(&self) -> Option<String> {
+        self.0.borrow().description.clone()
+    }
+
+    #[must_use]
+    pub fn get_opened_on(&self) -> Option<DateTime<Local>> {
+        self.0.borrow().opened_on
+    }
+
+    /// The institution set explicitly on this account, without looking at
+    /// the parent accounts.
+    #[must_use]
+    pub fn get_own_institution(&self) -> Option<Institution> {
+        self.0.borrow().institution.clone()
+    }
+
     pub fn add_reconciliation(&mut self, rec: Reconciliation) {
         self.0.borrow_mut().reconciliations.push(rec);
     }
@@ -365,6 +452,18 @@ impl Account {
         self.0.borrow().closed
     }
 
+    /// Whether this is a toplevel account used to balance imported
+    /// transactions until they are categorized.  This includes the ones
+    /// created by GnuCash for the same purpose, like "Imbalance-EUR" or
+    /// "Orphan-EUR".
+    #[must_use]
+    pub fn is_uncategorized(&self) -> bool {
+        let details = self.0.borrow();
+        details.parent.is_none()
+            && (details.name.starts_with(IMBALANCE_ACCOUNT)
+                || details.name.starts_with("Orphan"))
+    }
+
     /// Get the date when the account was closed.
     /// TODO: Store explicit closed date instead of inferring from last transaction
     #[must_use]
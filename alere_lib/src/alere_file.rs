@@ -0,0 +1,1372 @@
+//! Native file format for alere.
+//!
+//! The repository is saved as a versioned JSON document.  Objects reference
+//! each other through their index in the corresponding list of the document
+//! (so an account's parent is the index of another account), rather than
+//! through internal ids, which are reassigned on load.
+//!
+//! Prices computed from transactions are not saved, since they are registered
+//! again when the transactions are loaded.
+
+use crate::{
+    account_categories::AccountCategory,
+    account_kinds::AccountKind,
+    accounts::{Account, AccountId, AccountNameDepth, Reconciliation},
+    budgets::{Budget, BudgetLine, BudgetPeriod},
+    commodities::{Commodity, CommodityId},
+    errors::AlrError,
+    formatters::Formatter,
+    importers::{Exporter, Importer},
+    institutions::Institution,
+    lots::CostMethod,
+    multi_values::{MultiValue, Operation, Value},
+    payees::Payee,
+    price_sources::{PriceSource, PriceSourceFrom, PriceSourceId},
+    prices::Price,
+    repositories::Repository,
+    schedules::{
+        Frequency, Recurrence, RecurrenceEnd, Schedule, ScheduledSplit,
+        WeekendOption,
+    },
+    times::Intv,
+    transactions::{ReconcileKind, Transaction, TransactionArgs},
+};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Version of the file format written by the exporter.  The importer refuses
+/// files written by a more recent version.
+pub const FORMAT_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u32,
+    #[serde(default)]
+    institutions: Vec<InstitutionDoc>,
+    #[serde(default)]
+    account_kinds: Vec<AccountKindDoc>,
+    #[serde(default)]
+    commodities: Vec<CommodityDoc>,
+    #[serde(default)]
+    payees: Vec<String>,
+    #[serde(default)]
+    price_sources: Vec<String>,
+    #[serde(default)]
+    accounts: Vec<AccountDoc>,
+    #[serde(default)]
+    prices: Vec<PriceDoc>,
+    #[serde(default)]
+    transactions: Vec<TransactionDoc>,
+    #[serde(default)]
+    budgets: Vec<BudgetDoc>,
+    #[serde(default)]
+    schedules: Vec<ScheduleDoc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct InstitutionDoc {
+    name: String,
+    manager: Option<String>,
+    street: Option<String>,
+    zip: Option<String>,
+    city: Option<String>,
+    phone: Option<String>,
+    icon: Option<String>,
+    bic: Option<String>,
+    url: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountKindDoc {
+    name: String,
+    name_when_positive: String,
+    name_when_negative: String,
+    category: AccountCategory,
+    is_work_income: bool,
+    is_passive_income: bool,
+    #[serde(default)]
+    is_interest: bool,
+    is_unrealized: bool,
+    is_networth: bool,
+    is_trading: bool,
+    is_stock: bool,
+    is_income_tax: bool,
+    is_misc_tax: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CommodityDoc {
+    name: String,
+    symbol: String,
+    symbol_after: bool,
+    is_currency: bool,
+    display_precision: u8,
+    isin: Option<String>,
+    quote_symbol: Option<String>,
+    quote_source: Option<String>,
+    quote_currency: Option<usize>,
+    #[serde(default)]
+    asset_classes: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountDoc {
+    name: String,
+    kind: usize,
+    parent: Option<usize>,
+    institution: Option<usize>,
+    description: Option<String>,
+    iban: Option<String>,
+    number: Option<String>,
+    closed: bool,
+    opened_on: Option<DateTime<Local>>,
+    #[serde(default)]
+    reconciliations: Vec<ReconciliationDoc>,
+    #[serde(default)]
+    cost_method: CostMethodDoc,
+    #[serde(default)]
+    asset_classes: BTreeMap<String, String>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum CostMethodDoc {
+    #[default]
+    Fifo,
+    Lifo,
+    Hifo,
+    Average,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ReconciliationDoc {
+    timestamp: DateTime<Local>,
+    total: Vec<ValueDoc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ValueDoc {
+    amount: Decimal,
+    commodity: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum PriceSourceDoc {
+    Transaction,
+    Turnkey,
+    External { source: usize },
+}
+
+#[derive(Serialize, Deserialize)]
+struct PriceDoc {
+    origin: usize,
+    target: usize,
+    timestamp: DateTime<Local>,
+    price: Decimal,
+    source: PriceSourceDoc,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TransactionDoc {
+    memo: Option<String>,
+    check_number: Option<String>,
+    payee: Option<usize>,
+    entry_date: DateTime<Local>,
+    splits: Vec<SplitDoc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+enum ReconcileDoc {
+    New,
+    Cleared,
+    Reconciled { on: Option<DateTime<Local>> },
+}
+
+#[derive(Serialize, Deserialize)]
+struct SplitDoc {
+    account: usize,
+    reconciled: ReconcileDoc,
+    post_ts: DateTime<Local>,
+    operation: OperationDoc,
+    #[serde(default)]
+    bank_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum OperationDoc {
+    Credit {
+        value: Vec<ValueDoc>,
+    },
+    BuyAmount {
+        qty: ValueDoc,
+        amount: ValueDoc,
+    },
+    BuyPrice {
+        qty: ValueDoc,
+        price: ValueDoc,
+    },
+    AddShares {
+        qty: ValueDoc,
+    },
+    Reinvest {
+        shares: Vec<ValueDoc>,
+        amount: Vec<ValueDoc>,
+    },
+    Dividend,
+    Split {
+        ratio: Decimal,
+        commodity: usize,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct BudgetDoc {
+    name: String,
+    start: DateTime<Local>,
+    end: Option<DateTime<Local>>,
+    commodity: usize,
+    lines: Vec<BudgetLineDoc>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BudgetLineDoc {
+    account: usize,
+    period: BudgetPeriodDoc,
+    amounts: Vec<Decimal>,
+    #[serde(default)]
+    rollover: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum BudgetPeriodDoc {
+    Monthly,
+    Yearly,
+    Custom { intv: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScheduleDoc {
+    name: String,
+    payee: Option<usize>,
+    memo: Option<String>,
+    start: DateTime<Local>,
+    frequency: FrequencyDoc,
+    every: u32,
+    #[serde(default)]
+    last_day_of_month: bool,
+    weekend: WeekendDoc,
+    end: RecurrenceEndDoc,
+    last_entered: Option<DateTime<Local>>,
+    splits: Vec<ScheduledSplitDoc>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FrequencyDoc {
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WeekendDoc {
+    MoveBefore,
+    MoveAfter,
+    Ignore,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum RecurrenceEndDoc {
+    Never,
+    On { date: DateTime<Local> },
+    After { count: u32 },
+}
+
+#[derive(Serialize, Deserialize)]
+struct ScheduledSplitDoc {
+    account: usize,
+    value: Vec<ValueDoc>,
+}
+
+/// Lookup an element of the document by its index
+fn get<'a, T>(list: &'a [T], idx: usize, what: &str) -> Result<&'a T> {
+    Ok(list.get(idx).ok_or_else(|| {
+        AlrError::Str(format!("Invalid {what} index {idx} in alere file"))
+    })?)
+}
+
+/// Read and write repositories in alere's native format.
+#[derive(Default)]
+pub struct AlereFile {}
+
+//--------------------------------------------------------------------------
+// Export
+//--------------------------------------------------------------------------
+
+/// Maps the objects of a repository to their index in the document
+#[derive(Default)]
+struct ExportIndexes {
+    commodities: HashMap<CommodityId, usize>,
+    accounts: HashMap<AccountId, usize>,
+    institutions: Vec<Institution>,
+    kinds: Vec<AccountKind>,
+    payees: Vec<Payee>,
+    sources: HashMap<PriceSourceId, usize>,
+}
+
+impl ExportIndexes {
+    fn commodity(&self, c: &Commodity) -> usize {
+        *self
+            .commodities
+            .get(&c.get_id())
+            .expect("commodity not registered in repository")
+    }
+
+    fn account(&self, acc: &Account) -> usize {
+        *self
+            .accounts
+            .get(&acc.get_id())
+            .expect("account not registered in repository")
+    }
+
+    fn value(&self, v: &Value) -> ValueDoc {
+        ValueDoc {
+            amount: v.amount,
+            commodity: self.commodity(&v.commodity),
+        }
+    }
+
+    fn multi_value(&self, v: &MultiValue) -> Vec<ValueDoc> {
+        v.iter().map(|v| self.value(&v)).collect()
+    }
+
+    fn operation(&self, op: &Operation) -> OperationDoc {
+        match op {
+            Operation::Credit(value) => OperationDoc::Credit {
+                value: self.multi_value(value),
+            },
+            Operation::BuyAmount { qty, amount } => OperationDoc::BuyAmount {
+                qty: self.value(qty),
+                amount: self.value(amount),
+            },
+            Operation::BuyPrice { qty, price } => OperationDoc::BuyPrice {
+                qty: self.value(qty),
+                price: self.value(price),
+            },
+            Operation::AddShares { qty } => OperationDoc::AddShares {
+                qty: self.value(qty),
+            },
+            Operation::Reinvest { shares, amount } => OperationDoc::Reinvest {
+                shares: self.multi_value(shares),
+                amount: self.multi_value(amount),
+            },
+            Operation::Dividend => OperationDoc::Dividend,
+            Operation::Split { ratio, commodity } => OperationDoc::Split {
+                ratio: *ratio,
+                commodity: self.commodity(commodity),
+            },
+        }
+    }
+}
+
+impl AlereFile {
+    fn build_document(repo: &Repository) -> Document {
+        let mut idx = ExportIndexes::default();
+
+        let institutions = repo
+            .institutions
+            .iter()
+            .map(|inst| {
+                idx.institutions.push(inst.clone());
+                InstitutionDoc {
+                    name: inst.get_name(),
+                    manager: inst.get_manager(),
+                    street: inst.get_street(),
+                    zip: inst.get_zip(),
+                    city: inst.get_city(),
+                    phone: inst.get_phone(),
+                    icon: inst.get_icon(),
+                    bic: inst.get_bic(),
+                    url: inst.get_url(),
+                }
+            })
+            .collect();
+
+        let mut kinds = repo.account_kinds.iter().cloned().collect::<Vec<_>>();
+        kinds.sort_by(AccountKind::cmp_name);
+        let account_kinds = kinds
+            .iter()
+            .map(|k| AccountKindDoc {
+                name: k.get_name(),
+                name_when_positive: k.get_name_when_positive(),
+                name_when_negative: k.get_name_when_negative(),
+                category: k.get_category(),
+                is_work_income: k.is_work_income(),
+                is_passive_income: k.is_passive_income(),
+                is_interest: k.is_interest(),
+                is_unrealized: k.is_unrealized(),
+                is_networth: k.is_networth(),
+                is_trading: k.is_trading(),
+                is_stock: k.is_stock(),
+                is_income_tax: k.is_income_tax(),
+                is_misc_tax: k.is_misc_tax(),
+            })
+            .collect();
+        idx.kinds = kinds;
+
+        for (i, c) in repo.commodities.iter_commodities().enumerate() {
+            idx.commodities.insert(c.get_id(), i);
+        }
+        let commodities = repo
+            .commodities
+            .iter_commodities()
+            .map(|c| CommodityDoc {
+                name: c.get_name().clone(),
+                symbol: c.get_symbol().clone(),
+                symbol_after: c.symbol_after(),
+                is_currency: c.is_currency(),
+                display_precision: c.get_display_precision(),
+                isin: c.get_isin(),
+                quote_symbol: c.get_quote_symbol(),
+                quote_source: c.get_quote_source(),
+                quote_currency: c
+                    .get_quote_currency()
+                    .map(|q| idx.commodity(&q)),
+                asset_classes: c.get_asset_classes(),
+            })
+            .collect();
+
+        let payees = repo
+            .payees
+            .iter()
+            .map(|p| {
+                idx.payees.push(p.clone());
+                p.get_name().clone()
+            })
+            .collect();
+
+        let price_sources = repo
+            .price_sources
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                idx.sources.insert(s.get_id(), i);
+                s.get_name().clone()
+            })
+            .collect();
+
+        for (i, acc) in repo.accounts.iter().enumerate() {
+            idx.accounts.insert(acc.get_id(), i);
+        }
+        let accounts = repo
+            .accounts
+            .iter()
+            .map(|acc| AccountDoc {
+                name: acc.name(AccountNameDepth::basename()),
+                kind: idx
+                    .kinds
+                    .iter()
+                    .position(|k| *k == acc.get_kind())
+                    .expect("account kind not registered in repository"),
+                parent: acc.get_parent().map(|p| idx.account(&p)),
+                institution: acc.get_own_institution().and_then(|inst| {
+                    idx.institutions.iter().position(|i| *i == inst)
+                }),
+                description: acc.get_description(),
+                iban: acc.get_iban(),
+                number: acc.get_number(),
+                closed: acc.is_closed(),
+                opened_on: acc.get_opened_on(),
+                cost_method: match acc.get_cost_method() {
+                    CostMethod::Fifo => CostMethodDoc::Fifo,
+                    CostMethod::Lifo => CostMethodDoc::Lifo,
+                    CostMethod::Hifo => CostMethodDoc::Hifo,
+                    CostMethod::Average => CostMethodDoc::Average,
+                },
+                asset_classes: acc.get_asset_classes(),
+                reconciliations: acc
+                    .iter_reconciliations()
+                    .map(|r| ReconciliationDoc {
+                        timestamp: r.timestamp,
+                        total: idx.multi_value(&r.total),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let mut prices = Vec::new();
+        for ((origin, target), list) in repo.prices.iter() {
+            for p in list {
+                let source = match p.source() {
+                    // Recomputed when loading transactions
+                    PriceSourceFrom::Transaction => continue,
+                    PriceSourceFrom::Turnkey => PriceSourceDoc::Turnkey,
+                    PriceSourceFrom::External(id) => PriceSourceDoc::External {
+                        source: *idx.sources.get(&id).expect(
+                            "price source not registered in repository",
+                        ),
+                    },
+                };
+                prices.push(PriceDoc {
+                    origin: idx.commodity(origin),
+                    target: idx.commodity(target),
+                    timestamp: p.timestamp,
+                    price: p.price,
+                    source,
+                });
+            }
+        }
+
+        // Output is deterministic, which makes it easier to store the file in
+        // a version control system.
+        prices.sort_by(|a, b| {
+            (a.origin, a.target, a.timestamp).cmp(&(
+                b.origin,
+                b.target,
+                b.timestamp,
+            ))
+        });
+
+        let transactions = repo
+            .transactions
+            .iter()
+            .map(|tx| TransactionDoc {
+                memo: tx.memo().clone(),
+                check_number: tx.check_number().clone(),
+                payee: tx
+                    .payee()
+                    .and_then(|p| idx.payees.iter().position(|i| *i == p)),
+                entry_date: tx.entry_date(),
+                splits: tx
+                    .splits()
+                    .iter()
+                    .map(|s| SplitDoc {
+                        account: idx.account(&s.account),
+                        reconciled: match &s.reconciled {
+                            ReconcileKind::New => ReconcileDoc::New,
+                            ReconcileKind::Cleared => ReconcileDoc::Cleared,
+                            ReconcileKind::Reconciled(on) => {
+                                ReconcileDoc::Reconciled { on: *on }
+                            }
+                        },
+                        post_ts: s.post_ts,
+                        operation: idx.operation(&s.operation),
+                        bank_id: s.bank_id.clone(),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let budgets = repo
+            .budgets
+            .iter()
+            .map(|b| BudgetDoc {
+                name: b.name.clone(),
+                start: b.start,
+                end: b.end,
+                commodity: idx.commodity(&b.commodity),
+                lines: b
+                    .lines
+                    .iter()
+                    .map(|line| BudgetLineDoc {
+                        account: idx.account(&line.account),
+                        period: match &line.period {
+                            BudgetPeriod::Monthly => BudgetPeriodDoc::Monthly,
+                            BudgetPeriod::Yearly => BudgetPeriodDoc::Yearly,
+                            BudgetPeriod::Custom(intv) => {
+                                BudgetPeriodDoc::Custom {
+                                    intv: intv.to_string(),
+                                }
+                            }
+                        },
+                        amounts: line.amounts.clone(),
+                        rollover: line.rollover,
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        let schedules = repo
+            .schedules
+            .iter()
+            .map(|sch| ScheduleDoc {
+                name: sch.name.clone(),
+                payee: sch
+                    .payee
+                    .as_ref()
+                    .and_then(|p| idx.payees.iter().position(|i| i == p)),
+                memo: sch.memo.clone(),
+                start: sch.start,
+                frequency: match sch.recurrence.frequency {
+                    Frequency::Once => FrequencyDoc::Once,
+                    Frequency::Daily => FrequencyDoc::Daily,
+                    Frequency::Weekly => FrequencyDoc::Weekly,
+                    Frequency::Monthly => FrequencyDoc::Monthly,
+                    Frequency::Yearly => FrequencyDoc::Yearly,
+                },
+                every: sch.recurrence.every,
+                last_day_of_month: sch.recurrence.last_day_of_month,
+                weekend: match sch.recurrence.weekend {
+                    WeekendOption::MoveBefore => WeekendDoc::MoveBefore,
+                    WeekendOption::MoveAfter => WeekendDoc::MoveAfter,
+                    WeekendOption::Ignore => WeekendDoc::Ignore,
+                },
+                end: match &sch.recurrence.end {
+                    RecurrenceEnd::Never => RecurrenceEndDoc::Never,
+                    RecurrenceEnd::On(date) => {
+                        RecurrenceEndDoc::On { date: *date }
+                    }
+                    RecurrenceEnd::After(count) => {
+                        RecurrenceEndDoc::After { count: *count }
+                    }
+                },
+                last_entered: sch.last_entered,
+                splits: sch
+                    .splits
+                    .iter()
+                    .map(|s| ScheduledSplitDoc {
+                        account: idx.account(&s.account),
+                        value: idx.multi_value(&s.value),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        Document {
+            version: FORMAT_VERSION,
+            institutions,
+            account_kinds,
+            commodities,
+            payees,
+            price_sources,
+            accounts,
+            prices,
+            transactions,
+            budgets,
+            schedules,
+        }
+    }
+}
+
+impl Exporter for AlereFile {
+    fn export_file(
+        &mut self,
+        repo: &Repository,
+        export_to: &Path,
+        _format: &Formatter,
+    ) -> Result<()> {
+        let doc = AlereFile::build_document(repo);
+        let file = File::create(export_to)?;
+        let mut buf = BufWriter::new(file);
+        serde_json::to_writer_pretty(&mut buf, &doc)?;
+        buf.write_all(b"\n")?;
+        buf.flush()?;
+        Ok(())
+    }
+}
+
+//--------------------------------------------------------------------------
+// Import
+//--------------------------------------------------------------------------
+
+/// The objects created in the repository, in the same order as in the
+/// document.
+#[derive(Default)]
+struct ImportIndexes {
+    commodities: Vec<Commodity>,
+    accounts: Vec<Account>,
+    institutions: Vec<Institution>,
+    kinds: Vec<AccountKind>,
+    payees: Vec<Payee>,
+    sources: Vec<PriceSource>,
+}
+
+impl ImportIndexes {
+    fn value(&self, v: &ValueDoc) -> Result<Value> {
+        Ok(Value {
+            amount: v.amount,
+            commodity: get(&self.commodities, v.commodity, "commodity")?
+                .clone(),
+        })
+    }
+
+    fn multi_value(&self, values: &[ValueDoc]) -> Result<MultiValue> {
+        let mut result = MultiValue::zero();
+        for v in values {
+            result += &self.value(v)?;
+        }
+        Ok(result)
+    }
+
+    fn operation(&self, op: &OperationDoc) -> Result<Operation> {
+        Ok(match op {
+            OperationDoc::Credit { value } => {
+                Operation::Credit(self.multi_value(value)?)
+            }
+            OperationDoc::BuyAmount { qty, amount } => Operation::BuyAmount {
+                qty: self.value(qty)?,
+                amount: self.value(amount)?,
+            },
+            OperationDoc::BuyPrice { qty, price } => Operation::BuyPrice {
+                qty: self.value(qty)?,
+                price: self.value(price)?,
+            },
+            OperationDoc::AddShares { qty } => Operation::AddShares {
+                qty: self.value(qty)?,
+            },
+            OperationDoc::Reinvest { shares, amount } => Operation::Reinvest {
+                shares: self.multi_value(shares)?,
+                amount: self.multi_value(amount)?,
+            },
+            OperationDoc::Dividend => Operation::Dividend,
+            OperationDoc::Split { ratio, commodity } => Operation::Split {
+                ratio: *ratio,
+                commodity: get(&self.commodities, *commodity, "commodity")?
+                    .clone(),
+            },
+        })
+    }
+}
+
+impl AlereFile {
+    fn load_document(doc: Document) -> Result<Repository> {
+        if doc.version > FORMAT_VERSION {
+            Err(AlrError::Str(format!(
+                "Unsupported alere file version {} (expected at most {})",
+                doc.version, FORMAT_VERSION
+            )))?;
+        }
+
+        let mut repo = Repository::default();
+        let mut idx = ImportIndexes::default();
+
+        for inst in &doc.institutions {
+            let mut i = repo.institutions.add(
+                &inst.name,
+                inst.manager.as_deref(),
+                inst.street.as_deref(),
+                inst.zip.as_deref(),
+                inst.city.as_deref(),
+                inst.phone.as_deref(),
+            );
+            if let Some(icon) = &inst.icon {
+                i = i.set_icon(icon.clone());
+            }
+            if let Some(bic) = &inst.bic {
+                i.set_bic(bic);
+            }
+            if let Some(url) = &inst.url {
+                i.set_url(url);
+            }
+            idx.institutions.push(i);
+        }
+
+        for k in &doc.account_kinds {
+            let kind = AccountKind::new(
+                &k.name,
+                &k.name_when_positive,
+                &k.name_when_negative,
+                k.category,
+            )
+            .set_is_work_income(k.is_work_income)
+            .set_is_passive_income(k.is_passive_income)
+            .set_is_interest(k.is_interest)
+            .set_is_unrealized(k.is_unrealized)
+            .set_is_networth(k.is_networth)
+            .set_is_trading(k.is_trading)
+            .set_is_stock(k.is_stock)
+            .set_is_income_tax(k.is_income_tax)
+            .set_is_misc_tax(k.is_misc_tax);
+            repo.account_kinds.add(kind.clone());
+            idx.kinds.push(kind);
+        }
+
+        for c in &doc.commodities {
+            let mut com = repo.commodities.add(
+                &c.name,
+                &c.symbol,
+                c.symbol_after,
+                c.is_currency,
+                c.quote_symbol.as_deref(),
+                c.display_precision,
+            );
+            if let Some(isin) = &c.isin {
+                com.set_isin(isin);
+            }
+            if let Some(source) = &c.quote_source {
+                com.set_quote_source(source);
+            }
+            for (dimension, class) in &c.asset_classes {
+                com.set_asset_class(dimension, class);
+            }
+            idx.commodities.push(com);
+        }
+        for (c, com) in doc.commodities.iter().zip(idx.commodities.iter()) {
+            if let Some(q) = c.quote_currency {
+                let quote = get(&idx.commodities, q, "commodity")?.clone();
+                com.clone().set_quote_currency(&quote);
+            }
+        }
+
+        for p in &doc.payees {
+            idx.payees.push(repo.payees.add(p));
+        }
+
+        for s in &doc.price_sources {
+            idx.sources.push(repo.price_sources.add(s));
+        }
+
+        for a in &doc.accounts {
+            let acc = repo.accounts.add(
+                &a.name,
+                get(&idx.kinds, a.kind, "account kind")?.clone(),
+                None,
+                match a.institution {
+                    None => None,
+                    Some(i) => {
+                        Some(get(&idx.institutions, i, "institution")?.clone())
+                    }
+                },
+                a.description.as_deref(),
+                a.iban.as_deref(),
+                a.number.as_deref(),
+                a.closed,
+                a.opened_on,
+            );
+            idx.accounts.push(acc);
+        }
+        for (a, acc) in doc.accounts.iter().zip(idx.accounts.iter()) {
+            let mut acc = acc.clone();
+            if let Some(p) = a.parent {
+                acc.set_parent(get(&idx.accounts, p, "account")?.clone());
+            }
+            acc.set_cost_method(match a.cost_method {
+                CostMethodDoc::Fifo => CostMethod::Fifo,
+                CostMethodDoc::Lifo => CostMethod::Lifo,
+                CostMethodDoc::Hifo => CostMethod::Hifo,
+                CostMethodDoc::Average => CostMethod::Average,
+            });
+            for (dimension, class) in &a.asset_classes {
+                acc.set_asset_class(dimension, class);
+            }
+            for r in &a.reconciliations {
+                acc.add_reconciliation(Reconciliation {
+                    timestamp: r.timestamp,
+                    total: idx.multi_value(&r.total)?,
+                });
+            }
+        }
+
+        for p in &doc.prices {
+            let source = match &p.source {
+                PriceSourceDoc::Transaction => PriceSourceFrom::Transaction,
+                PriceSourceDoc::Turnkey => PriceSourceFrom::Turnkey,
+                PriceSourceDoc::External { source } => {
+                    PriceSourceFrom::External(
+                        get(&idx.sources, *source, "price source")?.get_id(),
+                    )
+                }
+            };
+            repo.add_price(
+                get(&idx.commodities, p.origin, "commodity")?,
+                get(&idx.commodities, p.target, "commodity")?,
+                Price::new(p.timestamp, p.price, source),
+            );
+        }
+
+        for t in &doc.transactions {
+            let mut tx = Transaction::new_with_details(TransactionArgs {
+                memo: t.memo.as_deref(),
+                check_number: t.check_number.as_deref(),
+                payee: match t.payee {
+                    None => None,
+                    Some(p) => Some(get(&idx.payees, p, "payee")?.clone()),
+                },
+                entry_date: t.entry_date,
+            });
+            for s in &t.splits {
+                let account = get(&idx.accounts, s.account, "account")?;
+                tx.add_split(
+                    account.clone(),
+                    match &s.reconciled {
+                        ReconcileDoc::New => ReconcileKind::New,
+                        ReconcileDoc::Cleared => ReconcileKind::Cleared,
+                        ReconcileDoc::Reconciled { on } => {
+                            ReconcileKind::Reconciled(*on)
+                        }
+                    },
+                    s.post_ts,
+                    idx.operation(&s.operation)?,
+                );
+                if let Some(bank_id) = &s.bank_id {
+                    tx.set_bank_id(account, bank_id);
+                }
+            }
+            repo.add_transaction(tx)?;
+        }
+
+        for b in &doc.budgets {
+            let mut lines = Vec::new();
+            for line in &b.lines {
+                lines.push(BudgetLine {
+                    account: get(&idx.accounts, line.account, "account")?
+                        .clone(),
+                    period: match &line.period {
+                        BudgetPeriodDoc::Monthly => BudgetPeriod::Monthly,
+                        BudgetPeriodDoc::Yearly => BudgetPeriod::Yearly,
+                        BudgetPeriodDoc::Custom { intv } => {
+                            BudgetPeriod::Custom(intv.parse::<Intv>()?)
+                        }
+                    },
+                    amounts: line.amounts.clone(),
+                    rollover: line.rollover,
+                });
+            }
+            repo.budgets.add(Budget {
+                name: b.name.clone(),
+                start: b.start,
+                end: b.end,
+                commodity: get(&idx.commodities, b.commodity, "commodity")?
+                    .clone(),
+                lines,
+            });
+        }
+
+        for sch in &doc.schedules {
+            let mut splits = Vec::new();
+            for s in &sch.splits {
+                splits.push(ScheduledSplit {
+                    account: get(&idx.accounts, s.account, "account")?.clone(),
+                    value: idx.multi_value(&s.value)?,
+                });
+            }
+            repo.schedules.add(Schedule {
+                name: sch.name.clone(),
+                payee: match sch.payee {
+                    None => None,
+                    Some(p) => Some(get(&idx.payees, p, "payee")?.clone()),
+                },
+                memo: sch.memo.clone(),
+                start: sch.start,
+                recurrence: Recurrence {
+                    frequency: match sch.frequency {
+                        FrequencyDoc::Once => Frequency::Once,
+                        FrequencyDoc::Daily => Frequency::Daily,
+                        FrequencyDoc::Weekly => Frequency::Weekly,
+                        FrequencyDoc::Monthly => Frequency::Monthly,
+                        FrequencyDoc::Yearly => Frequency::Yearly,
+                    },
+                    every: sch.every,
+                    last_day_of_month: sch.last_day_of_month,
+                    weekend: match sch.weekend {
+                        WeekendDoc::MoveBefore => WeekendOption::MoveBefore,
+                        WeekendDoc::MoveAfter => WeekendOption::MoveAfter,
+                        WeekendDoc::Ignore => WeekendOption::Ignore,
+                    },
+                    end: match sch.end {
+                        RecurrenceEndDoc::Never => RecurrenceEnd::Never,
+                        RecurrenceEndDoc::On { date } => {
+                            RecurrenceEnd::On(date)
+                        }
+                        RecurrenceEndDoc::After { count } => {
+                            RecurrenceEnd::After(count)
+                        }
+                    },
+                },
+                last_entered: sch.last_entered,
+                splits,
+            });
+        }
+
+        Ok(repo)
+    }
+}
+
+impl Importer for AlereFile {
+    async fn import_file(
+        &mut self,
+        path: &Path,
+        report_progress: impl Fn(u64, u64),
+    ) -> Result<Repository> {
+        const MAX_PROGRESS: u64 = 3;
+
+        report_progress(1, MAX_PROGRESS);
+        let file = File::open(path)?;
+        let doc: Document = serde_json::from_reader(BufReader::new(file))?;
+        report_progress(2, MAX_PROGRESS);
+        let repo = AlereFile::load_document(doc)?;
+        report_progress(3, MAX_PROGRESS);
+        Ok(repo)
+    }
+}
+
+#[cfg(test)]
+pub mod test {
+    use crate::{
+        accounts::{AccountNameDepth, Reconciliation},
+        alere_file::AlereFile,
+        budgets::{Budget, BudgetLine, BudgetPeriod},
+        commodities::test::{create_currency, create_security},
+        formatters::Formatter,
+        importers::{Exporter, Importer},
+        lots::CostMethod,
+        multi_values::{MultiValue, Operation, Value},
+        price_sources::PriceSourceFrom,
+        prices::Price,
+        repositories::Repository,
+        schedules::{
+            Frequency, Recurrence, RecurrenceEnd, Schedule, ScheduledSplit,
+            WeekendOption,
+        },
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal_macros::dec;
+
+    /// A repository that uses all kinds of operations
+    pub fn build_repo() -> Result<Repository> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let aapl = create_security(&mut repo.commodities, "AAPL");
+        let source = repo.price_sources.add("Yahoo");
+        let payee = repo.payees.add("Broker");
+        let mut inst = repo.institutions.add(
+            "Bank",
+            None,
+            None,
+            None,
+            Some("Paris"),
+            None,
+        );
+        inst.set_bic("BNKFRPP");
+
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let income_kind = repo.account_kinds.lookup("income").unwrap().clone();
+        let mut checking = repo.accounts.add(
+            "Checking",
+            checking_kind.clone(),
+            None,
+            Some(inst),
+            Some("Main account"),
+            Some("FR76"),
+            Some("1234"),
+            false,
+            None,
+        );
+        let mut stock = repo.accounts.add_dummy("AAPL", stock_kind);
+        stock.set_parent(checking.clone());
+        stock.set_cost_method(CostMethod::Hifo);
+        stock.set_asset_class("region", "us");
+        let income = repo.accounts.add_dummy("Dividends", income_kind);
+
+        let d1 = Local.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap();
+        let d2 = Local.with_ymd_and_hms(2024, 3, 10, 0, 0, 0).unwrap();
+        let d3 = Local.with_ymd_and_hms(2024, 6, 10, 0, 0, 0).unwrap();
+        let d4 = Local.with_ymd_and_hms(2024, 7, 10, 0, 0, 0).unwrap();
+
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo: Some("buy"),
+            check_number: Some("42"),
+            payee: Some(payee),
+            entry_date: d1,
+        });
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::Reconciled(Some(d2)),
+            d1,
+            Operation::BuyAmount {
+                qty: Value {
+                    amount: dec!(10),
+                    commodity: aapl.clone(),
+                },
+                amount: Value {
+                    amount: dec!(1500),
+                    commodity: eur.clone(),
+                },
+            },
+        );
+        tx.add_split(
+            checking.clone(),
+            ReconcileKind::Cleared,
+            d1,
+            Operation::Credit(MultiValue::new(dec!(-1500), &eur)),
+        );
+        tx.set_bank_id(&checking, "FIT0001");
+        repo.add_transaction(tx)?;
+
+        let mut tx = Transaction::new_with_default();
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d2,
+            Operation::Split {
+                ratio: dec!(2),
+                commodity: aapl.clone(),
+            },
+        );
+        repo.add_transaction(tx)?;
+
+        let mut tx = Transaction::new_with_default();
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d3,
+            Operation::Dividend,
+        );
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d3,
+            Operation::Reinvest {
+                shares: MultiValue::new(dec!(1), &aapl),
+                amount: MultiValue::new(dec!(20), &eur),
+            },
+        );
+        tx.add_split(
+            income.clone(),
+            ReconcileKind::New,
+            d3,
+            Operation::Credit(MultiValue::new(dec!(-20), &eur)),
+        );
+        repo.add_transaction(tx)?;
+
+        let mut tx = Transaction::new_with_default();
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d4,
+            Operation::BuyPrice {
+                qty: Value {
+                    amount: dec!(-2),
+                    commodity: aapl.clone(),
+                },
+                price: Value {
+                    amount: dec!(80),
+                    commodity: eur.clone(),
+                },
+            },
+        );
+        tx.add_split(
+            checking.clone(),
+            ReconcileKind::New,
+            d4,
+            Operation::Credit(MultiValue::new(dec!(160), &eur)),
+        );
+        repo.add_transaction(tx)?;
+
+        repo.add_price(
+            &aapl,
+            &eur,
+            Price::new(
+                d3,
+                dec!(85.5),
+                PriceSourceFrom::External(source.get_id()),
+            ),
+        );
+        checking.add_reconciliation(Reconciliation {
+            timestamp: d2,
+            total: MultiValue::new(dec!(-1500), &eur),
+        });
+        repo.budgets.add(Budget {
+            name: "2024".to_string(),
+            start: d1,
+            end: Some(d4),
+            commodity: eur.clone(),
+            lines: vec![
+                BudgetLine {
+                    account: income.clone(),
+                    period: BudgetPeriod::Monthly,
+                    amounts: vec![dec!(10), dec!(20)],
+                    rollover: true,
+                },
+                BudgetLine {
+                    account: income.clone(),
+                    period: BudgetPeriod::Custom("2024-01..2024-06".parse()?),
+                    amounts: vec![dec!(5)],
+                    rollover: false,
+                },
+            ],
+        });
+        repo.schedules.add(Schedule {
+            name: "Dividends".to_string(),
+            payee: repo.payees.iter().next().cloned(),
+            memo: Some("yearly".to_string()),
+            start: d4,
+            recurrence: Recurrence {
+                frequency: Frequency::Yearly,
+                every: 1,
+                last_day_of_month: true,
+                weekend: WeekendOption::MoveBefore,
+                end: RecurrenceEnd::After(3),
+            },
+            last_entered: Some(d4),
+            splits: vec![
+                ScheduledSplit {
+                    account: checking.clone(),
+                    value: MultiValue::new(dec!(20), &eur),
+                },
+                ScheduledSplit {
+                    account: income.clone(),
+                    value: MultiValue::new(dec!(-20), &eur),
+                },
+            ],
+        });
+        Ok(repo)
+    }
+
+    fn describe(repo: &Repository) -> Vec<String> {
+        let format = Formatter::default();
+        let mut result = Vec::new();
+        for acc in repo.accounts.iter() {
+            result.push(format!(
+                "{} kind={} inst={:?} {:?} {:?} {:?} closed={} cost={} {:?}",
+                acc.name(AccountNameDepth::unlimited()),
+                acc.get_kind().get_name(),
+                acc.get_institution().map(|i| (i.get_name(), i.get_bic())),
+                acc.get_description(),
+                acc.get_iban(),
+                acc.get_number(),
+                acc.is_closed(),
+                acc.get_cost_method(),
+                acc.get_asset_classes(),
+            ));
+            for r in acc.iter_reconciliations() {
+                result.push(format!(
+                    "  rec {} {}",
+                    r.timestamp,
+                    r.total.display(&format)
+                ));
+            }
+        }
+        for c in repo.commodities.iter_commodities() {
+            result.push(format!(
+                "{} {:?}",
+                c.get_name(),
+                c.get_asset_classes()
+            ));
+        }
+        for tx in repo.transactions.iter() {
+            result.push(format!(
+                "{:?} {:?} {:?} {}",
+                tx.memo(),
+                tx.check_number(),
+                tx.payee().map(|p| p.get_name().clone()),
+                tx.display(&format),
+            ));
+            for s in tx.splits().iter() {
+                if let Some(bank_id) = &s.bank_id {
+                    result.push(format!(
+                        "  bank_id {} {bank_id}",
+                        s.account.name(AccountNameDepth::basename()),
+                    ));
+                }
+            }
+        }
+        let mut prices = Vec::new();
+        for ((origin, target), list) in repo.prices.iter() {
+            for p in list {
+                prices.push(format!(
+                    "{} {} {} {} {:?}",
+                    origin.get_name(),
+                    target.get_name(),
+                    p.timestamp,
+                    p.price,
+                    p.source(),
+                ));
+            }
+        }
+        prices.sort();
+        result.extend(prices);
+        for sch in repo.schedules.iter() {
+            result.push(format!(
+                "schedule {} {:?} {:?} {} {:?} {:?}",
+                sch.name,
+                sch.payee.as_ref().map(|p| p.get_name().clone()),
+                sch.memo,
+                sch.start,
+                sch.recurrence,
+                sch.last_entered,
+            ));
+            for s in &sch.splits {
+                result.push(format!(
+                    "  {} {}",
+                    s.account.name(AccountNameDepth::unlimited()),
+                    s.value.display(&format)
+                ));
+            }
+        }
+        for b in repo.budgets.iter() {
+            result.push(format!(
+                "budget {} {} {:?} {}",
+                b.name,
+                b.start,
+                b.end,
+                b.commodity.get_name()
+            ));
+            for line in &b.lines {
+                result.push(format!(
+                    "  {} {:?} {:?} {}",
+                    line.account.name(AccountNameDepth::unlimited()),
+                    line.period,
+                    line.amounts,
+                    line.rollover
+                ));
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let repo = build_repo()?;
+        if let Some(mut aapl) = repo.commodities.find("AAPL") {
+            aapl.set_asset_class("class", "stocks");
+        }
+        let path = std::env::temp_dir()
+            .join(format!("alere_round_trip_{}.alere", std::process::id()));
+        AlereFile::default().export_file(
+            &repo,
+            &path,
+            &Formatter::default(),
+        )?;
+        let loaded = futures::executor::block_on(
+            AlereFile::default().import_file(&path, |_, _| {}),
+        );
+        let content = std::fs::read_to_string(&path)?;
+        std::fs::remove_file(&path)?;
+        let loaded = loaded?;
+
+        assert!(content.contains("\"version\": 1"));
+        assert_eq!(describe(&repo), describe(&loaded));
+        assert_eq!(
+            loaded.commodities.iter_commodities().count(),
+            repo.commodities.iter_commodities().count()
+        );
+        assert_eq!(loaded.payees.iter().count(), 1);
+        assert_eq!(loaded.price_sources.iter().count(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_future_version() {
+        let doc = serde_json::from_str(r#"{"version": 999}"#).unwrap();
+        assert!(AlereFile::load_document(doc).is_err());
+    }
+}
@@ -0,0 +1,370 @@
+//! Asset allocation.
+//!
+//! Holdings are classified along a dimension chosen by the user (the kind of
+//! asset, the region, the currency,...).  The class is first looked up on the
+//! account, then on the commodity it holds.  The market value of each class
+//! is compared to a target weight, to suggest how much to buy or sell.
+
+use crate::{
+    accounts::Account,
+    commodities::Commodity,
+    errors::AlrError,
+    multi_values::MultiValue,
+    networth::{GroupBy, Networth, Settings as NetworthSettings},
+    repositories::Repository,
+    times::{Instant, Intv},
+    tree_keys::Key,
+    utils::read_text_file,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{collections::BTreeMap, path::Path};
+
+/// The class for holdings that have none in the requested dimension
+pub const UNCLASSIFIED: &str = "unclassified";
+
+/// The configuration file for the asset allocation
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AllocationConfig {
+    /// For each commodity (symbol or name) or account (full or short name),
+    /// its class in each dimension, for instance
+    ///    "ACME": {"class": "stocks", "region": "us"}
+    /// Commodities are searched first.
+    #[serde(default)]
+    pub classes: BTreeMap<String, BTreeMap<String, String>>,
+
+    /// For each dimension, the target weight of each class, in percent
+    #[serde(default)]
+    pub targets: BTreeMap<String, BTreeMap<String, Decimal>>,
+}
+
+impl AllocationConfig {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        serde_json::from_str(&read_text_file(path)?).with_context(|| {
+            format!("Invalid allocation file {}", path.display())
+        })
+    }
+
+    /// Attach the classes from the configuration to the commodities and
+    /// accounts of the repository.
+    pub fn apply(&self, repo: &Repository) -> Result<()> {
+        for (name, classes) in &self.classes {
+            if let Some(mut c) = repo.commodities.find(name) {
+                for (dimension, class) in classes {
+                    c.set_asset_class(dimension, class);
+                }
+            } else if let Some(mut acc) = repo.accounts.find(name) {
+                for (dimension, class) in classes {
+                    acc.set_asset_class(dimension, class);
+                }
+            } else {
+                Err(AlrError::Str(format!(
+                    "unknown commodity or account {name}"
+                )))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub struct Settings {
+    // Currency for market values and rebalancing amounts
+    pub commodity: Option<Commodity>,
+
+    // The dimension to classify holdings by, e.g. "class" or "region"
+    pub dimension: String,
+
+    // Target weight of each class, in percent.  They must add up to 100.
+    // No rebalancing is suggested when this is empty.
+    pub targets: BTreeMap<String, Decimal>,
+
+    // Money that will be added to the portfolio
+    pub new_money: MultiValue,
+
+    // Only suggest purchases, using the new money, instead of also selling
+    // classes that are above their target.
+    pub only_new_money: bool,
+}
+
+pub struct AllocationRow {
+    pub class: String,
+    pub value: MultiValue,
+
+    // Current and target weights, as ratios (0.6 for 60%)
+    pub weight: Option<Decimal>,
+    pub target: Option<Decimal>,
+
+    // Positive to buy, negative to sell
+    pub rebalance: MultiValue,
+}
+
+pub struct Allocation {
+    pub total: MultiValue,
+    pub rows: Vec<AllocationRow>,
+}
+
+impl Allocation {
+    /// The class of the holdings of commodity in the account
+    #[must_use]
+    pub fn classify(
+        account: &Account,
+        commodity: &Commodity,
+        dimension: &str,
+    ) -> String {
+        account
+            .get_asset_class(dimension)
+            .or_else(|| commodity.get_asset_class(dimension))
+            .or_else(|| {
+                // Currencies are known even when not configured
+                if dimension != "currency" {
+                    None
+                } else if commodity.is_currency() {
+                    Some(commodity.get_symbol().clone())
+                } else {
+                    commodity
+                        .get_quote_currency()
+                        .map(|q| q.get_symbol().clone())
+                }
+            })
+            .unwrap_or_else(|| UNCLASSIFIED.to_string())
+    }
+
+    pub fn new(
+        repo: &Repository,
+        settings: &Settings,
+        now: DateTime<Local>,
+    ) -> Result<Self> {
+        let sum = settings.targets.values().sum::<Decimal>();
+        if !settings.targets.is_empty() && sum != Decimal::ONE_HUNDRED {
+            Err(AlrError::Str(format!(
+                "target weights add up to {sum}%, instead of 100%"
+            )))?;
+        }
+
+        let networth = Networth::new(
+            repo,
+            NetworthSettings {
+                hide_zero_rows: true,
+                hide_all_same: false,
+                group_by: GroupBy::None,
+                subtotals: false,
+                commodity: settings.commodity.clone(),
+                elide_boring_accounts: false,
+                intervals: vec![Intv::UpTo(Instant::Now)],
+            },
+            now,
+            |acc: &Account| acc.get_kind().is_networth(),
+        )?;
+
+        let mut prices = repo.market_prices(settings.commodity.clone());
+        let mut per_class: BTreeMap<String, MultiValue> = BTreeMap::new();
+        networth.tree.traverse(
+            |node| {
+                if let Key::Account(acc) = &node.data.key {
+                    for v in node.data.data.get_value(0)?.iter() {
+                        let class = Allocation::classify(
+                            acc,
+                            &v.commodity,
+                            &settings.dimension,
+                        );
+                        *per_class.entry(class).or_default() +=
+                            prices.convert_value(&v, &now);
+                    }
+                }
+                Ok(())
+            },
+            true,
+        )?;
+        for class in settings.targets.keys() {
+            per_class.entry(class.clone()).or_default();
+        }
+
+        let total = per_class
+            .values()
+            .fold(MultiValue::zero(), |total, v| total + v);
+        let total_after = &total + &settings.new_money;
+
+        let mut rows = per_class
+            .into_iter()
+            .map(|(class, value)| {
+                let target = settings
+                    .targets
+                    .get(&class)
+                    .map(|t| t / Decimal::ONE_HUNDRED)
+                    .or(if settings.targets.is_empty() {
+                        None
+                    } else {
+                        Some(Decimal::ZERO)
+                    });
+                AllocationRow {
+                    weight: &value / &total,
+                    rebalance: match target {
+                        None => MultiValue::zero(),
+                        Some(t) => &(&total_after * t) - &value,
+                    },
+                    class,
+                    value,
+                    target,
+                }
+            })
+            .collect::<Vec<_>>();
+
+        // Only buy the classes below their target, in proportion of how much
+        // they miss.
+        if settings.only_new_money {
+            let deficit = |r: &AllocationRow| {
+                (&r.rebalance / &total_after)
+                    .unwrap_or_default()
+                    .max(Decimal::ZERO)
+            };
+            let missing = rows.iter().map(deficit).sum::<Decimal>();
+            for r in &mut rows {
+                r.rebalance = if missing.is_zero() {
+                    MultiValue::zero()
+                } else {
+                    &settings.new_money * (deficit(r) / missing)
+                };
+            }
+        }
+
+        Ok(Allocation { total, rows })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        allocation::{Allocation, Settings},
+        commodities::test::{create_currency, create_security},
+        multi_values::{MultiValue, Operation, Value},
+        price_sources::PriceSourceFrom,
+        prices::Price,
+        repositories::Repository,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_allocation() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let mut acme = create_security(&mut repo.commodities, "ACME");
+        acme.set_asset_class("class", "stocks");
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let equity_kind = repo.account_kinds.lookup("equity").unwrap().clone();
+        let stock = repo.accounts.add_dummy("ACME", stock_kind);
+        let mut cash = repo.accounts.add_dummy("Cash", checking_kind);
+        cash.set_asset_class("class", "cash");
+        let equity = repo.accounts.add_dummy("Opening", equity_kind);
+
+        let d = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            entry_date: d,
+            ..TransactionArgs::default()
+        });
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::BuyAmount {
+                qty: Value {
+                    amount: dec!(10),
+                    commodity: acme.clone(),
+                },
+                amount: Value {
+                    amount: dec!(600),
+                    commodity: eur.clone(),
+                },
+            },
+        );
+        tx.add_split(
+            cash.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(400), &eur)),
+        );
+        tx.add_split(
+            equity.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(-1000), &eur)),
+        );
+        repo.add_transaction(tx)?;
+
+        let now = Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        repo.add_price(
+            &acme,
+            &eur,
+            Price::new(now, dec!(80), PriceSourceFrom::Transaction),
+        );
+
+        let amount =
+            |v: &MultiValue| v.iter().map(|v| v.amount).sum::<Decimal>();
+        let mut settings = Settings {
+            commodity: Some(eur.clone()),
+            dimension: "class".to_string(),
+            targets: BTreeMap::from([
+                ("stocks".to_string(), dec!(50)),
+                ("cash".to_string(), dec!(30)),
+                ("bonds".to_string(), dec!(20)),
+            ]),
+            new_money: MultiValue::new(dec!(300), &eur),
+            only_new_money: false,
+        };
+        let describe = |alloc: &Allocation| {
+            alloc
+                .rows
+                .iter()
+                .map(|r| {
+                    format!(
+                        "{} {} {:?} {}",
+                        r.class,
+                        amount(&r.value),
+                        r.weight.map(|w| w.round_dp(2)),
+                        amount(&r.rebalance).round_dp(2),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let alloc = Allocation::new(&repo, &settings, now)?;
+        assert_eq!(amount(&alloc.total), dec!(1200));
+        assert_eq!(
+            describe(&alloc),
+            vec![
+                "bonds 0 Some(0) 300.00",
+                "cash 400 Some(0.33) 50.00",
+                "stocks 800 Some(0.67) -50.00",
+            ]
+        );
+
+        settings.only_new_money = true;
+        let alloc = Allocation::new(&repo, &settings, now)?;
+        assert_eq!(
+            describe(&alloc),
+            vec![
+                "bonds 0 Some(0) 257.14",
+                "cash 400 Some(0.33) 42.86",
+                "stocks 800 Some(0.67) 0",
+            ]
+        );
+
+        settings.dimension = "currency".to_string();
+        settings.targets.clear();
+        let alloc = Allocation::new(&repo, &settings, now)?;
+        assert_eq!(
+            describe(&alloc),
+            vec!["EUR 400 Some(0.33) 0", "unclassified 800 Some(0.67) 0"]
+        );
+        Ok(())
+    }
+}
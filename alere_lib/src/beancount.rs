@@ -0,0 +1,704 @@
+//! Export to beancount.
+//!
+//! Beancount is stricter than hledger: account names must start with one of
+//! the five root accounts and only use a limited set of characters, commodity
+//! names must be upper-case, and accounts must be opened before they are used.
+//! Securities are held at cost, so that beancount can compute capital gains
+//! itself when they are sold.
+
+use crate::account_categories::AccountCategory;
+use crate::accounts::{Account, AccountId, AccountNameDepth};
+use crate::commodities::{Commodity, CommodityId};
+use crate::formatters::Formatter;
+use crate::hledger::exported_transactions;
+use crate::importers::Exporter;
+use crate::lots::{CostMethod, Lots};
+use crate::multi_values::{MultiValue, Operation, Value};
+use crate::price_sources::PriceSourceFrom;
+use crate::repositories::Repository;
+use crate::transactions::{Split, Transaction};
+use anyhow::Result;
+use chrono::{DateTime, Days, Local, NaiveDate};
+use rust_decimal::Decimal;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+/// Booking method for accounts that hold securities at cost.  Sales do not
+/// specify which lots they reduce, so we must use the same method as beancount
+/// when we track lots ourselves to handle stock splits.
+const BOOKING: &str = "FIFO";
+const BOOKING_METHOD: CostMethod = CostMethod::Fifo;
+
+/// Where beancount records the gains (or losses) when selling securities
+const GAINS_ACCOUNT: &str = "Income:Capital-Gains";
+
+/// Used to balance stock splits for shares that were not held at cost
+const SPLITS_ACCOUNT: &str = "Equity:Stock-Splits";
+
+#[derive(Default)]
+pub struct Beancount {
+    pub export_reconciliation: bool,
+}
+
+impl Exporter for Beancount {
+    fn export_file(
+        &mut self,
+        repo: &Repository,
+        export_to: &Path,
+        _format: &Formatter,
+    ) -> Result<()> {
+        let now = Local::now();
+        let names = Names::new(repo);
+
+        // Output transactions first, so that we know when accounts are first
+        // used and which commodities they contain.
+        let mut txs = Transactions {
+            names: &names,
+            out: String::new(),
+            usage: HashMap::new(),
+            lots: HashMap::new(),
+            used_gains: false,
+            used_splits: false,
+            start: now.date_naive(),
+        };
+        for (ts, tx) in exported_transactions(repo, now) {
+            txs.write_transaction(tx, ts)?;
+        }
+        let start = txs.start;
+
+        let mut out = String::new();
+        for com in repo.commodities.iter_commodities() {
+            writeln!(out, "{start} commodity {}", names.commodity(com))?;
+            writeln!(out, "  name: {}", quoted(&com.get_name()))?;
+        }
+        out.push('\n');
+
+        // Open accounts before their first use, with the list of commodities
+        // they can contain.
+        let mut closes = String::new();
+        for acc in repo.accounts.iter() {
+            let usage = txs.usage.remove(&acc.get_id()).unwrap_or_default();
+            let opened = acc.get_opened_on().map(|d| d.date_naive());
+            let open = match (opened, usage.first) {
+                (Some(o), Some(f)) => o.min(f),
+                (Some(d), None) | (None, Some(d)) => d,
+                (None, None) => start,
+            };
+            write!(out, "{open} open {}", names.account(&acc))?;
+            if !usage.commodities.is_empty() {
+                let coms = usage.commodities.into_iter().collect::<Vec<_>>();
+                write!(out, "  {}", coms.join(","))?;
+            }
+            if usage.has_lots {
+                write!(out, "  {}", quoted(BOOKING))?;
+            }
+            out.push('\n');
+
+            // Beancount does not accept postings on the day the account is
+            // closed.
+            if acc.is_closed() {
+                let last = acc
+                    .get_closed_date()
+                    .map(|d| d.date_naive())
+                    .into_iter()
+                    .chain(usage.last)
+                    .max()
+                    .unwrap_or(open);
+                let close = last + Days::new(1);
+                writeln!(closes, "{close} close {}", names.account(&acc))?;
+            }
+        }
+        if txs.used_gains {
+            writeln!(out, "{start} open {GAINS_ACCOUNT}")?;
+        }
+        if txs.used_splits {
+            writeln!(out, "{start} open {SPLITS_ACCOUNT}")?;
+        }
+        out.push('\n');
+        out.push_str(&txs.out);
+
+        // Balance assertions are checked at the beginning of the day, so are
+        // output for the day after the reconciliation.
+        // As for hledger, they might fail if some earlier transactions had
+        // not been cleared when the reconciliation was performed.
+        if self.export_reconciliation {
+            for acc in repo.accounts.iter() {
+                if !acc.get_kind().is_networth() {
+                    continue;
+                }
+                let closed = acc.get_closed_date().map(|d| d.date_naive());
+                for rec in acc.iter_reconciliations() {
+                    let date = rec.timestamp.date_naive() + Days::new(1);
+                    if closed.is_some_and(|c| date > c) {
+                        continue;
+                    }
+                    for v in rec.total.iter() {
+                        writeln!(
+                            out,
+                            "{date} balance {}  {}",
+                            names.account(&acc),
+                            names.value(&v),
+                        )?;
+                    }
+                }
+            }
+            out.push('\n');
+        }
+
+        // Prices deduced from transactions are not exported, beancount's
+        // implicit_prices plugin will compute them.
+        let mut prices = Vec::new();
+        for ((from, to), pr) in repo.prices.iter() {
+            for p in pr {
+                if from == to || p.source() == PriceSourceFrom::Transaction {
+                    continue;
+                }
+                prices.push(format!(
+                    "{} price {} {}",
+                    p.timestamp.date_naive(),
+                    names.commodity(from),
+                    names.value(&Value {
+                        amount: p.price,
+                        commodity: to.clone(),
+                    }),
+                ));
+            }
+        }
+        prices.sort();
+        for p in prices {
+            writeln!(out, "{p}")?;
+        }
+        out.push('\n');
+        out.push_str(&closes);
+
+        std::fs::write(export_to, out)?;
+        Ok(())
+    }
+}
+
+/// Outputs the transactions, while keeping track of how accounts are used and
+/// of the lots they hold.
+struct Transactions<'a> {
+    names: &'a Names,
+    out: String,
+    usage: HashMap<AccountId, Usage>,
+
+    // The lots held in each account.  Lots without a cost were not held at
+    // cost.
+    lots: HashMap<AccountId, Lots>,
+    used_gains: bool,
+    used_splits: bool,
+
+    // Date of the oldest transaction
+    start: NaiveDate,
+}
+
+impl Transactions<'_> {
+    fn write_transaction(
+        &mut self,
+        tx: &Transaction,
+        ts: DateTime<Local>,
+    ) -> Result<()> {
+        let date = ts.date_naive();
+        self.start = self.start.min(date);
+
+        write!(self.out, "{date} *")?;
+        if let Some(p) = tx.payee() {
+            write!(self.out, " {}", quoted(&p.get_name()))?;
+        }
+        let memo = tx.memo().as_ref().map(|m| m.replace('\n', " "));
+        writeln!(self.out, " {}", quoted(memo.as_deref().unwrap_or("")))?;
+        if let Some(num) = tx.check_number().as_ref() {
+            writeln!(self.out, "  check: {}", quoted(num))?;
+        }
+
+        let mut needs_gains = false;
+        for split in tx.splits().iter() {
+            needs_gains |= self.write_split(split, ts)?;
+        }
+
+        // Beancount computes the gains from the lots that were sold
+        if needs_gains {
+            self.used_gains = true;
+            writeln!(self.out, "  {GAINS_ACCOUNT}")?;
+        }
+        self.out.push('\n');
+        Ok(())
+    }
+
+    /// Output the postings for one split.
+    /// Returns true if shares were sold.
+    fn write_split(
+        &mut self,
+        split: &Split,
+        ts: DateTime<Local>,
+    ) -> Result<bool> {
+        let id = split.account.get_id();
+        let date = ts.date_naive();
+        let usage = self.usage.entry(id).or_default();
+        usage.first = Some(usage.first.map_or(date, |f| f.min(date)));
+        usage.last = Some(usage.last.map_or(date, |l| l.max(date)));
+
+        match &split.operation {
+            Operation::Credit(mv) => {
+                for v in mv.iter() {
+                    self.posting(&split.account, &v, "")?;
+                }
+            }
+            Operation::BuyAmount { qty, amount } => {
+                let total = format!(" @@ {}", self.names.value(&amount.abs()));
+                if qty.commodity.is_currency() {
+                    // A currency conversion, not held at cost
+                    self.posting(&split.account, qty, &total)?;
+                } else if !qty.amount.is_zero() {
+                    let cost = Value {
+                        amount: amount.amount / qty.amount,
+                        commodity: amount.commodity.clone(),
+                    };
+                    return self.trade(&split.account, qty, cost, &total, ts);
+                }
+            }
+            Operation::BuyPrice { qty, price } => {
+                let unit = format!(" @ {}", self.names.value(price));
+                if qty.commodity.is_currency() {
+                    self.posting(&split.account, qty, &unit)?;
+                } else {
+                    return self.trade(
+                        &split.account,
+                        qty,
+                        price.clone(),
+                        &unit,
+                        ts,
+                    );
+                }
+            }
+            Operation::AddShares { qty } => {
+                self.posting(&split.account, qty, "")?;
+                let lots = self.lots(&split.account);
+                if qty.is_negative() {
+                    lots.sell(ts, &qty.abs(), &MultiValue::zero());
+                } else {
+                    lots.buy(ts, qty, MultiValue::zero());
+                }
+            }
+            Operation::Reinvest { shares, amount } => {
+                writeln!(self.out, "  ; reinvest")?;
+                if let Some(s) = shares.iter().next() {
+                    match amount.iter().next() {
+                        Some(a) => {
+                            let cost = Value {
+                                amount: a.amount / s.amount,
+                                commodity: a.commodity,
+                            };
+                            self.trade(&split.account, &s, cost, "", ts)?;
+                        }
+                        None => {
+                            self.posting(&split.account, &s, "")?;
+                            self.lots(&split.account).buy(
+                                ts,
+                                &s,
+                                MultiValue::zero(),
+                            );
+                        }
+                    }
+                }
+            }
+            Operation::Dividend => {
+                writeln!(self.out, "  ; dividend")?;
+            }
+            Operation::Split { ratio, commodity } => {
+                self.stock_split(&split.account, *ratio, commodity)?;
+            }
+        }
+        Ok(false)
+    }
+
+    /// Output a posting, and remember which commodities the account uses
+    fn posting(
+        &mut self,
+        account: &Account,
+        units: &Value,
+        annotation: &str,
+    ) -> Result<()> {
+        self.usage
+            .entry(account.get_id())
+            .or_default()
+            .commodities
+            .insert(self.names.commodity(&units.commodity));
+        writeln!(
+            self.out,
+            "  {}  {}{annotation}",
+            self.names.account(account),
+            self.names.value(units),
+        )?;
+        Ok(())
+    }
+
+    /// The lots held in an account
+    fn lots(&mut self, account: &Account) -> &mut Lots {
+        self.lots
+            .entry(account.get_id())
+            .or_insert_with(|| Lots::empty(account, BOOKING_METHOD))
+    }
+
+    /// Buy shares at the given cost per share, or sell them at the price
+    /// given in the annotation.  Returns true if shares were sold.
+    fn trade(
+        &mut self,
+        account: &Account,
+        qty: &Value,
+        cost: Value,
+        sale: &str,
+        ts: DateTime<Local>,
+    ) -> Result<bool> {
+        if qty.is_negative() {
+            self.lots(account).sell(ts, &qty.abs(), &MultiValue::zero());
+            self.posting(account, qty, &format!(" {{}}{sale}"))?;
+            Ok(true)
+        } else {
+            self.usage.entry(account.get_id()).or_default().has_lots = true;
+            let annotation = format!(" {{{}}}", self.names.value(&cost));
+            self.posting(account, qty, &annotation)?;
+            let total =
+                MultiValue::new(cost.amount * qty.amount, &cost.commodity);
+            self.lots(account).buy(ts, qty, total);
+            Ok(false)
+        }
+    }
+
+    /// Remove each lot, and add it back with the new number of shares and
+    /// cost, preserving its acquisition date.
+    fn stock_split(
+        &mut self,
+        account: &Account,
+        ratio: Decimal,
+        commodity: &Commodity,
+    ) -> Result<()> {
+        let lots = self.lots(account).take(commodity);
+        for lot in lots {
+            let before = lot.shares.clone();
+            let after = Value {
+                amount: lot.shares.amount * ratio,
+                commodity: commodity.clone(),
+            };
+            let date = lot.acquired.date_naive();
+            match lot.cost.iter().next() {
+                Some(total) => {
+                    let cost = Value {
+                        amount: total.amount / lot.shares.amount,
+                        commodity: total.commodity.clone(),
+                    };
+                    let new_cost = Value {
+                        amount: cost.amount / ratio,
+                        commodity: cost.commodity.clone(),
+                    };
+                    let old =
+                        format!(" {{{}, {date}}}", self.names.value(&cost));
+                    let new =
+                        format!(" {{{}, {date}}}", self.names.value(&new_cost));
+                    self.posting(account, &-&before, &old)?;
+                    self.posting(account, &after, &new)?;
+                    self.lots(account).buy(lot.acquired, &after, lot.cost);
+                }
+                None => {
+                    self.used_splits = true;
+                    self.posting(account, &-&before, "")?;
+                    self.posting(account, &after, "")?;
+                    writeln!(
+                        self.out,
+                        "  {SPLITS_ACCOUNT}  {}",
+                        self.names.value(&Value {
+                            amount: before.amount - after.amount,
+                            commodity: commodity.clone(),
+                        }),
+                    )?;
+                    self.lots(account).buy(
+                        lot.acquired,
+                        &after,
+                        MultiValue::zero(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How an account is used in the transactions
+#[derive(Default)]
+struct Usage {
+    first: Option<NaiveDate>,
+    last: Option<NaiveDate>,
+    commodities: BTreeSet<String>,
+    has_lots: bool,
+}
+
+/// The names used in beancount for accounts and commodities, which must be
+/// unique and only use a restricted set of characters.
+struct Names {
+    accounts: HashMap<AccountId, String>,
+    commodities: HashMap<CommodityId, (String, u8)>,
+}
+
+impl Names {
+    fn new(repo: &Repository) -> Self {
+        let mut used = HashSet::new();
+        let mut commodities = HashMap::new();
+        for (idx, com) in repo.commodities.iter_commodities().enumerate() {
+            let name =
+                commodity_name(com).unwrap_or_else(|| format!("C{}", idx + 1));
+            commodities.insert(
+                com.get_id(),
+                (unique(&mut used, name), com.get_display_precision()),
+            );
+        }
+
+        let mut used = HashSet::from([
+            GAINS_ACCOUNT.to_string(),
+            SPLITS_ACCOUNT.to_string(),
+        ]);
+        let mut accounts = HashMap::new();
+        for acc in repo.accounts.iter() {
+            accounts
+                .insert(acc.get_id(), unique(&mut used, account_name(&acc)));
+        }
+
+        Names {
+            accounts,
+            commodities,
+        }
+    }
+
+    fn account(&self, acc: &Account) -> &str {
+        self.accounts
+            .get(&acc.get_id())
+            .expect("Account belongs to the repository")
+    }
+
+    fn commodity(&self, com: &Commodity) -> String {
+        self.commodities
+            .get(&com.get_id())
+            .expect("Commodity belongs to the repository")
+            .0
+            .clone()
+    }
+
+    /// Display a value, using at least the display precision of its
+    /// commodity, but never losing digits.
+    fn value(&self, value: &Value) -> String {
+        let (name, precision) = self
+            .commodities
+            .get(&value.commodity.get_id())
+            .expect("Commodity belongs to the repository");
+        let mut amount = value.amount.normalize();
+        if amount.scale() < u32::from(*precision) {
+            amount.rescale(u32::from(*precision));
+        }
+        format!("{amount} {name}")
+    }
+}
+
+/// Make a name unique by adding a suffix
+fn unique(used: &mut HashSet<String>, name: String) -> String {
+    let mut candidate = name.clone();
+    let mut count = 2;
+    while !used.insert(candidate.clone()) {
+        candidate = format!("{name}-{count}");
+        count += 1;
+    }
+    candidate
+}
+
+fn quoted(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Replace accented letters with their ASCII equivalent
+fn fold_accent(c: char) -> char {
+    match c {
+        'à' | 'á' | 'â' | 'ã' | 'ä' | 'å' => 'a',
+        'À' | 'Á' | 'Â' | 'Ã' | 'Ä' | 'Å' => 'A',
+        'è' | 'é' | 'ê' | 'ë' => 'e',
+        'È' | 'É' | 'Ê' | 'Ë' => 'E',
+        'ì' | 'í' | 'î' | 'ï' => 'i',
+        'Ì' | 'Í' | 'Î' | 'Ï' => 'I',
+        'ò' | 'ó' | 'ô' | 'õ' | 'ö' => 'o',
+        'Ò' | 'Ó' | 'Ô' | 'Õ' | 'Ö' => 'O',
+        'ù' | 'ú' | 'û' | 'ü' => 'u',
+        'Ù' | 'Ú' | 'Û' | 'Ü' => 'U',
+        'ç' => 'c',
+        'Ç' => 'C',
+        'ñ' => 'n',
+        'Ñ' => 'N',
+        _ => c,
+    }
+}
+
+/// Beancount commodities are upper-case, start with a letter and end with a
+/// letter or digit.  We prefer the quote symbol (an ISO code or a ticker),
+/// then the symbol and the name.
+fn commodity_name(com: &Commodity) -> Option<String> {
+    let candidates = [
+        com.get_quote_symbol(),
+        Some(com.get_symbol().clone()),
+        Some(com.get_name().clone()),
+    ];
+    candidates.into_iter().flatten().find_map(|c| {
+        let name = c
+            .chars()
+            .map(|c| fold_accent(c).to_ascii_uppercase())
+            .filter(|c| c.is_ascii_alphanumeric() || "'._-".contains(*c))
+            .skip_while(|c| !c.is_ascii_alphabetic())
+            .take(24)
+            .collect::<String>();
+        let name = name.trim_end_matches(|c: char| !c.is_ascii_alphanumeric());
+        (name.len() >= 2).then(|| name.to_string())
+    })
+}
+
+/// A component of an account name: starts with an upper-case letter or digit,
+/// and only contains letters, digits and dashes.
+fn account_component(name: &str) -> String {
+    let mut result = String::new();
+    for c in name.chars().map(fold_accent) {
+        if c.is_ascii_alphanumeric() {
+            result.push(c);
+        } else if !result.is_empty() && !result.ends_with('-') {
+            result.push('-');
+        }
+    }
+    while result.ends_with('-') {
+        result.pop();
+    }
+    let mut chars = result.chars();
+    match chars.next() {
+        None => "X".to_string(),
+        Some(first) => {
+            let mut s = first.to_ascii_uppercase().to_string();
+            s.push_str(chars.as_str());
+            s
+        }
+    }
+}
+
+/// The root account is chosen from the account kind, and any root already in
+/// the name (as in kmymoney's "Asset:Checking") is dropped.
+fn account_name(acc: &Account) -> String {
+    let kind = acc.get_kind();
+    let root = match kind.get_category() {
+        AccountCategory::EXPENSE => "Expenses",
+        AccountCategory::INCOME => "Income",
+        AccountCategory::LIABILITY => "Liabilities",
+        AccountCategory::ASSET => "Assets",
+        AccountCategory::EQUITY if kind.is_networth() => "Assets",
+        AccountCategory::EQUITY => "Equity",
+    };
+    let full = acc.name(AccountNameDepth::unlimited());
+    let mut parts = full.split(':').collect::<Vec<_>>();
+    if parts.len() > 1
+        && parts.first().is_some_and(|p| {
+            matches!(
+                p.to_lowercase().as_str(),
+                "asset"
+                    | "assets"
+                    | "liability"
+                    | "liabilities"
+                    | "equity"
+                    | "income"
+                    | "revenue"
+                    | "revenues"
+                    | "expense"
+                    | "expenses"
+            )
+        })
+    {
+        parts.remove(0);
+    }
+    let mut result = root.to_string();
+    for p in parts {
+        result.push(':');
+        result.push_str(&account_component(p));
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::alere_file::test::build_repo;
+    use crate::beancount::{Beancount, account_component, commodity_name};
+    use crate::commodities::CommodityCollection;
+    use crate::formatters::Formatter;
+    use crate::importers::Exporter;
+    use anyhow::Result;
+
+    #[test]
+    fn test_export() -> Result<()> {
+        let repo = build_repo()?;
+        let path = std::env::temp_dir()
+            .join(format!("alere_beancount_{}.beancount", std::process::id()));
+        let mut exporter = Beancount {
+            export_reconciliation: true,
+        };
+        exporter.export_file(&repo, &path, &Formatter::default())?;
+        let output = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            output?,
+            r#"2024-01-10 commodity EUR
+  name: "EUR"
+2024-01-10 commodity AAPL
+  name: "AAPL"
+
+2024-01-10 open Assets:Checking  EUR
+2024-01-10 open Assets:Checking:AAPL  AAPL  "FIFO"
+2024-06-10 open Income:Dividends  EUR
+2024-01-10 open Income:Capital-Gains
+
+2024-01-10 * "Broker" "buy"
+  check: "42"
+  Assets:Checking:AAPL  10.00 AAPL {150.00 EUR}
+  Assets:Checking  -1500.00 EUR
+
+2024-03-10 * ""
+  Assets:Checking:AAPL  -10.00 AAPL {150.00 EUR, 2024-01-10}
+  Assets:Checking:AAPL  20.00 AAPL {75.00 EUR, 2024-01-10}
+
+2024-06-10 * ""
+  ; dividend
+  ; reinvest
+  Assets:Checking:AAPL  1.00 AAPL {20.00 EUR}
+  Income:Dividends  -20.00 EUR
+
+2024-07-10 * ""
+  Assets:Checking:AAPL  -2.00 AAPL {} @ 80.00 EUR
+  Assets:Checking  160.00 EUR
+  Income:Capital-Gains
+
+2024-03-11 balance Assets:Checking  -1500.00 EUR
+
+2024-06-10 price AAPL 85.50 EUR
+
+"#
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_names() {
+        assert_eq!(
+            account_component("Dépenses courantes"),
+            "Depenses-courantes"
+        );
+        assert_eq!(account_component("(misc)"), "Misc");
+        assert_eq!(account_component("**"), "X");
+
+        let mut coms = CommodityCollection::default();
+        let euro = coms.add("Euro", "€", false, true, Some("EUR"), 2);
+        let fund = coms.add("Fonds à 5%", "", false, false, None, 4);
+        let bad = coms.add("€", "€", false, true, None, 2);
+        assert_eq!(commodity_name(&euro).as_deref(), Some("EUR"));
+        assert_eq!(commodity_name(&fund).as_deref(), Some("FONDSA5"));
+        assert_eq!(commodity_name(&bad), None);
+    }
+}
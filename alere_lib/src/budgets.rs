@@ -0,0 +1,542 @@
+//! Budgets.
+//!
+//! A budget sets the amount expected for income and expense accounts over
+//! each period (a month, a year or any interval).  The report compares those
+//! amounts with the actual transactions.
+
+use crate::{
+    accounts::{Account, AccountId},
+    commodities::Commodity,
+    errors::AlrError,
+    multi_values::MultiValue,
+    networth::{GroupBy, Networth, Settings},
+    repositories::Repository,
+    times::{Intv, TimeInterval},
+    tree_keys::Key,
+    trees::Tree,
+};
+use anyhow::Result;
+use chrono::{DateTime, Local, Months};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// How often the budgeted amounts apply
+#[derive(Clone, Debug, PartialEq)]
+pub enum BudgetPeriod {
+    Monthly,
+    Yearly,
+
+    // Each range of the interval is one period, so "2024-01..2024-06" is six
+    // monthly periods and "2024" is a single one.
+    Custom(Intv),
+}
+
+/// The start, end and budgeted amount of one period
+type Period = (DateTime<Local>, DateTime<Local>, Decimal);
+
+/// The budget for one account.  It also applies to the subaccounts, which
+/// do not need their own budget.
+#[derive(Clone, Debug)]
+pub struct BudgetLine {
+    pub account: Account,
+    pub period: BudgetPeriod,
+
+    // The amount for each period, positive for both expenses and income.
+    // When there are fewer amounts than periods, they are used in a cycle: a
+    // single amount applies to all periods, and twelve monthly amounts give
+    // a different amount for each month of the year.
+    pub amounts: Vec<Decimal>,
+
+    // Whether the amount not spent in a period is available in the following
+    // ones (and overspending reduces them).
+    pub rollover: bool,
+}
+
+impl BudgetLine {
+    /// The periods that start before `until`, with their budgeted amount.
+    fn periods(
+        &self,
+        budget: &Budget,
+        until: DateTime<Local>,
+        now: DateTime<Local>,
+    ) -> Result<Vec<Period>> {
+        let until = budget.end.map_or(until, |end| end.min(until));
+        let amount = |idx: usize| {
+            self.amounts
+                .get(idx % self.amounts.len().max(1))
+                .copied()
+                .unwrap_or_default()
+        };
+        let mut result = Vec::new();
+        let step = match &self.period {
+            BudgetPeriod::Monthly => 1,
+            BudgetPeriod::Yearly => 12,
+            BudgetPeriod::Custom(intv) => {
+                for (idx, range) in intv.to_ranges(now)?.iter().enumerate() {
+                    if let (Some(lower), Some(upper)) =
+                        (range.intv.lower(), range.intv.upper())
+                        && *lower < until
+                    {
+                        result.push((*lower, *upper, amount(idx)));
+                    }
+                }
+                return Ok(result);
+            }
+        };
+
+        // Always compute from the start, so that a budget starting on the
+        // 31st still uses the last day of shorter months.
+        let mut lower = budget.start;
+        let mut idx = 0_u32;
+        while lower < until {
+            let Some(upper) = budget
+                .start
+                .checked_add_months(Months::new(step * (idx + 1)))
+            else {
+                break;
+            };
+            result.push((lower, upper, amount(idx as usize)));
+            lower = upper;
+            idx += 1;
+        }
+        Ok(result)
+    }
+
+    /// The amount budgeted in [lower; upper[.  Periods that only partially
+    /// overlap this range are prorated by their number of days.
+    pub fn budgeted(
+        &self,
+        budget: &Budget,
+        lower: DateTime<Local>,
+        upper: DateTime<Local>,
+        now: DateTime<Local>,
+    ) -> Result<Decimal> {
+        let mut total = Decimal::ZERO;
+        for (start, end, amount) in self.periods(budget, upper, now)? {
+            let overlap = (end.min(upper).date_naive()
+                - start.max(lower).date_naive())
+            .num_days();
+            let days = (end.date_naive() - start.date_naive()).num_days();
+            if overlap > 0 && days > 0 {
+                total += amount * Decimal::from(overlap) / Decimal::from(days);
+            }
+        }
+        Ok(total)
+    }
+}
+
+/// A named set of budget lines
+#[derive(Clone, Debug)]
+pub struct Budget {
+    pub name: String,
+
+    // Start of the first monthly or yearly period
+    pub start: DateTime<Local>,
+
+    // Nothing is budgeted after this date
+    pub end: Option<DateTime<Local>>,
+
+    // All amounts are in this commodity, and actual transactions are
+    // converted to it.
+    pub commodity: Commodity,
+
+    pub lines: Vec<BudgetLine>,
+}
+
+#[derive(Default)]
+pub struct BudgetCollection {
+    budgets: Vec<Budget>,
+}
+
+impl BudgetCollection {
+    pub fn add(&mut self, budget: Budget) {
+        self.budgets.push(budget);
+    }
+
+    /// Find a budget by name.  This is case-insensitive.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<&Budget> {
+        self.budgets
+            .iter()
+            .find(|b| b.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Budget> {
+        self.budgets.iter()
+    }
+}
+
+//--------------------------------------------------------------
+// Report
+//--------------------------------------------------------------
+
+/// Budgeted and actual amounts for one account over one column.  Income is
+/// counted as positive, like expenses.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BudgetCell {
+    pub budgeted: Decimal,
+    pub actual: Decimal,
+}
+
+impl BudgetCell {
+    #[must_use]
+    pub fn remaining(&self) -> Decimal {
+        self.budgeted - self.actual
+    }
+
+    /// The percent of the budget already consumed, or None if nothing was
+    /// budgeted.
+    #[must_use]
+    pub fn consumed(&self) -> Option<Decimal> {
+        if self.budgeted.is_zero() {
+            None
+        } else {
+            Some(self.actual * Decimal::ONE_HUNDRED / self.budgeted)
+        }
+    }
+
+    fn is_zero(&self) -> bool {
+        self.budgeted.is_zero() && self.actual.is_zero()
+    }
+}
+
+/// One row of the report, with a cell for each column
+#[derive(Clone)]
+pub struct BudgetRow(Vec<BudgetCell>);
+
+impl BudgetRow {
+    fn new(size: usize) -> Self {
+        BudgetRow(vec![BudgetCell::default(); size])
+    }
+
+    pub fn get(&self, idx: usize) -> Result<&BudgetCell> {
+        Ok(self.0.get(idx).ok_or(AlrError::IndexError)?)
+    }
+}
+
+impl core::ops::AddAssign<&BudgetRow> for BudgetRow {
+    fn add_assign(&mut self, rhs: &BudgetRow) {
+        self.0.iter_mut().zip(&rhs.0).for_each(|(v1, v2)| {
+            v1.budgeted += v2.budgeted;
+            v1.actual += v2.actual;
+        });
+    }
+}
+
+fn is_budget_account(acc: &Account) -> bool {
+    acc.get_kind().is_expense() || acc.get_kind().is_income()
+}
+
+/// Actual amounts are positive for both expenses and income
+fn sign(acc: &Account) -> Decimal {
+    if acc.get_kind().is_income() {
+        Decimal::NEGATIVE_ONE
+    } else {
+        Decimal::ONE
+    }
+}
+
+/// The part of the value that is expressed in commodity.  Other parts could
+/// not be converted, and are ignored.
+fn amount_in(value: &MultiValue, commodity: &Commodity) -> Decimal {
+    value
+        .iter()
+        .filter(|v| v.commodity == *commodity)
+        .map(|v| v.amount)
+        .sum()
+}
+
+/// Compares a budget with the actual transactions.  The tree has one node
+/// per income and expense account, and parents include the amounts of their
+/// children.
+pub struct BudgetReport {
+    pub tree: Tree<Key, BudgetRow>,
+    pub intervals: Vec<TimeInterval>, //  Each column
+    pub commodity: Commodity,
+}
+
+impl BudgetReport {
+    pub fn new(
+        repo: &Repository,
+        budget: &Budget,
+        intervals: Vec<Intv>,
+        now: DateTime<Local>,
+    ) -> Result<Self> {
+        // Same computation as the cashflow, but without subtotals so that we
+        // get each account's own amount.
+        let networth = Networth::new(
+            repo,
+            Settings {
+                hide_zero_rows: false,
+                hide_all_same: false,
+                group_by: GroupBy::ParentAccount,
+                subtotals: false,
+                commodity: Some(budget.commodity.clone()),
+                elide_boring_accounts: false,
+                intervals,
+            },
+            now,
+            is_budget_account,
+        )?;
+        let col_count = networth.intervals.len();
+
+        let mut actual: HashMap<AccountId, Vec<Decimal>> = HashMap::new();
+        networth.tree.traverse(
+            |node| {
+                if let Key::Account(acc) = &node.data.key {
+                    let values = (0..col_count)
+                        .map(|idx| {
+                            Ok(sign(acc)
+                                * amount_in(
+                                    node.data.data.get_market_value(idx)?,
+                                    &budget.commodity,
+                                ))
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    actual.insert(acc.get_id(), values);
+                }
+                Ok(())
+            },
+            true,
+        )?;
+
+        let mut report = BudgetReport {
+            tree: Tree::default(),
+            intervals: networth.intervals,
+            commodity: budget.commodity.clone(),
+        };
+
+        for acc in repo.accounts.iter().filter(is_budget_account) {
+            let row = report.tree.try_get(
+                &Key::Account(acc.clone()),
+                repo.accounts.iter_parents(&acc).map(Key::Account),
+                |_| BudgetRow::new(col_count),
+            );
+            if let Some(values) = actual.get(&acc.get_id()) {
+                for (cell, value) in row.0.iter_mut().zip(values) {
+                    cell.actual = *value;
+                }
+            }
+            for line in budget.lines.iter().filter(|l| l.account == acc) {
+                for (cell, intv) in row.0.iter_mut().zip(&report.intervals) {
+                    let lower = *intv.intv.lower().expect("bounded interval");
+                    let upper = *intv.intv.upper().expect("bounded interval");
+                    let lower = lower.max(budget.start);
+                    cell.budgeted +=
+                        line.budgeted(budget, lower, upper, now)?;
+                    if line.rollover && lower > budget.start {
+                        cell.budgeted +=
+                            line.budgeted(budget, budget.start, lower, now)?
+                                - actual_between(
+                                    repo,
+                                    &line.account,
+                                    &budget.commodity,
+                                    budget.start,
+                                    lower,
+                                );
+                    }
+                }
+            }
+        }
+
+        report.tree.retain(|node| {
+            node.has_children() || !node.data.data.0.iter().all(|c| c.is_zero())
+        });
+
+        report.tree.traverse_mut(
+            |node| {
+                let mut tmp = BudgetRow::new(col_count);
+                node.iter_children().for_each(|child| {
+                    tmp += &child.data.data;
+                });
+                node.data.data += &tmp;
+                Ok(())
+            },
+            false,
+        )?;
+
+        Ok(report)
+    }
+}
+
+/// The actual amount for an account and its subaccounts, in [lower; upper[
+fn actual_between(
+    repo: &Repository,
+    account: &Account,
+    commodity: &Commodity,
+    lower: DateTime<Local>,
+    upper: DateTime<Local>,
+) -> Decimal {
+    let mut value = MultiValue::default();
+    for acc in repo.accounts.iter().filter(|a| {
+        a == account || repo.accounts.iter_parents(a).any(|p| p == *account)
+    }) {
+        acc.for_each_split(|s| {
+            if s.post_ts >= lower && s.post_ts < upper {
+                value.apply(&s.operation);
+            }
+        });
+    }
+    let converted = repo
+        .market_prices(Some(commodity.clone()))
+        .convert_multi_value(&value, &upper);
+    sign(account) * amount_in(&converted, commodity)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::Account,
+        budgets::{Budget, BudgetCell, BudgetLine, BudgetPeriod, BudgetReport},
+        commodities::{Commodity, test::create_currency},
+        multi_values::{MultiValue, Operation},
+        repositories::Repository,
+        times::Intv,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+        tree_keys::Key,
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+    use std::str::FromStr;
+
+    fn add(
+        repo: &mut Repository,
+        from: &Account,
+        to: &Account,
+        eur: &Commodity,
+        month: u32,
+        amount: Decimal,
+    ) -> Result<()> {
+        let d = Local.with_ymd_and_hms(2024, month, 10, 0, 0, 0).unwrap();
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo: None,
+            check_number: None,
+            payee: None,
+            entry_date: d,
+        });
+        tx.add_split(
+            from.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(-amount, eur)),
+        );
+        tx.add_split(
+            to.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(amount, eur)),
+        );
+        repo.add_transaction(tx)
+    }
+
+    #[test]
+    fn test_budget() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let expense_kind =
+            repo.account_kinds.lookup("expense").unwrap().clone();
+        let income_kind = repo.account_kinds.lookup("income").unwrap().clone();
+        let checking = repo.accounts.add_dummy("Checking", checking_kind);
+        let food = repo.accounts.add_dummy("Food", expense_kind.clone());
+        let groceries = repo.accounts.add(
+            "Groceries",
+            expense_kind,
+            Some(food.clone()),
+            None,
+            None,
+            None,
+            None,
+            false,
+            None,
+        );
+        let salary = repo.accounts.add_dummy("Salary", income_kind);
+
+        add(&mut repo, &checking, &groceries, &eur, 1, dec!(250))?;
+        add(&mut repo, &checking, &groceries, &eur, 2, dec!(400))?;
+        add(&mut repo, &salary, &checking, &eur, 1, dec!(2000))?;
+
+        let start = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let budget = Budget {
+            name: "2024".to_string(),
+            start,
+            end: None,
+            commodity: eur.clone(),
+            lines: vec![
+                BudgetLine {
+                    account: food.clone(),
+                    period: BudgetPeriod::Monthly,
+                    amounts: vec![dec!(300)],
+                    rollover: true,
+                },
+                BudgetLine {
+                    account: salary.clone(),
+                    period: BudgetPeriod::Yearly,
+                    amounts: vec![dec!(24000)],
+                    rollover: false,
+                },
+            ],
+        };
+
+        // Yearly amounts are prorated
+        let july = Local.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let line = budget.lines.get(1).unwrap();
+        assert_eq!(
+            line.budgeted(&budget, start, july, start)?.round_dp(2),
+            dec!(11934.43),
+        );
+
+        let report = BudgetReport::new(
+            &repo,
+            &budget,
+            vec![Intv::from_str("2024-01..2024-02")?],
+            start,
+        )?;
+        let mut rows = Vec::new();
+        report.tree.traverse(
+            |node| {
+                if let Key::Account(acc) = &node.data.key {
+                    rows.push((
+                        acc.name(crate::accounts::AccountNameDepth::basename()),
+                        node.data.data.0.clone(),
+                    ));
+                }
+                Ok(())
+            },
+            true,
+        )?;
+        let cell = |budgeted, actual| BudgetCell { budgeted, actual };
+        assert_eq!(
+            rows,
+            vec![
+                (
+                    "Food".to_string(),
+                    vec![
+                        cell(dec!(300), dec!(250)),
+                        cell(dec!(350), dec!(400))
+                    ]
+                ),
+                (
+                    "Groceries".to_string(),
+                    vec![cell(dec!(0), dec!(250)), cell(dec!(0), dec!(400))]
+                ),
+                (
+                    "Salary".to_string(),
+                    vec![
+                        cell(dec!(24000) * dec!(31) / dec!(366), dec!(2000)),
+                        cell(dec!(24000) * dec!(29) / dec!(366), dec!(0)),
+                    ]
+                ),
+            ],
+        );
+        let [_, feb] = rows.first().unwrap().1.as_slice() else {
+            panic!("expected two columns");
+        };
+        assert_eq!(feb.remaining(), dec!(-50));
+        assert_eq!(feb.consumed().map(|p| p.round_dp(1)), Some(dec!(114.3)));
+        Ok(())
+    }
+}
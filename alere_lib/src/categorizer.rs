@@ -0,0 +1,441 @@
+//! Suggest a category for imported transactions, based on the history.
+//!
+//! Importers balance new transactions with an "Imbalance" account, since
+//! they cannot know which expense or income account they apply to.  The
+//! categorizer learns from existing transactions which accounts are used
+//! with each payee, with the words found in the memo and payee name (banks
+//! often give a payee name like "CB CARREFOUR 12/01", which changes for each
+//! transaction), and with which amounts.
+
+use crate::{
+    accounts::{Account, AccountId},
+    multi_values::{MultiValue, Operation},
+    repositories::Repository,
+    transactions::{Transaction, TransactionCollection},
+};
+use chrono::{DateTime, Local};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use std::collections::{BTreeSet, HashMap};
+
+/// How much each criteria contributes to the confidence
+const PAYEE_WEIGHT: f64 = 0.5;
+const WORDS_WEIGHT: f64 = 0.3;
+const AMOUNT_WEIGHT: f64 = 0.2;
+
+/// A possible counter-account for a transaction
+#[derive(Debug)]
+pub struct Suggestion {
+    pub account: Account,
+
+    /// Between 0.0 and 1.0
+    pub confidence: f64,
+}
+
+/// Number of times each account was used with a given payee or word
+type Usage = HashMap<AccountId, u32>;
+
+/// The significant words of a memo or payee name.  Short words and numbers
+/// (dates, card numbers,...) are ignored.
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| {
+            w.chars().count() >= 3 && !w.chars().all(|c| c.is_ascii_digit())
+        })
+        .map(str::to_lowercase)
+}
+
+/// The amount of a split, if it is a simple transfer of money
+fn split_amount(operation: &Operation) -> Option<Decimal> {
+    let Operation::Credit(value) = operation else {
+        return None;
+    };
+    let mut iter = value.iter();
+    match (iter.next(), iter.next()) {
+        (Some(v), None) => Some(v.amount),
+        _ => None,
+    }
+}
+
+/// Fraction of the time each account was used
+fn ratios(usage: &Usage) -> impl Iterator<Item = (AccountId, f64)> + '_ {
+    let total: u32 = usage.values().sum();
+    usage
+        .iter()
+        .map(move |(id, count)| (*id, f64::from(*count) / f64::from(total)))
+}
+
+/// How close two amounts are, between 0.0 and 1.0.  Amounts with different
+/// signs (an expense and an income) are never similar.
+fn amount_similarity(a: Decimal, b: Decimal) -> f64 {
+    let largest = a.abs().max(b.abs());
+    if largest.is_zero() {
+        return 1.0;
+    }
+    let diff = ((a - b).abs() / largest).to_f64().unwrap_or(1.0);
+    (1.0 - diff).max(0.0)
+}
+
+/// The features of a transaction, as seen from one of its accounts
+struct Features {
+    payee: Option<String>,
+    words: BTreeSet<String>,
+    amount: Option<Decimal>,
+}
+
+impl Features {
+    fn new(
+        payee: Option<&str>,
+        memo: Option<&str>,
+        amount: Option<Decimal>,
+    ) -> Self {
+        Features {
+            payee: payee.map(str::to_lowercase),
+            words: payee.into_iter().chain(memo).flat_map(words).collect(),
+            amount,
+        }
+    }
+
+    fn from_transaction(tx: &Transaction, account: &Account) -> Self {
+        let amount = tx
+            .splits()
+            .iter()
+            .filter(|s| s.account == *account)
+            .find_map(|s| split_amount(&s.operation));
+        Features::new(
+            tx.payee().as_ref().map(|p| p.get_name().clone()).as_deref(),
+            tx.memo().as_deref(),
+            amount,
+        )
+    }
+}
+
+/// Learns from existing transactions which accounts are used for which
+/// payees, and suggests categories for new transactions.
+#[derive(Default)]
+pub struct Categorizer {
+    accounts: HashMap<AccountId, Account>,
+    by_payee: HashMap<String, Usage>,
+    by_word: HashMap<String, Usage>,
+    amounts: HashMap<AccountId, Vec<Decimal>>,
+}
+
+impl Categorizer {
+    /// Learn from all transactions of the collection
+    #[must_use]
+    pub fn new(transactions: &TransactionCollection) -> Self {
+        let mut cat = Categorizer::default();
+        for tx in transactions.iter() {
+            cat.learn(tx);
+        }
+        cat
+    }
+
+    /// Learn from a single transaction.  Every split in a networth account
+    /// (a bank account for instance) teaches us about the other accounts
+    /// of the transaction.  Transactions that have not been categorized yet
+    /// are ignored.
+    pub fn learn(&mut self, tx: &Transaction) {
+        let splits = tx.splits();
+        if splits.iter().any(|s| s.account.is_uncategorized()) {
+            return;
+        }
+        for s in splits.iter().filter(|s| s.account.get_kind().is_networth()) {
+            let features = Features::from_transaction(tx, &s.account);
+            for other in splits.iter().filter(|o| o.account != s.account) {
+                self.add(&other.account, &features);
+            }
+        }
+    }
+
+    fn add(&mut self, account: &Account, features: &Features) {
+        let id = account.get_id();
+        self.accounts.entry(id).or_insert_with(|| account.clone());
+        if let Some(payee) = &features.payee {
+            *self
+                .by_payee
+                .entry(payee.clone())
+                .or_default()
+                .entry(id)
+                .or_default() += 1;
+        }
+        for w in &features.words {
+            *self
+                .by_word
+                .entry(w.clone())
+                .or_default()
+                .entry(id)
+                .or_default() += 1;
+        }
+        if let Some(amount) = features.amount {
+            self.amounts.entry(id).or_default().push(amount);
+        }
+    }
+
+    /// Suggest counter-accounts for a transaction with the given payee, memo
+    /// and amount (as seen from the bank account, so negative for expenses).
+    /// The result is sorted by decreasing confidence.  Only accounts that
+    /// were used with the same payee or the same words are suggested.
+    #[must_use]
+    pub fn suggest(
+        &self,
+        payee: Option<&str>,
+        memo: Option<&str>,
+        amount: Option<Decimal>,
+    ) -> Vec<Suggestion> {
+        self.suggest_features(&Features::new(payee, memo, amount))
+    }
+
+    /// Suggest counter-accounts for a transaction, as seen from account
+    #[must_use]
+    pub fn suggest_for(
+        &self,
+        tx: &Transaction,
+        account: &Account,
+    ) -> Vec<Suggestion> {
+        self.suggest_features(&Features::from_transaction(tx, account))
+    }
+
+    fn suggest_features(&self, features: &Features) -> Vec<Suggestion> {
+        let mut scores: HashMap<AccountId, f64> = HashMap::new();
+        if let Some(usage) =
+            features.payee.as_ref().and_then(|p| self.by_payee.get(p))
+        {
+            for (id, ratio) in ratios(usage) {
+                *scores.entry(id).or_default() += PAYEE_WEIGHT * ratio;
+            }
+        }
+
+        #[allow(clippy::cast_precision_loss)]
+        let word_count = features.words.len() as f64;
+        for w in &features.words {
+            if let Some(usage) = self.by_word.get(w) {
+                for (id, ratio) in ratios(usage) {
+                    *scores.entry(id).or_default() +=
+                        WORDS_WEIGHT * ratio / word_count;
+                }
+            }
+        }
+
+        if let Some(amount) = features.amount {
+            for (id, score) in &mut scores {
+                let best = self
+                    .amounts
+                    .get(id)
+                    .into_iter()
+                    .flatten()
+                    .map(|a| amount_similarity(amount, *a))
+                    .fold(0.0, f64::max);
+                *score += AMOUNT_WEIGHT * best;
+            }
+        }
+
+        let mut result: Vec<Suggestion> = scores
+            .into_iter()
+            .filter_map(|(id, confidence)| {
+                self.accounts.get(&id).map(|account| Suggestion {
+                    account: account.clone(),
+                    confidence: confidence.min(1.0),
+                })
+            })
+            .collect();
+        result.sort_by(|a, b| {
+            b.confidence
+                .total_cmp(&a.confidence)
+                .then_with(|| a.account.cmp_name(&b.account))
+        });
+        result
+    }
+}
+
+/// A transaction balanced with an uncategorized account
+pub struct Uncategorized {
+    pub transaction: Transaction,
+
+    /// The account the transaction was imported into
+    pub account: Account,
+
+    /// The uncategorized account
+    pub uncategorized: Account,
+
+    pub timestamp: DateTime<Local>,
+
+    /// The amount, as seen from account
+    pub amount: MultiValue,
+}
+
+/// All transactions that still need to be categorized
+#[must_use]
+pub fn uncategorized(repo: &Repository) -> Vec<Uncategorized> {
+    let mut result = Vec::new();
+    for tx in repo.transactions.iter() {
+        let splits = tx.splits();
+        let Some(unknown) =
+            splits.iter().find(|s| s.account.is_uncategorized())
+        else {
+            continue;
+        };
+        if let Some(s) = splits.iter().find(|s| !s.account.is_uncategorized()) {
+            let mut amount = MultiValue::default();
+            amount.apply(&s.operation);
+            result.push(Uncategorized {
+                transaction: tx.clone(),
+                account: s.account.clone(),
+                uncategorized: unknown.account.clone(),
+                timestamp: s.post_ts,
+                amount,
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::{Account, AccountNameDepth},
+        categorizer::{Categorizer, uncategorized},
+        commodities::{Commodity, test::create_currency},
+        multi_values::{MultiValue, Operation},
+        repositories::Repository,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn add(
+        repo: &mut Repository,
+        from: &Account,
+        to: &Account,
+        eur: &Commodity,
+        payee: &str,
+        memo: Option<&str>,
+        amount: Decimal,
+    ) -> Result<()> {
+        let d = Local.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap();
+        let payee = repo.get_or_add_payee(payee);
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo,
+            check_number: None,
+            payee: Some(payee),
+            entry_date: d,
+        });
+        tx.add_split(
+            from.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(amount, eur)),
+        );
+        tx.add_split(
+            to.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(-amount, eur)),
+        );
+        repo.add_transaction(tx)
+    }
+
+    #[test]
+    fn test_suggest() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let expense_kind =
+            repo.account_kinds.lookup("expense").unwrap().clone();
+        let income_kind = repo.account_kinds.lookup("income").unwrap().clone();
+        let checking = repo.accounts.add_dummy("Checking", checking_kind);
+        let groceries =
+            repo.accounts.add_dummy("Groceries", expense_kind.clone());
+        let restaurant = repo.accounts.add_dummy("Restaurant", expense_kind);
+        let salary = repo.accounts.add_dummy("Salary", income_kind);
+        let imbalance = repo.get_or_add_imbalance_account();
+
+        add(
+            &mut repo,
+            &checking,
+            &groceries,
+            &eur,
+            "Carrefour",
+            None,
+            dec!(-50),
+        )?;
+        add(
+            &mut repo,
+            &checking,
+            &groceries,
+            &eur,
+            "Carrefour",
+            None,
+            dec!(-80),
+        )?;
+        add(
+            &mut repo,
+            &checking,
+            &restaurant,
+            &eur,
+            "Carrefour",
+            Some("cafeteria"),
+            dec!(-12),
+        )?;
+        add(
+            &mut repo,
+            &checking,
+            &salary,
+            &eur,
+            "ACME",
+            None,
+            dec!(2000),
+        )?;
+        add(
+            &mut repo,
+            &checking,
+            &imbalance,
+            &eur,
+            "CB CARREFOUR 12/01",
+            None,
+            dec!(-45),
+        )?;
+
+        let cat = Categorizer::new(&repo.transactions);
+        let names = |suggestions: &[super::Suggestion]| {
+            suggestions
+                .iter()
+                .map(|s| s.account.name(AccountNameDepth::basename()))
+                .collect::<Vec<_>>()
+        };
+
+        // Same payee, amount close to the groceries
+        let s = cat.suggest(Some("Carrefour"), None, Some(dec!(-60)));
+        assert_eq!(names(&s), vec!["Groceries", "Restaurant"]);
+        assert!(s.iter().all(|s| s.confidence > 0.0 && s.confidence <= 1.0));
+
+        // Same payee, but the memo and amount point to the restaurant
+        let s =
+            cat.suggest(Some("Carrefour"), Some("Cafeteria"), Some(dec!(-11)));
+        assert_eq!(names(&s), vec!["Restaurant", "Groceries"]);
+
+        // Unknown payee, but a known word
+        let s = cat.suggest(Some("CB CARREFOUR 15/01"), None, Some(dec!(-45)));
+        assert_eq!(names(&s), vec!["Groceries", "Restaurant"]);
+
+        // Nothing known
+        assert!(
+            cat.suggest(Some("Unknown"), None, Some(dec!(-45)))
+                .is_empty()
+        );
+
+        // Uncategorized transactions
+        let todo = uncategorized(&repo);
+        let [item] = todo.as_slice() else {
+            panic!("Expected one uncategorized transaction");
+        };
+        assert_eq!(item.account, checking);
+        assert_eq!(item.uncategorized, imbalance);
+        assert_eq!(item.amount, MultiValue::new(dec!(-45), &eur));
+        let s = cat.suggest_for(&item.transaction, &item.account);
+        assert_eq!(names(&s), vec!["Groceries", "Restaurant"]);
+        Ok(())
+    }
+}
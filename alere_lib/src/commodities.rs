@@ -1,5 +1,6 @@
 use std::{
     cell::{Ref, RefCell},
+    collections::BTreeMap,
     rc::Rc,
 };
 
@@ -44,12 +45,26 @@ impl Commodity {
 
     #[must_use]
     pub fn get_quote_symbol(&self) -> Option<String> {
-        self.0.borrow()._quote_symbol.clone()
+        self.0.borrow().quote_symbol.clone()
     }
 
     #[must_use]
     pub fn get_quote_source(&self) -> Option<String> {
-        self.0.borrow()._quote_source.clone()
+        self.0.borrow().quote_source.clone()
+    }
+
+    #[must_use]
+    pub fn get_quote_currency(&self) -> Option<Commodity> {
+        self.0.borrow().quote_currency.clone()
+    }
+
+    #[must_use]
+    pub fn get_isin(&self) -> Option<String> {
+        self.0.borrow().isin.clone()
+    }
+
+    pub fn set_quote_currency(&mut self, currency: &Commodity) {
+        self.0.borrow_mut().quote_currency = Some(currency.clone());
     }
 
     pub fn set_isin(&mut self, isin: &str) {
@@ -57,7 +72,26 @@ impl Commodity {
     }
 
     pub fn set_quote_source(&mut self, source: &str) {
-        self.0.borrow_mut()._quote_source = Some(source.to_string());
+        self.0.borrow_mut().quote_source = Some(source.to_string());
+    }
+
+    /// The asset class of the commodity for one dimension of the allocation
+    /// (for instance "class", "region",...)
+    #[must_use]
+    pub fn get_asset_class(&self, dimension: &str) -> Option<String> {
+        self.0.borrow().asset_classes.get(dimension).cloned()
+    }
+
+    #[must_use]
+    pub fn get_asset_classes(&self) -> BTreeMap<String, String> {
+        self.0.borrow().asset_classes.clone()
+    }
+
+    pub fn set_asset_class(&mut self, dimension: &str, class: &str) {
+        self.0
+            .borrow_mut()
+            .asset_classes
+            .insert(dimension.to_string(), class.to_string());
     }
 
     #[must_use]
@@ -65,7 +99,7 @@ impl Commodity {
         let details = self.0.borrow();
         details.name == name
             || details.symbol == name
-            || details._quote_symbol.as_deref() == Some(name)
+            || details.quote_symbol.as_deref() == Some(name)
     }
 }
 
@@ -113,10 +147,11 @@ impl CommodityCollection {
             symbol: symbol.trim().to_string(),
             symbol_after,
             is_currency,
-            _quote_symbol: quote_symbol.map(str::to_string),
-            _quote_source: None,
-            _quote_currency: None,
+            quote_symbol: quote_symbol.map(str::to_string),
+            quote_source: None,
+            quote_currency: None,
             isin: None,
+            asset_classes: BTreeMap::new(),
         })));
 
         if is_currency {
@@ -187,12 +222,16 @@ struct CommodityDetails {
     /// which is cached because fetching that information is slow in Yahoo.
     /// So if we start with the AAPL commodity,  quote_currency might be USD if
     /// the online source gives prices in USD.
-    _quote_symbol: Option<String>,
-    _quote_source: Option<String>,
-    _quote_currency: Option<Commodity>,
+    quote_symbol: Option<String>,
+    quote_source: Option<String>,
+    quote_currency: Option<Commodity>,
 
     /// Number of digits in the fractional part
     display_precision: u8,
+
+    /// User-defined classification, for each dimension of the asset
+    /// allocation (e.g. "class" => "stocks", "region" => "europe").
+    asset_classes: BTreeMap<String, String>,
 }
 
 #[cfg(test)]
@@ -0,0 +1,653 @@
+//! Import transactions from CSV files, as exported by most banks and brokers.
+//!
+//! Since there is no standard for the layout of those files, the import is
+//! driven by a rules file (in JSON), which describes the columns, how dates
+//! and amounts are written, and which category each transaction should be
+//! assigned to, based on its payee or memo.  For instance:
+//!
+//! ```json
+//! {
+//!     "institution": "My Bank",
+//!     "currency": "EUR",
+//!     "delimiter": ";",
+//!     "date_format": "%d/%m/%Y",
+//!     "decimal_separator": ",",
+//!     "columns": {"date": "Date", "amount": "Montant", "payee": "Libellé"},
+//!     "rules": [
+//!         {"payee": "(?i)carrefour", "account": "Expenses:Groceries"},
+//!         {"memo": "SALAIRE", "account": "Salary", "rename_payee": "ACME"}
+//!     ]
+//! }
+//! ```
+//!
+//! The same rules file can be used for all accounts of an institution.
+//! Rows that no rule matches are still imported, but balanced with the
+//! "Imbalance" account and reported to the caller.  Rows that cannot be
+//! parsed are skipped and reported, they do not abort the import.
+
+use crate::{
+    accounts::Account,
+    commodities::Commodity,
+    errors::AlrError,
+    multi_values::{MultiValue, Operation},
+    repositories::Repository,
+    transactions::{ReconcileKind, Transaction, TransactionArgs},
+    utils::read_text_file,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use regex::Regex;
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::{path::Path, str::FromStr};
+
+/// How to find a field in a row: either the name of the column (as found in
+/// the header line), or its index (starting at 0).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+/// The meaning of positive amounts
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignConvention {
+    /// Positive amounts are deposits to the account
+    #[default]
+    Deposit,
+
+    /// Positive amounts are withdrawals, as found in some credit card
+    /// statements.
+    Withdrawal,
+}
+
+/// The columns of the file.  The amount is either given in a single column,
+/// or split into a debit and a credit columns (both with positive values).
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Columns {
+    pub date: Column,
+    pub amount: Option<Column>,
+    pub debit: Option<Column>,
+    pub credit: Option<Column>,
+    pub payee: Option<Column>,
+    pub memo: Option<Column>,
+    pub check_number: Option<Column>,
+}
+
+/// Assign a category to rows whose payee and memo match the regular
+/// expressions.  When both are specified, both must match.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+
+    /// The account (full name or short name) for the other side of the
+    /// transaction.
+    pub account: String,
+
+    /// Use this payee rather than the one from the file
+    pub rename_payee: Option<String>,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+fn default_has_headers() -> bool {
+    true
+}
+fn default_date_format() -> String {
+    "%Y-%m-%d".to_string()
+}
+fn default_decimal_separator() -> char {
+    '.'
+}
+
+/// The description of a CSV layout, as read from a rules file
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CsvRules {
+    /// Only accounts from this institution can be imported with these rules
+    pub institution: Option<String>,
+
+    /// The account to import into, when not specified by the caller
+    pub account: Option<String>,
+
+    pub currency: String,
+
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+
+    /// Number of lines to ignore at the beginning of the file, before the
+    /// header line.
+    #[serde(default)]
+    pub skip_lines: usize,
+
+    #[serde(default = "default_has_headers")]
+    pub has_headers: bool,
+
+    pub columns: Columns,
+
+    /// The format of dates, see `chrono::format::strftime`
+    #[serde(default = "default_date_format")]
+    pub date_format: String,
+
+    /// Any other punctuation in amounts (or spaces, or currency symbols) is
+    /// ignored.
+    #[serde(default = "default_decimal_separator")]
+    pub decimal_separator: char,
+
+    #[serde(default)]
+    pub sign: SignConvention,
+
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+}
+
+impl CsvRules {
+    pub fn from_file(path: &Path) -> Result<Self> {
+        serde_json::from_str(&read_text_file(path)?)
+            .with_context(|| format!("Invalid rules file {}", path.display()))
+    }
+
+    fn parse_amount(&self, text: &str) -> Result<Decimal> {
+        let cleaned: String = text
+            .chars()
+            .filter_map(|c| {
+                if c == self.decimal_separator {
+                    Some('.')
+                } else if c.is_ascii_digit() || c == '-' || c == '+' {
+                    Some(c)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if cleaned.is_empty() {
+            Ok(Decimal::ZERO)
+        } else {
+            Decimal::from_str(&cleaned)
+                .with_context(|| format!("Invalid amount {text:?}"))
+        }
+    }
+
+    fn parse_date(&self, text: &str) -> Result<DateTime<Local>> {
+        NaiveDate::parse_from_str(text.trim(), &self.date_format)
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|d| d.and_local_timezone(Local).earliest())
+            .ok_or_else(|| AlrError::Str(format!("Invalid date {text:?}")))
+            .map_err(Into::into)
+    }
+}
+
+/// A row of the file that was imported but that no rule matched
+#[derive(Debug)]
+pub struct UnmatchedRow {
+    pub line: u64,
+    pub date: DateTime<Local>,
+    pub amount: Decimal,
+    pub payee: Option<String>,
+    pub memo: Option<String>,
+}
+
+/// The result of importing a CSV file
+#[derive(Debug)]
+pub struct CsvImport {
+    pub account: Account,
+    pub commodity: Commodity,
+    pub added: usize,
+
+    /// Rows imported into the "Imbalance" account
+    pub unmatched: Vec<UnmatchedRow>,
+
+    /// Rows that could not be imported, with the reason
+    pub errors: Vec<(u64, String)>,
+}
+
+/// A rule, once its regular expressions and account have been resolved
+struct CompiledRule<'a> {
+    payee: Option<Regex>,
+    memo: Option<Regex>,
+    account: Account,
+    rule: &'a Rule,
+}
+
+impl CompiledRule<'_> {
+    fn matches(&self, payee: Option<&str>, memo: Option<&str>) -> bool {
+        let check = |re: &Option<Regex>, value: Option<&str>| match re {
+            None => true,
+            Some(re) => value.is_some_and(|v| re.is_match(v)),
+        };
+        (self.payee.is_some() || self.memo.is_some())
+            && check(&self.payee, payee)
+            && check(&self.memo, memo)
+    }
+}
+
+/// Column indexes, once the header line has been read
+struct ColumnIndexes {
+    date: usize,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    payee: Option<usize>,
+    memo: Option<usize>,
+    check_number: Option<usize>,
+}
+
+impl ColumnIndexes {
+    fn new(
+        columns: &Columns,
+        headers: Option<&csv::StringRecord>,
+    ) -> Result<Self> {
+        let index = |col: &Column| -> Result<usize> {
+            match col {
+                Column::Index(idx) => Ok(*idx),
+                Column::Name(name) => Ok(headers
+                    .and_then(|h| h.iter().position(|c| c.trim() == name))
+                    .ok_or_else(|| {
+                        AlrError::Str(format!("No column named {name:?}"))
+                    })?),
+            }
+        };
+        let optional =
+            |col: &Option<Column>| col.as_ref().map(index).transpose();
+        let result = ColumnIndexes {
+            date: index(&columns.date)?,
+            amount: optional(&columns.amount)?,
+            debit: optional(&columns.debit)?,
+            credit: optional(&columns.credit)?,
+            payee: optional(&columns.payee)?,
+            memo: optional(&columns.memo)?,
+            check_number: optional(&columns.check_number)?,
+        };
+        if result.amount.is_none()
+            && result.debit.is_none()
+            && result.credit.is_none()
+        {
+            Err(AlrError::Str(
+                "Rules must specify the amount, or debit and credit columns"
+                    .into(),
+            ))?;
+        }
+        Ok(result)
+    }
+}
+
+/// Find the account to import into
+fn find_account(
+    repo: &Repository,
+    rules: &CsvRules,
+    account: Option<&str>,
+) -> Result<Account> {
+    let in_institution = |a: &Account| match &rules.institution {
+        None => true,
+        Some(inst) => a
+            .get_institution()
+            .is_some_and(|i| i.get_name().eq_ignore_ascii_case(inst)),
+    };
+    let acc = match account.or(rules.account.as_deref()) {
+        Some(name) => repo.accounts.find(name).ok_or_else(|| {
+            AlrError::Str(format!("Unknown account {name:?}"))
+        })?,
+        None => {
+            let candidates: Vec<Account> = repo
+                .accounts
+                .iter()
+                .filter(|a| {
+                    rules.institution.is_some()
+                        && in_institution(a)
+                        && a.get_kind().is_networth()
+                        && !a.is_closed()
+                })
+                .collect();
+            match candidates.as_slice() {
+                [a] => a.clone(),
+                _ => Err(AlrError::Str(
+                    "Cannot guess which account to import into".into(),
+                ))?,
+            }
+        }
+    };
+    if !in_institution(&acc) {
+        Err(AlrError::Str(format!(
+            "Account {} does not belong to {}",
+            acc.name(crate::accounts::AccountNameDepth::unlimited()),
+            rules.institution.as_deref().unwrap_or_default(),
+        )))?;
+    }
+    Ok(acc)
+}
+
+/// Import the CSV data into the repository.
+/// This only fails when the rules themselves are invalid (unknown account or
+/// column, invalid regular expression,...)
+pub fn import(
+    repo: &mut Repository,
+    rules: &CsvRules,
+    account: Option<&str>,
+    data: &str,
+) -> Result<CsvImport> {
+    let account = find_account(repo, rules, account)?;
+    let commodity =
+        repo.commodities.find(&rules.currency).ok_or_else(|| {
+            AlrError::Str(format!("Unknown currency {}", rules.currency))
+        })?;
+    let compiled = rules
+        .rules
+        .iter()
+        .map(|rule| {
+            let regex = |re: &Option<String>| {
+                re.as_deref().map(Regex::new).transpose().with_context(|| {
+                    format!("Invalid regular expression in rule {rule:?}")
+                })
+            };
+            Ok(CompiledRule {
+                payee: regex(&rule.payee)?,
+                memo: regex(&rule.memo)?,
+                account: repo.accounts.find(&rule.account).ok_or_else(
+                    || {
+                        AlrError::Str(format!(
+                            "Unknown account {:?}",
+                            rule.account
+                        ))
+                    },
+                )?,
+                rule,
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // The skipped lines are not necessarily valid CSV, so they are removed
+    // before parsing.  Line numbers still refer to the whole file.
+    let mut lines = data.split_inclusive('\n');
+    let skipped = lines.by_ref().take(rules.skip_lines).count() as u64;
+    let body = lines.collect::<String>();
+
+    let mut delimiter = [0; 4];
+    let delimiter = rules.delimiter.encode_utf8(&mut delimiter).as_bytes();
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(*delimiter.first().unwrap_or(&b','))
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(body.as_bytes());
+    let mut records = reader.records();
+    let headers = if rules.has_headers {
+        records.next().transpose()?
+    } else {
+        None
+    };
+    let columns = ColumnIndexes::new(&rules.columns, headers.as_ref())?;
+
+    let mut result = CsvImport {
+        account: account.clone(),
+        commodity: commodity.clone(),
+        added: 0,
+        unmatched: Vec::new(),
+        errors: Vec::new(),
+    };
+
+    for record in records {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                let line = e.position().map_or(0, |p| p.line() + skipped);
+                result.errors.push((line, e.to_string()));
+                continue;
+            }
+        };
+        let line = record.position().map_or(0, |p| p.line() + skipped);
+        let field = |idx: Option<usize>| {
+            idx.and_then(|i| record.get(i))
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+        };
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+
+        let parsed = (|| -> Result<(DateTime<Local>, Decimal)> {
+            let date = rules
+                .parse_date(field(Some(columns.date)).unwrap_or_default())?;
+            let amount = match columns.amount {
+                Some(idx) => {
+                    let a = rules
+                        .parse_amount(field(Some(idx)).unwrap_or_default())?;
+                    match rules.sign {
+                        SignConvention::Deposit => a,
+                        SignConvention::Withdrawal => -a,
+                    }
+                }
+                None => {
+                    rules
+                        .parse_amount(
+                            field(columns.credit).unwrap_or_default(),
+                        )?
+                        .abs()
+                        - rules
+                            .parse_amount(
+                                field(columns.debit).unwrap_or_default(),
+                            )?
+                            .abs()
+                }
+            };
+            Ok((date, amount))
+        })();
+        let (date, amount) = match parsed {
+            Ok(p) => p,
+            Err(e) => {
+                result.errors.push((line, e.to_string()));
+                continue;
+            }
+        };
+
+        let payee = field(columns.payee);
+        let memo = field(columns.memo);
+        let rule = compiled.iter().find(|r| r.matches(payee, memo));
+        let category = match rule {
+            Some(r) => r.account.clone(),
+            None => {
+                result.unmatched.push(UnmatchedRow {
+                    line,
+                    date,
+                    amount,
+                    payee: payee.map(str::to_string),
+                    memo: memo.map(str::to_string),
+                });
+                repo.get_or_add_imbalance_account()
+            }
+        };
+        let payee = rule
+            .and_then(|r| r.rule.rename_payee.as_deref())
+            .or(payee)
+            .map(|p| repo.get_or_add_payee(p));
+
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo,
+            check_number: field(columns.check_number),
+            payee,
+            entry_date: Local::now(),
+        });
+        tx.add_split(
+            account.clone(),
+            ReconcileKind::Cleared,
+            date,
+            Operation::Credit(MultiValue::new(amount, &commodity)),
+        );
+        tx.add_split(
+            category,
+            ReconcileKind::New,
+            date,
+            Operation::Credit(MultiValue::new(-amount, &commodity)),
+        );
+        repo.add_transaction(tx)?;
+        result.added += 1;
+    }
+
+    Ok(result)
+}
+
+/// Import a CSV file into the repository, see [`import`]
+pub fn import_file(
+    repo: &mut Repository,
+    rules: &CsvRules,
+    account: Option<&str>,
+    path: &Path,
+) -> Result<CsvImport> {
+    import(repo, rules, account, &read_text_file(path)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::AccountNameDepth,
+        commodities::test::create_currency,
+        csv_import::{CsvRules, import},
+        formatters::Formatter,
+        multi_values::MultiValue,
+        repositories::Repository,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    const RULES: &str = r#"{
+        "institution": "My Bank",
+        "currency": "EUR",
+        "delimiter": ";",
+        "skip_lines": 1,
+        "date_format": "%d/%m/%Y",
+        "decimal_separator": ",",
+        "columns": {
+            "date": "Date",
+            "debit": "Débit",
+            "credit": "Crédit",
+            "payee": "Libellé",
+            "memo": 4
+        },
+        "rules": [
+            {"payee": "(?i)carrefour", "account": "Groceries"},
+            {"memo": "^SALAIRE", "account": "Income:Salary",
+             "rename_payee": "ACME"}
+        ]
+    }"#;
+
+    const DATA: &str = "\"Compte courant n° 1234
+Date;Libellé;Débit;Crédit;Note
+12/01/2024;CB CARREFOUR PARIS;42,50;;
+15/01/2024;VIR ACME;;1 500,00;SALAIRE JANVIER
+16/01/2024;Unknown shop;10,00;;
+32/01/2024;Invalid date;1,00;;
+17/01/2024;Invalid amount;1,0,0;;
+";
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let bank = repo
+            .institutions
+            .add("My Bank", None, None, None, None, None);
+        let other =
+            repo.institutions.add("Other", None, None, None, None, None);
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let expense_kind =
+            repo.account_kinds.lookup("expense").unwrap().clone();
+        let income_kind = repo.account_kinds.lookup("income").unwrap().clone();
+        let checking = repo.accounts.add(
+            "Checking",
+            checking_kind.clone(),
+            None,
+            Some(bank),
+            None,
+            None,
+            None,
+            false,
+            None,
+        );
+        repo.accounts.add(
+            "Elsewhere",
+            checking_kind,
+            None,
+            Some(other),
+            None,
+            None,
+            None,
+            false,
+            None,
+        );
+        repo.accounts.add_dummy("Groceries", expense_kind);
+        let mut salary = repo.accounts.add_dummy("Salary", income_kind.clone());
+        salary.set_parent(repo.accounts.add_dummy("Income", income_kind));
+
+        let rules: CsvRules = serde_json::from_str(RULES)?;
+        let result = import(&mut repo, &rules, None, DATA)?;
+        assert_eq!(result.account, checking);
+        assert_eq!(result.commodity, eur);
+        assert_eq!(result.added, 3);
+        assert_eq!(
+            result
+                .unmatched
+                .iter()
+                .map(|r| (r.line, r.amount, r.payee.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![(5, dec!(-10), Some("Unknown shop"))],
+        );
+        assert_eq!(
+            result
+                .errors
+                .iter()
+                .map(|(line, _)| *line)
+                .collect::<Vec<_>>(),
+            vec![6, 7],
+        );
+
+        let format = Formatter::default();
+        let mut txs: Vec<String> = repo
+            .transactions
+            .iter()
+            .map(|tx| {
+                let splits = tx
+                    .splits()
+                    .iter()
+                    .map(|s| {
+                        let mut value = MultiValue::default();
+                        value.apply(&s.operation);
+                        format!(
+                            "{} {}",
+                            s.account.name(AccountNameDepth::unlimited()),
+                            value.display(&format),
+                        )
+                    })
+                    .collect::<Vec<_>>();
+                format!(
+                    "{} {:?} {:?} {}",
+                    tx.timestamp().date_naive(),
+                    tx.payee().map(|p| p.get_name().clone()),
+                    tx.memo(),
+                    splits.join(", "),
+                )
+            })
+            .collect();
+        txs.sort();
+        assert_eq!(
+            txs,
+            vec![
+                "2024-01-12 Some(\"CB CARREFOUR PARIS\") None \
+                 Checking -42.50 EUR, Groceries 42.50 EUR",
+                "2024-01-15 Some(\"ACME\") Some(\"SALAIRE JANVIER\") \
+                 Checking 1,500.00 EUR, Income:Salary -1,500.00 EUR",
+                "2024-01-16 Some(\"Unknown shop\") None \
+                 Checking -10.00 EUR, Imbalance 10.00 EUR",
+            ],
+        );
+
+        // Importing into an account from another institution fails
+        assert!(import(&mut repo, &rules, Some("Elsewhere"), DATA).is_err());
+        Ok(())
+    }
+}
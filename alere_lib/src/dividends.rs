@@ -0,0 +1,458 @@
+//! Dividends received for each security, and interests received on each
+//! account.
+//!
+//! The split on the stock account is only marked as a dividend, the amounts
+//! are found in the other splits of the same transaction: the income account
+//! gives the gross amount, and the tax accounts the withholding taxes.
+//! Interests are the transactions involving an income account whose kind is
+//! flagged as interest.  They are associated with the account (of the
+//! networth) that received them.
+
+use crate::{
+    accounts::Account,
+    commodities::Commodity,
+    lots::{Lots, Settings as LotSettings},
+    market_prices::MarketPrices,
+    multi_values::{MultiValue, Operation},
+    repositories::Repository,
+    transactions::Transaction,
+};
+use chrono::{DateTime, Datelike, Local, Months};
+use rust_decimal::Decimal;
+use std::collections::BTreeMap;
+
+/// The amounts received for one or more dividends
+#[derive(Clone, Debug, Default)]
+pub struct DividendAmounts {
+    pub gross: MultiValue,
+    pub withholding: MultiValue,
+}
+
+impl DividendAmounts {
+    #[must_use]
+    pub fn net(&self) -> MultiValue {
+        &self.gross - &self.withholding
+    }
+
+    fn add(&mut self, other: &DividendAmounts) {
+        self.gross += &other.gross;
+        self.withholding += &other.withholding;
+    }
+}
+
+/// The dividends for one security, or the interests for one account
+pub struct SecurityDividends {
+    pub account: Account,
+
+    // Whether these are the interests received on the account
+    pub interest: bool,
+
+    pub per_year: BTreeMap<i32, DividendAmounts>,
+
+    // Gross dividends over the last twelve months
+    pub trailing: MultiValue,
+
+    // Cost of the shares currently held, and their market value.  For
+    // interests, the cost is unknown and the value is the current balance.
+    pub cost: MultiValue,
+    pub market_value: MultiValue,
+}
+
+impl SecurityDividends {
+    /// Trailing dividends compared to what was paid for the shares
+    #[must_use]
+    pub fn yield_on_cost(&self) -> Option<Decimal> {
+        &self.trailing / &self.cost
+    }
+
+    /// Trailing dividends compared to the current value of the shares
+    #[must_use]
+    pub fn current_yield(&self) -> Option<Decimal> {
+        &self.trailing / &self.market_value
+    }
+}
+
+pub struct Settings {
+    pub commodity: Option<Commodity>,
+}
+
+pub struct DividendReport {
+    pub securities: Vec<SecurityDividends>,
+}
+
+impl DividendReport {
+    /// The amounts for a single dividend transaction, converted at the date
+    /// of the transaction.
+    fn amounts(
+        tx: &Transaction,
+        account: &Account,
+        prices: &mut MarketPrices,
+    ) -> DividendAmounts {
+        let mut result = DividendAmounts::default();
+        for s in tx.splits().iter().filter(|s| s.account != *account) {
+            let value = match &s.operation {
+                Operation::Credit(v) => {
+                    prices.convert_multi_value(v, &s.post_ts)
+                }
+                Operation::BuyAmount { qty, .. } => {
+                    prices.convert_value(qty, &s.post_ts)
+                }
+                Operation::BuyPrice { .. }
+                | Operation::AddShares { .. }
+                | Operation::Reinvest { .. }
+                | Operation::Dividend
+                | Operation::Split { .. } => continue,
+            };
+            let kind = s.account.get_kind();
+            if kind.is_income_tax() || kind.is_misc_tax() {
+                result.withholding += value;
+            } else if kind.is_income() {
+                result.gross -= value;
+            }
+        }
+        result
+    }
+
+    /// The amounts per year for the transactions of the account that are
+    /// selected by `date` (which returns the date of the income), and the
+    /// gross amount in the trailing period.
+    fn per_year(
+        account: &Account,
+        prices: &mut MarketPrices,
+        trailing: (DateTime<Local>, DateTime<Local>),
+        date: impl Fn(&Transaction) -> Option<DateTime<Local>>,
+    ) -> (BTreeMap<i32, DividendAmounts>, MultiValue) {
+        let mut per_year: BTreeMap<i32, DividendAmounts> = BTreeMap::new();
+        let mut trailing_gross = MultiValue::zero();
+        for tx in account.iter_transactions() {
+            let Some(ts) = date(&tx) else {
+                continue;
+            };
+            let amounts = DividendReport::amounts(&tx, account, prices);
+            if ts > trailing.0 && ts <= trailing.1 {
+                trailing_gross += &amounts.gross;
+            }
+            per_year.entry(ts.year()).or_default().add(&amounts);
+        }
+        (per_year, trailing_gross)
+    }
+
+    #[must_use]
+    pub fn new(
+        repo: &Repository,
+        settings: &Settings,
+        now: DateTime<Local>,
+    ) -> Self {
+        let mut prices = repo.market_prices(settings.commodity.clone());
+        let trailing_start =
+            now.checked_sub_months(Months::new(12)).unwrap_or(now);
+        let lots = Lots::load(
+            repo,
+            &LotSettings {
+                commodity: settings.commodity.clone(),
+                method: None,
+            },
+        );
+
+        let mut securities = Vec::new();
+        for acc in repo.accounts.iter() {
+            if !acc.get_kind().is_trading() {
+                continue;
+            }
+            let (per_year, trailing) = DividendReport::per_year(
+                &acc,
+                &mut prices,
+                (trailing_start, now),
+                |tx| {
+                    tx.splits()
+                        .iter()
+                        .find(|s| {
+                            s.account == acc
+                                && matches!(s.operation, Operation::Dividend)
+                        })
+                        .map(|s| s.post_ts)
+                },
+            );
+            if per_year.is_empty() {
+                continue;
+            }
+
+            let mut cost = MultiValue::zero();
+            let mut market_value = MultiValue::zero();
+            for lot in lots
+                .iter()
+                .filter(|l| l.account == acc)
+                .flat_map(|l| l.open.iter())
+            {
+                cost += &lot.cost;
+                market_value += prices.convert_value(&lot.shares, &now);
+            }
+
+            securities.push(SecurityDividends {
+                account: acc.clone(),
+                interest: false,
+                per_year,
+                trailing,
+                cost,
+                market_value,
+            });
+        }
+
+        for acc in repo.accounts.iter() {
+            if !acc.get_kind().is_networth() {
+                continue;
+            }
+
+            // A transfer between two accounts of the networth would list
+            // the interests twice: only the first account gets them.
+            let (per_year, trailing) = DividendReport::per_year(
+                &acc,
+                &mut prices,
+                (trailing_start, now),
+                |tx| {
+                    let splits = tx.splits();
+                    if !splits
+                        .iter()
+                        .any(|s| s.account.get_kind().is_interest())
+                    {
+                        return None;
+                    }
+                    splits
+                        .iter()
+                        .find(|s| s.account.get_kind().is_networth())
+                        .filter(|s| s.account == acc)
+                        .map(|s| s.post_ts)
+                },
+            );
+            if per_year.is_empty() {
+                continue;
+            }
+
+            let mut balance = MultiValue::zero();
+            acc.for_each_split(|s| {
+                if s.post_ts <= now {
+                    balance.apply(&s.operation);
+                }
+            });
+            securities.push(SecurityDividends {
+                account: acc.clone(),
+                interest: true,
+                per_year,
+                trailing,
+                cost: MultiValue::zero(),
+                market_value: prices.convert_multi_value(&balance, &now),
+            });
+        }
+        DividendReport { securities }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::AccountNameDepth,
+        commodities::test::create_currency,
+        dividends::{DividendReport, Settings},
+        multi_values::{MultiValue, Operation, Value},
+        price_sources::PriceSourceFrom,
+        prices::Price,
+        repositories::Repository,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_dividends() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let acme = create_currency(&mut repo.commodities, "ACME", 2, false);
+        let kind =
+            |name: &str| repo.account_kinds.lookup(name).unwrap().clone();
+        let stock_kind = kind("stock");
+        let checking_kind = kind("checking");
+        let income_kind = kind("passive income");
+        let tax_kind = kind("income tax");
+        let stock = repo.accounts.add_dummy("ACME", stock_kind);
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+        let income = repo.accounts.add_dummy("Dividends", income_kind);
+        let tax = repo.accounts.add_dummy("Withholding", tax_kind);
+
+        let d = Local.with_ymd_and_hms(2023, 1, 1, 0, 0, 0).unwrap();
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            entry_date: d,
+            ..TransactionArgs::default()
+        });
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::BuyAmount {
+                qty: Value {
+                    amount: dec!(10),
+                    commodity: acme.clone(),
+                },
+                amount: Value {
+                    amount: dec!(1000),
+                    commodity: eur.clone(),
+                },
+            },
+        );
+        tx.add_split(
+            cash.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(-1000), &eur)),
+        );
+        repo.add_transaction(tx)?;
+
+        for (y, m, gross) in [
+            (2023, 6, dec!(30)),
+            (2024, 1, dec!(40)),
+            (2024, 6, dec!(50)),
+        ] {
+            let d = Local.with_ymd_and_hms(y, m, 15, 0, 0, 0).unwrap();
+            let mut tx = Transaction::new_with_details(TransactionArgs {
+                entry_date: d,
+                ..TransactionArgs::default()
+            });
+            tx.add_split(
+                stock.clone(),
+                ReconcileKind::New,
+                d,
+                Operation::Dividend,
+            );
+            tx.add_split(
+                income.clone(),
+                ReconcileKind::New,
+                d,
+                Operation::Credit(MultiValue::new(-gross, &eur)),
+            );
+            tx.add_split(
+                tax.clone(),
+                ReconcileKind::New,
+                d,
+                Operation::Credit(MultiValue::new(gross * dec!(0.3), &eur)),
+            );
+            tx.add_split(
+                cash.clone(),
+                ReconcileKind::New,
+                d,
+                Operation::Credit(MultiValue::new(gross * dec!(0.7), &eur)),
+            );
+            repo.add_transaction(tx)?;
+        }
+
+        let now = Local.with_ymd_and_hms(2024, 9, 1, 0, 0, 0).unwrap();
+        repo.add_price(
+            &acme,
+            &eur,
+            Price::new(now, dec!(200), PriceSourceFrom::Transaction),
+        );
+
+        let report = DividendReport::new(
+            &repo,
+            &Settings {
+                commodity: Some(eur.clone()),
+            },
+            now,
+        );
+        let sec = report.securities.first().unwrap();
+        let years = sec
+            .per_year
+            .iter()
+            .map(|(y, a)| {
+                format!(
+                    "{} {:?} {:?} {:?}",
+                    y,
+                    a.gross.iter().map(|v| v.amount).sum::<Decimal>(),
+                    a.withholding.iter().map(|v| v.amount).sum::<Decimal>(),
+                    a.net().iter().map(|v| v.amount).sum::<Decimal>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(years, vec!["2023 30 9.0 21.0", "2024 90 27.0 63.0"]);
+        assert_eq!(sec.trailing, MultiValue::new(dec!(90), &eur));
+        assert_eq!(sec.yield_on_cost(), Some(dec!(0.09)));
+        assert_eq!(sec.current_yield(), Some(dec!(0.045)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_interests() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let kind =
+            |name: &str| repo.account_kinds.lookup(name).unwrap().clone();
+        let savings_kind = kind("savings");
+        let checking_kind = kind("checking");
+        let interest_kind = kind("interest income");
+        let tax_kind = kind("income tax");
+        let savings = repo.accounts.add_dummy("Savings", savings_kind);
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+        let interest = repo.accounts.add_dummy("Interests", interest_kind);
+        let tax = repo.accounts.add_dummy("Tax", tax_kind);
+
+        // A deposit, then interests paid with and without withholding taxes
+        for (y, splits) in [
+            (2023, vec![(&cash, dec!(-1000)), (&savings, dec!(1000))]),
+            (2023, vec![(&cash, dec!(30)), (&interest, dec!(-30))]),
+            (
+                2024,
+                vec![
+                    (&savings, dec!(35)),
+                    (&interest, dec!(-50)),
+                    (&tax, dec!(15)),
+                ],
+            ),
+        ] {
+            let d = Local.with_ymd_and_hms(y, 2, 1, 0, 0, 0).unwrap();
+            let mut tx = Transaction::new_with_details(TransactionArgs {
+                entry_date: d,
+                ..TransactionArgs::default()
+            });
+            for (account, amount) in splits {
+                tx.add_split(
+                    account.clone(),
+                    ReconcileKind::New,
+                    d,
+                    Operation::Credit(MultiValue::new(amount, &eur)),
+                );
+            }
+            repo.add_transaction(tx)?;
+        }
+
+        let now = Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let report = DividendReport::new(
+            &repo,
+            &Settings {
+                commodity: Some(eur.clone()),
+            },
+            now,
+        );
+        let accounts = report
+            .securities
+            .iter()
+            .map(|s| {
+                format!(
+                    "{} {} {:?}",
+                    s.account.name(AccountNameDepth::basename()),
+                    s.interest,
+                    s.per_year.keys().collect::<Vec<_>>(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(accounts, vec!["Savings true [2024]", "Cash true [2023]"]);
+
+        let sec = report.securities.first().unwrap();
+        let amounts = sec.per_year.get(&2024).unwrap();
+        assert_eq!(amounts.gross, MultiValue::new(dec!(50), &eur));
+        assert_eq!(amounts.withholding, MultiValue::new(dec!(15), &eur));
+        assert_eq!(sec.trailing, MultiValue::new(dec!(50), &eur));
+        assert_eq!(sec.market_value, MultiValue::new(dec!(1035), &eur));
+        assert_eq!(sec.yield_on_cost(), None);
+        Ok(())
+    }
+}
@@ -0,0 +1,388 @@
+//! Currency exposure of the networth.
+//!
+//! Cash is exposed to its own currency, while securities are exposed to the
+//! currency they are quoted in (see `MarketPrices::quote_currency`).  Over a
+//! period, the profit in the reporting currency is split between the local
+//! performance (the change in value in the exposure currency, converted at
+//! the end of the period) and the impact of exchange rates.  Deposits,
+//! withdrawals, income and purchases are flows, not performance.
+
+use crate::{
+    commodities::Commodity,
+    market_prices::MarketPrices,
+    multi_values::{MultiValue, Operation, Value},
+    repositories::Repository,
+    times::{Intv, TimeInterval},
+};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+pub struct Settings {
+    // The reporting currency
+    pub commodity: Option<Commodity>,
+
+    // Each column of the report
+    pub intervals: Vec<Intv>,
+}
+
+/// The exposure to one currency over one interval
+#[derive(Clone, Default)]
+pub struct ExposurePeriod {
+    // Holdings at the start and end of the interval, and the net flows in
+    // between, in the exposure currency.
+    pub start: MultiValue,
+    pub end: MultiValue,
+    pub flows: MultiValue,
+
+    // Same, in the reporting currency, using the exchange rate at the time.
+    pub start_value: MultiValue,
+    pub end_value: MultiValue,
+    pub flows_value: MultiValue,
+
+    // Share of the total networth at the end of the interval
+    pub weight: Option<Decimal>,
+
+    // Profit in the reporting currency, and how much of it comes from the
+    // performance in the exposure currency or from exchange rates.
+    pub pnl: MultiValue,
+    pub local_pnl: MultiValue,
+    pub fx_pnl: MultiValue,
+}
+
+pub struct CurrencyExposure {
+    pub currency: Commodity,
+    pub periods: Vec<ExposurePeriod>,
+}
+
+pub struct Exposure {
+    pub intervals: Vec<TimeInterval>,
+    pub currencies: Vec<CurrencyExposure>,
+
+    // Total networth at the end of each interval
+    pub total: Vec<MultiValue>,
+}
+
+/// Where a value contributes to an interval
+enum Part {
+    Start,
+    End,
+    Flow,
+}
+
+impl Exposure {
+    #[allow(clippy::mutable_key_type)]
+    pub fn new(
+        repo: &Repository,
+        settings: &Settings,
+        now: DateTime<Local>,
+    ) -> Result<Self> {
+        let intervals = settings
+            .intervals
+            .iter()
+            .map(|intv| intv.to_ranges(now))
+            .flatten_ok()
+            .collect::<Result<Vec<TimeInterval>>>()?;
+
+        // All changes to the holdings, in chronological order.  Stock splits
+        // change the number of shares, but are not flows.
+        let mut events: Vec<(DateTime<Local>, MultiValue, bool)> = Vec::new();
+        for acc in repo.accounts.iter().filter(|a| a.get_kind().is_networth()) {
+            let mut holdings = MultiValue::zero();
+            acc.for_each_split(|s| {
+                let before = holdings.clone();
+                holdings.apply(&s.operation);
+                events.push((
+                    s.post_ts,
+                    &holdings - &before,
+                    !matches!(s.operation, Operation::Split { .. }),
+                ));
+            });
+        }
+        events.sort_by_key(|(ts, _, _)| *ts);
+        let holdings_at = |ts: DateTime<Local>| {
+            events
+                .iter()
+                .take_while(|(t, _, _)| *t < ts)
+                .fold(MultiValue::zero(), |h, (_, delta, _)| h + delta)
+        };
+
+        let mut market = repo.market_prices(settings.commodity.clone());
+        let mut local: HashMap<Commodity, MarketPrices> = HashMap::new();
+        let mut per_currency: HashMap<Commodity, Vec<ExposurePeriod>> =
+            HashMap::new();
+
+        for (idx, intv) in intervals.iter().enumerate() {
+            let from = intv.intv.lower().copied();
+            let to = intv.intv.upper().map_or(now, |up| (*up).min(now));
+
+            let mut parts: Vec<(DateTime<Local>, MultiValue, Part)> =
+                Vec::new();
+            if let Some(f) = from {
+                parts.push((f, holdings_at(f), Part::Start));
+            }
+            parts.push((to, holdings_at(to), Part::End));
+            parts.extend(
+                events
+                    .iter()
+                    .filter(|(ts, _, is_flow)| {
+                        *is_flow && from.is_none_or(|f| *ts >= f) && *ts < to
+                    })
+                    .map(|(ts, delta, _)| (*ts, delta.clone(), Part::Flow)),
+            );
+
+            for (ts, value, part) in &parts {
+                for v in value.iter() {
+                    let (currency, amount) = Exposure::localize(
+                        repo, &market, &mut local, &v, ts, &to,
+                    );
+                    let converted = market.convert_multi_value(&amount, ts);
+                    let period = per_currency
+                        .entry(currency)
+                        .or_insert_with(|| {
+                            vec![ExposurePeriod::default(); intervals.len()]
+                        })
+                        .get_mut(idx);
+                    let Some(period) = period else { continue };
+                    match part {
+                        Part::Start => {
+                            period.start += &amount;
+                            period.start_value += &converted;
+                        }
+                        Part::End => {
+                            period.end += &amount;
+                            period.end_value += &converted;
+                        }
+                        Part::Flow => {
+                            period.flows += &amount;
+                            period.flows_value += &converted;
+                        }
+                    }
+                }
+            }
+
+            for period in
+                per_currency.values_mut().filter_map(|p| p.get_mut(idx))
+            {
+                period.pnl = &(&period.end_value - &period.start_value)
+                    - &period.flows_value;
+                period.local_pnl = market.convert_multi_value(
+                    &(&(&period.end - &period.start) - &period.flows),
+                    &to,
+                );
+                period.fx_pnl = &period.pnl - &period.local_pnl;
+            }
+        }
+
+        let total = (0..intervals.len())
+            .map(|idx| {
+                per_currency
+                    .values()
+                    .filter_map(|p| p.get(idx))
+                    .fold(MultiValue::zero(), |t, p| t + &p.end_value)
+            })
+            .collect::<Vec<_>>();
+        let mut currencies = per_currency
+            .into_iter()
+            .map(|(currency, mut periods)| {
+                for (p, t) in periods.iter_mut().zip(&total) {
+                    p.weight = &p.end_value / t;
+                }
+                CurrencyExposure { currency, periods }
+            })
+            .collect::<Vec<_>>();
+        currencies.retain(|c| {
+            c.periods.iter().any(|p| {
+                !p.start.is_zero() || !p.end.is_zero() || !p.flows.is_zero()
+            })
+        });
+        currencies.sort_by(|a, b| {
+            a.currency.get_symbol().cmp(&b.currency.get_symbol())
+        });
+
+        Ok(Exposure {
+            intervals,
+            currencies,
+            total,
+        })
+    }
+
+    /// The currency a value is exposed to, and the value in that currency.
+    /// Commodities for which we have no price are their own exposure.
+    #[allow(clippy::mutable_key_type)]
+    fn localize<'a>(
+        repo: &'a Repository,
+        market: &MarketPrices,
+        local: &mut HashMap<Commodity, MarketPrices<'a>>,
+        v: &Value,
+        ts: &DateTime<Local>,
+        classify_at: &DateTime<Local>,
+    ) -> (Commodity, MultiValue) {
+        let own =
+            || (v.commodity.clone(), MultiValue::new(v.amount, &v.commodity));
+        let Some(currency) = market.quote_currency(&v.commodity, classify_at)
+        else {
+            return own();
+        };
+        if currency == v.commodity {
+            return own();
+        }
+        let price = local
+            .entry(currency.clone())
+            .or_insert_with(|| repo.market_prices(Some(currency.clone())))
+            .get_price(&v.commodity, ts);
+        match price {
+            None => own(),
+            Some(p) => {
+                let amount = MultiValue::new(p * v.amount, &currency);
+                (currency, amount)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        commodities::test::{create_currency, create_security},
+        exposure::{Exposure, Settings},
+        multi_values::{MultiValue, Operation, Value},
+        price_sources::PriceSourceFrom,
+        prices::Price,
+        repositories::Repository,
+        times::Intv,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_exposure() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let usd = create_currency(&mut repo.commodities, "USD", 2, false);
+        let acme = create_security(&mut repo.commodities, "ACME");
+        let kind =
+            |name: &str| repo.account_kinds.lookup(name).unwrap().clone();
+        let stock_kind = kind("stock");
+        let checking_kind = kind("checking");
+        let income_kind = kind("income");
+        let stock = repo.accounts.add_dummy("ACME", stock_kind);
+        let cash_usd = repo.accounts.add_dummy("USD", checking_kind.clone());
+        let cash_eur = repo.accounts.add_dummy("EUR", checking_kind);
+        let income = repo.accounts.add_dummy("Salary", income_kind);
+
+        let d1 = Local.with_ymd_and_hms(2023, 12, 1, 0, 0, 0).unwrap();
+        let d2 = Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let d3 = Local.with_ymd_and_hms(2024, 12, 1, 0, 0, 0).unwrap();
+        let now = Local.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            entry_date: d1,
+            ..TransactionArgs::default()
+        });
+        tx.add_split(
+            cash_usd.clone(),
+            ReconcileKind::New,
+            d1,
+            Operation::Credit(MultiValue::new(dec!(2000), &usd)),
+        );
+        tx.add_split(
+            cash_eur.clone(),
+            ReconcileKind::New,
+            d1,
+            Operation::Credit(MultiValue::new(dec!(500), &eur)),
+        );
+        tx.add_split(
+            income.clone(),
+            ReconcileKind::New,
+            d1,
+            Operation::Credit(
+                MultiValue::new(dec!(-2000), &usd)
+                    + MultiValue::new(dec!(-500), &eur),
+            ),
+        );
+        repo.add_transaction(tx)?;
+
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            entry_date: d2,
+            ..TransactionArgs::default()
+        });
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            d2,
+            Operation::BuyAmount {
+                qty: Value {
+                    amount: dec!(10),
+                    commodity: acme.clone(),
+                },
+                amount: Value {
+                    amount: dec!(1000),
+                    commodity: usd.clone(),
+                },
+            },
+        );
+        tx.add_split(
+            cash_usd.clone(),
+            ReconcileKind::New,
+            d2,
+            Operation::Credit(MultiValue::new(dec!(-1000), &usd)),
+        );
+        repo.add_transaction(tx)?;
+
+        for (ts, origin, target, price) in [
+            (d1, &usd, &eur, dec!(0.9)),
+            (d3, &usd, &eur, dec!(1.0)),
+            (d2, &acme, &usd, dec!(100)),
+            (d3, &acme, &usd, dec!(120)),
+        ] {
+            repo.add_price(
+                origin,
+                target,
+                Price::new(ts, price, PriceSourceFrom::Transaction),
+            );
+        }
+
+        let exposure = Exposure::new(
+            &repo,
+            &Settings {
+                commodity: Some(eur.clone()),
+                intervals: vec![Intv::SpecificYear(2024)],
+            },
+            now,
+        )?;
+        let amount =
+            |v: &MultiValue| v.iter().map(|v| v.amount).sum::<Decimal>();
+        let rows = exposure
+            .currencies
+            .iter()
+            .map(|c| {
+                let p = c.periods.first().unwrap();
+                format!(
+                    "{} {} {} {} {:?} {} {} {}",
+                    c.currency.get_symbol(),
+                    amount(&p.start),
+                    amount(&p.end),
+                    amount(&p.flows),
+                    p.weight.map(|w| w.round_dp(2)),
+                    amount(&p.pnl),
+                    amount(&p.local_pnl),
+                    amount(&p.fx_pnl),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                "EUR 500 500 0 Some(0.19) 0 0 0",
+                "USD 2000 2200 0 Some(0.81) 400.0 200.0 200.0",
+            ]
+        );
+        Ok(())
+    }
+}
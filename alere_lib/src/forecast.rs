@@ -0,0 +1,279 @@
+//! Forecast of account balances.
+//!
+//! Starting from the current networth, the pending occurrences of all
+//! schedules are applied to find how balances will evolve.
+
+use crate::{
+    accounts::{Account, AccountId},
+    commodities::Commodity,
+    multi_values::MultiValue,
+    networth::{GroupBy, Networth, Settings},
+    repositories::Repository,
+    times::{Instant, Intv},
+    tree_keys::Key,
+    trees::Tree,
+};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+/// The projected balances of one account
+#[derive(Clone, Default)]
+pub struct ForecastRow {
+    pub current: MultiValue,
+    pub lowest: MultiValue,
+
+    // When the lowest balance is reached, or None if it is the current one
+    pub lowest_on: Option<DateTime<Local>>,
+
+    // Balance at the end of the forecast
+    pub end: MultiValue,
+
+    // Future changes to the balance
+    events: Vec<(DateTime<Local>, MultiValue)>,
+}
+
+impl ForecastRow {
+    /// Apply all events in chronological order
+    fn compute(&mut self) {
+        self.events.sort_by_key(|(date, _)| *date);
+        let mut balance = self.current.clone();
+        self.lowest = balance.clone();
+        self.lowest_on = None;
+        for (date, value) in &self.events {
+            balance += value;
+            if scalar(&balance) < scalar(&self.lowest) {
+                self.lowest = balance.clone();
+                self.lowest_on = Some(*date);
+            }
+        }
+        self.end = balance;
+    }
+}
+
+/// Balances are compared via the sum of their amounts, which is only
+/// meaningful for a single commodity.  This is the case for cash accounts,
+/// or when the forecast converts to a currency.
+fn scalar(value: &MultiValue) -> Decimal {
+    value.iter().map(|v| v.amount).sum()
+}
+
+fn is_forecast_account(acc: &Account) -> bool {
+    acc.get_kind().is_networth()
+}
+
+/// The projected balance of all networth accounts, up to some date.
+/// Parent accounts include their children.
+pub struct Forecast {
+    pub tree: Tree<Key, ForecastRow>,
+}
+
+impl Forecast {
+    pub fn new(
+        repo: &Repository,
+        commodity: Option<Commodity>,
+        until: DateTime<Local>,
+        now: DateTime<Local>,
+    ) -> Result<Self> {
+        let networth = Networth::new(
+            repo,
+            Settings {
+                hide_zero_rows: false,
+                hide_all_same: false,
+                group_by: GroupBy::ParentAccount,
+                subtotals: false,
+                commodity: commodity.clone(),
+                elide_boring_accounts: false,
+                intervals: vec![Intv::UpTo(Instant::Now)],
+            },
+            now,
+            is_forecast_account,
+        )?;
+
+        let mut rows: HashMap<AccountId, ForecastRow> = HashMap::new();
+        networth.tree.traverse(
+            |node| {
+                if let Key::Account(acc) = &node.data.key {
+                    rows.entry(acc.get_id()).or_default().current =
+                        node.data.data.get_market_value(0)?.clone();
+                }
+                Ok(())
+            },
+            true,
+        )?;
+
+        // Occurrences that are late are expected to happen now
+        let mut market = repo.market_prices(commodity);
+        for schedule in repo.schedules.iter() {
+            for date in schedule.pending(until) {
+                for split in &schedule.splits {
+                    rows.entry(split.account.get_id())
+                        .or_default()
+                        .events
+                        .push((
+                            date.max(now),
+                            market.convert_multi_value(&split.value, &now),
+                        ));
+                }
+            }
+        }
+
+        let mut forecast = Forecast {
+            tree: Tree::default(),
+        };
+        for acc in repo.accounts.iter().filter(is_forecast_account) {
+            if let Some(row) = rows.remove(&acc.get_id()) {
+                *forecast.tree.try_get(
+                    &Key::Account(acc.clone()),
+                    repo.accounts.iter_parents(&acc).map(Key::Account),
+                    |_| ForecastRow::default(),
+                ) = row;
+            }
+        }
+
+        forecast.tree.retain(|node| {
+            node.has_children()
+                || !node.data.data.current.is_zero()
+                || !node.data.data.events.is_empty()
+        });
+
+        forecast.tree.traverse_mut(
+            |node| {
+                let mut tmp = ForecastRow::default();
+                node.iter_children().for_each(|child| {
+                    tmp.current += &child.data.data.current;
+                    tmp.events.extend(child.data.data.events.iter().cloned());
+                });
+                node.data.data.current += &tmp.current;
+                node.data.data.events.extend(tmp.events);
+                node.data.data.compute();
+                Ok(())
+            },
+            false,
+        )?;
+
+        Ok(forecast)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        commodities::test::create_currency,
+        forecast::{Forecast, scalar},
+        multi_values::{MultiValue, Operation},
+        repositories::Repository,
+        schedules::{
+            Frequency, Recurrence, RecurrenceEnd, Schedule, ScheduledSplit,
+            WeekendOption,
+        },
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+        tree_keys::Key,
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_forecast() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let expense_kind =
+            repo.account_kinds.lookup("expense").unwrap().clone();
+        let income_kind = repo.account_kinds.lookup("income").unwrap().clone();
+        let checking = repo.accounts.add_dummy("Checking", checking_kind);
+        let rent = repo.accounts.add_dummy("Rent", expense_kind);
+        let salary = repo.accounts.add_dummy("Salary", income_kind);
+
+        let d = Local.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap();
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            entry_date: d,
+            ..TransactionArgs::default()
+        });
+        tx.add_split(
+            checking.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(500), &eur)),
+        );
+        tx.add_split(
+            salary.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(-500), &eur)),
+        );
+        repo.add_transaction(tx)?;
+
+        let monthly = |every| Recurrence {
+            frequency: Frequency::Monthly,
+            every,
+            last_day_of_month: false,
+            weekend: WeekendOption::Ignore,
+            end: RecurrenceEnd::Never,
+        };
+        repo.schedules.add(Schedule {
+            name: "Rent".to_string(),
+            payee: None,
+            memo: None,
+            start: Local.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap(),
+            recurrence: monthly(1),
+            last_entered: None,
+            splits: vec![
+                ScheduledSplit {
+                    account: checking.clone(),
+                    value: MultiValue::new(dec!(-800), &eur),
+                },
+                ScheduledSplit {
+                    account: rent.clone(),
+                    value: MultiValue::new(dec!(800), &eur),
+                },
+            ],
+        });
+        repo.schedules.add(Schedule {
+            name: "Salary".to_string(),
+            payee: None,
+            memo: None,
+            start: Local.with_ymd_and_hms(2024, 2, 25, 0, 0, 0).unwrap(),
+            recurrence: monthly(1),
+            last_entered: None,
+            splits: vec![
+                ScheduledSplit {
+                    account: checking.clone(),
+                    value: MultiValue::new(dec!(1000), &eur),
+                },
+                ScheduledSplit {
+                    account: salary.clone(),
+                    value: MultiValue::new(dec!(-1000), &eur),
+                },
+            ],
+        });
+
+        let now = Local.with_ymd_and_hms(2024, 1, 20, 0, 0, 0).unwrap();
+        let until = Local.with_ymd_and_hms(2024, 3, 31, 0, 0, 0).unwrap();
+        let forecast = Forecast::new(&repo, Some(eur.clone()), until, now)?;
+
+        let mut rows = Vec::new();
+        forecast.tree.traverse(
+            |node| {
+                if let Key::Account(acc) = &node.data.key {
+                    let row = &node.data.data;
+                    rows.push(format!(
+                        "{} {} {} {:?} {}",
+                        acc.name(crate::accounts::AccountNameDepth::basename()),
+                        scalar(&row.current),
+                        scalar(&row.lowest),
+                        row.lowest_on.map(|d| d.date_naive().to_string()),
+                        scalar(&row.end),
+                    ));
+                }
+                Ok(())
+            },
+            true,
+        )?;
+        assert_eq!(rows, vec!["Checking 500 -300 Some(\"2024-02-01\") 900"]);
+        Ok(())
+    }
+}
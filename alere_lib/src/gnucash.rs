@@ -0,0 +1,1118 @@
+//! Importer for GnuCash files.
+//!
+//! GnuCash saves its data either as XML (usually gzip'ed), or in a SQLite
+//! database.  Both backends are first read into the same in-memory `Book`,
+//! which is then converted to a repository.
+
+use crate::account_kinds::AccountKind;
+use crate::accounts::Account;
+use crate::commodities::Commodity;
+use crate::errors::AlrError;
+use crate::importers::Importer;
+use crate::multi_values::{MultiValue, Operation, Value};
+use crate::price_sources::PriceSourceFrom;
+use crate::prices::Price;
+use crate::repositories::Repository;
+use crate::transactions::{ReconcileKind, Transaction, TransactionArgs};
+use ::{
+    futures::TryStreamExt, //  make try_next visible
+    sqlx::{Connection, Row, SqliteConnection, query},
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::Path;
+
+/// Commodities are identified by their namespace (CURRENCY, NASDAQ,...) and
+/// their mnemonic.
+type CommodityKey = (String, String);
+
+/// Namespace for currencies.  Older versions of GnuCash used ISO4217
+const CURRENCY_NAMESPACES: [&str; 2] = ["CURRENCY", "ISO4217"];
+
+/// Commodity used by the template accounts of scheduled transactions
+const TEMPLATE_NAMESPACE: &str = "template";
+
+struct GncCommodity {
+    key: CommodityKey,
+    name: Option<String>,
+    isin: Option<String>,
+    fraction: u32,
+    quote_source: Option<String>,
+}
+
+struct GncAccount {
+    guid: String,
+    name: String,
+    kind: String, // BANK, STOCK, INCOME,...
+    commodity: Option<CommodityKey>,
+    parent: Option<String>,
+    code: Option<String>,
+    description: Option<String>,
+}
+
+struct GncSplit {
+    account: String,
+    memo: Option<String>,
+    action: Option<String>,
+    reconciled: char,
+    reconcile_date: Option<DateTime<Local>>,
+    value: Decimal,    // in the transaction's currency
+    quantity: Decimal, // in the account's commodity
+}
+
+struct GncTransaction {
+    currency: CommodityKey,
+    num: Option<String>,
+    posted: DateTime<Local>,
+    entered: Option<DateTime<Local>>,
+    description: Option<String>,
+    splits: Vec<GncSplit>,
+}
+
+struct GncPrice {
+    commodity: CommodityKey,
+    currency: CommodityKey,
+    timestamp: DateTime<Local>,
+    source: Option<String>,
+    value: Decimal,
+}
+
+/// The contents of a GnuCash file, independently of the backend
+#[derive(Default)]
+struct Book {
+    commodities: Vec<GncCommodity>,
+    accounts: Vec<GncAccount>,
+    transactions: Vec<GncTransaction>,
+    prices: Vec<GncPrice>,
+}
+
+/// Parse a GnuCash number, stored as "num/denom"
+fn parse_numeric(text: &str) -> Result<Decimal> {
+    let text = text.trim();
+    let (num, den) = text.split_once('/').unwrap_or((text, "1"));
+    let num = num
+        .parse::<i64>()
+        .with_context(|| format!("Invalid number {text:?}"))?;
+    let den = den
+        .parse::<i64>()
+        .with_context(|| format!("Invalid number {text:?}"))?;
+    if den == 0 {
+        Err(AlrError::Str(format!("Invalid number {text:?}")))?;
+    }
+    Ok((Decimal::from(num) / Decimal::from(den)).normalize())
+}
+
+/// Parse a timestamp.  The XML backend uses "2024-01-10 10:59:00 +0000", and
+/// the SQLite backend "2024-01-10 10:59:00" (or "20240110105900" in older
+/// versions), always in UTC.
+/// Only the date is relevant: GnuCash stores posted dates at 10:59 UTC, so
+/// that they are the same day in most timezones.
+fn parse_timestamp(text: &str) -> Result<DateTime<Local>> {
+    let text = text.trim();
+    let utc = DateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S %z")
+        .map(|d| d.naive_utc())
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(text, "%Y%m%d%H%M%S"))
+        .with_context(|| format!("Invalid timestamp {text:?}"))?;
+    let date = Local.from_utc_datetime(&utc).date_naive();
+    date.and_hms_opt(0, 0, 0)
+        .and_then(|d| d.and_local_timezone(Local).earliest())
+        .ok_or_else(|| {
+            AlrError::Str(format!("Invalid timestamp {text:?}")).into()
+        })
+}
+
+fn non_empty(s: Option<String>) -> Option<String> {
+    s.filter(|s| !s.trim().is_empty())
+}
+
+//--------------------------------------------------------------------------
+// XML backend
+//--------------------------------------------------------------------------
+
+type Node<'a> = roxmltree::Node<'a, 'a>;
+
+/// The first child element with the given (local) name
+fn child<'a>(node: Node<'a>, name: &str) -> Option<Node<'a>> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn child_text(node: Node, name: &str) -> Option<String> {
+    non_empty(child(node, name).and_then(|c| c.text()).map(str::to_string))
+}
+
+fn required_text(node: Node, name: &str) -> Result<String> {
+    child_text(node, name).ok_or_else(|| {
+        AlrError::Str(format!(
+            "Missing <{name}> in <{}>",
+            node.tag_name().name()
+        ))
+        .into()
+    })
+}
+
+/// A reference to a commodity, as in <act:commodity>
+fn commodity_ref(node: Node) -> Result<CommodityKey> {
+    Ok((required_text(node, "space")?, required_text(node, "id")?))
+}
+
+/// A timestamp, as in <trn:date-posted>
+fn timestamp(node: Node) -> Result<DateTime<Local>> {
+    parse_timestamp(&required_text(node, "date")?)
+}
+
+fn read_xml(text: &str) -> Result<Book> {
+    let doc = roxmltree::Document::parse(text)?;
+    let root = doc.root_element();
+    let gncbook = child(root, "book").unwrap_or(root);
+    let mut book = Book::default();
+
+    // Template transactions (for scheduled transactions) are stored in
+    // <gnc:template-transactions>, and are not direct children of the book.
+    for node in gncbook.children().filter(|c| c.is_element()) {
+        match node.tag_name().name() {
+            "commodity" => {
+                book.commodities.push(GncCommodity {
+                    key: commodity_ref(node)?,
+                    name: child_text(node, "name"),
+                    isin: child_text(node, "xcode"),
+                    fraction: child_text(node, "fraction")
+                        .and_then(|f| f.parse().ok())
+                        .unwrap_or(100),
+                    quote_source: child(node, "get_quotes")
+                        .and_then(|_| child_text(node, "quote_source")),
+                });
+            }
+            "account" => {
+                book.accounts.push(GncAccount {
+                    guid: required_text(node, "id")?,
+                    name: child_text(node, "name").unwrap_or_default(),
+                    kind: required_text(node, "type")?,
+                    commodity: child(node, "commodity")
+                        .map(commodity_ref)
+                        .transpose()?,
+                    parent: child_text(node, "parent"),
+                    code: child_text(node, "code"),
+                    description: child_text(node, "description"),
+                });
+            }
+            "transaction" => {
+                let mut splits = Vec::new();
+                if let Some(s) = child(node, "splits") {
+                    for split in s.children().filter(|c| c.is_element()) {
+                        splits.push(GncSplit {
+                            account: required_text(split, "account")?,
+                            memo: child_text(split, "memo"),
+                            action: child_text(split, "action"),
+                            reconciled: child_text(split, "reconciled-state")
+                                .and_then(|r| r.chars().next())
+                                .unwrap_or('n'),
+                            reconcile_date: child(split, "reconcile-date")
+                                .map(timestamp)
+                                .transpose()?,
+                            value: parse_numeric(&required_text(
+                                split, "value",
+                            )?)?,
+                            quantity: parse_numeric(&required_text(
+                                split, "quantity",
+                            )?)?,
+                        });
+                    }
+                }
+                book.transactions.push(GncTransaction {
+                    currency: commodity_ref(
+                        child(node, "currency").ok_or_else(|| {
+                            AlrError::Str("Missing <trn:currency>".into())
+                        })?,
+                    )?,
+                    num: child_text(node, "num"),
+                    posted: timestamp(child(node, "date-posted").ok_or_else(
+                        || AlrError::Str("Missing <trn:date-posted>".into()),
+                    )?)?,
+                    entered: child(node, "date-entered")
+                        .map(timestamp)
+                        .transpose()?,
+                    description: child_text(node, "description"),
+                    splits,
+                });
+            }
+            "pricedb" => {
+                for price in node.children().filter(|c| c.is_element()) {
+                    let get = |name: &str| {
+                        child(price, name).ok_or_else(|| {
+                            AlrError::Str(format!("Missing <price:{name}>"))
+                        })
+                    };
+                    book.prices.push(GncPrice {
+                        commodity: commodity_ref(get("commodity")?)?,
+                        currency: commodity_ref(get("currency")?)?,
+                        timestamp: timestamp(get("time")?)?,
+                        source: child_text(price, "source"),
+                        value: parse_numeric(&required_text(price, "value")?)?,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(book)
+}
+
+//--------------------------------------------------------------------------
+// SQLite backend
+//--------------------------------------------------------------------------
+
+fn row_numeric(
+    row: &sqlx::sqlite::SqliteRow,
+    num: &str,
+    denom: &str,
+) -> Result<Decimal> {
+    let den: i64 = row.get(denom);
+    if den == 0 {
+        Err(AlrError::Str(format!("Invalid denominator for {num}")))?;
+    }
+    Ok(
+        (Decimal::from(row.get::<i64, _>(num)) / Decimal::from(den))
+            .normalize(),
+    )
+}
+
+async fn read_sqlite(path: &Path) -> Result<Book> {
+    let mut conn = SqliteConnection::connect(path.to_str().ok_or(
+        AlrError::Str("Cannot convert path to a valid string".into()),
+    )?)
+    .await?;
+    let mut book = Book::default();
+
+    // SQL tables reference commodities by guid
+    let mut commodities = HashMap::new();
+    let mut stream = query("SELECT * FROM commodities").fetch(&mut conn);
+    while let Some(row) = stream.try_next().await? {
+        let key: CommodityKey = (row.get("namespace"), row.get("mnemonic"));
+        commodities.insert(row.get::<String, _>("guid"), key.clone());
+        book.commodities.push(GncCommodity {
+            key,
+            name: non_empty(row.get("fullname")),
+            isin: non_empty(row.get("cusip")),
+            fraction: row.get("fraction"),
+            quote_source: if row.get::<i32, _>("quote_flag") != 0 {
+                non_empty(row.get("quote_source"))
+            } else {
+                None
+            },
+        });
+    }
+    drop(stream);
+    let commodity = |guid: &str| {
+        commodities
+            .get(guid)
+            .cloned()
+            .ok_or_else(|| AlrError::Str(format!("No such commodity {guid:?}")))
+    };
+
+    let mut stream = query("SELECT * FROM accounts").fetch(&mut conn);
+    while let Some(row) = stream.try_next().await? {
+        book.accounts.push(GncAccount {
+            guid: row.get("guid"),
+            name: row.get("name"),
+            kind: row.get("account_type"),
+            commodity: row
+                .get::<Option<&str>, _>("commodity_guid")
+                .map(commodity)
+                .transpose()?,
+            parent: non_empty(row.get("parent_guid")),
+            code: non_empty(row.get("code")),
+            description: non_empty(row.get("description")),
+        });
+    }
+    drop(stream);
+
+    // Unlike the XML backend, template accounts are stored with the others,
+    // but under their own root.
+    let template_roots = query("SELECT root_template_guid FROM books")
+        .fetch_all(&mut conn)
+        .await?
+        .iter()
+        .map(|row| row.get::<String, _>("root_template_guid"))
+        .collect::<HashSet<_>>();
+    let parents = book
+        .accounts
+        .iter()
+        .map(|a| (a.guid.clone(), a.parent.clone()))
+        .collect::<HashMap<_, _>>();
+    book.accounts.retain(|a| {
+        let mut current = Some(a.guid.clone());
+        while let Some(guid) = current {
+            if template_roots.contains(&guid) {
+                return false;
+            }
+            current = parents.get(&guid).cloned().flatten();
+        }
+        true
+    });
+
+    let mut transactions = HashMap::new();
+    let mut stream = query("SELECT * FROM transactions").fetch(&mut conn);
+    while let Some(row) = stream.try_next().await? {
+        transactions
+            .insert(row.get::<String, _>("guid"), book.transactions.len());
+        book.transactions.push(GncTransaction {
+            currency: commodity(row.get("currency_guid"))?,
+            num: non_empty(row.get("num")),
+            posted: parse_timestamp(row.get("post_date"))?,
+            entered: row
+                .get::<Option<&str>, _>("enter_date")
+                .map(parse_timestamp)
+                .transpose()?,
+            description: non_empty(row.get("description")),
+            splits: Vec::new(),
+        });
+    }
+    drop(stream);
+
+    let mut stream = query("SELECT * FROM splits").fetch(&mut conn);
+    while let Some(row) = stream.try_next().await? {
+        let tx_guid: &str = row.get("tx_guid");
+        let tx = transactions
+            .get(tx_guid)
+            .and_then(|idx| book.transactions.get_mut(*idx))
+            .with_context(|| format!("No such transaction {tx_guid:?}"))?;
+        tx.splits.push(GncSplit {
+            account: row.get("account_guid"),
+            memo: non_empty(row.get("memo")),
+            action: non_empty(row.get("action")),
+            reconciled: row
+                .get::<&str, _>("reconcile_state")
+                .chars()
+                .next()
+                .unwrap_or('n'),
+            reconcile_date: row
+                .get::<Option<&str>, _>("reconcile_date")
+                .map(parse_timestamp)
+                .transpose()?,
+            value: row_numeric(&row, "value_num", "value_denom")?,
+            quantity: row_numeric(&row, "quantity_num", "quantity_denom")?,
+        });
+    }
+    drop(stream);
+
+    let mut stream = query("SELECT * FROM prices").fetch(&mut conn);
+    while let Some(row) = stream.try_next().await? {
+        book.prices.push(GncPrice {
+            commodity: commodity(row.get("commodity_guid"))?,
+            currency: commodity(row.get("currency_guid"))?,
+            timestamp: parse_timestamp(row.get("date"))?,
+            source: non_empty(row.get("source")),
+            value: row_numeric(&row, "value_num", "value_denom")?,
+        });
+    }
+    drop(stream);
+
+    Ok(book)
+}
+
+//--------------------------------------------------------------------------
+// Conversion to a repository
+//--------------------------------------------------------------------------
+
+#[derive(Default)]
+struct BookLoader {
+    repo: Repository,
+    commodities: HashMap<CommodityKey, Commodity>,
+    accounts: HashMap<String, Account>, // GnuCash guid -> account
+    account_commodity: HashMap<String, Commodity>,
+
+    // Number of shares held in each account, to compute the ratio of stock
+    // splits.
+    holdings: HashMap<String, Decimal>,
+
+    // Used to balance shares added without a cost
+    equity_account: Option<Account>,
+}
+
+impl BookLoader {
+    fn load(mut self, mut book: Book) -> Result<Repository> {
+        self.load_commodities(&book);
+        self.load_accounts(&book)?;
+        self.load_prices(&book)?;
+
+        // Splits need the number of shares held before them
+        book.transactions.sort_by_key(|t| t.posted);
+        for tx in &book.transactions {
+            self.load_transaction(tx)?;
+        }
+        Ok(self.repo)
+    }
+
+    fn load_commodities(&mut self, book: &Book) {
+        for c in &book.commodities {
+            let (space, mnemonic) = &c.key;
+            if space == TEMPLATE_NAMESPACE {
+                continue;
+            }
+            let mut comm = self.repo.commodities.add(
+                c.name.as_deref().unwrap_or(mnemonic),
+                mnemonic,
+                true, // symbol displayed after value
+                CURRENCY_NAMESPACES.contains(&space.as_str()),
+                Some(mnemonic),
+                c.fraction.max(1).ilog10() as u8,
+            );
+            if let Some(isin) = &c.isin {
+                comm.set_isin(isin);
+            }
+            if let Some(source) = &c.quote_source {
+                comm.set_quote_source(source);
+            }
+            self.commodities.insert(c.key.clone(), comm);
+        }
+    }
+
+    fn lookup_commodity(&self, key: &CommodityKey) -> Result<Commodity> {
+        self.commodities.get(key).cloned().ok_or_else(|| {
+            AlrError::Str(format!("No such commodity {}:{}", key.0, key.1))
+                .into()
+        })
+    }
+
+    /// As for kmymoney, a line starting with "alere:" in the description
+    /// overrides the kind of the account.  Otherwise it is guessed from the
+    /// GnuCash account type.
+    fn guess_account_kind(&self, acc: &GncAccount) -> Result<AccountKind> {
+        let hint = acc.description.as_deref().and_then(|d| {
+            d.lines()
+                .find_map(|line| line.strip_prefix("alere:"))
+                .map(str::trim)
+        });
+        let name = match hint {
+            Some(h) => h,
+            None => match acc.kind.as_str() {
+                "BANK" | "CASH" => "Checking",
+                "STOCK" | "MUTUAL" => "Stock",
+                "ASSET" | "RECEIVABLE" | "CURRENCY" => "Asset",
+                "CREDIT" | "LIABILITY" | "PAYABLE" => "Liability",
+                "INCOME" => "Income",
+                "EXPENSE" => "Expense",
+                "EQUITY" | "TRADING" => "Equity",
+                k => Err(AlrError::Str(format!("Unknown account type {k}")))?,
+            },
+        };
+        self.repo
+            .account_kinds
+            .lookup(name)
+            .cloned()
+            .ok_or_else(|| {
+                AlrError::Str(format!("Could not get account_kind '{name}'"))
+                    .into()
+            })
+    }
+
+    fn load_accounts(&mut self, book: &Book) -> Result<()> {
+        // The root accounts are not imported, their children become top-level
+        // accounts.
+        for acc in book.accounts.iter().filter(|a| a.kind != "ROOT") {
+            let commodity = match &acc.commodity {
+                Some(c) => self.lookup_commodity(c)?,
+                None => Err(AlrError::Str(format!(
+                    "No commodity for account {}",
+                    acc.name
+                )))?,
+            };
+            let a = self.repo.accounts.add(
+                &acc.name,
+                self.guess_account_kind(acc)?,
+                None,
+                None,
+                acc.description.as_deref(),
+                None,
+                acc.code.as_deref(),
+                false, // GnuCash only has hidden accounts, not closed ones
+                None,
+            );
+            self.accounts.insert(acc.guid.clone(), a);
+            self.account_commodity.insert(acc.guid.clone(), commodity);
+        }
+
+        for acc in &book.accounts {
+            let parent = acc.parent.as_ref().and_then(|p| self.accounts.get(p));
+            if let (Some(mut a), Some(p)) =
+                (self.accounts.get(&acc.guid).cloned(), parent.cloned())
+            {
+                a.set_parent(p);
+            }
+        }
+        Ok(())
+    }
+
+    fn load_prices(&mut self, book: &Book) -> Result<()> {
+        for p in &book.prices {
+            let origin = self.lookup_commodity(&p.commodity)?;
+            let target = self.lookup_commodity(&p.currency)?;
+            let source = self.repo.get_or_add_price_source(
+                p.source.as_deref().unwrap_or("gnucash"),
+            );
+            self.repo.add_price(
+                &origin,
+                &target,
+                Price::new(
+                    p.timestamp,
+                    p.value,
+                    PriceSourceFrom::External(source.get_id()),
+                ),
+            );
+        }
+        Ok(())
+    }
+
+    fn load_transaction(&mut self, gtx: &GncTransaction) -> Result<()> {
+        // Scheduled transactions use template accounts, which are not
+        // imported.
+        if gtx
+            .splits
+            .iter()
+            .any(|s| !self.accounts.contains_key(&s.account))
+        {
+            return Ok(());
+        }
+
+        let currency = self.lookup_commodity(&gtx.currency)?;
+        let memo = gtx
+            .description
+            .as_deref()
+            .or_else(|| gtx.splits.iter().find_map(|s| s.memo.as_deref()));
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo,
+            check_number: gtx.num.as_deref(),
+            entry_date: gtx.entered.unwrap_or(gtx.posted),
+            ..Default::default()
+        });
+
+        for split in &gtx.splits {
+            let operation =
+                self.operation(&mut tx, split, &currency, gtx.posted)?;
+            tx.add_split(
+                self.accounts
+                    .get(&split.account)
+                    .expect("Checked above")
+                    .clone(),
+                match split.reconciled {
+                    'c' => ReconcileKind::Cleared,
+                    'y' | 'f' => {
+                        ReconcileKind::Reconciled(split.reconcile_date)
+                    }
+                    _ => ReconcileKind::New, // 'n', or 'v' for voided
+                },
+                gtx.posted,
+                operation,
+            );
+        }
+
+        self.repo.add_transaction(tx).with_context(|| {
+            format!(
+                "Transaction {:?} on {}",
+                gtx.description.as_deref().unwrap_or_default(),
+                gtx.posted.date_naive(),
+            )
+        })
+    }
+
+    /// Convert a split.  Its value is given in the transaction's currency,
+    /// and its quantity in the account's commodity.
+    fn operation(
+        &mut self,
+        tx: &mut Transaction,
+        split: &GncSplit,
+        currency: &Commodity,
+        post_ts: DateTime<Local>,
+    ) -> Result<Operation> {
+        let commodity = self
+            .account_commodity
+            .get(&split.account)
+            .expect("Account was imported")
+            .clone();
+        let held = self
+            .holdings
+            .get(&split.account)
+            .copied()
+            .unwrap_or_default();
+        self.holdings
+            .insert(split.account.clone(), held + split.quantity);
+        let is_action = |a: &str| {
+            split
+                .action
+                .as_deref()
+                .is_some_and(|s| s.eq_ignore_ascii_case(a))
+        };
+
+        let op = if commodity == *currency {
+            Operation::Credit(MultiValue::new(split.quantity, &commodity))
+        } else if split.quantity.is_zero() {
+            if split.value.is_zero() && is_action("Dividend") {
+                Operation::Dividend
+            } else {
+                // For instance realized gains on a stock account
+                Operation::Credit(MultiValue::new(split.value, currency))
+            }
+        } else if !split.value.is_zero() {
+            Operation::BuyAmount {
+                qty: Value {
+                    amount: split.quantity,
+                    commodity: commodity.clone(),
+                },
+                amount: Value {
+                    amount: split.value,
+                    commodity: currency.clone(),
+                },
+            }
+        } else if is_action("Split") && !held.is_zero() {
+            // GnuCash stores the number of shares added by the split
+            Operation::Split {
+                ratio: (held + split.quantity) / held,
+                commodity,
+            }
+        } else {
+            // Shares added without a cost.  As for kmymoney, we create an
+            // extra split to make the transaction balanced.
+            let equity = match &self.equity_account {
+                Some(e) => e.clone(),
+                None => {
+                    let e = self.repo.accounts.add(
+                        "gnucash_import",
+                        self.repo.account_kinds.get_equity(),
+                        None,
+                        None,
+                        None,
+                        None,
+                        None,
+                        false,
+                        None,
+                    );
+                    self.equity_account = Some(e.clone());
+                    e
+                }
+            };
+            tx.add_split(
+                equity,
+                ReconcileKind::New,
+                post_ts,
+                Operation::Credit(MultiValue::new(-split.quantity, &commodity)),
+            );
+            Operation::AddShares {
+                qty: Value {
+                    amount: split.quantity,
+                    commodity,
+                },
+            }
+        };
+        Ok(op)
+    }
+}
+
+#[derive(Default)]
+pub struct GnucashImporter {}
+
+impl Importer for GnucashImporter {
+    async fn import_file(
+        &mut self,
+        path: &Path,
+        report_progress: impl Fn(u64, u64),
+    ) -> Result<Repository> {
+        const MAX_PROGRESS: u64 = 3;
+
+        report_progress(1, MAX_PROGRESS);
+        let content = std::fs::read(path)?;
+        let book = if content.starts_with(b"SQLite format 3\0") {
+            read_sqlite(path).await?
+        } else if content.starts_with(&[0x1f, 0x8b]) {
+            let mut text = String::new();
+            flate2::read::GzDecoder::new(content.as_slice())
+                .read_to_string(&mut text)?;
+            read_xml(&text)?
+        } else {
+            read_xml(std::str::from_utf8(&content)?)?
+        };
+        report_progress(2, MAX_PROGRESS);
+        let repo = BookLoader::default().load(book)?;
+        report_progress(3, MAX_PROGRESS);
+        Ok(repo)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::AccountNameDepth, formatters::Formatter,
+        gnucash::GnucashImporter, importers::Importer, multi_values::Operation,
+        repositories::Repository,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+    use sqlx::{Connection, Executor, SqliteConnection};
+    use std::io::Write;
+
+    const XML: &str = r#"<?xml version="1.0" encoding="utf-8" ?>
+<gnc-v2
+     xmlns:gnc="http://www.gnucash.org/XML/gnc"
+     xmlns:act="http://www.gnucash.org/XML/act"
+     xmlns:book="http://www.gnucash.org/XML/book"
+     xmlns:cd="http://www.gnucash.org/XML/cd"
+     xmlns:cmdty="http://www.gnucash.org/XML/cmdty"
+     xmlns:price="http://www.gnucash.org/XML/price"
+     xmlns:slot="http://www.gnucash.org/XML/slot"
+     xmlns:split="http://www.gnucash.org/XML/split"
+     xmlns:trn="http://www.gnucash.org/XML/trn"
+     xmlns:ts="http://www.gnucash.org/XML/ts">
+<gnc:count-data cd:type="book">1</gnc:count-data>
+<gnc:book version="2.0.0">
+<book:id type="guid">b1</book:id>
+<gnc:commodity version="2.0.0">
+  <cmdty:space>CURRENCY</cmdty:space>
+  <cmdty:id>EUR</cmdty:id>
+  <cmdty:get_quotes/>
+  <cmdty:quote_source>currency</cmdty:quote_source>
+</gnc:commodity>
+<gnc:commodity version="2.0.0">
+  <cmdty:space>NASDAQ</cmdty:space>
+  <cmdty:id>AAPL</cmdty:id>
+  <cmdty:name>Apple &amp; co</cmdty:name>
+  <cmdty:xcode>US0378331005</cmdty:xcode>
+  <cmdty:fraction>10000</cmdty:fraction>
+</gnc:commodity>
+<gnc:pricedb version="1">
+  <price>
+    <price:id type="guid">p1</price:id>
+    <price:commodity>
+      <cmdty:space>NASDAQ</cmdty:space>
+      <cmdty:id>AAPL</cmdty:id>
+    </price:commodity>
+    <price:currency>
+      <cmdty:space>CURRENCY</cmdty:space>
+      <cmdty:id>EUR</cmdty:id>
+    </price:currency>
+    <price:time><ts:date>2024-06-10 10:59:00 +0000</ts:date></price:time>
+    <price:source>user:price-editor</price:source>
+    <price:value>171/2</price:value>
+  </price>
+</gnc:pricedb>
+<gnc:account version="2.0.0">
+  <act:name>Root Account</act:name>
+  <act:id type="guid">root</act:id>
+  <act:type>ROOT</act:type>
+</gnc:account>
+<gnc:account version="2.0.0">
+  <act:name>Assets</act:name>
+  <act:id type="guid">assets</act:id>
+  <act:type>ASSET</act:type>
+  <act:commodity>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </act:commodity>
+  <act:parent type="guid">root</act:parent>
+</gnc:account>
+<gnc:account version="2.0.0">
+  <act:name>Checking</act:name>
+  <act:id type="guid">checking</act:id>
+  <act:type>BANK</act:type>
+  <act:commodity>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </act:commodity>
+  <act:code>1234</act:code>
+  <act:parent type="guid">assets</act:parent>
+</gnc:account>
+<gnc:account version="2.0.0">
+  <act:name>AAPL</act:name>
+  <act:id type="guid">aapl</act:id>
+  <act:type>STOCK</act:type>
+  <act:commodity>
+    <cmdty:space>NASDAQ</cmdty:space>
+    <cmdty:id>AAPL</cmdty:id>
+  </act:commodity>
+  <act:parent type="guid">assets</act:parent>
+</gnc:account>
+<gnc:account version="2.0.0">
+  <act:name>Dividends</act:name>
+  <act:id type="guid">dividends</act:id>
+  <act:type>INCOME</act:type>
+  <act:commodity>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </act:commodity>
+  <act:description>alere: passive income</act:description>
+  <act:parent type="guid">root</act:parent>
+</gnc:account>
+<gnc:account version="2.0.0">
+  <act:name>Old</act:name>
+  <act:id type="guid">old</act:id>
+  <act:type>BANK</act:type>
+  <act:commodity>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </act:commodity>
+  <act:slots>
+    <slot>
+      <slot:key>hidden</slot:key>
+      <slot:value type="string">true</slot:value>
+    </slot>
+  </act:slots>
+  <act:parent type="guid">assets</act:parent>
+</gnc:account>
+<gnc:transaction version="2.0.0">
+  <trn:id type="guid">t1</trn:id>
+  <trn:currency>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </trn:currency>
+  <trn:num>42</trn:num>
+  <trn:date-posted><ts:date>2024-01-10 10:59:00 +0000</ts:date></trn:date-posted>
+  <trn:date-entered><ts:date>2024-01-11 08:00:00 +0000</ts:date></trn:date-entered>
+  <trn:description>Buy AAPL</trn:description>
+  <trn:splits>
+    <trn:split>
+      <split:id type="guid">s1</split:id>
+      <split:action>Buy</split:action>
+      <split:reconciled-state>y</split:reconciled-state>
+      <split:reconcile-date><ts:date>2024-03-10 10:59:00 +0000</ts:date></split:reconcile-date>
+      <split:value>150000/100</split:value>
+      <split:quantity>100000/10000</split:quantity>
+      <split:account type="guid">aapl</split:account>
+    </trn:split>
+    <trn:split>
+      <split:id type="guid">s2</split:id>
+      <split:reconciled-state>c</split:reconciled-state>
+      <split:value>-150000/100</split:value>
+      <split:quantity>-150000/100</split:quantity>
+      <split:account type="guid">checking</split:account>
+    </trn:split>
+  </trn:splits>
+</gnc:transaction>
+<gnc:transaction version="2.0.0">
+  <trn:id type="guid">t2</trn:id>
+  <trn:currency>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </trn:currency>
+  <trn:date-posted><ts:date>2024-03-10 10:59:00 +0000</ts:date></trn:date-posted>
+  <trn:description>Stock split</trn:description>
+  <trn:splits>
+    <trn:split>
+      <split:id type="guid">s3</split:id>
+      <split:action>Split</split:action>
+      <split:reconciled-state>n</split:reconciled-state>
+      <split:value>0/100</split:value>
+      <split:quantity>100000/10000</split:quantity>
+      <split:account type="guid">aapl</split:account>
+    </trn:split>
+  </trn:splits>
+</gnc:transaction>
+<gnc:transaction version="2.0.0">
+  <trn:id type="guid">t3</trn:id>
+  <trn:currency>
+    <cmdty:space>CURRENCY</cmdty:space>
+    <cmdty:id>EUR</cmdty:id>
+  </trn:currency>
+  <trn:date-posted><ts:date>2024-06-10 10:59:00 +0000</ts:date></trn:date-posted>
+  <trn:description>Dividend</trn:description>
+  <trn:splits>
+    <trn:split>
+      <split:id type="guid">s4</split:id>
+      <split:action>Dividend</split:action>
+      <split:reconciled-state>n</split:reconciled-state>
+      <split:value>0/100</split:value>
+      <split:quantity>0/10000</split:quantity>
+      <split:account type="guid">aapl</split:account>
+    </trn:split>
+    <trn:split>
+      <split:id type="guid">s5</split:id>
+      <split:reconciled-state>n</split:reconciled-state>
+      <split:value>2000/100</split:value>
+      <split:quantity>2000/100</split:quantity>
+      <split:account type="guid">checking</split:account>
+    </trn:split>
+    <trn:split>
+      <split:id type="guid">s6</split:id>
+      <split:reconciled-state>n</split:reconciled-state>
+      <split:value>-2000/100</split:value>
+      <split:quantity>-2000/100</split:quantity>
+      <split:account type="guid">dividends</split:account>
+    </trn:split>
+  </trn:splits>
+</gnc:transaction>
+<gnc:template-transactions>
+  <gnc:account version="2.0.0">
+    <act:name>Template Root</act:name>
+    <act:id type="guid">troot</act:id>
+    <act:type>ROOT</act:type>
+  </gnc:account>
+  <gnc:transaction version="2.0.0">
+    <trn:id type="guid">t4</trn:id>
+  </gnc:transaction>
+</gnc:template-transactions>
+</gnc:book>
+</gnc-v2>
+"#;
+
+    /// The same contents as XML, for the SQLite backend.  Only the columns
+    /// we use are created.
+    const SQL: &str = r#"
+CREATE TABLE books (guid text, root_account_guid text,
+    root_template_guid text);
+CREATE TABLE commodities (guid text, namespace text, mnemonic text,
+    fullname text, cusip text, fraction integer, quote_flag integer,
+    quote_source text);
+CREATE TABLE accounts (guid text, name text, account_type text,
+    commodity_guid text, parent_guid text, code text, description text,
+    hidden integer);
+CREATE TABLE transactions (guid text, currency_guid text, num text,
+    post_date text, enter_date text, description text);
+CREATE TABLE splits (guid text, tx_guid text, account_guid text, memo text,
+    action text, reconcile_state text, reconcile_date text,
+    value_num bigint, value_denom bigint, quantity_num bigint,
+    quantity_denom bigint);
+CREATE TABLE prices (guid text, commodity_guid text, currency_guid text,
+    date text, source text, value_num bigint, value_denom bigint);
+
+INSERT INTO books VALUES ('b1', 'root', 'troot');
+INSERT INTO commodities VALUES
+    ('c1', 'CURRENCY', 'EUR', 'EUR', '', 100, 1, 'currency'),
+    ('c2', 'NASDAQ', 'AAPL', 'Apple & co', 'US0378331005', 10000, 0, NULL),
+    ('c3', 'template', 'template', 'template', '', 1, 0, NULL);
+INSERT INTO accounts VALUES
+    ('root', 'Root Account', 'ROOT', NULL, NULL, '', '', 0),
+    ('assets', 'Assets', 'ASSET', 'c1', 'root', '', '', 0),
+    ('checking', 'Checking', 'BANK', 'c1', 'assets', '1234', '', 0),
+    ('aapl', 'AAPL', 'STOCK', 'c2', 'assets', '', '', 0),
+    ('dividends', 'Dividends', 'INCOME', 'c1', 'root', '',
+        'alere: passive income', 0),
+    ('old', 'Old', 'BANK', 'c1', 'assets', '', '', 1),
+    ('troot', 'Template Root', 'ROOT', NULL, NULL, '', '', 0),
+    ('tacc', 'tpl', 'ASSET', 'c3', 'troot', '', '', 0);
+INSERT INTO transactions VALUES
+    ('t1', 'c1', '42', '2024-01-10 10:59:00', '2024-01-11 08:00:00',
+        'Buy AAPL'),
+    ('t2', 'c1', '', '2024-03-10 10:59:00', NULL, 'Stock split'),
+    ('t3', 'c1', '', '2024-06-10 10:59:00', NULL, 'Dividend'),
+    ('t4', 'c1', '', '2024-07-10 10:59:00', NULL, 'Scheduled');
+INSERT INTO splits VALUES
+    ('s1', 't1', 'aapl', '', 'Buy', 'y', '2024-03-10 10:59:00',
+        150000, 100, 100000, 10000),
+    ('s2', 't1', 'checking', '', '', 'c', NULL, -150000, 100, -150000, 100),
+    ('s3', 't2', 'aapl', '', 'Split', 'n', NULL, 0, 100, 100000, 10000),
+    ('s4', 't3', 'aapl', '', 'Dividend', 'n', NULL, 0, 100, 0, 10000),
+    ('s5', 't3', 'checking', '', '', 'n', NULL, 2000, 100, 2000, 100),
+    ('s6', 't3', 'dividends', '', '', 'n', NULL, -2000, 100, -2000, 100),
+    ('s7', 't4', 'tacc', '', '', 'n', NULL, 1000, 100, 1000, 100);
+INSERT INTO prices VALUES
+    ('p1', 'c2', 'c1', '2024-06-10 10:59:00', 'user:price-editor', 171, 2);
+"#;
+
+    fn describe(repo: &Repository) -> Vec<String> {
+        let format = Formatter::default();
+        let mut result = Vec::new();
+        for acc in repo.accounts.iter() {
+            result.push(format!(
+                "{} kind={} number={:?} closed={}",
+                acc.name(AccountNameDepth::unlimited()),
+                acc.get_kind().get_name(),
+                acc.get_number(),
+                acc.is_closed(),
+            ));
+        }
+        for tx in repo.transactions.iter() {
+            result.push(format!("{:?} {:?}", tx.memo(), tx.check_number()));
+            for s in tx.splits().iter() {
+                // Do not display the details of the commodity
+                result.push(
+                    if let Operation::Split { ratio, .. } = &s.operation {
+                        format!(
+                            "  Split({} {} {ratio})",
+                            s.post_ts.date_naive(),
+                            s.account.name(AccountNameDepth::unlimited()),
+                        )
+                    } else {
+                        format!("  {}", s.display(&format))
+                    },
+                );
+            }
+        }
+        result
+    }
+
+    fn check(repo: &Repository) {
+        assert_eq!(
+            describe(repo),
+            vec![
+                "Assets kind=Asset number=None closed=false",
+                "Assets:Checking kind=Checking number=Some(\"1234\") closed=false",
+                "Assets:AAPL kind=Stock number=None closed=false",
+                "Dividends kind=Passive Income number=None closed=false",
+                "Assets:Old kind=Checking number=None closed=false",
+                "Some(\"Buy AAPL\") Some(\"42\")",
+                "  BuyAmount(2024-01-10 00:00:00 +01:00 Assets:AAPL 10.0000 AAPL for 1,500.00 EUR)",
+                "  Credit(2024-01-10 00:00:00 +01:00 Assets:Checking -1,500.00 EUR)",
+                "Some(\"Stock split\") None",
+                "  Split(2024-03-10 Assets:AAPL 2)",
+                "Some(\"Dividend\") None",
+                "  Dividend(2024-06-10 00:00:00 +02:00 Assets:AAPL",
+                "  Credit(2024-06-10 00:00:00 +02:00 Assets:Checking 20.00 EUR)",
+                "  Credit(2024-06-10 00:00:00 +02:00 Dividends -20.00 EUR)",
+            ]
+        );
+
+        let eur = repo.commodities.find("EUR").unwrap();
+        let aapl = repo.commodities.find("Apple & co").unwrap();
+        assert!(eur.is_currency());
+        assert!(!aapl.is_currency());
+        assert_eq!(aapl.get_isin().as_deref(), Some("US0378331005"));
+        assert_eq!(aapl.get_display_precision(), 4);
+        assert!(repo.prices.iter().any(|((from, to), prices)| {
+            *from == aapl
+                && *to == eur
+                && prices.iter().any(|p| p.price == dec!(85.5))
+        }));
+    }
+
+    #[test]
+    fn test_import_xml() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("alere_gnucash_{}.gnucash", std::process::id()));
+        let mut encoder = flate2::write::GzEncoder::new(
+            std::fs::File::create(&path)?,
+            flate2::Compression::default(),
+        );
+        encoder.write_all(XML.as_bytes())?;
+        encoder.finish()?;
+
+        let repo = futures::executor::block_on(
+            GnucashImporter::default().import_file(&path, |_, _| {}),
+        );
+        std::fs::remove_file(&path)?;
+        check(&repo?);
+        Ok(())
+    }
+
+    #[test]
+    fn test_import_sqlite() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("alere_gnucash_{}.sqlite", std::process::id()));
+        let repo = futures::executor::block_on(async {
+            let mut conn = SqliteConnection::connect(&format!(
+                "sqlite://{}?mode=rwc",
+                path.display()
+            ))
+            .await?;
+            conn.execute(SQL).await?;
+            conn.close().await?;
+            GnucashImporter::default()
+                .import_file(&path, |_, _| {})
+                .await
+        });
+        std::fs::remove_file(&path)?;
+        check(&repo?);
+        Ok(())
+    }
+}
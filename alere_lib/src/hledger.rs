@@ -1,17 +1,27 @@
-use crate::importers::Exporter;
+use crate::account_kinds::AccountKind;
+use crate::accounts::{Account, Reconciliation};
+use crate::commodities::Commodity;
+use crate::errors::AlrError;
+use crate::importers::{Exporter, Importer};
 use crate::multi_values::{MultiValue, Operation, Value};
 use crate::networth::Networth;
+use crate::payees::Payee;
+use crate::price_sources::PriceSourceFrom;
+use crate::prices::Price;
 use crate::repositories::Repository;
 use crate::times::{Instant, Intv};
+use crate::transactions::{ReconcileKind, Transaction, TransactionArgs};
 use crate::tree_keys::Key;
 use crate::{accounts::AccountNameDepth, formatters::Formatter};
-use anyhow::Result;
-use chrono::Local;
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
 use itertools::min;
 use rust_decimal::Decimal;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufWriter, Write};
 use std::path::Path;
+use std::str::FromStr;
 
 #[derive(Default)]
 pub enum AssertionMode {
@@ -35,6 +45,19 @@ pub struct Hledger {
 
 impl Hledger {}
 
+/// The transactions to export, with the date they apply to.
+/// Future/scheduled transactions are skipped: they break assertions in ledger
+/// and beancount (though hledger is happy with them).
+pub(crate) fn exported_transactions(
+    repo: &Repository,
+    now: DateTime<Local>,
+) -> impl Iterator<Item = (DateTime<Local>, &Transaction)> {
+    repo.transactions.iter().filter_map(move |tx| {
+        let ts = min(tx.splits().iter().map(|s| s.post_ts))?;
+        (ts <= now).then_some((ts, tx))
+    })
+}
+
 impl Exporter for Hledger {
     fn export_file(
         &mut self,
@@ -49,6 +72,11 @@ impl Exporter for Hledger {
         for com in repo.commodities.iter_commodities() {
             buf.write_all(b"commodity ")?;
             buf.write_all(format.display_symbol(com).as_bytes())?;
+            buf.write_all(if com.is_currency() {
+                b"  ; alere: currency"
+            } else {
+                b"  ; alere: security"
+            })?;
             buf.write_all(b"\n   format ")?;
             let v = Value {
                 commodity: com.clone(),
@@ -58,23 +86,48 @@ impl Exporter for Hledger {
             buf.write_all(b"\n")?;
         }
 
-        for tx in repo.transactions.iter() {
-            let ts = min(tx.splits().iter().map(|s| s.post_ts)).unwrap();
-
-            //  Do not output future/scheduled transactions.  This breaks
-            //  assertions in ledger (though hledger is happy with them)
-            if ts > now {
-                continue;
-            }
+        // Declare accounts, with a hint for the importer so that we can
+        // restore the account kinds.
+        for acc in repo.accounts.iter() {
+            buf.write_all(b"account ")?;
+            buf.write_all(acc.name(AccountNameDepth::unlimited()).as_bytes())?;
+            buf.write_all(b"  ; alere: ")?;
+            buf.write_all(acc.get_kind().get_name().as_bytes())?;
+            buf.write_all(b"\n")?;
+        }
+        buf.write_all(b"\n")?;
 
+        for (ts, tx) in exported_transactions(repo, now) {
             buf.write_all(ts.date_naive().to_string().as_bytes())?;
 
             // ??? Should check if any split is reconciled
-            buf.write_all(b" * ")?;
+            buf.write_all(b" *")?;
+
+            if let Some(num) = tx.check_number().as_ref() {
+                buf.write_all(b" (")?;
+                buf.write_all(num.as_bytes())?;
+                buf.write_all(b")")?;
+            }
 
-            //   if let Some(m) = tx.memo() {
-            //       buf.write_all(m.as_bytes())?;
-            //   }
+            // hledger splits the description into "payee | note"
+            let payee = tx.payee();
+            let memo = tx.memo().as_ref().map(|m| m.replace('\n', " "));
+            match (&payee, &memo) {
+                (None, None) => {}
+                (Some(p), None) => {
+                    buf.write_all(b" ")?;
+                    buf.write_all(p.get_name().as_bytes())?;
+                }
+                (p, Some(m)) => {
+                    buf.write_all(b" ")?;
+                    if let Some(p) = p {
+                        buf.write_all(p.get_name().as_bytes())?;
+                        buf.write_all(b" ")?;
+                    }
+                    buf.write_all(b"| ")?;
+                    buf.write_all(m.as_bytes())?;
+                }
+            }
             buf.write_all(b"\n")?;
 
             for split in tx.splits().iter() {
@@ -108,10 +161,10 @@ impl Exporter for Hledger {
                         buf.write_all(shares.display(format).as_bytes())?;
                         buf.write_all(b" @@ ")?;
                         buf.write_all(amount.display(format).as_bytes())?;
-                        buf.write_all(b"  ; reinvest")?;
+                        buf.write_all(b"  ; alere: reinvest")?;
                     }
                     Operation::Dividend => {
-                        buf.write_all(b" ; dividend")?;
+                        buf.write_all(b" ; alere: dividend")?;
                     }
                     Operation::Split { .. } => {
                         // For now, sell every shares, then buy them back at
@@ -131,7 +184,7 @@ impl Exporter for Hledger {
                         });
 
                         buf.write_all((-&total).display(format).as_bytes())?;
-                        buf.write_all(b"  @ 0 ;  split\n   ")?;
+                        buf.write_all(b"  @ 0 ; alere: split\n   ")?;
                         buf.write_all(
                             split
                                 .account
@@ -255,3 +308,1192 @@ impl Exporter for Hledger {
         Ok(())
     }
 }
+
+//--------------------------------------------------------------------------
+// Importer
+//--------------------------------------------------------------------------
+
+/// An amount as read from the journal, before commodities are created
+#[derive(Clone, Debug)]
+struct RawAmount {
+    quantity: Decimal,
+    commodity: String,
+    symbol_after: bool,
+}
+
+#[derive(Debug)]
+enum RawCost {
+    Unit(RawAmount),  // @
+    Total(RawAmount), // @@
+}
+
+#[derive(Debug)]
+struct RawPosting {
+    account: String,
+    amount: Option<RawAmount>,
+    cost: Option<RawCost>,
+    assertion: Option<RawAmount>,
+    cleared: bool,
+    comment: String,
+}
+
+#[derive(Debug)]
+struct RawTransaction {
+    line: usize,
+    date: NaiveDate,
+    cleared: bool,
+    code: Option<String>,
+    description: String,
+    postings: Vec<RawPosting>,
+}
+
+#[derive(Default)]
+struct RawCommodity {
+    symbol_after: Option<bool>,
+    precision: Option<u8>,
+    is_currency: Option<bool>, // from the "alere:" hint
+}
+
+/// The result of parsing a journal.  Nothing has been created in the
+/// repository yet, since we need to see the whole journal to know, for
+/// instance, which commodities are currencies.
+#[derive(Default)]
+struct Journal {
+    accounts: Vec<String>, // in order of declaration
+    account_hints: HashMap<String, String>, // "alere:" or "type:" hint
+    commodities: Vec<String>, // in order of occurrence
+    commodity_decls: HashMap<String, RawCommodity>,
+    prices: Vec<(NaiveDate, String, RawAmount)>, // P directives
+    transactions: Vec<RawTransaction>,
+}
+
+enum ParseState {
+    TopLevel,
+    Transaction,
+    Commodity(String),
+    Account(String),
+    Skip, // periodic or auto transactions, unknown directives
+    BlockComment,
+}
+
+fn parse_error(line: usize, msg: &str) -> AlrError {
+    AlrError::ParseError(format!("line {}: {}", line, msg))
+}
+
+/// Split text on the first ';' that is not in a quoted commodity name.
+/// Returns the text and the comment.
+fn split_comment(text: &str) -> (&str, &str) {
+    let mut in_quote = false;
+    for (idx, c) in text.char_indices() {
+        match c {
+            '"' => in_quote = !in_quote,
+            ';' if !in_quote => {
+                let (before, after) = text.split_at(idx);
+                return (
+                    before,
+                    after.strip_prefix(';').unwrap_or_default().trim(),
+                );
+            }
+            _ => {}
+        }
+    }
+    (text, "")
+}
+
+/// Return the value of an "alere:" hint in a comment, if any
+fn alere_hint(comment: &str) -> Option<&str> {
+    comment
+        .split(',')
+        .find_map(|tag| tag.trim().strip_prefix("alere:"))
+        .map(str::trim)
+}
+
+/// Parse a date, in one of the formats accepted by hledger
+fn parse_date(text: &str, line: usize) -> Result<NaiveDate> {
+    let normalized = text.replace(['/', '.'], "-");
+    Ok(NaiveDate::parse_from_str(&normalized, "%Y-%m-%d")
+        .map_err(|_| parse_error(line, &format!("invalid date {text}")))?)
+}
+
+/// Parse an amount like "-1,234.56 EUR", "$-12", "-$12", or
+/// "12 \"US Dollar\"".  The commodity is empty for bare numbers.
+fn parse_amount(text: &str, line: usize) -> Result<RawAmount> {
+    fn take_commodity(text: &str) -> (&str, &str) {
+        if let Some(rest) = text.strip_prefix('"') {
+            rest.split_once('"').unwrap_or((rest, ""))
+        } else {
+            let end = text
+                .find(|c: char| {
+                    c.is_ascii_digit()
+                        || c.is_whitespace()
+                        || c == '-'
+                        || c == '+'
+                        || c == '.'
+                        || c == ','
+                })
+                .unwrap_or(text.len());
+            text.split_at(end)
+        }
+    }
+
+    fn take_sign(text: &str) -> (bool, &str) {
+        let text = text.trim_start();
+        if let Some(rest) = text.strip_prefix('-') {
+            (true, rest.trim_start())
+        } else if let Some(rest) = text.strip_prefix('+') {
+            (false, rest.trim_start())
+        } else {
+            (false, text)
+        }
+    }
+
+    let (mut negative, rest) = take_sign(text.trim());
+    let starts_with_number =
+        rest.starts_with(|c: char| c.is_ascii_digit() || c == '.');
+
+    let (commodity, num, symbol_after) = if starts_with_number {
+        let end = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(rest.len());
+        let (num, after) = rest.split_at(end);
+        let (com, _) = take_commodity(after.trim());
+        (com, num, true)
+    } else {
+        let (com, after) = take_commodity(rest);
+        let (neg, after) = take_sign(after);
+        negative = negative || neg;
+        (com, after.trim(), false)
+    };
+
+    if num.is_empty() {
+        Err(parse_error(
+            line,
+            &format!("missing number in amount {text}"),
+        ))?;
+    }
+    let mut quantity = Decimal::from_str(&num.replace(',', ""))
+        .map_err(|_| parse_error(line, &format!("invalid amount {text}")))?;
+    if negative {
+        quantity = -quantity;
+    }
+    Ok(RawAmount {
+        quantity,
+        commodity: commodity.trim().to_string(),
+        symbol_after,
+    })
+}
+
+impl Journal {
+    fn parse(content: &str) -> Result<Journal> {
+        let mut journal = Journal::default();
+        let mut state = ParseState::TopLevel;
+
+        for (idx, full_line) in content.lines().enumerate() {
+            let line = idx + 1;
+
+            if let ParseState::BlockComment = state {
+                if full_line.trim() == "end comment" {
+                    state = ParseState::TopLevel;
+                }
+                continue;
+            }
+
+            if full_line.trim().is_empty() {
+                state = ParseState::TopLevel;
+                continue;
+            }
+
+            // Indented lines belong to the previous transaction or directive
+            if full_line.starts_with([' ', '\t']) {
+                let text = full_line.trim();
+                match &state {
+                    ParseState::Transaction => {
+                        journal.parse_posting_line(text, line)?;
+                    }
+                    ParseState::Commodity(name) => {
+                        if let Some(fmt) = text.strip_prefix("format") {
+                            let (fmt, _) = split_comment(fmt);
+                            let amount = parse_amount(fmt, line)?;
+                            journal.declare_commodity(name, &amount);
+                        }
+                    }
+                    ParseState::Account(name) => {
+                        let (_, comment) = split_comment(text);
+                        journal.add_account_hint(name, comment);
+                    }
+                    ParseState::TopLevel
+                    | ParseState::Skip
+                    | ParseState::BlockComment => {}
+                }
+                continue;
+            }
+
+            state = ParseState::TopLevel;
+            let (text, comment) = split_comment(full_line);
+            let text = text.trim_end();
+
+            if full_line.starts_with(['#', '*', '%', '|']) || text.is_empty() {
+                continue;
+            }
+            if full_line.starts_with(|c: char| c.is_ascii_digit()) {
+                journal.parse_transaction_header(text, line)?;
+                state = ParseState::Transaction;
+                continue;
+            }
+
+            let (directive, args) = match text.split_once([' ', '\t']) {
+                Some((d, a)) => (d, a.trim()),
+                None => (text, ""),
+            };
+            match directive {
+                "comment" => {
+                    state = ParseState::BlockComment;
+                }
+                "account" => {
+                    let name = args.to_string();
+                    journal.add_account_hint(&name, comment);
+                    if !journal.accounts.contains(&name) {
+                        journal.accounts.push(name.clone());
+                    }
+                    state = ParseState::Account(name);
+                }
+                "commodity" => {
+                    let (is_amount, name) = match parse_amount(args, line) {
+                        Ok(amount) => {
+                            let name = amount.commodity.clone();
+                            journal.declare_commodity(&name, &amount);
+                            (true, name)
+                        }
+                        Err(_) => (false, args.trim_matches('"').to_string()),
+                    };
+                    if !is_amount {
+                        journal.register_commodity(&name);
+                    }
+                    let decl = journal
+                        .commodity_decls
+                        .entry(name.clone())
+                        .or_default();
+                    match alere_hint(comment) {
+                        Some("currency") => decl.is_currency = Some(true),
+                        Some("security") => decl.is_currency = Some(false),
+                        _ => {}
+                    }
+                    state = ParseState::Commodity(name);
+                }
+                "P" => {
+                    let mut parts = args.splitn(2, [' ', '\t']);
+                    let date = parse_date(parts.next().unwrap_or(""), line)?;
+                    let rest = parts.next().unwrap_or("").trim();
+
+                    // An optional time might follow the date
+                    let rest = match rest.split_once(' ') {
+                        Some((t, r)) if t.contains(':') => r.trim(),
+                        _ => rest,
+                    };
+                    let (name, price) = if let Some(r) = rest.strip_prefix('"')
+                    {
+                        r.split_once('"').ok_or_else(|| {
+                            parse_error(line, "unterminated commodity name")
+                        })?
+                    } else {
+                        rest.split_once([' ', '\t']).ok_or_else(|| {
+                            parse_error(line, "invalid price directive")
+                        })?
+                    };
+                    let price = parse_amount(price, line)?;
+                    journal.register_commodity(name);
+                    journal.register_commodity(&price.commodity);
+                    journal.prices.push((date, name.to_string(), price));
+                }
+                "include" => {
+                    Err(parse_error(line, "include directive not supported"))?;
+                }
+                _ => {
+                    // Periodic and auto transactions, and directives we do
+                    // not need, like "D", "alias", "payee", "tag", "year",...
+                    state = ParseState::Skip;
+                }
+            }
+        }
+        Ok(journal)
+    }
+
+    fn register_commodity(&mut self, name: &str) {
+        if !self.commodities.iter().any(|c| c == name) {
+            self.commodities.push(name.to_string());
+        }
+    }
+
+    fn declare_commodity(&mut self, name: &str, format: &RawAmount) {
+        self.register_commodity(name);
+        let decl = self.commodity_decls.entry(name.to_string()).or_default();
+        decl.symbol_after = Some(format.symbol_after);
+        decl.precision = Some(format.quantity.scale() as u8);
+    }
+
+    fn add_account_hint(&mut self, name: &str, comment: &str) {
+        if let Some(kind) = alere_hint(comment) {
+            self.account_hints
+                .insert(name.to_string(), kind.to_string());
+        } else if let Some(t) = comment
+            .split(',')
+            .find_map(|tag| tag.trim().strip_prefix("type:"))
+        {
+            self.account_hints
+                .entry(name.to_string())
+                .or_insert_with(|| format!("type:{}", t.trim()));
+        }
+    }
+
+    fn parse_transaction_header(
+        &mut self,
+        text: &str,
+        line: usize,
+    ) -> Result<()> {
+        let (date, mut rest) = match text.split_once([' ', '\t']) {
+            Some((d, r)) => (d, r.trim()),
+            None => (text, ""),
+        };
+
+        // Ignore secondary dates
+        let date = parse_date(date.split('=').next().unwrap_or(date), line)?;
+
+        let mut cleared = false;
+        if let Some(r) = rest.strip_prefix('*') {
+            cleared = true;
+            rest = r.trim_start();
+        } else if let Some(r) = rest.strip_prefix('!') {
+            rest = r.trim_start();
+        }
+
+        let mut code = None;
+        if let Some(r) = rest.strip_prefix('(')
+            && let Some((c, r)) = r.split_once(')')
+        {
+            code = Some(c.to_string());
+            rest = r.trim_start();
+        }
+
+        self.transactions.push(RawTransaction {
+            line,
+            date,
+            cleared,
+            code,
+            description: rest.to_string(),
+            postings: Vec::new(),
+        });
+        Ok(())
+    }
+
+    fn parse_posting_line(&mut self, text: &str, line: usize) -> Result<()> {
+        let (text, comment) = split_comment(text);
+        let Some(tx) = self.transactions.last_mut() else {
+            return Ok(());
+        };
+
+        if text.trim().is_empty() {
+            // A comment line, which applies to the previous posting if any.
+            if let Some(p) = tx.postings.last_mut() {
+                if !p.comment.is_empty() {
+                    p.comment.push(' ');
+                }
+                p.comment.push_str(comment);
+            }
+            return Ok(());
+        }
+
+        let mut text = text.trim();
+        let mut cleared = false;
+        if let Some(r) = text.strip_prefix('*') {
+            cleared = true;
+            text = r.trim_start();
+        } else if let Some(r) = text.strip_prefix('!') {
+            text = r.trim_start();
+        }
+
+        // The account name ends at two spaces or a tab
+        let (account, amounts) = match (text.find("  "), text.find('\t')) {
+            (Some(a), Some(b)) => text.split_at(a.min(b)),
+            (Some(a), None) | (None, Some(a)) => text.split_at(a),
+            (None, None) => (text, ""),
+        };
+
+        // Unbalanced virtual postings do not participate in the transaction
+        if account.starts_with('(') {
+            return Ok(());
+        }
+        let account = account.trim_matches(['[', ']']).to_string();
+
+        let (amounts, assertion) = match amounts.split_once('=') {
+            Some((a, b)) => (
+                a,
+                Some(parse_amount(b.trim_start_matches(['=', '*']), line)?),
+            ),
+            None => (amounts, None),
+        };
+
+        let (amount, cost) = if let Some((a, c)) = amounts.split_once("@@") {
+            (a, Some(RawCost::Total(parse_amount(c, line)?)))
+        } else if let Some((a, c)) = amounts.split_once('@') {
+            (a, Some(RawCost::Unit(parse_amount(c, line)?)))
+        } else {
+            (amounts, None)
+        };
+        let amount = if amount.trim().is_empty() {
+            None
+        } else {
+            Some(parse_amount(amount, line)?)
+        };
+
+        tx.postings.push(RawPosting {
+            account,
+            amount,
+            cost,
+            assertion,
+            cleared,
+            comment: comment.to_string(),
+        });
+
+        if let Some(tx) = self.transactions.last() {
+            let p = tx.postings.last().expect("posting was just added");
+            let mut names = Vec::new();
+            for a in [&p.amount, &p.assertion].into_iter().flatten() {
+                names.push(a.commodity.clone());
+            }
+            match &p.cost {
+                Some(RawCost::Unit(a) | RawCost::Total(a)) => {
+                    names.push(a.commodity.clone());
+                }
+                None => {}
+            }
+            for name in names {
+                self.register_commodity(&name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a commodity should be considered as a currency.  Unless we
+    /// have an explicit hint, this is the case for symbols like "$", for
+    /// three-letter ISO codes like "EUR", if it is used to express the price
+    /// of other commodities, or if it is never priced itself.
+    fn is_currency(&self, name: &str) -> bool {
+        if let Some(c) = self
+            .commodity_decls
+            .get(name)
+            .and_then(|decl| decl.is_currency)
+        {
+            return c;
+        }
+        if name.is_empty()
+            || !name.chars().all(char::is_alphanumeric)
+            || (name.len() == 3 && name.chars().all(|c| c.is_ascii_uppercase()))
+        {
+            return true;
+        }
+
+        let mut priced = false;
+        for (_, from, price) in &self.prices {
+            if price.commodity == name {
+                return true;
+            }
+            priced = priced || from == name;
+        }
+        for tx in &self.transactions {
+            for p in &tx.postings {
+                if let Some(RawCost::Unit(c) | RawCost::Total(c)) = &p.cost {
+                    if c.commodity == name {
+                        return true;
+                    }
+                    priced = priced
+                        || p.amount
+                            .as_ref()
+                            .is_some_and(|a| a.commodity == name);
+                }
+            }
+        }
+        !priced
+    }
+
+    /// Whether all postings to the account are for securities
+    fn only_securities(
+        &self,
+        account: &str,
+        currencies: &HashSet<String>,
+    ) -> bool {
+        let mut found = false;
+        for tx in &self.transactions {
+            for p in &tx.postings {
+                if p.account == account
+                    && let Some(a) = &p.amount
+                {
+                    if currencies.contains(&a.commodity) {
+                        return false;
+                    }
+                    found = true;
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Create the objects from a parsed journal
+#[derive(Default)]
+struct JournalLoader {
+    commodities: HashMap<String, Commodity>,
+    currencies: HashSet<String>,
+    accounts: HashMap<String, Account>,
+    payees: HashMap<String, Payee>,
+}
+
+impl JournalLoader {
+    fn commodity(&self, name: &str) -> Result<Commodity> {
+        Ok(self
+            .commodities
+            .get(name)
+            .ok_or_else(|| AlrError::Str(format!("Unknown commodity {name}")))?
+            .clone())
+    }
+
+    fn value(&self, amount: &RawAmount) -> Result<Value> {
+        Ok(Value {
+            amount: amount.quantity,
+            commodity: self.commodity(&amount.commodity)?,
+        })
+    }
+
+    /// A value, for which we might not know the commodity if it is zero
+    /// (zero amounts are exported without a commodity).
+    fn multi_value(&self, amount: &RawAmount) -> Result<MultiValue> {
+        if amount.quantity.is_zero() {
+            Ok(MultiValue::zero())
+        } else {
+            Ok(MultiValue::new(
+                amount.quantity,
+                &self.commodity(&amount.commodity)?,
+            ))
+        }
+    }
+
+    /// Find the kind for an account, from hints in the journal or from the
+    /// name of its toplevel parent.
+    fn account_kind(
+        &self,
+        repo: &Repository,
+        journal: &Journal,
+        name: &str,
+    ) -> Result<AccountKind> {
+        let kind_name =
+            match journal.account_hints.get(name).map(String::as_str) {
+                Some(hint) => match hint.strip_prefix("type:") {
+                    None => hint,
+                    Some("A" | "Asset") => "Asset",
+                    Some("C" | "Cash") => "Checking",
+                    Some("L" | "Liability") => "Liability",
+                    Some("E" | "Equity" | "V" | "Conversion") => "Equity",
+                    Some("R" | "Revenue") => "Income",
+                    Some("X" | "Expense") => "Expense",
+                    Some(t) => Err(AlrError::Str(format!(
+                        "Unknown account type {t} for {name}"
+                    )))?,
+                },
+                None => {
+                    let root =
+                        name.split(':').next().unwrap_or(name).to_lowercase();
+                    if root.starts_with("liabilit") {
+                        "Liability"
+                    } else if root.starts_with("equity") {
+                        "Equity"
+                    } else if root.starts_with("income")
+                        || root.starts_with("revenue")
+                    {
+                        "Income"
+                    } else if root.starts_with("expense") {
+                        "Expense"
+                    } else if journal.only_securities(name, &self.currencies) {
+                        "Stock"
+                    } else {
+                        "Asset"
+                    }
+                }
+            };
+        Ok(repo
+            .account_kinds
+            .lookup(kind_name)
+            .ok_or_else(|| {
+                AlrError::Str(format!("Unknown account kind {kind_name}"))
+            })?
+            .clone())
+    }
+
+    /// Create the account and all its parents
+    fn account(
+        &mut self,
+        repo: &mut Repository,
+        journal: &Journal,
+        name: &str,
+    ) -> Result<Account> {
+        if let Some(acc) = self.accounts.get(name) {
+            return Ok(acc.clone());
+        }
+        let (parent, basename) = match name.rsplit_once(':') {
+            Some((p, b)) => (Some(self.account(repo, journal, p)?), b),
+            None => (None, name),
+        };
+        let kind = self.account_kind(repo, journal, name)?;
+        let acc = repo
+            .accounts
+            .add(basename, kind, parent, None, None, None, None, false, None);
+        self.accounts.insert(name.to_string(), acc.clone());
+        Ok(acc)
+    }
+
+    fn load(&mut self, journal: &Journal) -> Result<Repository> {
+        let mut repo = Repository::default();
+
+        for name in &journal.commodities {
+            if journal.is_currency(name) {
+                self.currencies.insert(name.clone());
+            }
+        }
+        for name in &journal.commodities {
+            let decl = journal.commodity_decls.get(name);
+            let mut symbol_after = decl.and_then(|d| d.symbol_after);
+            let mut precision = decl.and_then(|d| d.precision);
+            if symbol_after.is_none() || precision.is_none() {
+                // Guess from the amounts in transactions
+                let mut scale = 0;
+                for tx in &journal.transactions {
+                    for a in
+                        tx.postings.iter().filter_map(|p| p.amount.as_ref())
+                    {
+                        if a.commodity == *name {
+                            symbol_after.get_or_insert(a.symbol_after);
+                            scale = scale.max(a.quantity.scale() as u8);
+                        }
+                    }
+                }
+                precision.get_or_insert(scale);
+            }
+            let c = repo.commodities.add(
+                name,
+                name,
+                symbol_after.unwrap_or(true),
+                self.currencies.contains(name),
+                None,
+                precision.unwrap_or(2),
+            );
+            self.commodities.insert(name.clone(), c);
+        }
+
+        for name in &journal.accounts {
+            self.account(&mut repo, journal, name)?;
+        }
+
+        if !journal.prices.is_empty() {
+            let source = repo.get_or_add_price_source("hledger");
+            for (date, from, price) in &journal.prices {
+                repo.add_price(
+                    &self.commodity(from)?,
+                    &self.commodity(&price.commodity)?,
+                    Price::new(
+                        to_timestamp(*date)?,
+                        price.quantity,
+                        PriceSourceFrom::External(source.get_id()),
+                    ),
+                );
+            }
+        }
+
+        for raw in &journal.transactions {
+            self.load_transaction(&mut repo, journal, raw)
+                .with_context(|| format!("line {}", raw.line))?;
+        }
+        Ok(repo)
+    }
+
+    fn load_transaction(
+        &mut self,
+        repo: &mut Repository,
+        journal: &Journal,
+        raw: &RawTransaction,
+    ) -> Result<()> {
+        let timestamp = to_timestamp(raw.date)?;
+
+        // hledger splits the description as "payee | note"
+        let (payee, memo) = match raw.description.split_once('|') {
+            Some((p, m)) => (p.trim(), Some(m.trim())),
+            None => (raw.description.trim(), None),
+        };
+        let payee = if payee.is_empty() {
+            None
+        } else {
+            Some(
+                self.payees
+                    .entry(payee.to_string())
+                    .or_insert_with(|| repo.payees.add(payee))
+                    .clone(),
+            )
+        };
+
+        let mut splits = Vec::new();
+        let mut elided: Option<(Account, ReconcileKind)> = None;
+        let mut postings = raw.postings.iter().peekable();
+
+        while let Some(p) = postings.next() {
+            let mut account = self.account(repo, journal, &p.account)?;
+            let reconciled = if p.cleared || raw.cleared {
+                ReconcileKind::Cleared
+            } else {
+                ReconcileKind::New
+            };
+
+            if let Some(a) = &p.assertion {
+                account.add_reconciliation(Reconciliation {
+                    timestamp,
+                    total: self.multi_value(a)?,
+                });
+            }
+
+            let op = match (&p.amount, &p.cost) {
+                (None, _) if alere_hint(&p.comment) == Some("dividend") => {
+                    Operation::Dividend
+                }
+                (None, _) => {
+                    if p.assertion.is_some() {
+                        continue;
+                    }
+                    if elided.is_some() {
+                        Err(AlrError::Str(
+                            "Only one posting can have no amount".into(),
+                        ))?;
+                    }
+                    elided = Some((account, reconciled));
+                    continue;
+                }
+                (Some(a), None) => {
+                    if a.quantity.is_zero() && p.assertion.is_some() {
+                        continue;
+                    }
+                    Operation::Credit(self.multi_value(a)?)
+                }
+                (Some(a), Some(RawCost::Unit(price)))
+                    if price.quantity.is_zero()
+                        && alere_hint(&p.comment) == Some("split") =>
+                {
+                    // Stock splits are exported as selling all shares at a
+                    // zero price, then adding the new number of shares.
+                    let next = postings.next_if(|n| n.account == p.account);
+                    let new_qty = next
+                        .and_then(|n| n.amount.as_ref())
+                        .map(|n| n.quantity)
+                        .unwrap_or_default();
+                    if a.quantity.is_zero() {
+                        continue;
+                    }
+                    Operation::Split {
+                        ratio: new_qty / -a.quantity,
+                        commodity: self.commodity(&a.commodity)?,
+                    }
+                }
+                (Some(a), Some(RawCost::Unit(price))) => {
+                    let qty = self.value(a)?;
+                    let price = if price.commodity.is_empty() {
+                        // A zero price exported without a commodity
+                        Value::zero(&qty.commodity)
+                    } else {
+                        self.value(price)?
+                    };
+                    Operation::BuyPrice { qty, price }
+                }
+                (Some(a), Some(RawCost::Total(total))) => {
+                    let qty = self.value(a)?;
+                    let mut amount = self.value(total)?;
+                    amount.amount = amount.amount.abs();
+                    if qty.is_negative() {
+                        amount.amount = -amount.amount;
+                    }
+                    if alere_hint(&p.comment) == Some("reinvest") {
+                        Operation::Reinvest {
+                            shares: MultiValue::new(qty.amount, &qty.commodity),
+                            amount: MultiValue::new(
+                                amount.amount,
+                                &amount.commodity,
+                            ),
+                        }
+                    } else if amount.amount.is_zero() {
+                        Operation::BuyPrice {
+                            price: Value::zero(&amount.commodity),
+                            qty,
+                        }
+                    } else {
+                        Operation::BuyAmount { qty, amount }
+                    }
+                }
+            };
+            splits.push((account, reconciled, op));
+        }
+
+        match elided {
+            Some((account, reconciled)) => {
+                let total = balance(&splits);
+                splits.push((account, reconciled, Operation::Credit(-&total)));
+            }
+            None => infer_conversion(&mut splits)?,
+        }
+
+        if splits.is_empty() {
+            return Ok(());
+        }
+
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo,
+            check_number: raw.code.as_deref(),
+            payee,
+            entry_date: timestamp,
+        });
+        for (account, reconciled, op) in splits {
+            tx.add_split(account, reconciled, timestamp, op);
+        }
+        repo.add_transaction(tx)
+    }
+}
+
+fn to_timestamp(date: NaiveDate) -> Result<DateTime<Local>> {
+    Ok(date
+        .and_hms_opt(0, 0, 0)
+        .and_then(|d| d.and_local_timezone(Local).earliest())
+        .ok_or_else(|| AlrError::Str(format!("Invalid date {date}")))?)
+}
+
+/// The sum of all splits, as used to check whether a transaction is balanced.
+fn balance(splits: &[(Account, ReconcileKind, Operation)]) -> MultiValue {
+    let mut total = MultiValue::zero();
+    for (_, _, op) in splits {
+        match op {
+            Operation::Credit(value) => total += value,
+            Operation::AddShares { qty } => total += qty,
+            Operation::BuyAmount { amount, .. } => total += amount,
+            Operation::BuyPrice { qty, price } => {
+                total += &Value {
+                    amount: qty.amount * price.amount,
+                    commodity: price.commodity.clone(),
+                };
+            }
+            Operation::Reinvest { amount, .. } => total += amount,
+            Operation::Split { .. } | Operation::Dividend => {}
+        }
+    }
+    total
+}
+
+/// hledger lets users omit the cost when a transaction involves exactly two
+/// commodities, and infers the conversion rate.  We convert the only split in
+/// one of the two commodities into a BuyAmount.
+fn infer_conversion(
+    splits: &mut [(Account, ReconcileKind, Operation)],
+) -> Result<()> {
+    let total = balance(splits);
+    let commodities = total.iter().collect::<Vec<_>>();
+    let [first, second] = commodities.as_slice() else {
+        return Ok(()); // Will report an unbalanced transaction
+    };
+
+    // The first posting (in the order of the journal) which is the only one
+    // in its commodity holds the conversion.
+    let is_only_credit = |this: &Value| {
+        splits
+            .iter()
+            .filter(|(_, _, op)| {
+                matches!(op, Operation::Credit(v)
+                         if v.commodity().as_ref() == Some(&this.commodity))
+            })
+            .count()
+            == 1
+    };
+    let pairs = [(first, second), (second, first)]
+        .into_iter()
+        .filter(|(this, _)| is_only_credit(this))
+        .collect::<Vec<_>>();
+
+    for split in splits.iter_mut() {
+        let Operation::Credit(v) = &split.2 else {
+            continue;
+        };
+        let Some((this, other)) = pairs.iter().find(|(this, _)| {
+            v.commodity().as_ref() == Some(&this.commodity)
+                && v.iter().all(|v| v.amount == this.amount)
+        }) else {
+            continue;
+        };
+        split.2 = Operation::BuyAmount {
+            qty: (*this).clone(),
+            amount: Value {
+                amount: -other.amount,
+                commodity: other.commodity.clone(),
+            },
+        };
+        return Ok(());
+    }
+    Ok(())
+}
+
+impl Importer for Hledger {
+    async fn import_file(
+        &mut self,
+        path: &Path,
+        report_progress: impl Fn(u64, u64),
+    ) -> Result<Repository> {
+        const MAX_PROGRESS: u64 = 3;
+
+        report_progress(1, MAX_PROGRESS);
+        let content = std::fs::read_to_string(path)?;
+        let journal = Journal::parse(&content)?;
+        report_progress(2, MAX_PROGRESS);
+        let repo = JournalLoader::default().load(&journal)?;
+        report_progress(3, MAX_PROGRESS);
+        Ok(repo)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::AccountNameDepth,
+        alere_file::test::build_repo,
+        formatters::{Formatter, SymbolQuote, Zero},
+        hledger::{AssertionMode, Hledger, Journal, JournalLoader},
+        importers::{Exporter, Importer},
+        repositories::Repository,
+    };
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    fn describe(repo: &Repository) -> Vec<String> {
+        let format = Formatter::default();
+        let mut result = Vec::new();
+        for acc in repo.accounts.iter() {
+            result.push(format!(
+                "{} kind={}",
+                acc.name(AccountNameDepth::unlimited()),
+                acc.get_kind().get_name(),
+            ));
+            for r in acc.iter_reconciliations() {
+                result.push(format!(
+                    "  rec {} {}",
+                    r.timestamp,
+                    r.total.display(&format)
+                ));
+            }
+        }
+        for tx in repo.transactions.iter() {
+            result.push(format!(
+                "{:?} {:?} {:?} {}",
+                tx.memo(),
+                tx.check_number(),
+                tx.payee().map(|p| p.get_name().clone()),
+                tx.display(&format),
+            ));
+        }
+        result
+    }
+
+    #[test]
+    fn test_round_trip() -> Result<()> {
+        let repo = build_repo()?;
+        let path = std::env::temp_dir()
+            .join(format!("alere_hledger_{}.journal", std::process::id()));
+        let format = Formatter {
+            quote_symbol: SymbolQuote::QuotedNameIfSpecial,
+            zero: Zero::Replace("0"),
+            ..Formatter::default()
+        };
+        let mut hledger = Hledger {
+            export_reconciliation: true,
+            assertions: AssertionMode::None,
+        };
+        hledger.export_file(&repo, &path, &format)?;
+        let loaded =
+            futures::executor::block_on(hledger.import_file(&path, |_, _| {}));
+        std::fs::remove_file(&path)?;
+        let loaded = loaded?;
+
+        assert_eq!(describe(&repo), describe(&loaded));
+
+        let aapl = loaded.commodities.find("AAPL").unwrap();
+        let eur = loaded.commodities.find("EUR").unwrap();
+        assert!(eur.is_currency());
+        assert!(!aapl.is_currency());
+        assert!(loaded.prices.iter().any(|((from, to), prices)| {
+            *from == aapl
+                && *to == eur
+                && prices.iter().any(|p| p.price == dec!(85.5))
+        }));
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_journal() -> Result<()> {
+        let journal = Journal::parse(
+            r#"
+; A comment
+# Another comment
+account Assets:Bank   ; type: C
+account Assets:Broker:ACME
+
+commodity $1,000.00
+commodity ACME
+   format 1.000 ACME
+
+comment
+2024-01-01 ignored
+end comment
+
+P 2024-01-05 ACME $12.50
+P 2024-01-06 EUR 1.10 $
+
+2024-01-01 * (101) Employer | Salary
+    Assets:Bank          $2,500.00
+    Income:Salary
+
+2024/01/10 Broker
+    Assets:Broker:ACME    10.000 ACME @ $12
+    Assets:Bank          $-125.00   ; fees included
+    Expenses:Fees         $5
+
+2024-01-12 Travel
+    Expenses:Travel       100 EUR
+    Assets:Bank          $-110.00
+
+2024-01-31 Statement
+    Assets:Bank           0 = $2,265.00
+
+~ monthly
+    Expenses:Rent   $1000
+    Assets:Bank
+"#,
+        )?;
+        let repo = JournalLoader::default().load(&journal)?;
+        let format = Formatter::default();
+
+        let txs = repo
+            .transactions
+            .iter()
+            .map(|tx| {
+                format!(
+                    "{:?} {:?} {:?} {}",
+                    tx.memo(),
+                    tx.check_number(),
+                    tx.payee().map(|p| p.get_name().clone()),
+                    tx.display(&format),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            txs,
+            vec![
+                "Some(\"Salary\") Some(\"101\") Some(\"Employer\") \
+                 [Credit(2024-01-01 00:00:00 +01:00 Assets:Bank $ 2,500.00), \
+                 Credit(2024-01-01 00:00:00 +01:00 Income:Salary $ -2,500.00)]",
+                "None None Some(\"Broker\") \
+                 [BuyPrice(2024-01-10 00:00:00 +01:00 Assets:Broker:ACME \
+                 10.000 ACME@$ 12.00), \
+                 Credit(2024-01-10 00:00:00 +01:00 Assets:Bank $ -125.00), \
+                 Credit(2024-01-10 00:00:00 +01:00 Expenses:Fees $ 5.00)]",
+                "None None Some(\"Travel\") \
+                 [BuyAmount(2024-01-12 00:00:00 +01:00 Expenses:Travel \
+                 100 EUR for $ 110.00), \
+                 Credit(2024-01-12 00:00:00 +01:00 Assets:Bank $ -110.00)]",
+            ]
+        );
+
+        let kinds = repo
+            .accounts
+            .iter()
+            .map(|a| {
+                format!(
+                    "{}={}",
+                    a.name(AccountNameDepth::unlimited()),
+                    a.get_kind().get_name()
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![
+                "Assets=Asset",
+                "Assets:Bank=Checking",
+                "Assets:Broker=Asset",
+                "Assets:Broker:ACME=Stock",
+                "Income=Income",
+                "Income:Salary=Income",
+                "Expenses=Expense",
+                "Expenses:Fees=Expense",
+                "Expenses:Travel=Expense",
+            ]
+        );
+
+        let bank = repo
+            .accounts
+            .iter()
+            .find(|a| a.name(AccountNameDepth::basename()) == "Bank")
+            .unwrap();
+        let recs = bank
+            .iter_reconciliations()
+            .map(|r| r.total.display(&format))
+            .collect::<Vec<_>>();
+        assert_eq!(recs, vec!["$ 2,265.00"]);
+
+        assert!(repo.commodities.find("$").unwrap().is_currency());
+        assert!(repo.commodities.find("EUR").unwrap().is_currency());
+        assert!(!repo.commodities.find("ACME").unwrap().is_currency());
+        Ok(())
+    }
+
+    #[test]
+    fn test_operation_tags() -> Result<()> {
+        let journal = Journal::parse(
+            r#"
+2024-03-10 Dividend
+    Assets:Broker:ACME   ; alere: dividend
+    Income:Dividends     -60 EUR
+    Assets:Bank           60 EUR
+
+2024-03-11 Bank
+    Assets:Bank           20 EUR
+    Income:Dividends     ; quarterly dividend, to split and reinvest
+"#,
+        )?;
+        let repo = JournalLoader::default().load(&journal)?;
+        let format = Formatter::default();
+        let txs = repo
+            .transactions
+            .iter()
+            .map(|tx| tx.display(&format))
+            .collect::<Vec<_>>();
+        let [tagged, free_text] = txs.as_slice() else {
+            panic!("expected two transactions: {txs:?}");
+        };
+        assert!(
+            tagged.starts_with(
+                "[Dividend(2024-03-10 00:00:00 +01:00 Assets:Broker:ACME"
+            ),
+            "{tagged}"
+        );
+
+        // A free-text comment is not an operation tag: the posting balances
+        // the transaction as usual.
+        assert!(
+            free_text.starts_with("[Credit(")
+                && !free_text.contains("Dividend("),
+            "{free_text}"
+        );
+        assert!(
+            free_text
+                .contains("Credit(2024-03-11 00:00:00 +01:00 Income:Dividends"),
+            "{free_text}"
+        );
+        Ok(())
+    }
+}
@@ -17,11 +17,11 @@ impl InstitutionCollection {
     ) -> Institution {
         let inst = Institution(Rc::new(RefCell::new(InstitutionDetails {
             name: name.into(),
-            _manager: manager.map(|s| s.into()),
-            _street: street.map(|s| s.into()),
-            _zip: zip.map(|s| s.into()),
-            _city: city.map(|s| s.into()),
-            _phone: phone.map(|s| s.into()),
+            manager: manager.map(|s| s.into()),
+            street: street.map(|s| s.into()),
+            zip: zip.map(|s| s.into()),
+            city: city.map(|s| s.into()),
+            phone: phone.map(|s| s.into()),
             icon: None,
             bic: None,
             url: None,
@@ -29,6 +29,10 @@ impl InstitutionCollection {
         self.insts.push(inst.clone());
         inst
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Institution> {
+        self.insts.iter()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -58,6 +62,46 @@ impl Institution {
     pub fn get_name(&self) -> String {
         self.0.borrow().name.clone()
     }
+
+    #[must_use]
+    pub fn get_manager(&self) -> Option<String> {
+        self.0.borrow().manager.clone()
+    }
+
+    #[must_use]
+    pub fn get_street(&self) -> Option<String> {
+        self.0.borrow().street.clone()
+    }
+
+    #[must_use]
+    pub fn get_zip(&self) -> Option<String> {
+        self.0.borrow().zip.clone()
+    }
+
+    #[must_use]
+    pub fn get_city(&self) -> Option<String> {
+        self.0.borrow().city.clone()
+    }
+
+    #[must_use]
+    pub fn get_phone(&self) -> Option<String> {
+        self.0.borrow().phone.clone()
+    }
+
+    #[must_use]
+    pub fn get_icon(&self) -> Option<String> {
+        self.0.borrow().icon.clone()
+    }
+
+    #[must_use]
+    pub fn get_bic(&self) -> Option<String> {
+        self.0.borrow().bic.clone()
+    }
+
+    #[must_use]
+    pub fn get_url(&self) -> Option<String> {
+        self.0.borrow().url.clone()
+    }
 }
 
 impl PartialEq for Institution {
@@ -71,11 +115,11 @@ impl Eq for Institution {}
 #[derive(Debug)]
 pub struct InstitutionDetails {
     name: String, // Display name
-    _manager: Option<String>,
-    _street: Option<String>,
-    _zip: Option<String>,
-    _city: Option<String>,
-    _phone: Option<String>,
+    manager: Option<String>,
+    street: Option<String>,
+    zip: Option<String>,
+    city: Option<String>,
+    phone: Option<String>,
     icon: Option<String>, // URL to the icon
     bic: Option<String>,
     url: Option<String>,
@@ -1,6 +1,7 @@
 use crate::account_kinds::AccountKind;
 use crate::accounts::{Account, Reconciliation};
-use crate::commodities::Commodity;
+use crate::budgets::{Budget, BudgetLine, BudgetPeriod};
+use crate::commodities::{Commodity, CommodityId};
 use crate::errors::AlrError;
 use crate::importers::Importer;
 use crate::institutions::Institution;
@@ -9,9 +10,13 @@ use crate::payees::Payee;
 use crate::price_sources::{PriceSource, PriceSourceFrom};
 use crate::prices::Price;
 use crate::repositories::Repository;
+use crate::schedules::{
+    Frequency, Recurrence, RecurrenceEnd, Schedule, ScheduledSplit,
+    WeekendOption,
+};
 use crate::transactions::{ReconcileKind, Transaction, TransactionArgs};
 use anyhow::{Context, Result};
-use chrono::{DateTime, Local, NaiveDate};
+use chrono::{DateTime, Local, Months, NaiveDate};
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 use std::collections::HashMap;
@@ -51,6 +56,16 @@ pub fn parse_price(text: &str, price_precision: u8) -> Result<Option<Decimal>> {
     }
 }
 
+/// The reverse of parse_price: convert a price to the "num/den" text stored in
+/// kmy files.  The price is truncated to price_precision, as kmymoney would do,
+/// so that parsing the result gives back the same value.
+#[must_use]
+pub fn format_price(price: Decimal, price_precision: u8) -> String {
+    let mut rounded = price.trunc_with_scale(price_precision as u32);
+    rounded.normalize_assign();
+    format!("{}/{}", rounded.mantissa(), 10_i128.pow(rounded.scale()))
+}
+
 #[cfg(feature = "kmymoney")]
 use ::{
     futures::TryStreamExt, //  make try_next visible
@@ -570,22 +585,7 @@ impl KmyMoneyImporter {
                     //    postDate
                 }
                 "S" => {
-                    // Do not import scheduled transactions that haven't been
-                    // entered.
-                    // ??? Not imported from kmmSchedules
-                    //    id
-                    //    name
-                    //    type + typeString
-                    //    occurrence + occurrenceString
-                    //    occurrenceMultiplier
-                    //    paymentType + paymentTypeString
-                    //    startDate
-                    //    endDate
-                    //    fixed
-                    //    lastDayInMonth
-                    //    autoEnter
-                    //    lastPayment
-                    //    weekendOption + weekendOptionString
+                    // The template for a schedule, see import_schedules
                 }
                 t => {
                     panic!("??? Does not handle transactions with type {}", t);
@@ -595,6 +595,215 @@ impl KmyMoneyImporter {
         Ok(tx)
     }
 
+    /// Import schedules.  Each has a template transaction (with the same id
+    /// as the schedule) in kmmTransactions, and the corresponding splits.
+    async fn import_schedules(
+        &mut self,
+        repo: &mut Repository,
+        conn: &mut SqliteConnection,
+    ) -> Result<()> {
+        let mut splits: HashMap<String, Vec<ScheduledSplit>> = HashMap::new();
+        let mut payees: HashMap<String, Payee> = HashMap::new();
+        let mut stream = query(
+            "SELECT * FROM kmmSplits WHERE txType='S' \
+             ORDER BY transactionId, splitId",
+        )
+        .fetch(&mut *conn);
+        while let Some(row) = stream.try_next().await? {
+            let tid: String = row.get("transactionId");
+            let k_account: &str = row.get("accountId");
+            let Some(account) = self.accounts.get(k_account) else {
+                continue;
+            };
+            let commodity = self.commodities.get(k_account).unwrap();
+            let shares = parse_price(
+                row.get("shares"),
+                *self.smallest_account_fraction.get(commodity).unwrap(),
+            )?
+            .unwrap_or_default();
+            if let Some(p) = row
+                .get::<Option<&str>, _>("payeeId")
+                .and_then(|p| self.payees.get(p))
+            {
+                payees.entry(tid.clone()).or_insert_with(|| p.clone());
+            }
+            splits.entry(tid).or_default().push(ScheduledSplit {
+                account: account.clone(),
+                value: MultiValue::new(shares, commodity),
+            });
+        }
+        drop(stream);
+
+        let to_local = |d: NaiveDate| {
+            d.and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap()
+        };
+        let mut stream = query(
+            "SELECT kmmSchedules.*, kmmTransactions.memo \
+             FROM kmmSchedules LEFT JOIN kmmTransactions \
+             ON kmmSchedules.id = kmmTransactions.id",
+        )
+        .fetch(conn);
+        while let Some(row) = stream.try_next().await? {
+            let id: String = row.get("id");
+
+            // kmymoney stores a base occurrence, multiplied by
+            // occurenceMultiplier.  Some of the base values are themselves
+            // multiples (e.g. every other week).
+            let multiplier =
+                u32::try_from(row.get::<i32, _>("occurenceMultiplier"))
+                    .unwrap_or(1)
+                    .max(1);
+            let (frequency, every) = match row.get::<i32, _>("occurence") {
+                2 => (Frequency::Daily, 1),
+                4 => (Frequency::Weekly, 1),
+                8 | 16 => (Frequency::Weekly, 2),
+                18 => (Frequency::Daily, 15),
+                20 => (Frequency::Weekly, 3),
+                30 => (Frequency::Daily, 30),
+                32 => (Frequency::Monthly, 1),
+                64 => (Frequency::Weekly, 4),
+                126 => (Frequency::Weekly, 8),
+                128 => (Frequency::Monthly, 2),
+                256 | 4096 => (Frequency::Monthly, 3),
+                512 => (Frequency::Monthly, 4),
+                1024 => (Frequency::Monthly, 6),
+                2048 => (Frequency::Yearly, 2),
+                8192 => (Frequency::Yearly, 1),
+                _ => (Frequency::Once, 1),
+            };
+            let weekend = match row.get::<i32, _>("weekendOption") {
+                0 => WeekendOption::MoveBefore,
+                1 => WeekendOption::MoveAfter,
+                _ => WeekendOption::Ignore,
+            };
+            let end = match row
+                .try_get::<NaiveDate, _>("endDate")
+                .ok() // we can have NULL or empty string
+            {
+                Some(d) => RecurrenceEnd::On(to_local(d)),
+                None => RecurrenceEnd::Never,
+            };
+
+            repo.schedules.add(Schedule {
+                name: row.get("name"),
+                payee: payees.remove(&id),
+                memo: row
+                    .get::<Option<String>, _>("memo")
+                    .filter(|m| !m.is_empty()),
+                start: to_local(row.get::<NaiveDate, _>("startDate")),
+                recurrence: Recurrence {
+                    frequency,
+                    every: every * multiplier,
+                    last_day_of_month: row.get::<&str, _>("lastDayInMonth")
+                        == "Y",
+                    weekend,
+                    end,
+                },
+                last_entered: row
+                    .try_get::<NaiveDate, _>("lastPayment")
+                    .ok()
+                    .map(to_local),
+                splits: splits.remove(&id).unwrap_or_default(),
+            });
+
+            // ??? Not imported from kmmSchedules
+            //    type + typeString
+            //    paymentType + paymentTypeString
+            //    fixed
+            //    autoEnter
+            //    nextPaymentDue (computed from the recurrence)
+        }
+        Ok(())
+    }
+
+    /// Import budgets.  Their definition is stored as XML, for instance:
+    ///    <BUDGET name="2024" start="2024-01-01" id="B000001" version="2">
+    ///      <ACCOUNT id="A000012" budgetlevel="monthly" budgetsubaccounts="0">
+    ///        <PERIOD start="2024-01-01" amount="300/1"/>
+    ///      </ACCOUNT>
+    ///    </BUDGET>
+    /// The budget level is one of "monthly" (same amount every month),
+    /// "monthbymonth" (one PERIOD per month), "yearly" or "none".
+    /// A kmymoney budget covers a single year, and its amounts are in the base
+    /// currency.
+    async fn import_budgets(
+        &mut self,
+        repo: &mut Repository,
+        conn: &mut SqliteConnection,
+    ) -> Result<()> {
+        let base: String = query("SELECT baseCurrency FROM kmmFileInfo")
+            .fetch_one(&mut *conn)
+            .await?
+            .get("baseCurrency");
+        let Some(commodity) = self.commodities.get(&base).cloned() else {
+            return Ok(());
+        };
+        let precision = *self.price_precisions.get(&commodity).unwrap();
+
+        let mut stream = query("SELECT * FROM kmmBudgetConfig").fetch(conn);
+        while let Some(row) = stream.try_next().await? {
+            let start = row
+                .get::<NaiveDate, _>("start")
+                .and_hms_opt(0, 0, 0)
+                .unwrap()
+                .and_local_timezone(Local)
+                .unwrap();
+            let xml: &str = row.get("XML");
+            let doc = roxmltree::Document::parse_with_options(
+                xml,
+                roxmltree::ParsingOptions {
+                    allow_dtd: true,
+                    ..roxmltree::ParsingOptions::default()
+                },
+            )?;
+
+            let mut lines = Vec::new();
+            for node in doc.descendants().filter(|n| n.has_tag_name("ACCOUNT"))
+            {
+                let Some(account) =
+                    node.attribute("id").and_then(|id| self.accounts.get(id))
+                else {
+                    continue;
+                };
+                let period = match node.attribute("budgetlevel") {
+                    Some("monthly" | "monthbymonth") => BudgetPeriod::Monthly,
+                    Some("yearly") => BudgetPeriod::Yearly,
+                    Some(_) | None => continue,
+                };
+                let mut amounts = Vec::new();
+                for p in node.children().filter(|n| n.has_tag_name("PERIOD")) {
+                    if let Some(amount) = parse_price(
+                        p.attribute("amount").unwrap_or_default(),
+                        precision,
+                    )? {
+                        amounts.push(amount.abs());
+                    }
+                }
+                lines.push(BudgetLine {
+                    account: account.clone(),
+                    period,
+                    amounts,
+                    rollover: false,
+                });
+            }
+
+            repo.budgets.add(Budget {
+                name: row.get("name"),
+                start,
+                end: start.checked_add_months(Months::new(12)),
+                commodity: commodity.clone(),
+                lines,
+            });
+
+            // ??? Not imported from kmmBudgetConfig
+            //    budgetsubaccounts (subaccounts are always included)
+        }
+        Ok(())
+    }
+
     async fn import_splits(
         &mut self,
         repo: &mut Repository,
@@ -844,9 +1053,14 @@ impl KmyMoneyImporter {
                 post_ts,
                 operation,
             );
+            if let Some(bank_id) = row
+                .get::<Option<&str>, _>("bankId")
+                .filter(|b| !b.is_empty())
+            {
+                tx.set_bank_id(account, bank_id);
+            }
 
             // ??? Not imported from kmmSplits
-            //    bankId
             //    costCenterId
             //    txType
         }
@@ -865,7 +1079,7 @@ impl Importer for KmyMoneyImporter {
         path: &Path,
         report_progress: impl Fn(u64, u64),
     ) -> Result<Repository> {
-        const MAX_PROGRESS: u64 = 13;
+        const MAX_PROGRESS: u64 = 15;
 
         let mut repo = Repository::default();
         report_progress(1, MAX_PROGRESS);
@@ -909,6 +1123,209 @@ impl Importer for KmyMoneyImporter {
         self.import_key_values(&mut conn).await?;
         report_progress(13, MAX_PROGRESS);
 
+        self.import_budgets(&mut repo, &mut conn).await?;
+        report_progress(14, MAX_PROGRESS);
+
+        self.import_schedules(&mut repo, &mut conn).await?;
+        report_progress(15, MAX_PROGRESS);
+
         Ok(repo)
     }
 }
+
+/// Open an existing kmy file
+#[cfg(feature = "kmymoney")]
+async fn connect_kmy(path: &Path) -> Result<SqliteConnection> {
+    Ok(SqliteConnection::connect(path.to_str().ok_or(AlrError::Str(
+        "Cannot convert path to a valid string".into(),
+    ))?)
+    .await?)
+}
+
+/// The kmymoney id and price precision for each commodity.
+/// Commodities are matched to the kmymoney currencies (via their ISO code) and
+/// securities (via their name and symbol), as done when importing.
+#[cfg(feature = "kmymoney")]
+async fn find_kmy_commodities<'a>(
+    conn: &mut SqliteConnection,
+    path: &Path,
+    commodities: impl IntoIterator<Item = &'a Commodity>,
+) -> Result<HashMap<CommodityId, (String, u8)>> {
+    let mut ids: HashMap<CommodityId, (String, u8)> = HashMap::new();
+    let mut currencies = HashMap::new();
+    let mut stream = query("SELECT ISOcode, pricePrecision FROM kmmCurrencies")
+        .fetch(&mut *conn);
+    while let Some(row) = stream.try_next().await? {
+        let iso: String = row.get("ISOcode");
+        let precision = row.get_unchecked::<u8, _>("pricePrecision");
+        currencies.insert(iso, precision);
+    }
+    drop(stream);
+
+    let mut securities = HashMap::new();
+    let mut stream =
+        query("SELECT id, name, symbol, pricePrecision FROM kmmSecurities")
+            .fetch(&mut *conn);
+    while let Some(row) = stream.try_next().await? {
+        let name: String = row.get("name");
+        let symbol: String = row.get("symbol");
+        let precision = row.get_unchecked::<u8, _>("pricePrecision");
+        securities.insert((name, symbol), (row.get("id"), precision));
+    }
+    drop(stream);
+
+    for c in commodities {
+        if ids.contains_key(&c.get_id()) {
+            continue;
+        }
+        let found = if c.is_currency() {
+            c.get_quote_symbol()
+                .and_then(|iso| currencies.get(&iso).map(|p| (iso.clone(), *p)))
+        } else {
+            securities
+                .get(&(c.get_name().clone(), c.get_symbol().clone()))
+                .cloned()
+        };
+        match found {
+            Some(f) => {
+                ids.insert(c.get_id(), f);
+            }
+            None => Err(AlrError::Str(format!(
+                "Commodity {} not found in {}",
+                c.get_name(),
+                path.display()
+            )))?,
+        }
+    }
+    Ok(ids)
+}
+
+/// The kmymoney id and price precision of a commodity, as found by
+/// `find_kmy_commodities`.
+#[cfg(feature = "kmymoney")]
+fn kmy_commodity<'a>(
+    ids: &'a HashMap<CommodityId, (String, u8)>,
+    path: &Path,
+    commodity: &Commodity,
+) -> Result<&'a (String, u8)> {
+    Ok(ids.get(&commodity.get_id()).ok_or_else(|| {
+        AlrError::Str(format!(
+            "Commodity {} not found in {}",
+            commodity.get_name(),
+            path.display()
+        ))
+    })?)
+}
+
+/// Save new prices in an existing kmy file.
+/// Existing prices for the same commodities and date are replaced.
+/// Returns the number of prices that were saved, or an error if one of the
+/// commodities is not in the file.
+#[cfg(feature = "kmymoney")]
+pub async fn save_prices(
+    path: &Path,
+    source_name: &str,
+    prices: &[(Commodity, Commodity, Price)],
+) -> Result<usize> {
+    let mut conn = connect_kmy(path).await?;
+    let ids = find_kmy_commodities(
+        &mut conn,
+        path,
+        prices
+            .iter()
+            .flat_map(|(origin, target, _)| [origin, target]),
+    )
+    .await?;
+
+    let mut tx = conn.begin().await?;
+    let mut saved = 0;
+    for (origin, target, price) in prices {
+        let (from_id, precision) = kmy_commodity(&ids, path, origin)?;
+        let (to_id, _) = kmy_commodity(&ids, path, target)?;
+        saved += query(
+            "INSERT OR REPLACE INTO kmmPrices \
+             (fromId, toId, priceDate, price, priceSource) \
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(from_id)
+        .bind(to_id)
+        .bind(price.timestamp.date_naive())
+        .bind(format_price(price.price, *precision))
+        .bind(source_name)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    }
+    query("UPDATE kmmFileInfo SET prices = (SELECT COUNT(*) FROM kmmPrices)")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(usize::try_from(saved)?)
+}
+
+/// Delete the prices of origin in target (or the reverse) on the given date,
+/// in an existing kmy file.
+/// Returns the number of prices that were deleted, or an error if one of the
+/// commodities is not in the file.
+#[cfg(feature = "kmymoney")]
+pub async fn delete_prices(
+    path: &Path,
+    origin: &Commodity,
+    target: &Commodity,
+    date: NaiveDate,
+) -> Result<usize> {
+    let mut conn = connect_kmy(path).await?;
+    let ids = find_kmy_commodities(&mut conn, path, [origin, target]).await?;
+    let (from_id, _) = kmy_commodity(&ids, path, origin)?;
+    let (to_id, _) = kmy_commodity(&ids, path, target)?;
+
+    let mut tx = conn.begin().await?;
+    let deleted = query(
+        "DELETE FROM kmmPrices \
+         WHERE ((fromId = ? AND toId = ?) OR (fromId = ? AND toId = ?)) \
+         AND priceDate = ?",
+    )
+    .bind(from_id)
+    .bind(to_id)
+    .bind(to_id)
+    .bind(from_id)
+    .bind(date)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    query("UPDATE kmmFileInfo SET prices = (SELECT COUNT(*) FROM kmmPrices)")
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+
+    Ok(usize::try_from(deleted)?)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::kmymoney::{format_price, parse_price};
+    use anyhow::Result;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_format_price() -> Result<()> {
+        assert_eq!(format_price(dec!(391.0571), 4), "3910571/10000");
+        assert_eq!(format_price(dec!(391.0571), 2), "39105/100");
+        assert_eq!(format_price(dec!(12), 4), "12/1");
+        assert_eq!(format_price(dec!(-1.5), 4), "-15/10");
+
+        for (price, precision) in [
+            (dec!(391.0571428), 4),
+            (dec!(0.84), 2),
+            (dec!(1.189343), 6),
+            (dec!(123456.789), 0),
+        ] {
+            assert_eq!(
+                parse_price(&format_price(price, precision), precision)?,
+                Some(price.trunc_with_scale(precision as u32)),
+            );
+        }
+        Ok(())
+    }
+}
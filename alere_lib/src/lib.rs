@@ -1,21 +1,35 @@
 pub mod account_categories;
 pub mod account_kinds;
 pub mod accounts;
+pub mod alere_file;
+pub mod allocation;
+pub mod beancount;
+pub mod budgets;
+pub mod categorizer;
 pub mod commodities;
+pub mod csv_import;
+pub mod dividends;
 pub mod errors;
+pub mod exposure;
+pub mod forecast;
 pub mod formatters;
 pub mod hledger;
 pub mod importers;
 pub mod institutions;
+pub mod lots;
 pub mod market_prices;
 pub mod metrics;
 pub mod multi_values;
 pub mod networth;
+pub mod ofx;
 pub mod payees;
 pub mod perf;
+pub mod price_check;
 pub mod price_sources;
 pub mod prices;
 pub mod repositories;
+pub mod schedules;
+pub mod tax_report;
 pub mod times;
 pub mod transactions;
 pub mod tree_keys;
@@ -25,5 +39,8 @@ mod utils;
 #[cfg(feature = "kmymoney")]
 pub mod kmymoney;
 
+#[cfg(feature = "gnucash")]
+pub mod gnucash;
+
 // #[macro_use]
 // extern crate bitmask;
@@ -0,0 +1,596 @@
+//! Cost basis tracking via lots.
+//!
+//! Each purchase of shares creates a lot, which remembers when the shares
+//! were acquired and how much they cost (including fees).  When shares are
+//! sold, the cost method of the account decides which lots are consumed, and
+//! a realized gain is recorded for each of them.
+
+use crate::{
+    accounts::Account,
+    commodities::Commodity,
+    errors::AlrError,
+    market_prices::MarketPrices,
+    multi_values::{MultiValue, Operation, Value},
+    repositories::Repository,
+};
+use chrono::{DateTime, Local, TimeDelta};
+use rust_decimal::Decimal;
+
+/// Which shares are considered sold first
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CostMethod {
+    #[default]
+    Fifo, // oldest shares first
+    Lifo, // most recent shares first
+    Hifo, // most expensive shares first
+
+    // All shares have the same cost, the average of the purchases.  The
+    // oldest shares are sold first, which matters for the holding period.
+    Average,
+}
+
+impl std::fmt::Display for CostMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CostMethod::Fifo => write!(f, "fifo"),
+            CostMethod::Lifo => write!(f, "lifo"),
+            CostMethod::Hifo => write!(f, "hifo"),
+            CostMethod::Average => write!(f, "average"),
+        }
+    }
+}
+
+impl std::str::FromStr for CostMethod {
+    type Err = AlrError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fifo" => Ok(CostMethod::Fifo),
+            "lifo" => Ok(CostMethod::Lifo),
+            "hifo" => Ok(CostMethod::Hifo),
+            "average" | "avg" => Ok(CostMethod::Average),
+            _ => Err(AlrError::ParseError(format!(
+                "unknown cost method {s}, expected fifo, lifo, hifo or average"
+            ))),
+        }
+    }
+}
+
+/// Shares acquired together, and not sold yet
+#[derive(Clone, Debug)]
+pub struct Lot {
+    pub acquired: DateTime<Local>,
+    pub shares: Value,
+    pub cost: MultiValue, // for all the shares, including fees
+}
+
+impl Lot {
+    /// The cost of a single share.  Lots are compared via the sum of the
+    /// amounts, which is only meaningful when they are all in the same
+    /// commodity.
+    fn unit_cost(&self) -> Decimal {
+        if self.shares.amount.is_zero() {
+            return Decimal::ZERO;
+        }
+        self.cost.iter().map(|v| v.amount).sum::<Decimal>() / self.shares.amount
+    }
+}
+
+/// The gain when selling (part of) a lot
+#[derive(Clone, Debug)]
+pub struct RealizedGain {
+    pub acquired: DateTime<Local>,
+    pub sold_on: DateTime<Local>,
+    pub shares: Value,
+    pub cost: MultiValue,
+    pub proceeds: MultiValue, // net of fees
+
+    // Whether the shares were not bought in a known lot, so that their
+    // cost and acquisition date are unknown.
+    pub unknown_basis: bool,
+}
+
+impl RealizedGain {
+    #[must_use]
+    pub fn gain(&self) -> MultiValue {
+        &self.proceeds - &self.cost
+    }
+
+    #[must_use]
+    pub fn holding_period(&self) -> TimeDelta {
+        self.sold_on - self.acquired
+    }
+}
+
+pub struct Settings {
+    // Commodity in which costs and proceeds are expressed.  If None, they
+    // use the commodity of the transactions.
+    pub commodity: Option<Commodity>,
+
+    // Overrides the cost method set on each account
+    pub method: Option<CostMethod>,
+}
+
+/// The lots for one stock account
+pub struct Lots {
+    pub account: Account,
+    pub method: CostMethod,
+    pub open: Vec<Lot>,
+    pub realized: Vec<RealizedGain>,
+}
+
+impl Lots {
+    /// No lot yet for the account
+    pub(crate) fn empty(account: &Account, method: CostMethod) -> Self {
+        Lots {
+            account: account.clone(),
+            method,
+            open: Vec::new(),
+            realized: Vec::new(),
+        }
+    }
+
+    /// Buy shares.  A purchase of zero shares (which only records fees, for
+    /// instance) does not create a lot.
+    pub(crate) fn buy(
+        &mut self,
+        acquired: DateTime<Local>,
+        shares: &Value,
+        cost: MultiValue,
+    ) {
+        if shares.amount.is_zero() {
+            return;
+        }
+        self.open.push(Lot {
+            acquired,
+            shares: shares.clone(),
+            cost,
+        });
+    }
+
+    /// Sell shares (a positive number), consuming open lots in the order
+    /// given by the cost method.
+    pub(crate) fn sell(
+        &mut self,
+        sold_on: DateTime<Local>,
+        shares: &Value,
+        proceeds: &MultiValue,
+    ) {
+        if shares.amount.is_zero() {
+            return;
+        }
+        if self.method == CostMethod::Average {
+            self.pool(&shares.commodity);
+        }
+
+        let mut order = self
+            .open
+            .iter()
+            .enumerate()
+            .filter(|(_, lot)| {
+                lot.shares.commodity == shares.commodity
+                    && !lot.shares.amount.is_zero()
+            })
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        match self.method {
+            CostMethod::Fifo | CostMethod::Average => {}
+            CostMethod::Lifo => order.reverse(),
+            CostMethod::Hifo => order.sort_by(|a, b| {
+                let cost = |idx: &usize| {
+                    self.open.get(*idx).map(Lot::unit_cost).unwrap_or_default()
+                };
+                cost(b).cmp(&cost(a))
+            }),
+        }
+
+        let mut remaining = shares.amount;
+        for idx in order {
+            let Some(lot) = self.open.get_mut(idx) else {
+                continue;
+            };
+            if remaining.is_zero() {
+                break;
+            }
+            let taken = remaining.min(lot.shares.amount);
+            let cost = &lot.cost * (taken / lot.shares.amount);
+            lot.cost -= &cost;
+            lot.shares.amount -= taken;
+            remaining -= taken;
+            self.realized.push(RealizedGain {
+                acquired: lot.acquired,
+                sold_on,
+                shares: Value {
+                    amount: taken,
+                    commodity: shares.commodity.clone(),
+                },
+                cost,
+                proceeds: proceeds * (taken / shares.amount),
+                unknown_basis: false,
+            });
+        }
+
+        // Selling shares we did not know about (maybe the history is
+        // incomplete): their cost is unknown.
+        if !remaining.is_zero() {
+            self.realized.push(RealizedGain {
+                acquired: sold_on,
+                sold_on,
+                shares: Value {
+                    amount: remaining,
+                    commodity: shares.commodity.clone(),
+                },
+                cost: MultiValue::zero(),
+                proceeds: proceeds * (remaining / shares.amount),
+                unknown_basis: true,
+            });
+        }
+
+        self.open.retain(|lot| !lot.shares.amount.is_zero());
+    }
+
+    /// Spread the total cost of the lots evenly among all shares
+    fn pool(&mut self, commodity: &Commodity) {
+        let mut shares = Decimal::ZERO;
+        let mut cost = MultiValue::zero();
+        for lot in self
+            .open
+            .iter()
+            .filter(|l| l.shares.commodity == *commodity)
+        {
+            shares += lot.shares.amount;
+            cost += &lot.cost;
+        }
+        if shares.is_zero() {
+            return;
+        }
+        for lot in self
+            .open
+            .iter_mut()
+            .filter(|l| l.shares.commodity == *commodity)
+        {
+            lot.cost = &cost * (lot.shares.amount / shares);
+        }
+    }
+
+    /// Remove all open lots for the commodity, oldest first
+    pub(crate) fn take(&mut self, commodity: &Commodity) -> Vec<Lot> {
+        let (taken, kept) = std::mem::take(&mut self.open)
+            .into_iter()
+            .partition(|lot| lot.shares.commodity == *commodity);
+        self.open = kept;
+        taken
+    }
+
+    fn split(&mut self, commodity: &Commodity, ratio: Decimal) {
+        for lot in self
+            .open
+            .iter_mut()
+            .filter(|l| l.shares.commodity == *commodity)
+        {
+            lot.shares.amount *= ratio;
+        }
+    }
+
+    fn new(
+        account: &Account,
+        method: CostMethod,
+        prices: &mut MarketPrices,
+    ) -> Self {
+        let mut lots = Lots::empty(account, method);
+
+        for tx in account.iter_transactions() {
+            // Fees are the amounts sent to expense accounts.  They increase
+            // the cost of purchases, and decrease the proceeds of sales.
+            let mut fees = MultiValue::zero();
+            for s in tx.splits().iter() {
+                if s.account != *account && s.account.get_kind().is_expense() {
+                    match &s.operation {
+                        Operation::Credit(v) => {
+                            fees += prices.convert_multi_value(v, &s.post_ts);
+                        }
+                        Operation::BuyAmount { qty, .. } => {
+                            fees += prices.convert_value(qty, &s.post_ts);
+                        }
+                        Operation::BuyPrice { .. }
+                        | Operation::AddShares { .. }
+                        | Operation::Reinvest { .. }
+                        | Operation::Dividend
+                        | Operation::Split { .. } => {}
+                    }
+                }
+            }
+
+            for s in tx.splits().iter().filter(|s| s.account == *account) {
+                let (qty, amount) = match &s.operation {
+                    Operation::BuyAmount { qty, amount } => {
+                        (qty.clone(), prices.convert_value(amount, &s.post_ts))
+                    }
+                    Operation::BuyPrice { qty, price } => (
+                        qty.clone(),
+                        prices.convert_value(
+                            &Value {
+                                commodity: price.commodity.clone(),
+                                amount: qty.amount * price.amount,
+                            },
+                            &s.post_ts,
+                        ),
+                    ),
+                    Operation::AddShares { qty } => {
+                        (qty.clone(), MultiValue::zero())
+                    }
+                    Operation::Reinvest { shares, amount } => {
+                        // When several commodities are bought, the cost is
+                        // shared according to their market value.
+                        let cost =
+                            prices.convert_multi_value(amount, &s.post_ts);
+                        let values = shares
+                            .iter()
+                            .map(|v| {
+                                let value =
+                                    prices.convert_value(&v, &s.post_ts);
+                                (v, value.iter().map(|c| c.amount).sum())
+                            })
+                            .collect::<Vec<(Value, Decimal)>>();
+                        let total: Decimal =
+                            values.iter().map(|(_, w)| *w).sum();
+                        for (v, weight) in &values {
+                            let part = if values.len() == 1 {
+                                Decimal::ONE
+                            } else if total.is_zero() {
+                                Decimal::ONE / Decimal::from(values.len())
+                            } else {
+                                weight / total
+                            };
+                            lots.buy(s.post_ts, v, &cost * part);
+                        }
+                        continue;
+                    }
+                    Operation::Split { ratio, commodity } => {
+                        lots.split(commodity, *ratio);
+                        continue;
+                    }
+                    Operation::Credit(_) | Operation::Dividend => continue,
+                };
+                if qty.is_negative() {
+                    lots.sell(s.post_ts, &qty.abs(), &(-&amount - &fees));
+                } else {
+                    lots.buy(s.post_ts, &qty, &amount + &fees);
+                }
+            }
+        }
+        lots
+    }
+
+    /// Compute the lots for all trading accounts that ever had shares
+    #[must_use]
+    pub fn load(repo: &Repository, settings: &Settings) -> Vec<Self> {
+        let mut prices = repo.market_prices(settings.commodity.clone());
+        repo.accounts
+            .iter()
+            .filter(|acc| acc.get_kind().is_trading())
+            .map(|acc| {
+                let method =
+                    settings.method.unwrap_or_else(|| acc.get_cost_method());
+                Lots::new(&acc, method, &mut prices)
+            })
+            .filter(|lots| !lots.open.is_empty() || !lots.realized.is_empty())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        accounts::Account,
+        commodities::test::create_currency,
+        lots::{CostMethod, Lots, Settings},
+        multi_values::{MultiValue, Operation, Value},
+        repositories::Repository,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{DateTime, Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    fn trade(
+        repo: &mut Repository,
+        stock: &Account,
+        cash: &Account,
+        date: DateTime<Local>,
+        qty: Decimal,
+        amount: Decimal,
+    ) -> Result<()> {
+        let eur = repo.commodities.find("EUR").unwrap();
+        let aapl = repo.commodities.find("AAPL").unwrap();
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            entry_date: date,
+            ..TransactionArgs::default()
+        });
+        tx.add_split(
+            stock.clone(),
+            ReconcileKind::New,
+            date,
+            Operation::BuyAmount {
+                qty: Value {
+                    amount: qty,
+                    commodity: aapl,
+                },
+                amount: Value {
+                    amount,
+                    commodity: eur.clone(),
+                },
+            },
+        );
+        tx.add_split(
+            cash.clone(),
+            ReconcileKind::New,
+            date,
+            Operation::Credit(MultiValue::new(-amount, &eur)),
+        );
+        repo.add_transaction(tx)?;
+        Ok(())
+    }
+
+    fn describe(lots: &Lots) -> Vec<String> {
+        let amount = |v: &MultiValue| {
+            v.iter().map(|v| v.amount).sum::<Decimal>().round_dp(2)
+        };
+        lots.open
+            .iter()
+            .map(|lot| {
+                format!(
+                    "open {} {} {}",
+                    lot.acquired.date_naive(),
+                    lot.shares.amount,
+                    amount(&lot.cost)
+                )
+            })
+            .chain(lots.realized.iter().map(|r| {
+                format!(
+                    "sold {} {} {} gain={} days={}",
+                    r.acquired.date_naive(),
+                    r.shares.amount,
+                    amount(&r.cost),
+                    amount(&r.gain()),
+                    r.holding_period().num_days(),
+                )
+            }))
+            .collect()
+    }
+
+    #[test]
+    fn test_lots() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        create_currency(&mut repo.commodities, "AAPL", 2, false);
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let mut stock = repo.accounts.add_dummy("AAPL", stock_kind);
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+
+        let ts = |y, m, d| Local.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap();
+        trade(
+            &mut repo,
+            &stock,
+            &cash,
+            ts(2023, 1, 1),
+            dec!(10),
+            dec!(100),
+        )?;
+        trade(
+            &mut repo,
+            &stock,
+            &cash,
+            ts(2023, 6, 1),
+            dec!(10),
+            dec!(300),
+        )?;
+        trade(
+            &mut repo,
+            &stock,
+            &cash,
+            ts(2023, 9, 1),
+            dec!(10),
+            dec!(200),
+        )?;
+        trade(
+            &mut repo,
+            &stock,
+            &cash,
+            ts(2024, 3, 1),
+            dec!(-15),
+            dec!(-450),
+        )?;
+
+        let settings = |method| Settings {
+            commodity: Some(eur.clone()),
+            method,
+        };
+        let lots = Lots::load(&repo, &settings(None));
+        assert_eq!(lots.len(), 1);
+        assert_eq!(
+            describe(lots.first().unwrap()),
+            vec![
+                "open 2023-06-01 5 150.00",
+                "open 2023-09-01 10 200",
+                "sold 2023-01-01 10 100 gain=200.00 days=425",
+                "sold 2023-06-01 5 150.00 gain=0.00 days=274",
+            ]
+        );
+
+        let lots = Lots::load(&repo, &settings(Some(CostMethod::Hifo)));
+        assert_eq!(
+            describe(lots.first().unwrap()),
+            vec![
+                "open 2023-01-01 10 100",
+                "open 2023-09-01 5 100.00",
+                "sold 2023-06-01 10 300 gain=0.00 days=274",
+                "sold 2023-09-01 5 100.00 gain=50.00 days=182",
+            ]
+        );
+
+        stock.set_cost_method(CostMethod::Average);
+        let lots = Lots::load(&repo, &settings(None));
+        assert_eq!(
+            describe(lots.first().unwrap()),
+            vec![
+                "open 2023-06-01 5 100.00",
+                "open 2023-09-01 10 200.00",
+                "sold 2023-01-01 10 200.00 gain=100.00 days=425",
+                "sold 2023-06-01 5 100.00 gain=50.00 days=274",
+            ]
+        );
+
+        Ok(())
+    }
+    #[test]
+    fn test_zero_shares() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        create_currency(&mut repo.commodities, "AAPL", 2, false);
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let stock = repo.accounts.add_dummy("AAPL", stock_kind);
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+
+        // A purchase of zero shares, which only records a fee
+        let ts = |y, m, d| Local.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap();
+        trade(&mut repo, &stock, &cash, ts(2023, 1, 1), dec!(0), dec!(10))?;
+        trade(
+            &mut repo,
+            &stock,
+            &cash,
+            ts(2023, 2, 1),
+            dec!(10),
+            dec!(100),
+        )?;
+        trade(
+            &mut repo,
+            &stock,
+            &cash,
+            ts(2023, 3, 1),
+            dec!(-5),
+            dec!(-60),
+        )?;
+
+        let lots = Lots::load(
+            &repo,
+            &Settings {
+                commodity: Some(eur),
+                method: None,
+            },
+        );
+        assert_eq!(
+            describe(lots.first().unwrap()),
+            vec![
+                "open 2023-02-01 5 50.00",
+                "sold 2023-02-01 5 50.00 gain=10.00 days=28",
+            ]
+        );
+        Ok(())
+    }
+}
@@ -3,9 +3,12 @@ use crate::multi_values::{MultiValue, Value};
 use crate::price_sources::PriceSourceFrom;
 use crate::prices::{Price, PriceCollection};
 use bisection::bisect_right_by;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, TimeDelta};
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashMap};
+
+/// Maximum number of prices combined to convert a commodity
+const MAX_STEPS: usize = 3;
 
 /// A struct that can return the current market prices for a commodity, at any
 /// point in time.
@@ -16,6 +19,72 @@ pub struct MarketPrices<'a> {
     known_prices: &'a PriceCollection,
     turnkey_currencies: &'a [Commodity],
     to_commodity: Option<Commodity>,
+
+    // For each commodity, the ones we know an exchange rate with (in either
+    // direction), sorted by symbol so that the search is deterministic.
+    neighbours: HashMap<Commodity, Vec<Commodity>>,
+
+    // Ignore prices older than this, compared to the requested date
+    max_staleness: Option<TimeDelta>,
+
+    // The price of each commodity at a given date, as computed from the
+    // best chain.
+    best_prices: HashMap<(Commodity, DateTime<Local>), Option<Price>>,
+}
+
+/// One of the prices combined to convert a commodity
+#[derive(Clone, Debug)]
+pub struct PriceStep {
+    pub from: Commodity,
+    pub to: Commodity,
+    pub price: Price,
+
+    // Whether the price was registered from `to` to `from`, and inverted
+    pub inverted: bool,
+}
+
+/// A sequence of prices, e.g. AAPL to USD then USD to EUR, and the resulting
+/// price.  Its timestamp is the one of the oldest step.
+#[derive(Clone, Debug)]
+pub struct PriceChain {
+    pub steps: Vec<PriceStep>,
+    pub price: Price,
+}
+
+impl PriceChain {
+    fn new(steps: Vec<PriceStep>) -> Option<Self> {
+        let price = chain_price(&steps)?;
+        Some(PriceChain { steps, price })
+    }
+}
+
+/// The price resulting from combining all steps.  Its timestamp is the one
+/// of the oldest step.
+fn chain_price(steps: &[PriceStep]) -> Option<Price> {
+    match steps {
+        [] => None,
+        [single] => Some(single.price.clone()),
+        [first, ..] => Some(Price::new(
+            steps
+                .iter()
+                .map(|s| s.price.timestamp)
+                .min()
+                .unwrap_or(first.price.timestamp),
+            steps.iter().map(|s| s.price.price).product(),
+            PriceSourceFrom::Turnkey,
+        )),
+    }
+}
+
+/// Sort chains of prices: the preferred one is the chain whose oldest price
+/// is the most recent, or the shortest one when several are as recent.
+fn compare_chains(
+    a: &Price,
+    a_len: usize,
+    b: &Price,
+    b_len: usize,
+) -> Ordering {
+    b.older_than(a).then(a_len.cmp(&b_len))
 }
 
 impl<'a> MarketPrices<'a> {
@@ -23,19 +92,41 @@ impl<'a> MarketPrices<'a> {
     /// the repository.
     /// If to_commodity is None, no conversion is made.
     #[must_use]
+    #[allow(clippy::mutable_key_type)]
     pub fn new(
         known_prices: &'a PriceCollection,
         turnkey_currencies: &'a [Commodity],
         to_commodity: Option<Commodity>,
     ) -> Self {
+        let mut neighbours: HashMap<Commodity, Vec<Commodity>> = HashMap::new();
+        for (from, to) in known_prices.prices.keys() {
+            neighbours.entry(from.clone()).or_default().push(to.clone());
+            neighbours.entry(to.clone()).or_default().push(from.clone());
+        }
+        for list in neighbours.values_mut() {
+            list.sort_by(|a, b| a.get_symbol().cmp(&b.get_symbol()));
+            list.dedup();
+        }
         MarketPrices {
             known_prices,
             turnkey_currencies,
             to_commodity,
+            neighbours,
+            max_staleness: None,
+            best_prices: HashMap::new(),
             cache: HashMap::new(),
         }
     }
 
+    /// Ignore prices that are older than the given delay, when looking up
+    /// the price at some date.  This is set by the repository, so that all
+    /// reports use the same rule.
+    #[must_use]
+    pub(crate) fn with_max_staleness(mut self, max: Option<TimeDelta>) -> Self {
+        self.max_staleness = max;
+        self
+    }
+
     /// Convert each component of the multi-value to to_commodity, and sum
     /// the results.  We still return a MultiValue, since we might be missing
     /// some exchange-rates, and could therefore left some of the components
@@ -72,10 +163,53 @@ impl<'a> MarketPrices<'a> {
         }
     }
 
+    /// The currency in which a commodity is traded.  This is the commodity
+    /// itself for currencies, then the quote currency retrieved from the
+    /// online source, and otherwise the currency of the most recent price
+    /// known for the commodity as of the given date.
+    #[must_use]
+    pub fn quote_currency(
+        &self,
+        commodity: &Commodity,
+        as_of: &DateTime<Local>,
+    ) -> Option<Commodity> {
+        if commodity.is_currency() {
+            return Some(commodity.clone());
+        }
+        if let Some(q) = commodity.get_quote_currency() {
+            return Some(q);
+        }
+        let mut best: Option<(DateTime<Local>, Commodity)> = None;
+        for ((from, to), prices) in self.known_prices.iter() {
+            let other = if from == commodity {
+                to
+            } else if to == commodity {
+                from
+            } else {
+                continue;
+            };
+            if !other.is_currency() {
+                continue;
+            }
+            let Some(ts) = prices
+                .iter()
+                .rev()
+                .map(|p| p.timestamp)
+                .find(|ts| ts <= as_of)
+            else {
+                continue;
+            };
+            if best.as_ref().is_none_or(|(b, _)| ts > *b) {
+                best = Some((ts, other.clone()));
+            }
+        }
+        best.map(|(_, c)| c)
+    }
+
     /// Return the price for the specified commodity.
     /// The prices are computed using various sources: either direct exchange
     /// rates (or reverse one, if we only knew that one); or perhaps going
-    /// through a turnkey currency (like USD).
+    /// through one or more turnkey currencies (like USD).
     pub fn get_price(
         &mut self,
         commodity: &Commodity,
@@ -98,40 +232,108 @@ impl<'a> MarketPrices<'a> {
                 PriceSourceFrom::Transaction,
             )),
             Some(c) => {
-                let mut result =
-                    self.get_price_no_turnkey(commodity, &c, as_of);
-
-                for turnkey in
-                    self.turnkey_currencies.iter().filter(|curr| **curr != c)
-                {
-                    match self.get_price_no_turnkey(commodity, turnkey, as_of) {
-                        None => {}
-                        Some(p1) => {
-                            match self.get_price_no_turnkey(turnkey, &c, as_of)
-                            {
-                                None => {}
-                                Some(p2) => {
-                                    keep_most_recent(
-                                        &mut result,
-                                        Price::new(
-                                            std::cmp::min(
-                                                p1.timestamp,
-                                                p2.timestamp,
-                                            ),
-                                            p1.price * p2.price,
-                                            PriceSourceFrom::Turnkey,
-                                        ),
-                                    );
-                                }
-                            }
-                        }
-                    }
+                let key = (commodity.clone(), *as_of);
+                if let Some(price) = self.best_prices.get(&key) {
+                    return price.clone();
                 }
-                result
+                let mut best: Option<(Price, usize)> = None;
+                self.find_chains(
+                    &mut vec![commodity.clone()],
+                    &c,
+                    as_of,
+                    &mut Vec::new(),
+                    &mut |steps| {
+                        let Some(price) = chain_price(steps) else {
+                            return;
+                        };
+                        if best.as_ref().is_none_or(|(b, len)| {
+                            compare_chains(&price, steps.len(), b, *len)
+                                == Ordering::Less
+                        }) {
+                            best = Some((price, steps.len()));
+                        }
+                    },
+                );
+                let price = best.map(|(p, _)| p);
+                self.best_prices.insert(key, price.clone());
+                price
             }
         }
     }
 
+    /// All the ways to convert the commodity, each combining one or more
+    /// known prices.  Intermediate commodities must be turnkey currencies,
+    /// and at most MAX_STEPS prices are combined.
+    /// The first chain is the one used to compute market values: the chain
+    /// whose oldest price is the most recent, or the shortest one when
+    /// several are as recent.
+    pub fn explain(
+        &mut self,
+        commodity: &Commodity,
+        as_of: &DateTime<Local>,
+    ) -> Vec<PriceChain> {
+        let Some(target) = self.to_commodity.clone() else {
+            return Vec::new();
+        };
+        let mut result = Vec::new();
+        self.find_chains(
+            &mut vec![commodity.clone()],
+            &target,
+            as_of,
+            &mut Vec::new(),
+            &mut |steps| result.extend(PriceChain::new(steps.to_vec())),
+        );
+        result.sort_by(|a, b| {
+            compare_chains(&a.price, a.steps.len(), &b.price, b.steps.len())
+        });
+        result
+    }
+
+    /// Recursively look for chains of prices from the last commodity in path
+    /// to target, and call found for each of them.
+    fn find_chains(
+        &mut self,
+        path: &mut Vec<Commodity>,
+        target: &Commodity,
+        as_of: &DateTime<Local>,
+        steps: &mut Vec<PriceStep>,
+        found: &mut dyn FnMut(&[PriceStep]),
+    ) {
+        let Some(current) = path.last().cloned() else {
+            return;
+        };
+        let mut idx = 0;
+        while let Some(next) = self
+            .neighbours
+            .get(&current)
+            .and_then(|n| n.get(idx))
+            .cloned()
+        {
+            idx += 1;
+            let is_target = next == *target;
+            if path.contains(&next)
+                || (!is_target
+                    && (steps.len() + 2 > MAX_STEPS
+                        || !self.turnkey_currencies.contains(&next)))
+            {
+                continue;
+            }
+            let Some(step) = self.get_price_no_turnkey(&current, &next, as_of)
+            else {
+                continue;
+            };
+            steps.push(step);
+            if is_target {
+                found(steps);
+            } else {
+                path.push(next);
+                self.find_chains(path, target, as_of, steps, found);
+                path.pop();
+            }
+            steps.pop();
+        }
+    }
+
     /// Compute prices by looking at exchange rate and reverse exchange rate,
     /// but not going through turnkey currencies.
     fn get_price_no_turnkey(
@@ -139,12 +341,26 @@ impl<'a> MarketPrices<'a> {
         from: &Commodity,
         to: &Commodity,
         as_of: &DateTime<Local>,
-    ) -> Option<Price> {
-        let mut result: Option<Price> = self.lookup_price(from, to, as_of);
-        if let Some(p) = self.lookup_price(to, from, as_of) {
-            keep_most_recent(&mut result, p.invert());
+    ) -> Option<PriceStep> {
+        let step = |price: Price, inverted: bool| PriceStep {
+            from: from.clone(),
+            to: to.clone(),
+            price,
+            inverted,
+        };
+        let mut result =
+            self.lookup_price(from, to, as_of).map(|p| step(p, false));
+        if let Some(p) = self.lookup_price(to, from, as_of)
+            && result
+                .as_ref()
+                .is_none_or(|r| r.price.older_than(&p) == Ordering::Less)
+        {
+            result = Some(step(p.invert(), true));
         }
-        result
+        result.filter(|r| {
+            self.max_staleness
+                .is_none_or(|max| *as_of - r.price.timestamp <= max)
+        })
     }
 
     /// Lookup a direct exchange rate, possibly reusing an existing cache.
@@ -262,25 +478,13 @@ struct PairCacheLine {
     found: Option<(usize, Price)>,
 }
 
-/// Keep the most recent of two prices
-fn keep_most_recent(left: &mut Option<Price>, right: Price) {
-    match left {
-        None => *left = Some(right),
-        Some(r) => {
-            if matches!(r.older_than(&right), std::cmp::Ordering::Less) {
-                *left = Some(right);
-            }
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
     use crate::commodities::CommodityCollection;
     use crate::market_prices::MarketPrices;
     use crate::price_sources::PriceSourceFrom;
     use crate::prices::{Price, PriceCollection};
-    use chrono::{Days, Local, TimeZone};
+    use chrono::{Days, Local, TimeDelta, TimeZone};
     use rust_decimal_macros::dec;
 
     #[test]
@@ -411,4 +615,83 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn test_explain() {
+        let mut prices = PriceCollection::default();
+        let mut coms = CommodityCollection::default();
+        let aapl = coms.add_dummy("AAPL", false);
+        let usd = coms.add_dummy("USD", true);
+        let gbp = coms.add_dummy("GBP", true);
+        let eur = coms.add_dummy("EUR", true);
+        let turnkeys = [usd.clone(), gbp.clone(), eur.clone()];
+        let t1 = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let t2 = t1 + Days::new(10);
+        let t3 = t1 + Days::new(20);
+
+        prices.add(
+            &aapl,
+            &usd,
+            Price::new(t2, dec!(100), PriceSourceFrom::Transaction),
+        );
+        prices.add(
+            &gbp,
+            &usd,
+            Price::new(t3, dec!(1.25), PriceSourceFrom::Transaction),
+        );
+        prices.add(
+            &gbp,
+            &eur,
+            Price::new(t3, dec!(1.2), PriceSourceFrom::Transaction),
+        );
+        prices.add(
+            &eur,
+            &usd,
+            Price::new(t1, dec!(1.1), PriceSourceFrom::Transaction),
+        );
+
+        // Both AAPL->USD->EUR and AAPL->USD->GBP->EUR are possible, the
+        // second is fresher since the EUR/USD rate is older.
+        let mut to_eur =
+            MarketPrices::new(&prices, &turnkeys, Some(eur.clone()));
+        let as_of = t3 + Days::new(1);
+        let chains = to_eur.explain(&aapl, &as_of);
+        let described = chains
+            .iter()
+            .map(|c| {
+                c.steps
+                    .iter()
+                    .map(|s| {
+                        format!(
+                            "{}->{} {}{}",
+                            s.from.get_symbol(),
+                            s.to.get_symbol(),
+                            s.price.timestamp.date_naive(),
+                            if s.inverted { " inverted" } else { "" },
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            described,
+            vec![
+                "AAPL->USD 2024-01-11, USD->GBP 2024-01-21 inverted, \
+                 GBP->EUR 2024-01-21",
+                "AAPL->USD 2024-01-11, USD->EUR 2024-01-01 inverted",
+            ]
+        );
+        assert_eq!(
+            to_eur.get_price(&aapl, &as_of),
+            Some(dec!(100) / dec!(1.25) * dec!(1.2)),
+        );
+
+        // With a maximum staleness, old prices are ignored
+        let mut to_eur =
+            MarketPrices::new(&prices, &turnkeys, Some(eur.clone()))
+                .with_max_staleness(Some(TimeDelta::days(5)));
+        assert_eq!(to_eur.get_price(&aapl, &as_of), None);
+        assert_eq!(to_eur.get_price(&gbp, &as_of), Some(dec!(1.2)),);
+    }
 }
@@ -2,6 +2,7 @@ use crate::{
     commodities::Commodity,
     market_prices::MarketPrices,
     multi_values::{MultiValue, Operation},
+    perf::{Benchmark, Performance, amount_in},
     repositories::Repository,
     times::{Intv, TimeInterval},
 };
@@ -16,6 +17,10 @@ pub struct Settings {
     // What columns to display.  Each column aggregates all transaction within
     // a time interval.
     pub intervals: Vec<Intv>,
+
+    // If set, compare the networth with investing the same cash flows in
+    // this commodity.  Requires `commodity`.
+    pub benchmark: Option<Commodity>,
 }
 
 /// Changes in one time range
@@ -101,6 +106,11 @@ pub struct Metrics {
     pub income_tax: MultiValue,
     pub misc_tax: MultiValue,
     pub income_tax_rate: Option<Decimal>,
+
+    // What the starting networth and the cashflow would have given if
+    // invested in the benchmark commodity.  The excess return compares it
+    // with the IRR of the networth.
+    pub benchmark: Option<Benchmark>,
 }
 
 #[derive(Default)]
@@ -115,11 +125,15 @@ struct MetricsArgs {
     expense: MultiValue,
     income_tax: MultiValue,
     misc_tax: MultiValue,
+
+    // Income and expenses, with their date, for the benchmark
+    cash_flows: Vec<(DateTime<Local>, MultiValue)>,
 }
 
 impl Metrics {
     fn new(
         prices: &mut MarketPrices,
+        settings: &Settings,
         now: DateTime<Local>,
         args: MetricsArgs,
         interval: TimeInterval,
@@ -142,6 +156,20 @@ impl Metrics {
         let unrealized_liquid = &pnl_liquid + &cashflow;
         let days = interval.duration(now).num_days();
         let daily_expense = &args.expense / Decimal::from(days);
+        let benchmark = settings
+            .commodity
+            .as_ref()
+            .zip(settings.benchmark.as_ref())
+            .and_then(|(target, bench)| {
+                Self::benchmark(
+                    prices,
+                    target,
+                    bench,
+                    (*lo, &start_nw),
+                    ((*up).min(now), &end_nw),
+                    &args.cash_flows,
+                )
+            });
         Metrics {
             interval,
             unrealized_liquid: &pnl_liquid + &cashflow,
@@ -175,7 +203,31 @@ impl Metrics {
             pnl_liquid,
             pnl_illiquid,
             cashflow,
+            benchmark,
+        }
+    }
+
+    /// Simulate investing the starting networth, then each income and
+    /// expense, in the benchmark commodity.  Returns None if some of the
+    /// values could not be converted to the target commodity.
+    fn benchmark(
+        prices: &mut MarketPrices,
+        target: &Commodity,
+        bench: &Commodity,
+        start: (DateTime<Local>, &MultiValue),
+        end: (DateTime<Local>, &MultiValue),
+        cash_flows: &[(DateTime<Local>, MultiValue)],
+    ) -> Option<Benchmark> {
+        // Cash flows are negative when money is added to the networth, which
+        // matches the sign of income.
+        let mut flows = vec![(start.0, -amount_in(start.1, target)?)];
+        for (ts, value) in cash_flows {
+            flows.push((*ts, amount_in(value, target)?));
         }
+        flows.retain(|(_, amount)| !amount.is_zero());
+        let end_value = amount_in(end.1, target)?;
+        let irr = Performance::calculate_irr(&flows, end_value, end.0);
+        Benchmark::new(&flows, irr, prices, bench, end.0)
     }
 
     /// Compute various statistics over a range of time
@@ -223,6 +275,9 @@ impl Metrics {
                         };
 
                         if interval.intv.contains(s.post_ts) {
+                            if settings.benchmark.is_some() {
+                                args.cash_flows.push((s.post_ts, val.clone()));
+                            }
                             if kind.is_income_tax() {
                                 args.income_tax += &val;
                             } else if kind.is_misc_tax() {
@@ -265,7 +320,13 @@ impl Metrics {
                 }
             }
 
-            result.push(Metrics::new(&mut prices, now, args, interval));
+            result.push(Metrics::new(
+                &mut prices,
+                &settings,
+                now,
+                args,
+                interval,
+            ));
         }
 
         Ok(result)
@@ -331,6 +331,31 @@ impl core::ops::Div<Decimal> for &MultiValue {
     }
 }
 
+impl core::ops::Mul<Decimal> for &MultiValue {
+    type Output = MultiValue;
+
+    fn mul(self, rhs: Decimal) -> Self::Output {
+        assert!(self.is_normalized());
+        if rhs.is_zero() {
+            return MultiValue::zero();
+        }
+        match &self.0 {
+            InnerValue::Zero => MultiValue::zero(),
+            InnerValue::One(p1) => MultiValue(InnerValue::One(Value {
+                amount: p1.amount * rhs,
+                commodity: p1.commodity.clone(),
+            })),
+            InnerValue::Multi(m1) => {
+                let mut map = m1.clone();
+                for v in map.values_mut() {
+                    v.amount *= rhs;
+                }
+                MultiValue(InnerValue::Multi(map))
+            }
+        }
+    }
+}
+
 impl core::ops::Div<Decimal> for MultiValue {
     type Output = MultiValue;
 
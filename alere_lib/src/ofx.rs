@@ -0,0 +1,676 @@
+//! Import bank statements in OFX format.  QFX files are the same format, with
+//! a few extra elements specific to Quicken that we ignore.
+//!
+//! Both OFX 1.x (an SGML format, where elements that contain data have no
+//! closing tag) and OFX 2.x (plain XML) are supported.  We only look at the
+//! statements themselves (`STMTRS` for bank accounts, `CCSTMTRS` for credit
+//! cards), and ignore the sign-on and investment messages.
+//!
+//! Each line of a statement has a unique identifier (its FITID), which is
+//! stored in the split so that importing the same file (or overlapping
+//! statements) several times does not duplicate transactions.  Lines that
+//! were never imported are compared with the existing splits, in case the
+//! user had already entered them manually.
+
+use crate::{
+    accounts::{Account, AccountNameDepth},
+    commodities::Commodity,
+    errors::AlrError,
+    multi_values::{MultiValue, Operation},
+    repositories::Repository,
+    transactions::{ReconcileKind, Transaction, TransactionArgs},
+    utils::read_text_file,
+};
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use rust_decimal::Decimal;
+use std::{path::Path, str::FromStr};
+
+/// Maximum number of days between the date of a line in the statement and
+/// the date of an existing split for the latter to be considered a duplicate.
+const DUPLICATE_MAX_DAYS: i64 = 4;
+
+//--------------------------------------------------------------------------
+// Parsing
+//--------------------------------------------------------------------------
+
+/// An element of the OFX document.  Elements either contain data (and then
+/// have no children), or are aggregates of other elements.
+#[derive(Debug, Default)]
+struct Element {
+    name: String,
+    data: Option<String>,
+    children: Vec<Element>,
+}
+
+impl Element {
+    fn new(name: &str) -> Self {
+        Element {
+            name: name.trim().to_uppercase(),
+            ..Default::default()
+        }
+    }
+
+    /// The first direct child with the given name
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// The data of the first direct child with the given name, if not empty
+    fn data(&self, name: &str) -> Option<&str> {
+        self.child(name)
+            .and_then(|c| c.data.as_deref())
+            .filter(|d| !d.is_empty())
+    }
+
+    fn required(&self, name: &str) -> Result<&str> {
+        Ok(self.data(name).ok_or_else(|| {
+            AlrError::Str(format!("Missing {name} in {}", self.name))
+        })?)
+    }
+
+    /// All descendants with the given name (but not their own descendants)
+    fn find_all<'a>(&'a self, name: &str, into: &mut Vec<&'a Element>) {
+        for c in &self.children {
+            if c.name == name {
+                into.push(c);
+            } else {
+                c.find_all(name, into);
+            }
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+/// Close the innermost aggregate, and add it to its parent
+fn close_element(stack: &mut Vec<Element>) {
+    if stack.len() > 1
+        && let Some(elem) = stack.pop()
+        && let Some(parent) = stack.last_mut()
+    {
+        parent.children.push(elem);
+    }
+}
+
+/// Parse the document into a tree of elements.
+/// The headers (either the `KEY:VALUE` lines of OFX 1.x or the XML
+/// declaration and processing instructions of OFX 2.x) are skipped.
+fn parse_document(text: &str) -> Result<Element> {
+    let mut rest = text
+        .find("<OFX>")
+        .and_then(|start| text.get(start..))
+        .ok_or_else(|| AlrError::Str("Not an OFX document".into()))?;
+
+    // The bottom of the stack is a dummy element that contains the OFX one
+    let mut stack = vec![Element::default()];
+
+    while let Some(open) = rest.find('<') {
+        let after = rest.get(open + 1..).unwrap_or_default();
+        let Some(close) = after.find('>') else {
+            break;
+        };
+        let tag = after.get(..close).unwrap_or_default().trim();
+        rest = after.get(close + 1..).unwrap_or_default();
+
+        if tag.starts_with('?') || tag.starts_with('!') {
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            // In OFX 1.x, closing tags are optional for elements with data,
+            // and those elements were already added to their parent.  So we
+            // only need to look for aggregates here.
+            let name = name.trim().to_uppercase();
+            if let Some(pos) = stack.iter().rposition(|e| e.name == name) {
+                while stack.len() > pos {
+                    close_element(&mut stack);
+                }
+            }
+            continue;
+        }
+
+        let data_end = rest.find('<').unwrap_or(rest.len());
+        let data = rest.get(..data_end).unwrap_or_default().trim();
+
+        if let Some(name) = tag.strip_suffix('/') {
+            // An empty element in XML
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(Element::new(name));
+            }
+        } else if data.is_empty() {
+            stack.push(Element::new(tag));
+        } else {
+            let mut elem = Element::new(tag);
+            elem.data = Some(unescape(data));
+            if let Some(parent) = stack.last_mut() {
+                parent.children.push(elem);
+            }
+            rest = rest.get(data_end..).unwrap_or_default();
+        }
+    }
+
+    while stack.len() > 1 {
+        close_element(&mut stack);
+    }
+    Ok(stack.pop().unwrap_or_default())
+}
+
+/// Dates are given as `YYYYMMDDHHMMSS.XXX[offset:TZ]`, where everything
+/// after the day is optional.  We only keep the day.
+fn parse_date(text: &str) -> Result<DateTime<Local>> {
+    text.get(..8)
+        .and_then(|d| NaiveDate::parse_from_str(d, "%Y%m%d").ok())
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|d| d.and_local_timezone(Local).earliest())
+        .ok_or_else(|| AlrError::Str(format!("Invalid date {text:?}")).into())
+}
+
+/// Amounts use either a dot or a comma as the decimal separator
+fn parse_amount(text: &str) -> Result<Decimal> {
+    Decimal::from_str(&text.trim().replace(',', "."))
+        .with_context(|| format!("Invalid amount {text:?}"))
+}
+
+/// A line of a bank statement
+#[derive(Debug)]
+pub struct StatementLine {
+    /// Unique identifier for this line, assigned by the bank
+    pub fitid: String,
+
+    /// The kind of transaction (DEBIT, CREDIT, CHECK, ATM, ...)
+    pub kind: String,
+
+    pub posted: DateTime<Local>,
+
+    /// Positive when money is deposited to the account
+    pub amount: Decimal,
+
+    pub name: Option<String>,
+    pub memo: Option<String>,
+    pub check_number: Option<String>,
+}
+
+/// A bank or credit card statement
+#[derive(Debug)]
+pub struct Statement {
+    pub bank_id: Option<String>,
+    pub branch_id: Option<String>,
+    pub account_id: String,
+    pub currency: String,
+    pub lines: Vec<StatementLine>,
+}
+
+impl Statement {
+    fn from_element(rs: &Element) -> Result<Self> {
+        let from = rs
+            .child("BANKACCTFROM")
+            .or_else(|| rs.child("CCACCTFROM"))
+            .ok_or_else(|| {
+                AlrError::Str(format!("No account found in {}", rs.name))
+            })?;
+        let mut lines = Vec::new();
+        if let Some(list) = rs.child("BANKTRANLIST") {
+            for t in list.children.iter().filter(|c| c.name == "STMTTRN") {
+                lines.push(StatementLine {
+                    fitid: t.required("FITID")?.to_string(),
+                    kind: t.data("TRNTYPE").unwrap_or("OTHER").to_string(),
+                    posted: parse_date(t.required("DTPOSTED")?)?,
+                    amount: parse_amount(t.required("TRNAMT")?)?,
+                    name: t
+                        .data("NAME")
+                        .or_else(|| {
+                            t.child("PAYEE").and_then(|p| p.data("NAME"))
+                        })
+                        .map(str::to_string),
+                    memo: t.data("MEMO").map(str::to_string),
+                    check_number: t.data("CHECKNUM").map(str::to_string),
+                });
+            }
+        }
+        Ok(Statement {
+            bank_id: from.data("BANKID").map(str::to_string),
+            branch_id: from.data("BRANCHID").map(str::to_string),
+            account_id: from.required("ACCTID")?.to_string(),
+            currency: rs.required("CURDEF")?.to_string(),
+            lines,
+        })
+    }
+}
+
+/// Parse an OFX document, and return all the statements it contains
+pub fn parse(text: &str) -> Result<Vec<Statement>> {
+    let doc = parse_document(text)?;
+    let mut responses = Vec::new();
+    doc.find_all("STMTRS", &mut responses);
+    doc.find_all("CCSTMTRS", &mut responses);
+    responses.into_iter().map(Statement::from_element).collect()
+}
+
+/// Read all the statements from an OFX file
+pub fn read_file(path: &Path) -> Result<Vec<Statement>> {
+    parse(&read_text_file(path)?)
+}
+
+//--------------------------------------------------------------------------
+// Merging
+//--------------------------------------------------------------------------
+
+/// Only keep letters and digits, so that "FR76 3000 4000" and "FR7630004000"
+/// compare equal.
+fn normalize(id: &str) -> String {
+    id.chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Find the account the statement applies to.  The account id from the
+/// statement is compared with the account number and the IBAN (which
+/// includes the bank id, the branch id and the account id for most banks).
+pub fn find_account(
+    repo: &Repository,
+    statement: &Statement,
+) -> Result<Account> {
+    let acct = normalize(&statement.account_id);
+    let bank = statement.bank_id.as_deref().map(normalize);
+    let matches: Vec<Account> = repo
+        .accounts
+        .iter()
+        .filter(|a| {
+            a.get_number().is_some_and(|n| normalize(&n) == acct)
+                || a.get_iban().is_some_and(|iban| {
+                    let iban = normalize(&iban);
+                    iban == acct
+                        || (iban.contains(&acct)
+                            && bank.as_ref().is_none_or(|b| iban.contains(b)))
+                })
+        })
+        .collect();
+    match matches.as_slice() {
+        [account] => Ok(account.clone()),
+        [] => Err(AlrError::Str(format!(
+            "No account found for {}",
+            statement.account_id
+        )))?,
+        _ => Err(AlrError::Str(format!(
+            "Several accounts match {}: {}",
+            statement.account_id,
+            matches
+                .iter()
+                .map(|a| a.name(AccountNameDepth::unlimited()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        )))?,
+    }
+}
+
+/// Whether the payee of an existing transaction could be the one found in the
+/// statement.  Banks often add extra text to the name (card number, city,...)
+/// so we only check that one contains the other.
+fn similar_payee(tx: &Transaction, name: Option<&str>) -> bool {
+    match (tx.payee(), name) {
+        (Some(payee), Some(name)) => {
+            let payee = payee.get_name().to_lowercase();
+            let name = name.to_lowercase();
+            payee.contains(&name) || name.contains(&payee)
+        }
+        _ => true,
+    }
+}
+
+/// What to do with a line of a statement
+#[derive(Debug)]
+pub enum LineStatus {
+    /// The line is not known yet, and will be added as a new transaction
+    New,
+
+    /// The line was imported before (one of the splits has the same FITID)
+    AlreadyImported(Transaction),
+
+    /// The line looks like a transaction that was entered manually (same
+    /// amount, close dates and similar payee).  It will not be added, but the
+    /// existing split is associated with the FITID so that the next imports
+    /// recognize it.
+    Duplicate(Transaction),
+}
+
+/// The result of comparing a statement with the repository
+pub struct StatementImport<'a> {
+    pub account: Account,
+    pub commodity: Commodity,
+    pub lines: Vec<(&'a StatementLine, LineStatus)>,
+}
+
+impl StatementImport<'_> {
+    #[must_use]
+    pub fn count_new(&self) -> usize {
+        self.lines
+            .iter()
+            .filter(|(_, s)| matches!(s, LineStatus::New))
+            .count()
+    }
+}
+
+/// Compare each line of the statement with the existing transactions of the
+/// account.  This does not modify the repository, see [`merge`].
+pub fn prepare<'a>(
+    repo: &Repository,
+    statement: &'a Statement,
+) -> Result<StatementImport<'a>> {
+    let account = find_account(repo, statement)?;
+    let commodity =
+        repo.commodities.find(&statement.currency).ok_or_else(|| {
+            AlrError::Str(format!("Unknown currency {}", statement.currency))
+        })?;
+
+    // Existing splits that were already matched with a line of the statement
+    let mut claimed: Vec<Transaction> = Vec::new();
+    let mut lines = Vec::new();
+
+    for line in &statement.lines {
+        let expected = MultiValue::new(line.amount, &commodity);
+        let mut imported = None;
+        let mut duplicate: Option<(i64, Transaction)> = None;
+
+        for tx in account.iter_transactions() {
+            for s in tx.splits().iter().filter(|s| s.account == account) {
+                if s.bank_id.as_deref() == Some(line.fitid.as_str()) {
+                    imported = Some(tx.clone());
+                } else if s.bank_id.is_none()
+                    && matches!(&s.operation, Operation::Credit(v) if *v == expected)
+                    && similar_payee(&tx, line.name.as_deref())
+                    && !claimed.contains(&tx)
+                {
+                    let days = (s.post_ts - line.posted).num_days().abs();
+                    if days <= DUPLICATE_MAX_DAYS
+                        && duplicate.as_ref().is_none_or(|(d, _)| days < *d)
+                    {
+                        duplicate = Some((days, tx.clone()));
+                    }
+                }
+            }
+        }
+
+        let status = match (imported, duplicate) {
+            (Some(tx), _) => LineStatus::AlreadyImported(tx),
+            (None, Some((_, tx))) => {
+                claimed.push(tx.clone());
+                LineStatus::Duplicate(tx)
+            }
+            (None, None) => LineStatus::New,
+        };
+        lines.push((line, status));
+    }
+
+    Ok(StatementImport {
+        account,
+        commodity,
+        lines,
+    })
+}
+
+/// Add the new lines of the statement to the repository, and associate the
+/// duplicates with their FITID.
+/// New transactions are balanced with a special "Imbalance" account, until
+/// the user assigns a category to them.
+pub fn merge(repo: &mut Repository, import: &StatementImport) -> Result<()> {
+    for (line, status) in &import.lines {
+        match status {
+            LineStatus::AlreadyImported(_) => {}
+            LineStatus::Duplicate(tx) => {
+                tx.set_bank_id(&import.account, &line.fitid);
+            }
+            LineStatus::New => {
+                let payee =
+                    line.name.as_deref().map(|n| repo.get_or_add_payee(n));
+                let imbalance = repo.get_or_add_imbalance_account();
+                let mut tx = Transaction::new_with_details(TransactionArgs {
+                    memo: line.memo.as_deref(),
+                    check_number: line.check_number.as_deref(),
+                    payee,
+                    entry_date: Local::now(),
+                });
+                tx.add_split(
+                    import.account.clone(),
+                    ReconcileKind::Cleared,
+                    line.posted,
+                    Operation::Credit(MultiValue::new(
+                        line.amount,
+                        &import.commodity,
+                    )),
+                );
+                tx.add_split(
+                    imbalance,
+                    ReconcileKind::New,
+                    line.posted,
+                    Operation::Credit(MultiValue::new(
+                        -line.amount,
+                        &import.commodity,
+                    )),
+                );
+                tx.set_bank_id(&import.account, &line.fitid);
+                repo.add_transaction(tx)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        commodities::test::create_currency,
+        multi_values::{MultiValue, Operation},
+        ofx::{LineStatus, merge, parse, prepare},
+        repositories::Repository,
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal_macros::dec;
+
+    const SGML: &str = "OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+ENCODING:USASCII
+CHARSET:1252
+
+<OFX>
+<SIGNONMSGSRSV1><SONRS>
+<STATUS><CODE>0<SEVERITY>INFO</STATUS>
+<DTSERVER>20240201120000
+</SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1><STMTTRNRS><TRNUID>1
+<STMTRS>
+<CURDEF>EUR
+<BANKACCTFROM>
+<BANKID>30004
+<BRANCHID>00123
+<ACCTID>00012345678
+<ACCTTYPE>CHECKING
+</BANKACCTFROM>
+<BANKTRANLIST>
+<DTSTART>20240101
+<DTEND>20240131
+<STMTTRN>
+<TRNTYPE>DEBIT
+<DTPOSTED>20240112
+<TRNAMT>-42,50
+<FITID>F0001
+<NAME>CB CARREFOUR PARIS
+<MEMO>Courses
+</STMTTRN>
+<STMTTRN>
+<TRNTYPE>CHECK
+<DTPOSTED>20240115000000.000[+1:CET]
+<TRNAMT>-100.00
+<FITID>F0002
+<CHECKNUM>1234567
+<NAME>Plumber &amp; Co
+</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>1000.00<DTASOF>20240131</LEDGERBAL>
+</STMTRS>
+</STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+    const XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE"?>
+<OFX>
+  <CREDITCARDMSGSRSV1>
+    <CCSTMTTRNRS>
+      <CCSTMTRS>
+        <CURDEF>EUR</CURDEF>
+        <CCACCTFROM><ACCTID>4970-1234</ACCTID></CCACCTFROM>
+        <BANKTRANLIST>
+          <STMTTRN>
+            <TRNTYPE>DEBIT</TRNTYPE>
+            <DTPOSTED>20240203</DTPOSTED>
+            <TRNAMT>-12.00</TRNAMT>
+            <FITID>C1</FITID>
+            <PAYEE><NAME>Bakery</NAME></PAYEE>
+            <MEMO/>
+          </STMTTRN>
+        </BANKTRANLIST>
+      </CCSTMTRS>
+    </CCSTMTTRNRS>
+  </CREDITCARDMSGSRSV1>
+</OFX>
+"#;
+
+    #[test]
+    fn test_parse_sgml() -> Result<()> {
+        let statements = parse(SGML)?;
+        let [st] = statements.as_slice() else {
+            panic!("Expected one statement, got {statements:?}");
+        };
+        assert_eq!(st.bank_id.as_deref(), Some("30004"));
+        assert_eq!(st.branch_id.as_deref(), Some("00123"));
+        assert_eq!(st.account_id, "00012345678");
+        assert_eq!(st.currency, "EUR");
+        let [l, l2] = st.lines.as_slice() else {
+            panic!("Expected two lines, got {:?}", st.lines);
+        };
+        assert_eq!(l.fitid, "F0001");
+        assert_eq!(l.kind, "DEBIT");
+        assert_eq!(l.amount, dec!(-42.50));
+        assert_eq!(
+            l.posted,
+            Local.with_ymd_and_hms(2024, 1, 12, 0, 0, 0).unwrap()
+        );
+        assert_eq!(l.name.as_deref(), Some("CB CARREFOUR PARIS"));
+        assert_eq!(l.memo.as_deref(), Some("Courses"));
+        let l = l2;
+        assert_eq!(
+            l.posted,
+            Local.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap()
+        );
+        assert_eq!(l.check_number.as_deref(), Some("1234567"));
+        assert_eq!(l.name.as_deref(), Some("Plumber & Co"));
+        assert_eq!(l.memo, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_xml() -> Result<()> {
+        let statements = parse(XML)?;
+        let [st] = statements.as_slice() else {
+            panic!("Expected one statement, got {statements:?}");
+        };
+        assert_eq!(st.bank_id, None);
+        assert_eq!(st.account_id, "4970-1234");
+        let [l] = st.lines.as_slice() else {
+            panic!("Expected one line, got {:?}", st.lines);
+        };
+        assert_eq!(l.fitid, "C1");
+        assert_eq!(l.amount, dec!(-12));
+        assert_eq!(l.name.as_deref(), Some("Bakery"));
+        assert_eq!(l.memo, None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let expense_kind =
+            repo.account_kinds.lookup("expense").unwrap().clone();
+        let checking = repo.accounts.add(
+            "Checking",
+            checking_kind,
+            None,
+            None,
+            None,
+            Some("FR76 3000 4001 2300 0123 4567 890"),
+            None,
+            false,
+            None,
+        );
+        let groceries = repo.accounts.add_dummy("Groceries", expense_kind);
+
+        // A transaction entered manually, two days before the bank
+        let d = Local.with_ymd_and_hms(2024, 1, 10, 0, 0, 0).unwrap();
+        let payee = repo.payees.add("Carrefour");
+        let mut tx = Transaction::new_with_details(TransactionArgs {
+            memo: None,
+            check_number: None,
+            payee: Some(payee),
+            entry_date: d,
+        });
+        tx.add_split(
+            checking.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(-42.5), &eur)),
+        );
+        tx.add_split(
+            groceries.clone(),
+            ReconcileKind::New,
+            d,
+            Operation::Credit(MultiValue::new(dec!(42.5), &eur)),
+        );
+        repo.add_transaction(tx)?;
+
+        let statements = parse(SGML)?;
+        let st = statements.first().unwrap();
+        let import = prepare(&repo, st)?;
+        assert_eq!(import.account, checking);
+        assert!(matches!(
+            import.lines.as_slice(),
+            [(_, LineStatus::Duplicate(_)), (_, LineStatus::New)]
+        ));
+        assert_eq!(import.count_new(), 1);
+        merge(&mut repo, &import)?;
+        assert_eq!(repo.transactions.iter().count(), 2);
+
+        let imbalance = repo.get_or_add_imbalance_account();
+        assert_eq!(imbalance.iter_transactions().count(), 1);
+
+        // Importing again does not change anything
+        let import = prepare(&repo, st)?;
+        assert!(
+            import
+                .lines
+                .iter()
+                .all(|(_, s)| matches!(s, LineStatus::AlreadyImported(_)))
+        );
+        merge(&mut repo, &import)?;
+        assert_eq!(repo.transactions.iter().count(), 2);
+
+        // The XML statement is for another account
+        let statements = parse(XML)?;
+        assert!(prepare(&repo, statements.first().unwrap()).is_err());
+        Ok(())
+    }
+}
@@ -15,6 +15,14 @@ impl Payee {
     }
 }
 
+impl PartialEq for Payee {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self.0.as_ptr(), other.0.as_ptr())
+    }
+}
+
+impl Eq for Payee {}
+
 #[derive(Default)]
 pub struct PayeeCollection {
     payees: Vec<Payee>,
@@ -28,6 +36,19 @@ impl PayeeCollection {
         self.payees.push(p.clone());
         p
     }
+
+    /// Find a payee by name.  This is case-insensitive.
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<Payee> {
+        self.payees
+            .iter()
+            .find(|p| p.get_name().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Payee> {
+        self.payees.iter()
+    }
 }
 
 #[derive(Debug)]
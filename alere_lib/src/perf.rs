@@ -3,15 +3,46 @@ use crate::{
     commodities::Commodity,
     market_prices::MarketPrices,
     multi_values::{MultiValue, Operation, Value},
+    networth::GroupBy,
     repositories::Repository,
+    times::{Intv, TimeInterval},
+    tree_keys::Key,
+    trees::Tree,
 };
 use anyhow::Result;
 use chrono::{DateTime, Local};
+use itertools::Itertools;
 use rust_decimal::Decimal;
 use rust_decimal::prelude::ToPrimitive;
 
 pub struct Settings {
     pub commodity: Option<Commodity>,
+
+    // How accounts are grouped in a Portfolio
+    pub group_by: GroupBy,
+
+    // Compute the performance of a Portfolio over each of these intervals,
+    // in addition to the performance since the first transaction.
+    pub intervals: Vec<Intv>,
+
+    // Compare each group of accounts with investing the same amounts in
+    // this commodity (typically an index fund)
+    pub benchmark: Option<Commodity>,
+}
+
+/// The state of an account after one of its transactions, used to compute
+/// the time-weighted return.
+struct Snapshot {
+    ts: DateTime<Local>,
+
+    // The number of shares for stock accounts, the money otherwise
+    holdings: MultiValue,
+
+    // Whether money or shares were moved in or out of the account
+    is_flow: bool,
+
+    // Dividends received, which count as returns
+    income: MultiValue,
 }
 
 #[derive(Default)]
@@ -27,6 +58,7 @@ struct PerfArgs {
 
     first_tx: Option<DateTime<Local>>,
     cash_flows: Vec<(DateTime<Local>, Decimal)>,
+    timeline: Vec<Snapshot>,
 }
 
 pub struct Performance {
@@ -36,14 +68,379 @@ pub struct Performance {
     pub realized: MultiValue,
     pub equity: MultiValue,
     pub roi: Option<Decimal>,
-    pub period_roi: Option<Decimal>,
     pub annualized_roi: Option<Decimal>,
     pub irr: Option<Decimal>,
+    pub twr: Option<Decimal>,
     pub pnl: MultiValue,
-    pub period_pnl: MultiValue,
     pub average_cost: Option<MultiValue>,
     pub weighted_average: Option<MultiValue>,
     pub price: Option<MultiValue>,
+    cash_flows: Vec<(DateTime<Local>, Decimal)>,
+    timeline: Vec<Snapshot>,
+}
+
+/// The combined performance of a group of accounts
+#[derive(Clone, Default)]
+pub struct PortfolioPerformance {
+    pub invested: MultiValue,
+    pub realized: MultiValue,
+    pub equity: MultiValue,
+    pub pnl: MultiValue,
+    pub roi: Option<Decimal>,
+    pub irr: Option<Decimal>,
+    pub twr: Option<Decimal>,
+
+    // One entry per interval in the settings
+    pub periods: Vec<PeriodPerformance>,
+
+    pub benchmark: Option<Benchmark>,
+
+    // Index of the accounts in the group
+    members: Vec<usize>,
+}
+
+impl PortfolioPerformance {
+    fn compute(
+        &mut self,
+        perfs: &[Performance],
+        prices: &mut MarketPrices,
+        settings: &Settings,
+        intervals: &[TimeInterval],
+        now: DateTime<Local>,
+    ) {
+        let members = self
+            .members
+            .iter()
+            .filter_map(|idx| perfs.get(*idx))
+            .collect::<Vec<_>>();
+        for p in &members {
+            self.invested += &p.invested;
+            self.realized += &p.realized;
+            self.equity += &p.equity;
+            self.pnl += &p.pnl;
+        }
+        self.roi = (&self.equity + &self.realized) / &self.invested;
+
+        // IRR and TWR require all values in the same currency
+        if let Some(target) = &settings.commodity {
+            let mut cash_flows = members
+                .iter()
+                .flat_map(|p| p.cash_flows.iter().copied())
+                .collect::<Vec<_>>();
+            cash_flows.sort_by_key(|(ts, _)| *ts);
+            self.irr = amount_in(&self.equity, target).and_then(|equity| {
+                Performance::calculate_irr(&cash_flows, equity, now)
+            });
+            self.twr =
+                Performance::time_weighted(&members, prices, target, None, now);
+            self.periods = intervals
+                .iter()
+                .map(|intv| {
+                    PeriodPerformance::new(&members, prices, target, intv, now)
+                })
+                .collect();
+            self.benchmark = settings.benchmark.as_ref().and_then(|bench| {
+                Benchmark::new(&cash_flows, self.irr, prices, bench, now)
+            });
+        }
+    }
+}
+
+/// What the same investments would have given in a benchmark commodity
+#[derive(Clone)]
+pub struct Benchmark {
+    // Market value of the benchmark shares that would be owned now
+    pub value: MultiValue,
+    pub irr: Option<Decimal>,
+
+    // IRR of the accounts minus IRR of the benchmark
+    pub excess: Option<Decimal>,
+}
+
+impl Benchmark {
+    /// Simulate buying (or selling) the benchmark commodity for each of the
+    /// cash flows, at its market price on the same date.  Returns None if
+    /// the benchmark has no price for one of the dates.
+    pub(crate) fn new(
+        cash_flows: &[(DateTime<Local>, Decimal)],
+        irr: Option<Decimal>,
+        prices: &mut MarketPrices,
+        bench: &Commodity,
+        now: DateTime<Local>,
+    ) -> Option<Self> {
+        let mut shares = Decimal::ZERO;
+        for (ts, amount) in cash_flows {
+            let price = prices.get_price(bench, ts)?;
+            if price.is_zero() {
+                return None;
+            }
+            // Cash flows are negative when money is invested
+            shares -= amount / price;
+        }
+        let value = shares * prices.get_price(bench, &now)?;
+        let bench_irr = Performance::calculate_irr(cash_flows, value, now);
+        Some(Benchmark {
+            value: prices.convert_value(
+                &Value {
+                    amount: shares,
+                    commodity: bench.clone(),
+                },
+                &now,
+            ),
+            irr: bench_irr,
+            excess: irr.zip(bench_irr).map(|(a, b)| a - b),
+        })
+    }
+}
+
+/// The performance of a group of accounts over one time interval.  The
+/// holdings at the start of the interval are valued at market price, as if
+/// they had been bought at that time.
+#[derive(Clone, Default)]
+pub struct PeriodPerformance {
+    // Market value of the holdings at the start and end of the interval
+    pub start_value: MultiValue,
+    pub end_value: MultiValue,
+
+    // Money invested during the interval, minus the proceeds of sales
+    pub invested: MultiValue,
+
+    // Dividends received during the interval
+    pub income: MultiValue,
+
+    pub pnl: MultiValue,
+    pub roi: Option<Decimal>,
+    pub irr: Option<Decimal>,
+    pub twr: Option<Decimal>,
+}
+
+impl PeriodPerformance {
+    fn new(
+        accounts: &[&Performance],
+        prices: &mut MarketPrices,
+        target: &Commodity,
+        intv: &TimeInterval,
+        now: DateTime<Local>,
+    ) -> Self {
+        let from = intv.intv.lower().copied();
+        let to = intv.intv.upper().map_or(now, |up| (*up).min(now));
+        let value = |v: Decimal| MultiValue::new(v, target);
+
+        let start_value = from.map_or(Some(Decimal::ZERO), |f| {
+            market_value(accounts, prices, target, f, false)
+        });
+        let end_value = market_value(accounts, prices, target, to, false);
+        let income = income_between(accounts, target, from, to);
+        let (Some(start_value), Some(end_value), Some(income)) =
+            (start_value, end_value, income)
+        else {
+            return PeriodPerformance::default();
+        };
+
+        // Cash flows are negative when money is invested
+        let mut cash_flows = accounts
+            .iter()
+            .flat_map(|p| p.cash_flows.iter().copied())
+            .filter(|(ts, _)| from.is_none_or(|f| *ts >= f) && *ts < to)
+            .collect::<Vec<_>>();
+        cash_flows.sort_by_key(|(ts, _)| *ts);
+        let bought: Decimal = cash_flows
+            .iter()
+            .filter(|(_, amount)| amount.is_sign_negative())
+            .map(|(_, amount)| -amount)
+            .sum();
+        let sold: Decimal = cash_flows
+            .iter()
+            .filter(|(_, amount)| amount.is_sign_positive())
+            .map(|(_, amount)| amount)
+            .sum();
+
+        let mut irr_flows = Vec::new();
+        if let Some(f) = from
+            && !start_value.is_zero()
+        {
+            irr_flows.push((f, -start_value));
+        }
+        irr_flows.extend(cash_flows.iter().copied());
+        let invested = start_value + bought;
+
+        PeriodPerformance {
+            start_value: value(start_value),
+            end_value: value(end_value),
+            invested: value(bought - sold),
+            income: value(income),
+            pnl: value(end_value + sold + income - invested),
+            roi: if invested.is_zero() {
+                None
+            } else {
+                Some((end_value + sold + income) / invested)
+            },
+            irr: Performance::calculate_irr(&irr_flows, end_value + income, to),
+            twr: Performance::time_weighted(accounts, prices, target, from, to),
+        }
+    }
+}
+
+/// The performance of stock accounts, grouped in a tree (by parent account,
+/// institution or account kind).
+pub struct Portfolio {
+    pub tree: Tree<Key, PortfolioPerformance>,
+
+    // All stock accounts combined.  This only covers the investments, not
+    // the whole networth.
+    pub total: PortfolioPerformance,
+    pub intervals: Vec<TimeInterval>,
+}
+
+impl Portfolio {
+    pub fn new(
+        repo: &Repository,
+        perfs: &[Performance],
+        settings: &Settings,
+        now: DateTime<Local>,
+    ) -> Result<Self> {
+        let mut result = Portfolio {
+            tree: Tree::default(),
+            total: PortfolioPerformance::default(),
+            intervals: settings
+                .intervals
+                .iter()
+                .map(|intv| intv.to_ranges(now))
+                .flatten_ok() // itertools: preserve errors
+                .collect::<Result<Vec<TimeInterval>>>()?,
+        };
+
+        for (idx, p) in perfs.iter().enumerate() {
+            if p.invested.is_zero() {
+                continue;
+            }
+            let acc = &p.account;
+            let key = Key::Account(acc.clone());
+            let newrow = |_: &Key| PortfolioPerformance::default();
+            let row = match settings.group_by {
+                GroupBy::None => {
+                    result.tree.try_get(&key, std::iter::empty(), newrow)
+                }
+                GroupBy::ParentAccount => result.tree.try_get(
+                    &key,
+                    repo.accounts.iter_parents(acc).map(Key::Account),
+                    newrow,
+                ),
+                GroupBy::AccountKind => result.tree.try_get(
+                    &key,
+                    std::iter::once(Key::AccountKind(acc.get_kind())),
+                    newrow,
+                ),
+                GroupBy::Institution => result.tree.try_get(
+                    &key,
+                    std::iter::once(Key::Institution(acc.get_institution())),
+                    newrow,
+                ),
+            };
+            row.members.push(idx);
+            result.total.members.push(idx);
+        }
+
+        // Each node includes the accounts of all its children.  Parent
+        // accounts whose only child is a stock account are combined with it.
+        result.tree.traverse_mut(
+            |node| {
+                if node.data.data.members.is_empty()
+                    && matches!(settings.group_by, GroupBy::ParentAccount)
+                    && node.iter_children().all(|c| !c.has_children())
+                {
+                    node.collapse_if_one_child();
+                }
+                let children = node
+                    .iter_children()
+                    .flat_map(|c| c.data.data.members.iter().copied())
+                    .collect::<Vec<_>>();
+                node.data.data.members.extend(children);
+                Ok(())
+            },
+            false,
+        )?;
+
+        let mut prices = repo.market_prices(settings.commodity.clone());
+        let intervals = &result.intervals;
+        result.tree.traverse_mut(
+            |node| {
+                node.data.data.compute(
+                    perfs,
+                    &mut prices,
+                    settings,
+                    intervals,
+                    now,
+                );
+                Ok(())
+            },
+            true,
+        )?;
+        result
+            .total
+            .compute(perfs, &mut prices, settings, intervals, now);
+        Ok(result)
+    }
+}
+
+/// Market value of the holdings of the accounts at ts, either just before
+/// or just after the transactions at that time.
+fn market_value(
+    accounts: &[&Performance],
+    prices: &mut MarketPrices,
+    target: &Commodity,
+    ts: DateTime<Local>,
+    after: bool,
+) -> Option<Decimal> {
+    let mut total = Decimal::ZERO;
+    for p in accounts {
+        if let Some(snap) = p
+            .timeline
+            .iter()
+            .rev()
+            .find(|s| if after { s.ts <= ts } else { s.ts < ts })
+        {
+            total += amount_in(
+                &prices.convert_multi_value(&snap.holdings, &ts),
+                target,
+            )?;
+        }
+    }
+    Some(total)
+}
+
+/// Dividends received by the accounts in [from, to[
+fn income_between(
+    accounts: &[&Performance],
+    target: &Commodity,
+    from: Option<DateTime<Local>>,
+    to: DateTime<Local>,
+) -> Option<Decimal> {
+    let mut total = Decimal::ZERO;
+    for snap in accounts
+        .iter()
+        .flat_map(|p| p.timeline.iter())
+        .filter(|s| from.is_none_or(|f| s.ts >= f) && s.ts < to)
+    {
+        total += amount_in(&snap.income, target)?;
+    }
+    Some(total)
+}
+
+/// The amount in the target commodity, or None if some of the components
+/// could not be converted.
+pub(crate) fn amount_in(
+    value: &MultiValue,
+    target: &Commodity,
+) -> Option<Decimal> {
+    let mut total = Decimal::ZERO;
+    for v in value.iter() {
+        if v.commodity != *target {
+            return None;
+        }
+        total += v.amount;
+    }
+    Some(total)
 }
 
 impl Performance {
@@ -55,7 +452,7 @@ impl Performance {
     //
     // Newton-Raphson iteratively solves: r_new = r_old - NPV / NPV'
     // where NPV' is the derivative of NPV with respect to r
-    fn calculate_irr(
+    pub(crate) fn calculate_irr(
         cash_flows: &[(DateTime<Local>, Decimal)],
         final_value: Decimal,
         now: DateTime<Local>,
@@ -164,6 +561,58 @@ impl Performance {
         None
     }
 
+    // Time-Weighted Return (TWR) for a group of accounts
+    //
+    // The period is split at each external cash flow (in any of the
+    // accounts), and the return of each sub-period is computed from the
+    // market value of the holdings at its start (just after the flow) and at
+    // its end (just before the next flow), adding the dividends received.
+    // Chain-linking the sub-periods gives a return that does not depend on
+    // the amount and timing of deposits and withdrawals.
+    // If from is None, this is the return since the first transaction.
+    // Returns the growth factor (1.0 means no gain), like roi.
+    fn time_weighted(
+        accounts: &[&Performance],
+        prices: &mut MarketPrices,
+        target: &Commodity,
+        from: Option<DateTime<Local>>,
+        now: DateTime<Local>,
+    ) -> Option<Decimal> {
+        let mut flows = accounts
+            .iter()
+            .flat_map(|p| p.timeline.iter())
+            .filter(|s| {
+                s.is_flow && s.ts < now && from.is_none_or(|f| s.ts >= f)
+            })
+            .map(|s| s.ts)
+            .collect::<Vec<_>>();
+        flows.sort();
+        flows.dedup();
+
+        let mut result = None;
+        let mut start = match from {
+            None => None,
+            Some(f) => {
+                Some((f, market_value(accounts, prices, target, f, false)?))
+            }
+        };
+        for ts in flows.into_iter().chain(std::iter::once(now)) {
+            if let Some((start_ts, start_value)) = start
+                && !start_value.is_zero()
+            {
+                let end_value =
+                    market_value(accounts, prices, target, ts, false)?
+                        + income_between(accounts, target, Some(start_ts), ts)?;
+                result = Some(
+                    result.unwrap_or(Decimal::ONE) * end_value / start_value,
+                );
+            }
+            start =
+                Some((ts, market_value(accounts, prices, target, ts, true)?));
+        }
+        result
+    }
+
     fn new(
         account: &Account,
         args: PerfArgs,
@@ -223,14 +672,13 @@ impl Performance {
             None
         };
 
-        Performance {
+        let mut perf = Performance {
             account: account.clone(),
             roi,
-            period_roi: None,
             annualized_roi,
             irr,
+            twr: None,
             pnl: &equity - &args.invested + &args.realized,
-            period_pnl: MultiValue::default(),
             average_cost: shares.map(|s| (&args.invested - &args.realized) / s),
             weighted_average: shares.map(|s| &args.invested / s),
             price: args.shares.commodity().map(|c| {
@@ -243,12 +691,18 @@ impl Performance {
             shares: args.shares,
             invested: args.invested,
             realized: args.realized,
-        }
+            cash_flows: args.cash_flows,
+            timeline: args.timeline,
+        };
+        perf.twr = target_commodity.and_then(|target| {
+            Self::time_weighted(&[&perf], prices, target, None, end_date)
+        });
+        perf
     }
 
     pub fn load(
         repo: &Repository,
-        settings: Settings,
+        settings: &Settings,
         now: DateTime<Local>,
     ) -> Result<Vec<Self>> {
         let mut result = Vec::new();
@@ -279,6 +733,8 @@ impl Performance {
                 let mut external_amount = MultiValue::zero();
                 let mut internal_unrealized = MultiValue::zero();
                 let mut is_unrealized = false;
+                let mut is_flow = false;
+                let mut income = MultiValue::zero();
                 for s in tx.splits().iter() {
                     if s.account != acc {
                         match &s.operation {
@@ -320,6 +776,7 @@ impl Performance {
                                 if is_unrealized {
                                     args.unrealized += &v2;
                                 } else {
+                                    is_flow = true;
                                     if let Some(val) = v2.iter().next() {
                                         args.cash_flows
                                             .push((s.post_ts, -val.amount));
@@ -328,9 +785,11 @@ impl Performance {
                                 }
                             }
                             Operation::AddShares { qty } => {
+                                is_flow = true;
                                 args.shares += qty;
                             }
                             Operation::BuyAmount { qty, amount } => {
+                                is_flow = true;
                                 args.shares += qty;
 
                                 if !qty.is_negative() {
@@ -404,6 +863,7 @@ impl Performance {
                                 }
                             }
                             Operation::BuyPrice { qty, price } => {
+                                is_flow = true;
                                 args.shares += qty;
                                 let fees = prices.convert_multi_value(
                                     &external_amount,
@@ -431,14 +891,15 @@ impl Performance {
                             Operation::Dividend => {
                                 //  Also count internal_unrealized in case the
                                 //  dividend was wrongly classified by user.
-                                args.realized += prices.convert_multi_value(
+                                let dividend = &prices.convert_multi_value(
                                     &external_amount,
                                     &s.post_ts,
-                                );
-                                args.realized -= prices.convert_multi_value(
+                                ) - &prices.convert_multi_value(
                                     &internal_unrealized,
                                     &s.post_ts,
                                 );
+                                args.realized += &dividend;
+                                income += dividend;
                             }
                         };
                     }
@@ -451,6 +912,24 @@ impl Performance {
                 //    args.realized.display(&Formatter::default())
                 //);
                 //dbg!(tx, &args.shares, &args.invested, &args.realized);
+
+                if let Some(ts) = tx
+                    .splits()
+                    .iter()
+                    .find(|s| s.account == acc)
+                    .map(|s| s.post_ts)
+                {
+                    args.timeline.push(Snapshot {
+                        ts,
+                        holdings: if acc.get_kind().is_stock() {
+                            args.shares.clone()
+                        } else {
+                            &args.invested + &args.unrealized
+                        },
+                        is_flow,
+                        income,
+                    });
+                }
             }
 
             result.push(Performance::new(
@@ -944,4 +1423,158 @@ mod tests {
             irr_val
         );
     }
+
+    #[test]
+    fn test_portfolio() -> Result<()> {
+        use crate::{
+            accounts::AccountNameDepth,
+            commodities::test::create_currency,
+            price_sources::PriceSourceFrom,
+            prices::Price,
+            transactions::{ReconcileKind, Transaction, TransactionArgs},
+        };
+        use rust_decimal_macros::dec;
+
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let acme = create_currency(&mut repo.commodities, "ACME", 2, false);
+        let beta = create_currency(&mut repo.commodities, "BETA", 2, false);
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let broker = repo.accounts.add_dummy("Broker", checking_kind.clone());
+        let mut acc_acme = repo.accounts.add_dummy("ACME", stock_kind.clone());
+        let mut acc_beta = repo.accounts.add_dummy("BETA", stock_kind);
+        acc_acme.set_parent(broker.clone());
+        acc_beta.set_parent(broker.clone());
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+
+        let t0 = Local.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let t1 = Local.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let now = Local.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap();
+
+        // The price of ACME goes up 20%, then down 10%.  The second purchase
+        // is larger, so money-weighted returns would be lower.
+        for (acc, commodity, date, qty, amount) in [
+            (&acc_acme, &acme, t0, dec!(10), dec!(1000)),
+            (&acc_acme, &acme, t1, dec!(10), dec!(1200)),
+            (&acc_beta, &beta, t1, dec!(10), dec!(1000)),
+        ] {
+            let mut tx = Transaction::new_with_details(TransactionArgs {
+                entry_date: date,
+                ..TransactionArgs::default()
+            });
+            tx.add_split(
+                acc.clone(),
+                ReconcileKind::New,
+                date,
+                Operation::BuyAmount {
+                    qty: Value {
+                        amount: qty,
+                        commodity: commodity.clone(),
+                    },
+                    amount: Value {
+                        amount,
+                        commodity: eur.clone(),
+                    },
+                },
+            );
+            tx.add_split(
+                cash.clone(),
+                ReconcileKind::New,
+                date,
+                Operation::Credit(MultiValue::new(-amount, &eur)),
+            );
+            repo.add_transaction(tx)?;
+        }
+        for (commodity, date, price) in [
+            (&acme, t0, dec!(100)),
+            (&acme, t1, dec!(120)),
+            (&acme, now, dec!(108)),
+            (&beta, t1, dec!(100)),
+            (&beta, now, dec!(110)),
+        ] {
+            repo.add_price(
+                commodity,
+                &eur,
+                Price::new(date, price, PriceSourceFrom::Transaction),
+            );
+        }
+
+        let settings = Settings {
+            commodity: Some(eur.clone()),
+            group_by: GroupBy::ParentAccount,
+            intervals: vec![Intv::SpecificYear(2024), Intv::LastNMonths(6)],
+            benchmark: Some(acme.clone()),
+        };
+        let perfs = Performance::load(&repo, &settings, now)?;
+        let twr = |name: &str| {
+            perfs
+                .iter()
+                .find(|p| p.account.name(AccountNameDepth::basename()) == name)
+                .and_then(|p| p.twr)
+                .map(|r| r.round_dp(4))
+        };
+        assert_eq!(twr("ACME"), Some(dec!(1.08)));
+        assert_eq!(twr("BETA"), Some(dec!(1.1)));
+
+        // First half: 1200 / 1000, second half: (2160 + 1100) / (2400 + 1000)
+        let portfolio = Portfolio::new(&repo, &perfs, &settings, now)?;
+        let mut broker = None;
+        portfolio.tree.traverse(
+            |node| {
+                if node.has_children() {
+                    broker = Some((
+                        node.data.data.invested.clone(),
+                        node.data.data.twr.map(|r| r.round_dp(4)),
+                    ));
+                }
+                Ok(())
+            },
+            true,
+        )?;
+        assert_eq!(
+            broker,
+            Some((MultiValue::new(dec!(3200), &eur), Some(dec!(1.1506))))
+        );
+        let total = &portfolio.total;
+        assert_eq!(total.twr.map(|r| r.round_dp(4)), Some(dec!(1.1506)));
+        assert_eq!(total.pnl, MultiValue::new(dec!(60), &eur));
+
+        // Investing everything in ACME would have given 28.33 shares
+        let bench = total.benchmark.as_ref().unwrap();
+        assert_eq!(
+            bench
+                .value
+                .iter()
+                .map(|v| v.amount.round_dp(2))
+                .sum::<Decimal>(),
+            dec!(3060)
+        );
+        assert!(bench.excess.unwrap() > Decimal::ZERO);
+
+        // Over the last six months, the ACME shares bought earlier are
+        // valued at the market price at the start.
+        let periods = total
+            .periods
+            .iter()
+            .map(|p| {
+                format!(
+                    "{:?} {:?} {:?} {:?}",
+                    p.start_value.iter().map(|v| v.amount).sum::<Decimal>(),
+                    p.pnl.iter().map(|v| v.amount).sum::<Decimal>(),
+                    p.roi.map(|r| r.round_dp(4)),
+                    p.twr.map(|r| r.round_dp(4)),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            periods,
+            vec![
+                "0 60 Some(1.0188) Some(1.1506)",
+                "1200 -140 Some(0.9588) Some(0.9588)",
+            ]
+        );
+        Ok(())
+    }
 }
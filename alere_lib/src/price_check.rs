@@ -0,0 +1,265 @@
+//! Detection of suspicious prices.
+//!
+//! Bad quotes, like prices that were not adjusted after a split or a security
+//! quoted in pence rather than pounds, silently distort the market value of
+//! the holdings.  We look for sudden jumps between consecutive prices, for
+//! prices that disagree with the prices actually paid in nearby transactions,
+//! and for commodities that are still held but no longer have recent prices.
+
+use crate::{
+    commodities::Commodity, price_sources::PriceSourceFrom, prices::Price,
+    repositories::Repository,
+};
+use chrono::{DateTime, Local, TimeDelta};
+use itertools::Itertools;
+use rust_decimal::Decimal;
+
+pub struct Settings {
+    // Relative change between two consecutive prices above which they are
+    // reported (0.5 for 50%)
+    pub max_jump: Decimal,
+
+    // Relative difference with the price of a transaction above which a
+    // price is reported
+    pub max_deviation: Decimal,
+
+    // How far from a price we look for transactions
+    pub window: TimeDelta,
+
+    // Commodities still held are reported when their latest price is older
+    pub max_age: TimeDelta,
+
+    // The reporting currency, which needs no price.  The staleness is that
+    // of the price used to convert to it.
+    pub commodity: Option<Commodity>,
+}
+
+pub enum Anomaly {
+    /// The price changed too much since the previous one
+    Jump {
+        origin: Commodity,
+        target: Commodity,
+        previous: Price,
+        price: Price,
+    },
+
+    /// A price disagrees with the price paid in a nearby transaction
+    Contradiction {
+        origin: Commodity,
+        target: Commodity,
+        price: Price,
+        transaction: Price,
+    },
+
+    /// A commodity still held has no recent price (or no price at all)
+    Stale {
+        commodity: Commodity,
+        latest: Option<DateTime<Local>>,
+    },
+}
+
+impl Anomaly {
+    /// The relative difference between the price and the one it is compared
+    /// with.
+    #[must_use]
+    pub fn change(&self) -> Option<Decimal> {
+        match self {
+            Anomaly::Jump {
+                previous, price, ..
+            } => relative_change(previous, price),
+            Anomaly::Contradiction {
+                transaction, price, ..
+            } => relative_change(transaction, price),
+            Anomaly::Stale { .. } => None,
+        }
+    }
+}
+
+fn relative_change(reference: &Price, price: &Price) -> Option<Decimal> {
+    if reference.price.is_zero() {
+        None
+    } else {
+        Some(price.price / reference.price - Decimal::ONE)
+    }
+}
+
+/// Scan all known prices for anomalies, grouped by pair of commodities.
+#[must_use]
+pub fn check_prices(
+    repo: &Repository,
+    settings: &Settings,
+    now: DateTime<Local>,
+) -> Vec<Anomaly> {
+    let mut result = Vec::new();
+    let pairs = repo.prices().iter().sorted_by_cached_key(|((o, t), _)| {
+        (o.get_symbol().clone(), t.get_symbol().clone())
+    });
+
+    for ((origin, target), prices) in pairs {
+        for (previous, price) in prices.iter().tuple_windows() {
+            if relative_change(previous, price)
+                .is_some_and(|c| c.abs() > settings.max_jump)
+            {
+                result.push(Anomaly::Jump {
+                    origin: origin.clone(),
+                    target: target.clone(),
+                    previous: previous.clone(),
+                    price: price.clone(),
+                });
+            }
+        }
+
+        // Transactions register their prices in either direction
+        let is_transaction =
+            |p: &&Price| p.source() == PriceSourceFrom::Transaction;
+        let transactions = prices
+            .iter()
+            .filter(is_transaction)
+            .cloned()
+            .chain(
+                repo.prices()
+                    .prices
+                    .get(&(target.clone(), origin.clone()))
+                    .into_iter()
+                    .flatten()
+                    .filter(is_transaction)
+                    .filter(|p| !p.price.is_zero())
+                    .map(Price::invert),
+            )
+            .collect::<Vec<_>>();
+        for price in prices
+            .iter()
+            .filter(|p| p.source() != PriceSourceFrom::Transaction)
+        {
+            let distance = |t: &&Price| (t.timestamp - price.timestamp).abs();
+            if let Some(transaction) = transactions
+                .iter()
+                .filter(|t| distance(t) <= settings.window)
+                .min_by_key(distance)
+                && relative_change(transaction, price)
+                    .is_some_and(|c| c.abs() > settings.max_deviation)
+            {
+                result.push(Anomaly::Contradiction {
+                    origin: origin.clone(),
+                    target: target.clone(),
+                    price: price.clone(),
+                    transaction: transaction.clone(),
+                });
+            }
+        }
+    }
+
+    #[allow(clippy::mutable_key_type)]
+    let balances = repo.compute_commodity_balances();
+    let held = balances
+        .iter()
+        .filter(|(c, amount)| {
+            !amount.is_zero() && Some(*c) != settings.commodity.as_ref()
+        })
+        .map(|(c, _)| c)
+        .sorted_by_cached_key(|c| c.get_symbol().clone());
+    // Old prices must be found to be reported, whatever the maximum
+    // staleness of reports
+    let mut market = repo
+        .market_prices(settings.commodity.clone())
+        .with_max_staleness(None);
+    for commodity in held {
+        // The date of the price used for the market value, or lacking a
+        // reporting currency, of the latest price
+        let latest = match &settings.commodity {
+            Some(_) => market
+                .get_price_with_date(commodity, &now)
+                .map(|p| p.timestamp),
+            None => repo
+                .prices()
+                .iter()
+                .filter(|((o, t), _)| o == commodity || t == commodity)
+                .filter_map(|(_, p)| p.last())
+                .map(|p| p.timestamp)
+                .max(),
+        };
+        if latest.is_none_or(|ts| now - ts > settings.max_age) {
+            result.push(Anomaly::Stale {
+                commodity: commodity.clone(),
+                latest,
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        commodities::test::{create_currency, create_security},
+        price_check::{Anomaly, Settings, check_prices},
+        price_sources::PriceSourceFrom,
+        prices::Price,
+        repositories::Repository,
+    };
+    use chrono::{Local, TimeDelta, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_check_prices() {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let acme = create_security(&mut repo.commodities, "ACME");
+        let source = repo.get_or_add_price_source("manual");
+        let day = |d: u32| Local.with_ymd_and_hms(2024, 3, d, 0, 0, 0).unwrap();
+        for (d, price) in [(4, dec!(100)), (5, dec!(102)), (6, dec!(10100))] {
+            repo.add_price(
+                &acme,
+                &eur,
+                Price::new(
+                    day(d),
+                    price,
+                    PriceSourceFrom::External(source.get_id()),
+                ),
+            );
+        }
+        repo.add_price(
+            &acme,
+            &eur,
+            Price::new(day(7), dec!(101), PriceSourceFrom::Transaction),
+        );
+
+        let settings = Settings {
+            max_jump: dec!(0.5),
+            max_deviation: dec!(0.2),
+            window: TimeDelta::days(3),
+            max_age: TimeDelta::days(7),
+            commodity: Some(eur.clone()),
+        };
+        let anomalies = check_prices(&repo, &settings, day(10));
+        let summary = anomalies
+            .iter()
+            .map(|a| match a {
+                Anomaly::Jump { price, .. } => {
+                    format!("jump {}", price.timestamp.date_naive())
+                }
+                Anomaly::Contradiction { price, .. } => {
+                    format!("contradiction {}", price.timestamp.date_naive())
+                }
+                Anomaly::Stale { commodity, .. } => {
+                    format!("stale {}", commodity.get_symbol())
+                }
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                "jump 2024-03-06",
+                "jump 2024-03-07",
+                "contradiction 2024-03-06"
+            ]
+        );
+        assert_eq!(
+            anomalies
+                .first()
+                .and_then(Anomaly::change)
+                .map(|c| c.round_dp(2)),
+            Some(dec!(98.02))
+        );
+    }
+}
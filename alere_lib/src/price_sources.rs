@@ -56,6 +56,27 @@ impl PriceSourceCollection {
         self.sources.insert(id, s.clone());
         s
     }
+
+    /// Lookup a source by name
+    #[must_use]
+    pub fn find(&self, name: &str) -> Option<PriceSource> {
+        self.sources
+            .values()
+            .find(|s| *s.get_name() == name)
+            .cloned()
+    }
+
+    #[must_use]
+    pub fn get(&self, id: PriceSourceId) -> Option<&PriceSource> {
+        self.sources.get(&id)
+    }
+
+    /// Return all sources, sorted by id
+    pub fn iter(&self) -> impl Iterator<Item = &PriceSource> {
+        let mut all = self.sources.values().collect::<Vec<_>>();
+        all.sort_by_key(|s| s.get_id().0);
+        all.into_iter()
+    }
 }
 
 #[derive(Debug)]
@@ -1,8 +1,8 @@
 use crate::commodities::Commodity;
 use crate::price_sources::PriceSourceFrom;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
 use rust_decimal::Decimal;
-use std::collections::HashMap;
+use std::{collections::HashMap, ops::RangeInclusive};
 
 #[derive(Default)]
 pub struct PriceCollection {
@@ -27,13 +27,96 @@ impl PriceCollection {
         };
         p.insert(pos, price);
     }
+
+    /// Remove all prices of origin in target (or the reverse) on the given
+    /// date, and return them.
+    pub fn remove(
+        &mut self,
+        origin: &Commodity,
+        target: &Commodity,
+        date: NaiveDate,
+    ) -> Vec<Price> {
+        let mut removed = Vec::new();
+        for pair in [
+            (origin.clone(), target.clone()),
+            (target.clone(), origin.clone()),
+        ] {
+            if let Some(p) = self.prices.get_mut(&pair) {
+                let (gone, kept): (Vec<_>, Vec<_>) = std::mem::take(p)
+                    .into_iter()
+                    .partition(|pr| pr.timestamp.date_naive() == date);
+                *p = kept;
+                removed.extend(gone);
+                if p.is_empty() {
+                    self.prices.remove(&pair);
+                }
+            }
+        }
+        removed
+    }
+
+    /// Iterate over all known pairs of commodities, and their sorted list of
+    /// historical prices.
+    pub fn iter(
+        &self,
+    ) -> impl Iterator<Item = (&(Commodity, Commodity), &Vec<Price>)> {
+        self.prices.iter()
+    }
+
+    /// The days, between since and until, for which we have no price for
+    /// origin in target (or the reverse).  Gaps shorter than max_gap (for
+    /// instance weekends or bank holidays) are ignored.
+    #[must_use]
+    pub fn missing_ranges(
+        &self,
+        origin: &Commodity,
+        target: &Commodity,
+        since: DateTime<Local>,
+        until: DateTime<Local>,
+        max_gap: TimeDelta,
+    ) -> Vec<RangeInclusive<NaiveDate>> {
+        let mut known = [
+            (origin.clone(), target.clone()),
+            (target.clone(), origin.clone()),
+        ]
+        .iter()
+        .filter_map(|pair| self.prices.get(pair))
+        .flatten()
+        .map(|p| p.timestamp)
+        .filter(|ts| *ts > since && *ts < until)
+        .collect::<Vec<_>>();
+        known.sort();
+
+        let mut result = Vec::new();
+        let mut previous: Option<DateTime<Local>> = None;
+        for ts in known.iter().map(Some).chain([None]) {
+            let start = previous.unwrap_or(since);
+            let end = ts.copied().unwrap_or(until);
+            if end - start > max_gap {
+                // Known prices are excluded, but not the boundaries
+                let first = match previous {
+                    Some(p) => p.date_naive() + TimeDelta::days(1),
+                    None => since.date_naive(),
+                };
+                let last = match ts {
+                    Some(t) => t.date_naive() - TimeDelta::days(1),
+                    None => until.date_naive(),
+                };
+                if first <= last {
+                    result.push(first..=last);
+                }
+            }
+            previous = ts.copied();
+        }
+        result
+    }
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Price {
     pub timestamp: DateTime<Local>,
     pub price: Decimal,
-    _source: PriceSourceFrom,
+    source: PriceSourceFrom,
 }
 
 impl Price {
@@ -47,10 +130,15 @@ impl Price {
         Price {
             timestamp,
             price,
-            _source: source,
+            source,
         }
     }
 
+    #[must_use]
+    pub fn source(&self) -> PriceSourceFrom {
+        self.source
+    }
+
     /// Compare two prices chronologically.
     /// We do not implement std::cmd::PartialOrd since it seems like the latter
     /// should compare actual prices.
@@ -78,7 +166,57 @@ impl Price {
         Price {
             timestamp: self.timestamp,
             price: Decimal::ONE / self.price,
-            _source: self._source,
+            source: self.source,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        commodities::test::{create_currency, create_security},
+        price_sources::PriceSourceFrom,
+        prices::{Price, PriceCollection},
+        repositories::Repository,
+    };
+    use chrono::{Local, TimeDelta, TimeZone};
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_missing_ranges() {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let acme = create_security(&mut repo.commodities, "ACME");
+        let day = |d: u32| Local.with_ymd_and_hms(2024, 3, d, 0, 0, 0).unwrap();
+        let mut prices = PriceCollection::default();
+        for d in [4, 5, 6, 7, 8, 11, 20] {
+            prices.add(
+                &acme,
+                &eur,
+                Price::new(day(d), dec!(1), PriceSourceFrom::Transaction),
+            );
         }
+        let missing = |from, to| {
+            prices
+                .missing_ranges(from, to, day(1), day(25), TimeDelta::days(4))
+                .iter()
+                .map(|r| format!("{}..{}", r.start(), r.end()))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            missing(&acme, &eur),
+            vec!["2024-03-12..2024-03-19", "2024-03-21..2024-03-25"]
+        );
+
+        // Prices are also looked up in the reverse direction
+        assert_eq!(
+            missing(&eur, &acme),
+            vec!["2024-03-12..2024-03-19", "2024-03-21..2024-03-25"]
+        );
+
+        let removed = prices.remove(&eur, &acme, day(11).date_naive());
+        assert_eq!(removed.len(), 1);
+        assert!(prices.remove(&acme, &eur, day(11).date_naive()).is_empty());
+        assert_eq!(prices.iter().map(|(_, p)| p.len()).sum::<usize>(), 6);
     }
 }
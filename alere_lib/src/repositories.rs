@@ -1,17 +1,23 @@
 use crate::{
     account_kinds::AccountKindCollection,
-    accounts::AccountCollection,
+    accounts::{
+        Account, AccountCollection, AccountNameDepth, IMBALANCE_ACCOUNT,
+    },
+    budgets::BudgetCollection,
     commodities::{Commodity, CommodityCollection},
     institutions::InstitutionCollection,
     market_prices::MarketPrices,
     multi_values::Operation,
-    payees::PayeeCollection,
-    price_sources::{PriceSourceCollection, PriceSourceFrom},
+    payees::{Payee, PayeeCollection},
+    price_sources::{
+        PriceSource, PriceSourceCollection, PriceSourceFrom, PriceSourceId,
+    },
     prices::{Price, PriceCollection},
+    schedules::ScheduleCollection,
     transactions::{Transaction, TransactionCollection},
 };
 use anyhow::Result;
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
 
 #[derive(Default)]
 pub struct Repository {
@@ -23,6 +29,11 @@ pub struct Repository {
     pub(crate) price_sources: PriceSourceCollection,
     pub(crate) prices: PriceCollection,
     pub(crate) transactions: TransactionCollection,
+    pub(crate) budgets: BudgetCollection,
+    pub(crate) schedules: ScheduleCollection,
+
+    // Ignore prices older than this when computing market values
+    pub(crate) max_price_staleness: Option<TimeDelta>,
 }
 
 impl Repository {
@@ -41,6 +52,16 @@ impl Repository {
         &self.accounts
     }
 
+    #[must_use]
+    pub fn budgets(&self) -> &BudgetCollection {
+        &self.budgets
+    }
+
+    #[must_use]
+    pub fn schedules(&self) -> &ScheduleCollection {
+        &self.schedules
+    }
+
     pub fn add_transaction(&mut self, tx: Transaction) -> Result<()> {
         for s in tx.splits().iter() {
             // Register prices from transactions
@@ -88,6 +109,13 @@ impl Repository {
             self.commodities.list_currencies(),
             to_commodity,
         )
+        .with_max_staleness(self.max_price_staleness)
+    }
+
+    /// Ignore prices older than the given delay, compared to the date at
+    /// which market values are computed.  This applies to all reports.
+    pub fn set_max_price_staleness(&mut self, max: Option<TimeDelta>) {
+        self.max_price_staleness = max;
     }
 
     pub fn add_price(
@@ -99,6 +127,60 @@ impl Repository {
         self.prices.add(origin, target, price);
     }
 
+    /// Remove the prices of origin in target (or the reverse) on that date
+    pub fn remove_prices(
+        &mut self,
+        origin: &Commodity,
+        target: &Commodity,
+        date: NaiveDate,
+    ) -> Vec<Price> {
+        self.prices.remove(origin, target, date)
+    }
+
+    /// Return the price source with the given name, creating it if needed
+    pub fn get_or_add_price_source(&mut self, name: &str) -> PriceSource {
+        match self.price_sources.find(name) {
+            Some(s) => s,
+            None => self.price_sources.add(name),
+        }
+    }
+
+    #[must_use]
+    pub fn get_price_source(&self, id: PriceSourceId) -> Option<PriceSource> {
+        self.price_sources.get(id).cloned()
+    }
+
+    /// Return the payee with the given name, creating it if needed
+    pub fn get_or_add_payee(&mut self, name: &str) -> Payee {
+        match self.payees.find(name) {
+            Some(p) => p,
+            None => self.payees.add(name),
+        }
+    }
+
+    /// Return the toplevel account used to balance imported transactions
+    /// until the user assigns them a category, creating it if needed.
+    pub fn get_or_add_imbalance_account(&mut self) -> Account {
+        let existing = self.accounts.iter().find(|a| {
+            a.get_parent().is_none()
+                && a.name(AccountNameDepth::basename()) == IMBALANCE_ACCOUNT
+        });
+        match existing {
+            Some(a) => a,
+            None => self.accounts.add(
+                IMBALANCE_ACCOUNT,
+                self.account_kinds.get_equity(),
+                None,
+                None,
+                Some("Imported transactions not yet categorized"),
+                None,
+                None,
+                false,
+                None,
+            ),
+        }
+    }
+
     #[must_use]
     #[allow(clippy::mutable_key_type)]
     pub fn compute_commodity_balances(
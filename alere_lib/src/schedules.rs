@@ -0,0 +1,250 @@
+//! Scheduled transactions.
+//!
+//! A schedule describes a transaction that repeats over time, like a salary
+//! or a rent.  Its occurrences are not part of the repository until they are
+//! entered as actual transactions, but they are used to forecast future
+//! balances.
+
+use crate::{accounts::Account, multi_values::MultiValue, payees::Payee};
+use chrono::{
+    DateTime, Datelike, Days, Local, Months, NaiveDate, TimeDelta, Weekday,
+};
+
+/// The unit of time between two occurrences
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Frequency {
+    Once,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+/// What to do when an occurrence falls on a week-end
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WeekendOption {
+    MoveBefore, // to the previous Friday
+    MoveAfter,  // to the next Monday
+    Ignore,
+}
+
+/// When a schedule stops
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecurrenceEnd {
+    Never,
+    On(DateTime<Local>), // no occurrence after this date
+    After(u32),          // after this number of occurrences
+}
+
+/// When the occurrences of a schedule happen.
+/// For instance, "the last business day of every month" is a monthly
+/// frequency on the last day of the month, moved before week-ends.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Recurrence {
+    pub frequency: Frequency,
+    pub every: u32, // every N days, weeks,...
+    pub last_day_of_month: bool,
+    pub weekend: WeekendOption,
+    pub end: RecurrenceEnd,
+}
+
+impl Recurrence {
+    /// The n-th occurrence (starting at 0), or None if the schedule has
+    /// stopped by then.  This is always computed from the start, so that a
+    /// monthly schedule on the 31st still uses the last day of shorter months.
+    fn occurrence(
+        &self,
+        start: DateTime<Local>,
+        n: u32,
+    ) -> Option<DateTime<Local>> {
+        let steps = n.checked_mul(self.every.max(1))?;
+        let nominal = match self.frequency {
+            Frequency::Once if n == 0 => start,
+            Frequency::Once => return None,
+            Frequency::Daily => {
+                start.checked_add_days(Days::new(steps.into()))?
+            }
+            Frequency::Weekly => {
+                start.checked_add_days(Days::new(u64::from(steps) * 7))?
+            }
+            Frequency::Monthly => {
+                start.checked_add_months(Months::new(steps))?
+            }
+            Frequency::Yearly => {
+                start.checked_add_months(Months::new(steps.checked_mul(12)?))?
+            }
+        };
+        let stopped = match &self.end {
+            RecurrenceEnd::Never => false,
+            RecurrenceEnd::On(end) => nominal > *end,
+            RecurrenceEnd::After(count) => n >= *count,
+        };
+        if stopped {
+            return None;
+        }
+
+        let mut date = nominal.date_naive();
+        if self.last_day_of_month {
+            date = last_day_of_month(date)?;
+        }
+        date = match (self.weekend, date.weekday()) {
+            (WeekendOption::MoveBefore, Weekday::Sat) => date.pred_opt()?,
+            (WeekendOption::MoveBefore, Weekday::Sun) => {
+                date.checked_sub_days(Days::new(2))?
+            }
+            (WeekendOption::MoveAfter, Weekday::Sat) => {
+                date.checked_add_days(Days::new(2))?
+            }
+            (WeekendOption::MoveAfter, Weekday::Sun) => date.succ_opt()?,
+            _ => date,
+        };
+        nominal.checked_add_signed(TimeDelta::days(
+            (date - nominal.date_naive()).num_days(),
+        ))
+    }
+}
+
+fn last_day_of_month(date: NaiveDate) -> Option<NaiveDate> {
+    date.with_day(1)?
+        .checked_add_months(Months::new(1))?
+        .pred_opt()
+}
+
+/// One split of the transactions created by a schedule
+#[derive(Clone, Debug)]
+pub struct ScheduledSplit {
+    pub account: Account,
+    pub value: MultiValue,
+}
+
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    pub name: String,
+    pub payee: Option<Payee>,
+    pub memo: Option<String>,
+    pub start: DateTime<Local>, // first occurrence
+    pub recurrence: Recurrence,
+
+    // Occurrences up to this date have already been entered as transactions
+    pub last_entered: Option<DateTime<Local>>,
+
+    pub splits: Vec<ScheduledSplit>,
+}
+
+impl Schedule {
+    /// The occurrences that have not been entered yet, up to `until`
+    /// included.
+    #[must_use]
+    pub fn pending(&self, until: DateTime<Local>) -> Vec<DateTime<Local>> {
+        let mut result = Vec::new();
+        let mut n = 0;
+        while let Some(date) = self.recurrence.occurrence(self.start, n) {
+            if date > until {
+                break;
+            }
+            if self.last_entered.is_none_or(|last| date > last) {
+                result.push(date);
+            }
+            n += 1;
+        }
+        result
+    }
+}
+
+#[derive(Default)]
+pub struct ScheduleCollection {
+    schedules: Vec<Schedule>,
+}
+
+impl ScheduleCollection {
+    pub fn add(&mut self, schedule: Schedule) {
+        self.schedules.push(schedule);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Schedule> {
+        self.schedules.iter()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::schedules::{
+        Frequency, Recurrence, RecurrenceEnd, Schedule, WeekendOption,
+    };
+    use chrono::{Local, TimeZone};
+
+    fn dates(
+        recurrence: Recurrence,
+        start: (i32, u32, u32),
+        last_entered: Option<(i32, u32, u32)>,
+    ) -> Vec<String> {
+        let ts = |(y, m, d)| Local.with_ymd_and_hms(y, m, d, 0, 0, 0).unwrap();
+        let schedule = Schedule {
+            name: String::new(),
+            payee: None,
+            memo: None,
+            start: ts(start),
+            recurrence,
+            last_entered: last_entered.map(ts),
+            splits: Vec::new(),
+        };
+        schedule
+            .pending(ts((2024, 6, 30)))
+            .iter()
+            .map(|d| d.date_naive().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_occurrences() {
+        // Last business day of every other month
+        assert_eq!(
+            dates(
+                Recurrence {
+                    frequency: Frequency::Monthly,
+                    every: 2,
+                    last_day_of_month: true,
+                    weekend: WeekendOption::MoveBefore,
+                    end: RecurrenceEnd::Never,
+                },
+                (2024, 1, 15),
+                None,
+            ),
+            vec!["2024-01-31", "2024-03-29", "2024-05-31"],
+        );
+
+        // Every three weeks, limited number of occurrences
+        assert_eq!(
+            dates(
+                Recurrence {
+                    frequency: Frequency::Weekly,
+                    every: 3,
+                    last_day_of_month: false,
+                    weekend: WeekendOption::Ignore,
+                    end: RecurrenceEnd::After(4),
+                },
+                (2024, 4, 1),
+                Some((2024, 4, 1)),
+            ),
+            vec!["2024-04-22", "2024-05-13", "2024-06-03"],
+        );
+
+        // Monthly on the 31st, with an end date, moved after week-ends
+        assert_eq!(
+            dates(
+                Recurrence {
+                    frequency: Frequency::Monthly,
+                    every: 1,
+                    last_day_of_month: false,
+                    weekend: WeekendOption::MoveAfter,
+                    end: RecurrenceEnd::On(
+                        Local.with_ymd_and_hms(2024, 4, 1, 0, 0, 0).unwrap()
+                    ),
+                },
+                (2024, 1, 31),
+                None,
+            ),
+            vec!["2024-01-31", "2024-02-29", "2024-04-01"],
+        );
+    }
+}
@@ -0,0 +1,452 @@
+//! Capital gains for tax purposes.
+//!
+//! For each sale in a fiscal year, the cost basis is computed from the lots
+//! of the stock account, and the gain is classified as short or long term
+//! depending on how long the shares were held.
+//! Sales of shares that were never bought (e.g. because the history is
+//! incomplete) have an unknown cost basis, and are left out of the totals.
+
+use crate::{
+    accounts::{Account, AccountNameDepth},
+    commodities::Commodity,
+    errors::AlrError,
+    lots::{CostMethod, Lots, RealizedGain, Settings as LotSettings},
+    multi_values::{MultiValue, Value},
+    repositories::Repository,
+};
+use anyhow::Result;
+use chrono::{DateTime, Local, Months, NaiveDate};
+use rust_decimal::Decimal;
+use std::io::Write;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Term {
+    Short,
+    Long,
+}
+
+impl std::fmt::Display for Term {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Term::Short => write!(f, "short"),
+            Term::Long => write!(f, "long"),
+        }
+    }
+}
+
+pub struct Settings {
+    // The reporting currency.  Amounts are converted at the trade date.
+    pub commodity: Option<Commodity>,
+
+    // Overrides the cost method set on each account
+    pub method: Option<CostMethod>,
+
+    // The fiscal year starts on the first day of this month (1 to 12) in
+    // the given year.
+    pub year: i32,
+    pub fiscal_start_month: u32,
+
+    // Gains are long term when the shares were held for more than this
+    pub long_term_months: u32,
+}
+
+/// One sale (or the part of a sale that applies to a single lot)
+pub struct TaxRow {
+    pub account: Account,
+    pub acquired: DateTime<Local>,
+    pub sold_on: DateTime<Local>,
+    pub shares: Value,
+    pub proceeds: MultiValue,
+    pub cost: MultiValue,
+    pub gain: MultiValue,
+    pub term: Term,
+
+    // The shares were not found in any lot, so the acquisition date, cost,
+    // gain and term are meaningless.
+    pub unknown_basis: bool,
+}
+
+/// Totals for one commodity (the shares sold)
+pub struct TaxTotal {
+    pub commodity: Commodity,
+    pub proceeds: MultiValue,
+    pub cost: MultiValue,
+    pub short_term: MultiValue,
+    pub long_term: MultiValue,
+}
+
+pub struct TaxReport {
+    pub start: DateTime<Local>,
+    pub end: DateTime<Local>, // excluded
+    pub rows: Vec<TaxRow>,
+    pub totals: Vec<TaxTotal>,
+
+    // Number of rows with an unknown cost basis
+    pub unknown_basis: usize,
+}
+
+impl TaxReport {
+    pub fn new(repo: &Repository, settings: &Settings) -> Result<Self> {
+        let start = NaiveDate::from_ymd_opt(
+            settings.year,
+            settings.fiscal_start_month,
+            1,
+        )
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .and_then(|d| d.and_local_timezone(Local).single())
+        .ok_or_else(|| {
+            AlrError::Str(format!(
+                "invalid fiscal year {}-{}",
+                settings.year, settings.fiscal_start_month
+            ))
+        })?;
+        let end = start
+            .checked_add_months(Months::new(12))
+            .ok_or(AlrError::InvalidNumber)?;
+
+        let mut report = TaxReport {
+            start,
+            end,
+            rows: Vec::new(),
+            totals: Vec::new(),
+            unknown_basis: 0,
+        };
+
+        let all = Lots::load(
+            repo,
+            &LotSettings {
+                commodity: settings.commodity.clone(),
+                method: settings.method,
+            },
+        );
+        for lots in all {
+            for r in lots.realized {
+                if r.sold_on < start || r.sold_on >= end {
+                    continue;
+                }
+                let long_term_from = r
+                    .acquired
+                    .checked_add_months(Months::new(settings.long_term_months))
+                    .ok_or(AlrError::InvalidNumber)?;
+                let term = if r.sold_on > long_term_from {
+                    Term::Long
+                } else {
+                    Term::Short
+                };
+                let gain = r.gain();
+                if r.unknown_basis {
+                    report.unknown_basis += 1;
+                } else {
+                    report.add_to_totals(&r, &gain, term);
+                }
+                report.rows.push(TaxRow {
+                    account: lots.account.clone(),
+                    acquired: r.acquired,
+                    sold_on: r.sold_on,
+                    shares: r.shares,
+                    proceeds: r.proceeds,
+                    cost: r.cost,
+                    gain,
+                    term,
+                    unknown_basis: r.unknown_basis,
+                });
+            }
+        }
+        report.rows.sort_by_key(|r| r.sold_on);
+        Ok(report)
+    }
+
+    fn add_to_totals(
+        &mut self,
+        sale: &RealizedGain,
+        gain: &MultiValue,
+        term: Term,
+    ) {
+        let commodity = &sale.shares.commodity;
+        let total =
+            match self.totals.iter().position(|t| t.commodity == *commodity) {
+                Some(pos) => pos,
+                None => {
+                    self.totals.push(TaxTotal {
+                        commodity: commodity.clone(),
+                        proceeds: MultiValue::zero(),
+                        cost: MultiValue::zero(),
+                        short_term: MultiValue::zero(),
+                        long_term: MultiValue::zero(),
+                    });
+                    self.totals.len() - 1
+                }
+            };
+        if let Some(total) = self.totals.get_mut(total) {
+            total.proceeds += &sale.proceeds;
+            total.cost += &sale.cost;
+            match term {
+                Term::Short => total.short_term += gain,
+                Term::Long => total.long_term += gain,
+            }
+        }
+    }
+
+    /// Write one line per sale, with plain numbers (rounded to the precision
+    /// of the currency) so that the file can be loaded in a spreadsheet.  All
+    /// amounts of a line must be in the same currency, so a reporting
+    /// currency should be used.
+    /// The acquisition date, term, cost and gain are left empty when the cost
+    /// basis is unknown.
+    pub fn write_csv(&self, into: impl Write) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(into);
+        writer.write_record([
+            "account",
+            "commodity",
+            "shares",
+            "acquired",
+            "sold",
+            "term",
+            "proceeds",
+            "cost",
+            "gain",
+            "currency",
+        ])?;
+        for row in &self.rows {
+            let mut currency = None;
+            let mut amount = |v: &MultiValue| -> Result<Decimal> {
+                match v.iter().collect::<Vec<_>>().as_slice() {
+                    [] => Ok(Decimal::ZERO),
+                    [v] if currency.is_none()
+                        || currency.as_ref() == Some(&v.commodity) =>
+                    {
+                        currency = Some(v.commodity.clone());
+                        Ok(v.amount.round_dp(
+                            v.commodity.get_display_precision().into(),
+                        ))
+                    }
+                    _ => Err(AlrError::Str(format!(
+                        "amounts in several currencies for the sale of {} \
+                         on {}, a reporting currency is needed",
+                        row.shares.commodity.get_symbol(),
+                        row.sold_on.date_naive(),
+                    )))?,
+                }
+            };
+            let proceeds = amount(&row.proceeds)?;
+            let cost = amount(&row.cost)?;
+            let gain = amount(&row.gain)?;
+            let known = |text: String| {
+                if row.unknown_basis {
+                    String::new()
+                } else {
+                    text
+                }
+            };
+            writer.write_record([
+                row.account.name(AccountNameDepth::unlimited()),
+                row.shares.commodity.get_symbol().clone(),
+                row.shares.amount.to_string(),
+                known(row.acquired.date_naive().to_string()),
+                row.sold_on.date_naive().to_string(),
+                known(row.term.to_string()),
+                proceeds.to_string(),
+                known(cost.to_string()),
+                known(gain.to_string()),
+                currency.map(|c| c.get_symbol().clone()).unwrap_or_default(),
+            ])?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        commodities::test::create_currency,
+        multi_values::{MultiValue, Operation, Value},
+        repositories::Repository,
+        tax_report::{Settings, TaxReport},
+        transactions::{ReconcileKind, Transaction, TransactionArgs},
+    };
+    use anyhow::Result;
+    use chrono::{Local, TimeZone};
+    use rust_decimal::Decimal;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_tax_report() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let acme = create_currency(&mut repo.commodities, "ACME", 2, false);
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let stock = repo.accounts.add_dummy("ACME", stock_kind);
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+
+        for (y, m, qty, amount) in [
+            (2022, 6, dec!(10), dec!(100)),
+            (2023, 9, dec!(10), dec!(300)),
+            (2023, 10, dec!(-15), dec!(-400)),
+            (2024, 2, dec!(-5), dec!(-200)),
+        ] {
+            let date = Local.with_ymd_and_hms(y, m, 1, 0, 0, 0).unwrap();
+            let mut tx = Transaction::new_with_details(TransactionArgs {
+                entry_date: date,
+                ..TransactionArgs::default()
+            });
+            tx.add_split(
+                stock.clone(),
+                ReconcileKind::New,
+                date,
+                Operation::BuyAmount {
+                    qty: Value {
+                        amount: qty,
+                        commodity: acme.clone(),
+                    },
+                    amount: Value {
+                        amount,
+                        commodity: eur.clone(),
+                    },
+                },
+            );
+            tx.add_split(
+                cash.clone(),
+                ReconcileKind::New,
+                date,
+                Operation::Credit(MultiValue::new(-amount, &eur)),
+            );
+            repo.add_transaction(tx)?;
+        }
+
+        let report = TaxReport::new(
+            &repo,
+            &Settings {
+                commodity: Some(eur.clone()),
+                method: None,
+                year: 2023,
+                fiscal_start_month: 4,
+                long_term_months: 12,
+            },
+        )?;
+        let amount = |v: &MultiValue| {
+            v.iter().map(|v| v.amount).sum::<Decimal>().round_dp(2)
+        };
+        let rows = report
+            .rows
+            .iter()
+            .map(|r| {
+                format!(
+                    "{} {} {} {}",
+                    r.acquired.date_naive(),
+                    r.shares.amount,
+                    amount(&r.gain),
+                    r.term,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                "2022-06-01 10 166.67 long",
+                "2023-09-01 5 -16.67 short",
+                "2023-09-01 5 50.00 short",
+            ]
+        );
+        let total = report.totals.first().unwrap();
+        assert_eq!(total.commodity, acme);
+        assert_eq!(amount(&total.proceeds), dec!(600));
+        assert_eq!(amount(&total.short_term), dec!(33.33));
+        assert_eq!(amount(&total.long_term), dec!(166.67));
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?.lines().nth(1),
+            Some(
+                "ACME,ACME,10,2022-06-01,2023-10-01,long,266.67,100,166.67,EUR"
+            )
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_unknown_basis() -> Result<()> {
+        let mut repo = Repository::default();
+        let eur = create_currency(&mut repo.commodities, "EUR", 2, true);
+        let acme = create_currency(&mut repo.commodities, "ACME", 2, false);
+        let stock_kind = repo.account_kinds.lookup("stock").unwrap().clone();
+        let checking_kind =
+            repo.account_kinds.lookup("checking").unwrap().clone();
+        let stock = repo.accounts.add_dummy("ACME", stock_kind);
+        let cash = repo.accounts.add_dummy("Cash", checking_kind);
+
+        // Sell 8 shares, though only 5 were bought
+        for (m, qty, amount) in
+            [(1, dec!(5), dec!(200)), (6, dec!(-8), dec!(-400))]
+        {
+            let date = Local.with_ymd_and_hms(2023, m, 1, 0, 0, 0).unwrap();
+            let mut tx = Transaction::new_with_details(TransactionArgs {
+                entry_date: date,
+                ..TransactionArgs::default()
+            });
+            tx.add_split(
+                stock.clone(),
+                ReconcileKind::New,
+                date,
+                Operation::BuyAmount {
+                    qty: Value {
+                        amount: qty,
+                        commodity: acme.clone(),
+                    },
+                    amount: Value {
+                        amount,
+                        commodity: eur.clone(),
+                    },
+                },
+            );
+            tx.add_split(
+                cash.clone(),
+                ReconcileKind::New,
+                date,
+                Operation::Credit(MultiValue::new(-amount, &eur)),
+            );
+            repo.add_transaction(tx)?;
+        }
+
+        let report = TaxReport::new(
+            &repo,
+            &Settings {
+                commodity: Some(eur.clone()),
+                method: None,
+                year: 2023,
+                fiscal_start_month: 1,
+                long_term_months: 12,
+            },
+        )?;
+        let amount = |v: &MultiValue| {
+            v.iter().map(|v| v.amount).sum::<Decimal>().round_dp(2)
+        };
+        let rows = report
+            .rows
+            .iter()
+            .map(|r| (r.shares.amount, amount(&r.proceeds), r.unknown_basis))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![(dec!(5), dec!(250), false), (dec!(3), dec!(150), true)]
+        );
+        assert_eq!(report.unknown_basis, 1);
+
+        // Only the known lot is included in the totals
+        let total = report.totals.first().unwrap();
+        assert_eq!(amount(&total.proceeds), dec!(250));
+        assert_eq!(amount(&total.cost), dec!(200));
+        assert_eq!(amount(&total.short_term), dec!(50));
+
+        let mut csv = Vec::new();
+        report.write_csv(&mut csv)?;
+        assert_eq!(
+            String::from_utf8(csv)?.lines().nth(2),
+            Some("ACME,ACME,3,,2023-06-01,,150.00,,,EUR")
+        );
+        Ok(())
+    }
+}
@@ -577,6 +577,25 @@ fn end_of_month<TZ: TimeZone>(
     Ok(next_month - chrono::TimeDelta::nanoseconds(1))
 }
 
+/// The textual form of an interval, which can be parsed back via from_str.
+impl std::fmt::Display for Intv {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Intv::UpTo(then) => write!(f, "upto {}", then),
+            Intv::LastNDays(count) => write!(f, "{}d", count),
+            Intv::LastNMonths(count) => write!(f, "{}m", count),
+            Intv::MonthAgo(count) => write!(f, "m{}", count),
+            Intv::LastNYears(count) => write!(f, "{}y", count),
+            Intv::SpecificYear(year) => write!(f, "{}", year),
+            Intv::YearAgo(count) => write!(f, "y{}", count),
+            Intv::YearToDate => write!(f, "ytd"),
+            Intv::Monthly { begin, end } | Intv::Yearly { begin, end } => {
+                write!(f, "{}..{}", begin, end)
+            }
+        }
+    }
+}
+
 impl FromStr for Intv {
     type Err = AlrError;
 
@@ -958,4 +977,23 @@ mod tests {
             Instant::YearsAgo(2)
         ));
     }
+
+    #[test]
+    fn test_intv_display() {
+        for s in [
+            "ytd",
+            "7d",
+            "3m",
+            "2y",
+            "m-1",
+            "y1",
+            "2023",
+            "upto yesterday",
+            "2024-01..2024-06",
+            "2020..2023",
+        ] {
+            let intv = Intv::from_str(s).unwrap();
+            assert_eq!(Intv::from_str(&intv.to_string()).unwrap(), intv, "{s}");
+        }
+    }
 }
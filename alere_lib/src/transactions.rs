@@ -66,7 +66,7 @@ struct TransactionDetails {
     memo: Option<String>,
     check_number: Option<String>,
     payee: Option<Payee>,
-    _entry_date: DateTime<Local>,
+    entry_date: DateTime<Local>,
 
     // The splits that make up the transaction.  The sum of these splits must
     // always be balanced.  The transaction owns the splits.
@@ -78,6 +78,14 @@ struct TransactionDetails {
 #[derive(Debug, Clone)]
 pub struct Transaction(Rc<RefCell<TransactionDetails>>);
 
+impl PartialEq for Transaction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Transaction {}
+
 impl Transaction {
     // Create a new transaction with default fields
 
@@ -88,7 +96,7 @@ impl Transaction {
                 .and_then(|m| if m.is_empty() { None } else { Some(m.into()) }),
             check_number: details.check_number.map(str::to_string),
             payee: details.payee,
-            _entry_date: details.entry_date,
+            entry_date: details.entry_date,
             splits: Vec::default(),
         })))
     }
@@ -111,6 +119,7 @@ impl Transaction {
             reconciled,
             post_ts,
             operation,
+            bank_id: None,
         };
         let mut tr = Rc::get_mut(&mut self.0)
             .expect("Couldn'get get mut ref to transation")
@@ -118,6 +127,22 @@ impl Transaction {
         tr.splits.push(split);
     }
 
+    /// Record the identifier used by the bank for the split of this account
+    /// (for instance the FITID in OFX statements), so that the same statement
+    /// can be imported again without duplicating transactions.
+    /// This applies to the first split of the account that has no identifier
+    /// yet.
+    pub fn set_bank_id(&self, account: &Account, bank_id: &str) {
+        let mut tx = self.0.borrow_mut();
+        if let Some(s) = tx
+            .splits
+            .iter_mut()
+            .find(|s| s.account == *account && s.bank_id.is_none())
+        {
+            s.bank_id = Some(bank_id.to_string());
+        }
+    }
+
     /// Check that the transaction obeys the accounting equations, i.e.
     ///    Equity = Assets + Income − Expenses
     #[must_use]
@@ -220,6 +245,16 @@ impl Transaction {
         Ref::map(self.0.borrow(), |tx| &tx.memo)
     }
 
+    #[must_use]
+    pub fn check_number(&self) -> Ref<'_, Option<String>> {
+        Ref::map(self.0.borrow(), |tx| &tx.check_number)
+    }
+
+    #[must_use]
+    pub fn entry_date(&self) -> DateTime<Local> {
+        self.0.borrow().entry_date
+    }
+
     #[must_use]
     pub fn payee(&self) -> Option<Payee> {
         self.0.borrow().payee.clone()
@@ -331,6 +366,10 @@ pub struct Split {
     pub post_ts: DateTime<Local>,
 
     pub operation: Operation,
+
+    // The identifier used by the bank for this split, when it was imported
+    // from a bank statement.
+    pub bank_id: Option<String>,
 }
 
 impl Split {
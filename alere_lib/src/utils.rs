@@ -1,3 +1,6 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+
 /// Whether the vector contains all-equal elements
 pub fn is_all_same<T: PartialEq>(arr: &[T]) -> bool {
     match arr {
@@ -5,3 +8,14 @@ pub fn is_all_same<T: PartialEq>(arr: &[T]) -> bool {
         [first, ..] => arr.iter().all(|v| v == first),
     }
 }
+
+/// Read a text file.  Files exported by banks are often encoded in latin1
+/// rather than UTF-8, which we fall back to when the file is not valid UTF-8.
+pub fn read_text_file(path: &Path) -> Result<String> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Cannot read {}", path.display()))?;
+    Ok(match String::from_utf8(bytes) {
+        Ok(text) => text,
+        Err(e) => e.into_bytes().into_iter().map(char::from).collect(),
+    })
+}
@@ -0,0 +1,162 @@
+use crate::global_settings::{GlobalSettings, format_percent};
+use alere_lib::{
+    allocation::{Allocation, AllocationConfig, Settings},
+    multi_values::MultiValue,
+    repositories::Repository,
+};
+use anyhow::{Result, anyhow};
+use rust_decimal::Decimal;
+use std::path::PathBuf;
+use tabled::builder::Builder;
+
+pub struct AllocationArgs {
+    pub config: Option<PathBuf>,
+    pub by: String,
+    pub new_money: Option<Decimal>,
+    pub only_new_money: bool,
+}
+
+/// Show the market value and weight of each asset class.  When the config
+/// file has target weights for the dimension, also show how much to buy or
+/// sell to reach them.
+pub fn allocation_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    args: &AllocationArgs,
+) -> Result<String> {
+    let config = match &args.config {
+        None => AllocationConfig::default(),
+        Some(path) => AllocationConfig::from_file(path)?,
+    };
+    config.apply(repo)?;
+
+    let new_money = match (args.new_money, &globals.commodity) {
+        (None, _) => MultiValue::zero(),
+        (Some(amount), Some(c)) => MultiValue::new(amount, c),
+        (Some(_), None) => Err(anyhow!(
+            "--new-money requires a reporting currency (--currency)"
+        ))?,
+    };
+    let targets = config.targets.get(&args.by).cloned().unwrap_or_default();
+    let has_targets = !targets.is_empty();
+
+    let alloc = Allocation::new(
+        repo,
+        &Settings {
+            commodity: globals.commodity.clone(),
+            dimension: args.by.clone(),
+            targets,
+            new_money,
+            only_new_money: args.only_new_money,
+        },
+        globals.reftime,
+    )?;
+
+    let mut builder = Builder::default();
+    let mut header = vec!["Class", "Value", "Weight"];
+    if has_targets {
+        header.extend(["Target", "Rebalance"]);
+    }
+    builder.push_record(header);
+    for row in &alloc.rows {
+        let mut record = vec![
+            row.class.clone(),
+            row.value.display(&globals.format),
+            format_percent(&row.weight),
+        ];
+        if has_targets {
+            record.push(format_percent(&row.target));
+            record.push(row.rebalance.display(&globals.format));
+        }
+        builder.push_record(record);
+    }
+    builder.push_record([
+        "Total".to_string(),
+        alloc.total.display(&globals.format),
+    ]);
+    Ok(globals.finalize_table(builder, Some(1), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "allocation",
+            "account Assets:Bank   ; type: C\n\
+             account Assets:Broker:ACME\n\
+             account Income:Salary   ; type: R\n\
+             \n\
+             2024-01-05 Salary\n    \
+             Assets:Bank          1500 EUR\n    \
+             Income:Salary\n\
+             \n\
+             2024-01-10 Buy\n    \
+             Assets:Broker:ACME    10 ACME @@ 500 EUR\n    \
+             Assets:Bank          -500 EUR\n",
+        )
+    }
+
+    #[test]
+    fn test_allocation_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        );
+
+        let config = std::env::temp_dir()
+            .join(format!("test_allocation_{}.json", std::process::id()));
+        std::fs::write(
+            &config,
+            r#"{
+                "classes": {
+                    "ACME": {"class": "stocks"},
+                    "Assets:Bank": {"class": "cash"}
+                },
+                "targets": {"class": {"stocks": 40, "cash": 60}}
+            }"#,
+        )?;
+        let mut args = AllocationArgs {
+            config: Some(config.clone()),
+            by: "class".to_string(),
+            new_money: None,
+            only_new_money: false,
+        };
+        let output = allocation_view(&repo, &settings, &args);
+        args.new_money = Some(Decimal::from(100));
+        args.only_new_money = true;
+        let with_new_money = allocation_view(&repo, &settings, &args);
+        std::fs::remove_file(&config)?;
+
+        let output = output?;
+        let l = table_lines(&output);
+        assert!(
+            l.iter()
+                .any(|l| l.contains("cash │ 1,000 │ 66.66% │ 60.00% │ -100")),
+            "{output}"
+        );
+        assert!(
+            l.iter()
+                .any(|l| l.contains("stocks │ 500 │ 33.33% │ 40.00% │ 100")),
+            "{output}"
+        );
+
+        let output = with_new_money?;
+        let l = table_lines(&output);
+        assert!(
+            l.iter()
+                .any(|l| l.contains("cash │ 1,000 │ 66.66% │ 60.00% │ 0")),
+            "{output}"
+        );
+        assert!(
+            l.iter()
+                .any(|l| l.contains("stocks │ 500 │ 33.33% │ 40.00% │ 100")),
+            "{output}"
+        );
+        Ok(())
+    }
+}
@@ -1,5 +1,9 @@
-use alere_lib::times::{Instant, Intv};
+use alere_lib::{
+    lots::CostMethod,
+    times::{Instant, Intv},
+};
 use clap::{Parser, Subcommand};
+use rust_decimal::Decimal;
 use std::path::PathBuf;
 
 /// Manage your finances
@@ -9,7 +13,9 @@ pub struct Cli {
     #[command(flatten)]
     pub global: crate::global_settings::GlobalSettings,
 
-    /// Input file (KMyMoney format)
+    /// Input file (KMyMoney format, alere format if the extension is .alere,
+    /// GnuCash format for .gnucash, or hledger format for .journal, .hledger
+    /// and .ledger)
     #[arg(short, long, global = true, default_value = "./Comptes.kmy")]
     pub input: PathBuf,
 
@@ -24,6 +30,11 @@ pub enum Commands {
         /// Periods to display (e.g 1y or 2m..now)
         #[arg(short, long, value_delimiter = ',', default_value = "1y,ytd")]
         periods: Vec<Intv>,
+
+        /// Compare the networth with investing the same amounts, at the same
+        /// dates, in this commodity (e.g. an index fund)
+        #[arg(long)]
+        benchmark: Option<String>,
     },
 
     /// Show stock performance
@@ -31,6 +42,20 @@ pub enum Commands {
         /// Columns to display (comma-separated)
         #[arg(long, value_delimiter = ',')]
         columns: Option<Vec<crate::perfs_view::PerfColumn>>,
+
+        /// How to group accounts, showing combined performance for each
+        /// group
+        #[arg(long, value_enum, default_value_t)]
+        group_by: crate::perfs_view::PerfGroupBy,
+
+        /// Also show performance over these periods (e.g 2024,ytd)
+        #[arg(short, long, value_delimiter = ',')]
+        periods: Vec<Intv>,
+
+        /// Compare with investing the same amounts, at the same dates, in
+        /// this commodity (e.g. an index fund)
+        #[arg(long)]
+        benchmark: Option<String>,
     },
 
     /// Generate shell completions
@@ -46,6 +71,99 @@ pub enum Commands {
         format: ExportFormat,
     },
 
+    /// Import transactions from a bank statement
+    Import {
+        #[command(subcommand)]
+        format: ImportFormat,
+    },
+
+    /// Compare a budget with actual income and expenses
+    Budget {
+        /// Name of the budget, required when there are several
+        name: Option<String>,
+
+        /// Periods to display (e.g m0 or 2024-01..2024-06)
+        #[arg(short, long, value_delimiter = ',', default_value = "m0,ytd")]
+        periods: Vec<Intv>,
+    },
+
+    /// List uncategorized transactions, with suggested categories
+    Categorize {
+        /// Maximum number of suggestions for each transaction
+        #[arg(long, default_value_t = 3)]
+        suggestions: usize,
+    },
+
+    /// Project account balances, applying scheduled transactions
+    Forecast {
+        /// Number of days to look ahead
+        #[arg(long, default_value_t = 90)]
+        days: u32,
+    },
+
+    /// Show open lots of stock accounts, or the realized gains
+    Lots {
+        /// Cost method (fifo, lifo, hifo or average), overriding the one set
+        /// on each account
+        #[arg(long)]
+        method: Option<CostMethod>,
+
+        /// Show realized gains for each lot sold, rather than open lots
+        #[arg(long)]
+        realized: bool,
+    },
+
+    /// Show capital gains for each sale in a fiscal year
+    Tax {
+        /// The fiscal year (defaults to the current year)
+        #[arg(long)]
+        year: Option<i32>,
+
+        /// First month of the fiscal year (1 to 12)
+        #[arg(long, default_value_t = 1)]
+        fiscal_start: u32,
+
+        /// Gains are long term when the shares were held for more than this
+        /// number of months
+        #[arg(long, default_value_t = 12)]
+        long_term_months: u32,
+
+        /// Cost method (fifo, lifo, hifo or average), overriding the one set
+        /// on each account
+        #[arg(long)]
+        method: Option<CostMethod>,
+
+        /// Output the sales as CSV
+        #[arg(long)]
+        csv: bool,
+    },
+
+    /// Show dividends received for each security and interests received on
+    /// each account, and their yield
+    Dividends,
+
+    /// Show the asset allocation, and how to rebalance it
+    Allocation {
+        /// JSON file with the asset class of commodities and accounts, and
+        /// the target weights for each dimension
+        #[arg(long)]
+        config: Option<PathBuf>,
+
+        /// Dimension to classify holdings by (for instance class, region or
+        /// currency)
+        #[arg(long, default_value = "class")]
+        by: String,
+
+        /// Money that will be invested, in the reporting currency
+        #[arg(long)]
+        new_money: Option<Decimal>,
+
+        /// Only invest the new money to get closer to the targets, instead
+        /// of also selling
+        #[arg(long)]
+        only_new_money: bool,
+    },
+
     /// Show current networth
     Networth {
         /// Periods to display (e.g 1y or 2m..now)
@@ -119,6 +237,12 @@ pub enum Commands {
         /// Show percent of total column
         #[arg(long)]
         percent: bool,
+
+        /// Show the exposure to each currency instead of accounts.  Cash is
+        /// exposed to its own currency, and securities to the currency they
+        /// are quoted in.
+        #[arg(long)]
+        by_currency: bool,
     },
 
     /// Show cashflow
@@ -199,8 +323,50 @@ pub enum Commands {
         command: AccountsCommand,
     },
 
+    /// Inspect, edit and check prices
+    Prices {
+        #[command(subcommand)]
+        command: PricesCommand,
+    },
+
     /// Update stock prices and show networth changes
-    Update,
+    Update {
+        /// Save the new prices in the input file
+        #[arg(long, conflicts_with = "dry_run")]
+        save: bool,
+
+        /// Only show the new prices, without using them in the networth
+        #[arg(long)]
+        dry_run: bool,
+
+        /// JSON file describing additional quote providers, as URL
+        /// templates.  They are selected by the quote source of commodities.
+        #[arg(long)]
+        providers: Option<PathBuf>,
+
+        /// Instead of the latest prices, download the daily prices missing
+        /// from the history since that date.
+        #[arg(long, value_name = "SINCE")]
+        backfill: Option<Instant>,
+
+        /// Keep the responses of the providers in this directory, and reuse
+        /// them while they are recent enough.
+        #[arg(long, conflicts_with_all = ["record", "replay"])]
+        cache: Option<PathBuf>,
+
+        /// How long cached responses remain valid, in hours
+        #[arg(long, default_value_t = 12)]
+        cache_ttl: u32,
+
+        /// Store all responses of the providers in this directory
+        #[arg(long, conflicts_with = "replay")]
+        record: Option<PathBuf>,
+
+        /// Only use the responses stored with --record, without accessing
+        /// the network.
+        #[arg(long)]
+        replay: Option<PathBuf>,
+    },
 
     /// Show account balance history over time
     History {
@@ -232,6 +398,121 @@ pub enum AccountsCommand {
     },
 }
 
+#[derive(Debug, Subcommand)]
+pub enum PricesCommand {
+    /// Show which prices are combined to convert a commodity
+    Explain {
+        /// The commodity to convert (e.g. AAPL)
+        commodity: String,
+
+        /// The commodity to convert to (e.g. EUR)
+        target: String,
+
+        /// Date of the conversion (defaults to now)
+        #[arg(long)]
+        at: Option<Instant>,
+    },
+
+    /// List the known prices of a commodity
+    List {
+        /// The commodity (e.g. AAPL)
+        commodity: String,
+
+        /// Only show the prices in this commodity (e.g. USD)
+        target: Option<String>,
+    },
+
+    /// Add a price, and save it in the input file
+    Add {
+        /// The commodity (e.g. AAPL)
+        commodity: String,
+
+        /// The price of one unit of the commodity
+        price: Decimal,
+
+        /// The commodity the price is expressed in (e.g. USD)
+        target: String,
+
+        /// Date of the price (defaults to now)
+        #[arg(long)]
+        at: Option<Instant>,
+
+        /// Name of the price source
+        #[arg(long, default_value = "User")]
+        source: String,
+    },
+
+    /// Delete the prices of a commodity on a given date, and save the input
+    /// file
+    Delete {
+        /// The commodity (e.g. AAPL)
+        commodity: String,
+
+        /// The commodity the prices are expressed in (e.g. USD)
+        target: String,
+
+        /// Date of the prices to delete
+        date: Instant,
+    },
+
+    /// Look for suspicious prices: large changes from one price to the next,
+    /// prices far from those paid in transactions, and commodities still
+    /// held without recent prices.
+    Check {
+        /// Report changes between consecutive prices above this percentage
+        #[arg(long, default_value = "50")]
+        max_jump: Decimal,
+
+        /// Report prices that differ from the price paid in a nearby
+        /// transaction by more than this percentage
+        #[arg(long, default_value = "20")]
+        max_deviation: Decimal,
+
+        /// How many days around a price to look for transactions
+        #[arg(long, default_value_t = 5)]
+        window: u32,
+
+        /// Report commodities still held that have no price in this number
+        /// of days
+        #[arg(long, default_value_t = 7)]
+        max_age: u32,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ImportFormat {
+    /// Import a bank statement in OFX or QFX format.  Lines already imported
+    /// are ignored, and those that look like existing transactions are only
+    /// reported.
+    Ofx {
+        /// The statement to import
+        file: PathBuf,
+
+        /// Only show what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Import transactions from a CSV file, as described by a rules file
+    Csv {
+        /// The file to import
+        file: PathBuf,
+
+        /// The rules file (JSON) describing the layout of the file and how
+        /// to categorize transactions
+        #[arg(short, long)]
+        rules: PathBuf,
+
+        /// The account to import into (defaults to the one from the rules)
+        #[arg(short, long)]
+        account: Option<String>,
+
+        /// Only show what would be imported
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
 #[derive(Subcommand)]
 pub enum ExportFormat {
     /// Export to hledger format
@@ -240,4 +521,18 @@ pub enum ExportFormat {
         #[arg(short, long, default_value = "hledger.journal")]
         output: String,
     },
+
+    /// Export to beancount format
+    Beancount {
+        /// Name of output file
+        #[arg(short, long, default_value = "comptes.beancount")]
+        output: String,
+    },
+
+    /// Export to alere's native format, which can be used as input later on
+    Alere {
+        /// Name of output file
+        #[arg(short, long, default_value = "comptes.alere")]
+        output: String,
+    },
 }
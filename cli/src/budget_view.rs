@@ -0,0 +1,169 @@
+use crate::global_settings::GlobalSettings;
+use alere_lib::{
+    accounts::AccountNameDepth,
+    budgets::{Budget, BudgetReport, BudgetRow},
+    repositories::Repository,
+    times::Intv,
+    tree_keys::Key,
+    trees::NodeData,
+};
+use anyhow::Result;
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use tabled::builder::Builder;
+
+/// The budget to display: the one with the given name, or the only one
+/// defined in the repository.
+fn find_budget<'a>(
+    repo: &'a Repository,
+    name: Option<&str>,
+) -> Result<&'a Budget> {
+    if let Some(name) = name {
+        return repo
+            .budgets()
+            .find(name)
+            .ok_or_else(|| anyhow::anyhow!("no budget named {}", name));
+    }
+    match repo.budgets().iter().collect::<Vec<_>>().as_slice() {
+        [] => Err(anyhow::anyhow!("no budget defined")),
+        [budget] => Ok(budget),
+        budgets => Err(anyhow::anyhow!(
+            "several budgets defined, specify one of: {}",
+            budgets.iter().map(|b| &b.name).join(", ")
+        )),
+    }
+}
+
+/// Show budgeted, actual and remaining amounts for each income and expense
+/// account, for each period.
+pub fn budget_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    name: Option<&str>,
+    periods: &[Intv],
+) -> Result<String> {
+    let budget = find_budget(repo, name)?;
+    let mut report =
+        BudgetReport::new(repo, budget, periods.to_vec(), globals.reftime)?;
+
+    let node_name = |row: &NodeData<Key, BudgetRow>| match &row.key {
+        Key::Account(a) => a.name(AccountNameDepth::basename()),
+        Key::Institution(Some(inst)) => inst.get_name(),
+        Key::Institution(None) => "Unknown".to_string(),
+        Key::AccountKind(kind) => kind.get_name(),
+    };
+    let display = |v: Decimal| globals.format.display(v, &report.commodity);
+
+    let mut header = vec!["Account".to_string()];
+    for intv in &report.intervals {
+        header.push(format!("{} budget", intv.descr));
+        header.push("Actual".to_string());
+        header.push("Remaining".to_string());
+        header.push("%".to_string());
+    }
+    let mut builder = Builder::default();
+    builder.push_record(header);
+
+    report.tree.sort(node_name);
+    report.tree.traverse(
+        |node| {
+            let indent = "  ".repeat(node.data.depth);
+            let mut row = vec![format!("{}{}", indent, node_name(&node.data))];
+            for idx in 0..report.intervals.len() {
+                let cell = node.data.data.get(idx)?;
+                row.push(display(cell.budgeted));
+                row.push(display(cell.actual));
+                row.push(display(cell.remaining()));
+                row.push(
+                    cell.consumed()
+                        .map(|p| format!("{:.1}%", p))
+                        .unwrap_or_default(),
+                );
+            }
+            builder.push_record(row);
+            Ok(())
+        },
+        true,
+    )?;
+
+    Ok(globals.finalize_table(builder, Some(1), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::table_lines;
+    use alere_lib::{importers::Importer, kmymoney::KmyMoneyImporter};
+    use chrono::{Local, TimeZone};
+    use futures::executor::block_on;
+    use std::str::FromStr;
+
+    fn load_test_repo() -> Result<Repository> {
+        let mut editor = kmy_editor::KmyEditor::new()?;
+        editor.add_currency("EUR", "Euro", "€")?;
+        let checking = editor.add_account("Checking", "1", "EUR")?;
+        let groceries =
+            editor.add_standard_account("Expense", "Expense", "13", "EUR")?;
+        let salary =
+            editor.add_standard_account("Income", "Income", "12", "EUR")?;
+
+        let t1 = editor.add_transaction("2024-01-10", None, "EUR")?;
+        editor.add_split(&t1, 0, &checking, "-250/1", "2024-01-10", None)?;
+        editor.add_split(&t1, 1, &groceries, "250/1", "2024-01-10", None)?;
+        let t2 = editor.add_transaction("2024-01-25", None, "EUR")?;
+        editor.add_split(&t2, 0, &checking, "2000/1", "2024-01-25", None)?;
+        editor.add_split(&t2, 1, &salary, "-2000/1", "2024-01-25", None)?;
+
+        editor.add_budget(
+            "2024",
+            "2024-01-01",
+            &format!(
+                "<!DOCTYPE BUDGET>\
+                 <BUDGET-CONTAINER>\
+                 <BUDGET name=\"2024\" start=\"2024-01-01\" id=\"B000001\">\
+                 <ACCOUNT id=\"{groceries}\" budgetlevel=\"monthly\">\
+                 <PERIOD amount=\"300/1\" start=\"2024-01-01\"/>\
+                 </ACCOUNT>\
+                 <ACCOUNT id=\"{salary}\" budgetlevel=\"yearly\">\
+                 <PERIOD amount=\"-24000/1\" start=\"2024-01-01\"/>\
+                 </ACCOUNT>\
+                 </BUDGET>\
+                 </BUDGET-CONTAINER>"
+            ),
+        )?;
+
+        let mut kmy = KmyMoneyImporter::default();
+        block_on(kmy.import_file(editor.path(), |_, _| {}))
+    }
+
+    #[test]
+    fn test_budget_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = GlobalSettings {
+            reftime: Local.with_ymd_and_hms(2024, 2, 15, 0, 0, 0).unwrap(),
+            ..GlobalSettings::default()
+        };
+
+        let output = budget_view(
+            &repo,
+            &settings,
+            None,
+            &[Intv::from_str("2024-01..2024-01")?],
+        )?;
+        let lines = table_lines(&output);
+        assert!(
+            lines.iter().any(|l| l
+                .contains("Expense │ 300.00 € │ 250.00 € │ 50.00 € │ 83.3%")),
+            "{output}"
+        );
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "Income │ 2,032.79 € │ 2,000.00 € │ 32.79 € │ 98.3%"
+            )),
+            "{output}"
+        );
+
+        assert!(budget_view(&repo, &settings, Some("other"), &[]).is_err());
+        Ok(())
+    }
+}
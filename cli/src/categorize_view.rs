@@ -0,0 +1,55 @@
+use alere_lib::{
+    accounts::AccountNameDepth,
+    categorizer::{Categorizer, uncategorized},
+    repositories::Repository,
+};
+use anyhow::Result;
+use tabled::builder::Builder;
+
+/// List the transactions that still need a category, with the accounts
+/// suggested from the history.
+pub fn categorize_view(
+    repo: &Repository,
+    settings: &crate::global_settings::GlobalSettings,
+    max_suggestions: usize,
+) -> Result<String> {
+    let categorizer = Categorizer::new(repo.transactions());
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Date",
+        "Account",
+        "Payee",
+        "Memo",
+        "Suggestions",
+        "Amount",
+    ]);
+
+    for item in uncategorized(repo) {
+        let suggestions = categorizer
+            .suggest_for(&item.transaction, &item.account)
+            .iter()
+            .take(max_suggestions)
+            .map(|s| {
+                format!(
+                    "{} ({:.0}%)",
+                    s.account.name(AccountNameDepth::unlimited()),
+                    s.confidence * 100.0
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        builder.push_record([
+            item.timestamp.date_naive().to_string(),
+            item.account.name(AccountNameDepth::unlimited()),
+            item.transaction
+                .payee()
+                .map(|p| p.get_name().clone())
+                .unwrap_or_default(),
+            item.transaction.memo().clone().unwrap_or_default(),
+            suggestions,
+            item.amount.display(&settings.format),
+        ]);
+    }
+
+    Ok(settings.finalize_table(builder, Some(5), true))
+}
@@ -0,0 +1,128 @@
+use crate::global_settings::{GlobalSettings, format_percent};
+use alere_lib::{
+    accounts::AccountNameDepth,
+    dividends::{DividendReport, SecurityDividends, Settings},
+    repositories::Repository,
+};
+use anyhow::Result;
+use tabled::builder::Builder;
+
+/// Show the dividends received for each security and the interests received
+/// on each account, per year, followed by the trailing yields.
+pub fn dividends_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+) -> Result<String> {
+    let report = DividendReport::new(
+        repo,
+        &Settings {
+            commodity: globals.commodity.clone(),
+        },
+        globals.reftime,
+    );
+
+    let income = |sec: &SecurityDividends| {
+        if sec.interest { "interest" } else { "dividend" }.to_string()
+    };
+
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Account",
+        "Income",
+        "Year",
+        "Gross",
+        "Withholding",
+        "Net",
+    ]);
+    for sec in &report.securities {
+        for (year, amounts) in &sec.per_year {
+            builder.push_record([
+                sec.account.name(AccountNameDepth::unlimited()),
+                income(sec),
+                year.to_string(),
+                amounts.gross.display(&globals.format),
+                amounts.withholding.display(&globals.format),
+                amounts.net().display(&globals.format),
+            ]);
+        }
+    }
+    let per_year = globals.finalize_table(builder, Some(2), true);
+
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Account",
+        "Income",
+        "Trailing 12m",
+        "Cost",
+        "Yield on cost",
+        "Value",
+        "Current yield",
+    ]);
+    for sec in &report.securities {
+        builder.push_record([
+            sec.account.name(AccountNameDepth::unlimited()),
+            income(sec),
+            sec.trailing.display(&globals.format),
+            sec.cost.display(&globals.format),
+            format_percent(&sec.yield_on_cost()),
+            sec.market_value.display(&globals.format),
+            format_percent(&sec.current_yield()),
+        ]);
+    }
+    let yields = globals.finalize_table(builder, Some(2), true);
+
+    Ok(format!("{}\n{}", per_year, yields))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "dividends",
+            "account Assets:Bank   ; type: C\n\
+             account Assets:Broker:ACME\n\
+             account Income:Dividends   ; type: R\n\
+             \n\
+             2023-01-10 Buy\n    \
+             Assets:Broker:ACME    10 ACME @@ 1000 EUR\n    \
+             Assets:Bank          -1000 EUR\n\
+             \n\
+             P 2024-05-01 ACME 120 EUR\n\
+             \n\
+             2024-03-10 Dividend\n    \
+             Assets:Broker:ACME   ; alere: dividend\n    \
+             Income:Dividends     -60 EUR\n    \
+             Assets:Bank           60 EUR\n",
+        )
+    }
+
+    #[test]
+    fn test_dividends_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        );
+
+        let output = dividends_view(&repo, &settings)?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("ACME │ dividend │ 2024 │ 60 │ 0 │ 60")),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l
+                    .contains("dividend │ 60 │ 1,000 │ 6.00% │ 1,200 │ 5.00%")),
+            "{output}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+use crate::global_settings::{GlobalSettings, format_percent};
+use alere_lib::{
+    exposure::{Exposure, Settings},
+    multi_values::MultiValue,
+    repositories::Repository,
+    times::Intv,
+};
+use anyhow::Result;
+use tabled::builder::Builder;
+
+/// Show the networth broken down by the currency each holding is exposed to,
+/// and for each period how much of the profit comes from exchange rates.
+pub fn exposure_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    periods: Vec<Intv>,
+) -> Result<String> {
+    let exposure = Exposure::new(
+        repo,
+        &Settings {
+            commodity: globals.commodity.clone(),
+            intervals: periods,
+        },
+        globals.reftime,
+    )?;
+
+    let mut builder = Builder::default();
+    let mut header = vec!["Currency".to_string()];
+    for intv in &exposure.intervals {
+        for col in ["Holdings", "Value", "%", "Local PnL", "FX PnL"] {
+            header.push(format!("{} {}", col, intv.descr));
+        }
+    }
+    builder.push_record(header);
+
+    let mut pnl = vec![
+        (MultiValue::zero(), MultiValue::zero());
+        exposure.intervals.len()
+    ];
+    for c in &exposure.currencies {
+        let mut row = vec![c.currency.get_symbol().clone()];
+        for (p, total) in c.periods.iter().zip(&mut pnl) {
+            row.push(p.end.display(&globals.format));
+            row.push(p.end_value.display(&globals.format));
+            row.push(format_percent(&p.weight));
+            row.push(p.local_pnl.display(&globals.format));
+            row.push(p.fx_pnl.display(&globals.format));
+            total.0 += &p.local_pnl;
+            total.1 += &p.fx_pnl;
+        }
+        builder.push_record(row);
+    }
+
+    let mut row = vec!["Total".to_string()];
+    for (total, (local_pnl, fx_pnl)) in exposure.total.iter().zip(&pnl) {
+        row.push(String::new());
+        row.push(total.display(&globals.format));
+        row.push(String::new());
+        row.push(local_pnl.display(&globals.format));
+        row.push(fx_pnl.display(&globals.format));
+    }
+    builder.push_record(row);
+
+    Ok(globals.finalize_table(builder, Some(1), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "exposure",
+            "account Assets:Bank   ; type: C\n\
+             account Assets:US Bank   ; type: C\n\
+             account Income:Salary   ; type: R\n\
+             \n\
+             P 2023-12-01 USD 0.9 EUR\n\
+             P 2024-12-01 USD 1 EUR\n\
+             \n\
+             2023-12-01 Salary\n    \
+             Assets:Bank          500 EUR\n    \
+             Assets:US Bank       2000 USD\n    \
+             Income:Salary       -500 EUR\n    \
+             Income:Salary       -2000 USD\n",
+        )
+    }
+
+    #[test]
+    fn test_exposure_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+        );
+
+        let output =
+            exposure_view(&repo, &settings, vec![Intv::SpecificYear(2024)])?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("EUR │ 500 │ 500 │ 20.00% │ 0 │ 0")),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l
+                    .contains("USD │ 2,000 USD │ 2,000 │ 80.00% │ 0 │ 200")),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("Total │ │ 2,500 │ │ 0 │ 200")),
+            "{output}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,136 @@
+use crate::global_settings::GlobalSettings;
+use alere_lib::{
+    accounts::AccountNameDepth,
+    forecast::{Forecast, ForecastRow},
+    repositories::Repository,
+    tree_keys::Key,
+    trees::NodeData,
+};
+use anyhow::Result;
+use chrono::Days;
+use tabled::builder::Builder;
+
+/// Show the current balance of each account, and how it evolves when the
+/// pending scheduled transactions are applied over the next `days`.
+pub fn forecast_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    days: u32,
+) -> Result<String> {
+    let until = globals
+        .reftime
+        .checked_add_days(Days::new(days.into()))
+        .unwrap_or(globals.reftime);
+    let mut forecast =
+        Forecast::new(repo, globals.commodity.clone(), until, globals.reftime)?;
+
+    let node_name = |row: &NodeData<Key, ForecastRow>| match &row.key {
+        Key::Account(a) => a.name(AccountNameDepth::basename()),
+        Key::Institution(Some(inst)) => inst.get_name(),
+        Key::Institution(None) => "Unknown".to_string(),
+        Key::AccountKind(kind) => kind.get_name(),
+    };
+
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Account".to_string(),
+        "Today".to_string(),
+        "Lowest".to_string(),
+        "On".to_string(),
+        until.date_naive().to_string(),
+    ]);
+
+    forecast.tree.sort(node_name);
+    forecast.tree.traverse(
+        |node| {
+            let indent = "  ".repeat(node.data.depth);
+            let row = &node.data.data;
+            builder.push_record([
+                format!("{}{}", indent, node_name(&node.data)),
+                row.current.display(&globals.format),
+                row.lowest.display(&globals.format),
+                row.lowest_on
+                    .map(|d| d.date_naive().to_string())
+                    .unwrap_or_default(),
+                row.end.display(&globals.format),
+            ]);
+            Ok(())
+        },
+        true,
+    )?;
+
+    Ok(globals.finalize_table(builder, Some(1), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::table_lines;
+    use alere_lib::{importers::Importer, kmymoney::KmyMoneyImporter};
+    use chrono::{Local, TimeZone};
+    use futures::executor::block_on;
+
+    fn load_test_repo() -> Result<Repository> {
+        let mut editor = kmy_editor::KmyEditor::new()?;
+        editor.add_currency("EUR", "Euro", "€")?;
+        let checking = editor.add_account("Checking", "1", "EUR")?;
+        let rent =
+            editor.add_standard_account("Expense", "Expense", "13", "EUR")?;
+        let salary =
+            editor.add_standard_account("Income", "Income", "12", "EUR")?;
+
+        let t1 = editor.add_transaction("2024-01-10", None, "EUR")?;
+        editor.add_split(&t1, 0, &checking, "500/1", "2024-01-10", None)?;
+        editor.add_split(&t1, 1, &salary, "-500/1", "2024-01-10", None)?;
+
+        // Monthly rent, on the last day of the month
+        let s1 = editor.add_schedule(
+            "Rent",
+            32,
+            1,
+            "2024-01-31",
+            None,
+            Some("2024-01-31"),
+            2,
+            "EUR",
+        )?;
+        editor.add_schedule_split(&s1, 0, &checking, "-800/1", None)?;
+        editor.add_schedule_split(&s1, 1, &rent, "800/1", None)?;
+
+        // Salary every four weeks, until the end of April
+        let s2 = editor.add_schedule(
+            "Salary",
+            4,
+            4,
+            "2024-03-05",
+            Some("2024-04-30"),
+            None,
+            2,
+            "EUR",
+        )?;
+        editor.add_schedule_split(&s2, 0, &checking, "1000/1", None)?;
+        editor.add_schedule_split(&s2, 1, &salary, "-1000/1", None)?;
+
+        let mut kmy = KmyMoneyImporter::default();
+        block_on(kmy.import_file(editor.path(), |_, _| {}))
+    }
+
+    #[test]
+    fn test_forecast_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = GlobalSettings {
+            reftime: Local.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap(),
+            ..GlobalSettings::default()
+        };
+
+        let output = forecast_view(&repo, &settings, 90)?;
+        let lines = table_lines(&output);
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "Checking │ 500.00 € │ -300.00 € │ 2024-02-29 │ 1,100.00 €"
+            )),
+            "{output}"
+        );
+        Ok(())
+    }
+}
@@ -3,7 +3,7 @@ use alere_lib::{
     formatters::{Formatter, Negative, Separators, SymbolQuote, Zero},
     repositories::Repository,
 };
-use chrono::{DateTime, Local};
+use chrono::{DateTime, Local, TimeDelta};
 use clap::{Parser, ValueEnum};
 use tabled::settings::Style;
 
@@ -113,6 +113,11 @@ pub struct GlobalSettings {
     #[arg(long, global = true, default_value = "psql")]
     pub style: TableStyle,
 
+    /// Ignore prices older than this number of days when computing market
+    /// values
+    #[arg(long, global = true)]
+    pub max_staleness: Option<u32>,
+
     #[clap(skip)]
     pub commodity: Option<Commodity>,
 
@@ -138,6 +143,11 @@ impl GlobalSettings {
         }
     }
 
+    /// The maximum age of prices used for market values
+    pub fn max_price_staleness(&self) -> Option<TimeDelta> {
+        self.max_staleness.map(|d| TimeDelta::days(i64::from(d)))
+    }
+
     /// Finalize a table with style and optional formatting
     pub fn finalize_table(
         &self,
@@ -169,6 +179,7 @@ impl Default for GlobalSettings {
             reftime: Local::now(),
             empty: false,
             style: TableStyle::Modern,
+            max_staleness: None,
             format: Formatter {
                 quote_symbol: SymbolQuote::UnquotedSymbol,
                 hide_symbol_if: None,
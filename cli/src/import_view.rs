@@ -0,0 +1,140 @@
+use crate::global_settings::GlobalSettings;
+use alere_lib::{
+    accounts::AccountNameDepth,
+    alere_file::AlereFile,
+    csv_import::{self, CsvRules},
+    formatters::Formatter,
+    importers::Exporter,
+    multi_values::MultiValue,
+    ofx::{self, LineStatus, StatementImport},
+    repositories::Repository,
+};
+use anyhow::Result;
+use std::path::Path;
+use tabled::builder::Builder;
+
+fn status_text(status: &LineStatus) -> String {
+    match status {
+        LineStatus::New => "new".to_string(),
+        LineStatus::AlreadyImported(_) => "already imported".to_string(),
+        LineStatus::Duplicate(tx) => format!(
+            "duplicate of {} {}",
+            tx.timestamp().date_naive(),
+            tx.payee().map(|p| p.get_name().clone()).unwrap_or_default(),
+        ),
+    }
+}
+
+fn import_table(settings: &GlobalSettings, import: &StatementImport) -> String {
+    let mut builder = Builder::default();
+    builder.push_record(["Date", "Payee", "Memo", "Status", "Amount"]);
+    for (line, status) in &import.lines {
+        builder.push_record([
+            line.posted.date_naive().to_string(),
+            line.name.clone().unwrap_or_default(),
+            line.memo.clone().unwrap_or_default(),
+            status_text(status),
+            MultiValue::new(line.amount, &import.commodity)
+                .display(&settings.format),
+        ]);
+    }
+    settings.finalize_table(builder, Some(4), true)
+}
+
+/// Import a bank statement in OFX format, and merge its new lines into the
+/// repository.  Lines that look like existing transactions are reported and
+/// associated with them, but not added.
+pub fn import_ofx(
+    repo: &mut Repository,
+    settings: &GlobalSettings,
+    input: &Path,
+    file: &Path,
+    dry_run: bool,
+) -> Result<()> {
+    let statements = ofx::read_file(file)?;
+    let mut added = 0;
+
+    for statement in &statements {
+        let import = ofx::prepare(repo, statement)?;
+        println!(
+            "{}: {} lines, {} new",
+            import.account.name(AccountNameDepth::unlimited()),
+            import.lines.len(),
+            import.count_new(),
+        );
+        println!("{}", import_table(settings, &import));
+        if !dry_run {
+            ofx::merge(repo, &import)?;
+            added += import.count_new();
+        }
+    }
+
+    save(repo, input, added, dry_run)
+}
+
+/// Import transactions from a CSV file.  Rows that no rule matched, or that
+/// could not be parsed, are reported.
+pub fn import_csv(
+    repo: &mut Repository,
+    settings: &GlobalSettings,
+    input: &Path,
+    file: &Path,
+    rules: &Path,
+    account: Option<&str>,
+    dry_run: bool,
+) -> Result<()> {
+    let rules = CsvRules::from_file(rules)?;
+    let result = csv_import::import_file(repo, &rules, account, file)?;
+    println!(
+        "{}: {} transactions",
+        result.account.name(AccountNameDepth::unlimited()),
+        result.added,
+    );
+
+    if !result.unmatched.is_empty() {
+        let mut builder = Builder::default();
+        builder.push_record(["Line", "Date", "Payee", "Memo", "Amount"]);
+        for row in &result.unmatched {
+            builder.push_record([
+                row.line.to_string(),
+                row.date.date_naive().to_string(),
+                row.payee.clone().unwrap_or_default(),
+                row.memo.clone().unwrap_or_default(),
+                MultiValue::new(row.amount, &result.commodity)
+                    .display(&settings.format),
+            ]);
+        }
+        println!("\nRows not matched by any rule:");
+        println!("{}", settings.finalize_table(builder, Some(4), true));
+    }
+
+    if !result.errors.is_empty() {
+        println!("\nRows not imported:");
+        for (line, error) in &result.errors {
+            println!("  line {line}: {error}");
+        }
+    }
+
+    save(repo, input, result.added, dry_run)
+}
+
+/// Save the imported transactions in the input file
+fn save(
+    repo: &Repository,
+    input: &Path,
+    added: usize,
+    dry_run: bool,
+) -> Result<()> {
+    if dry_run {
+        println!("\nDry run, no transaction was added");
+    } else if input.extension().is_some_and(|e| e == "alere") {
+        AlereFile::default().export_file(repo, input, &Formatter::default())?;
+        println!("\nSaved {} new transactions in {}", added, input.display());
+    } else {
+        println!(
+            "\nTransactions can only be saved in alere files, use `export \
+             alere` first"
+        );
+    }
+    Ok(())
+}
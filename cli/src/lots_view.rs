@@ -0,0 +1,138 @@
+use crate::global_settings::GlobalSettings;
+use alere_lib::{
+    accounts::AccountNameDepth,
+    lots::{CostMethod, Lots, Settings},
+    repositories::Repository,
+};
+use anyhow::Result;
+use tabled::builder::Builder;
+
+/// Show the open lots of each stock account, with their current market
+/// value, or the realized gains for each lot that was sold.
+pub fn lots_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    method: Option<CostMethod>,
+    realized: bool,
+) -> Result<String> {
+    let all = Lots::load(
+        repo,
+        &Settings {
+            commodity: globals.commodity.clone(),
+            method,
+        },
+    );
+    let mut prices = repo.market_prices(globals.commodity.clone());
+    let mut builder = Builder::default();
+
+    if realized {
+        builder.push_record([
+            "Account", "Method", "Acquired", "Sold", "Days", "Shares", "Cost",
+            "Proceeds", "Gain",
+        ]);
+        for lots in &all {
+            for r in &lots.realized {
+                builder.push_record([
+                    lots.account.name(AccountNameDepth::unlimited()),
+                    lots.method.to_string(),
+                    r.acquired.date_naive().to_string(),
+                    r.sold_on.date_naive().to_string(),
+                    r.holding_period().num_days().to_string(),
+                    r.shares.display(&globals.format),
+                    r.cost.display(&globals.format),
+                    r.proceeds.display(&globals.format),
+                    r.gain().display(&globals.format),
+                ]);
+            }
+        }
+    } else {
+        builder.push_record([
+            "Account",
+            "Method",
+            "Acquired",
+            "Shares",
+            "Cost",
+            "Value",
+            "Unrealized",
+        ]);
+        for lots in &all {
+            for lot in &lots.open {
+                let value = prices.convert_value(&lot.shares, &globals.reftime);
+                builder.push_record([
+                    lots.account.name(AccountNameDepth::unlimited()),
+                    lots.method.to_string(),
+                    lot.acquired.date_naive().to_string(),
+                    lot.shares.display(&globals.format),
+                    lot.cost.display(&globals.format),
+                    value.display(&globals.format),
+                    (&value - &lot.cost).display(&globals.format),
+                ]);
+            }
+        }
+    }
+
+    Ok(globals.finalize_table(builder, Some(2), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "lots",
+            "account Assets:Bank   ; type: C\n\
+             account Assets:Broker:ACME\n\
+             \n\
+             2023-01-10 Buy\n    \
+             Assets:Broker:ACME    10 ACME @@ 1000 EUR\n    \
+             Assets:Bank          -1000 EUR\n\
+             \n\
+             2024-02-10 Buy\n    \
+             Assets:Broker:ACME    5 ACME @@ 600 EUR\n    \
+             Assets:Bank          -600 EUR\n\
+             \n\
+             2024-03-10 Sell\n    \
+             Assets:Broker:ACME    -12 ACME @@ 1560 EUR\n    \
+             Assets:Bank          1560 EUR\n",
+        )
+    }
+
+    #[test]
+    fn test_lots_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        );
+
+        let output = lots_view(&repo, &settings, None, true)?;
+        let lines = table_lines(&output);
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "fifo │ 2023-01-10 │ 2024-03-10 │ 425 │ 10 ACME │ 1,000 │ \
+                 1,300 │ 300"
+            )),
+            "{output}"
+        );
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "fifo │ 2024-02-10 │ 2024-03-10 │ 29 │ 2 ACME │ 240 │ 260 │ 20"
+            )),
+            "{output}"
+        );
+
+        let output =
+            lots_view(&repo, &settings, Some(CostMethod::Lifo), false)?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("lifo │ 2023-01-10 │ 3 ACME │ 300 │")),
+            "{output}"
+        );
+        Ok(())
+    }
+}
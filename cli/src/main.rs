@@ -1,16 +1,31 @@
 mod accounts_view;
+mod allocation_view;
 mod args;
+mod budget_view;
+mod categorize_view;
+mod dividends_view;
+mod exposure_view;
+mod forecast_view;
 mod global_settings;
 mod history_view;
+mod import_view;
 mod ledger_view;
+mod lots_view;
 mod metrics_view;
 mod networth_view;
 mod perfs_view;
+mod prices_view;
+mod tax_view;
+#[cfg(test)]
+mod test_utils;
 mod update_view;
 
 use crate::{
     accounts_view::accounts_list,
-    args::{AccountsCommand, Cli, Commands, ExportFormat},
+    args::{
+        AccountsCommand, Cli, Commands, ExportFormat, ImportFormat,
+        PricesCommand,
+    },
     global_settings::GlobalSettings,
     ledger_view::ledger_view,
     metrics_view::metrics_view,
@@ -19,7 +34,10 @@ use crate::{
 };
 use alere_lib::{
     accounts::AccountNameDepth,
+    alere_file::AlereFile,
+    beancount::Beancount,
     formatters::{Formatter, SymbolQuote, Zero},
+    gnucash::GnucashImporter,
     hledger::Hledger,
     importers::{Exporter, Importer},
     kmymoney::KmyMoneyImporter,
@@ -28,11 +46,12 @@ use alere_lib::{
     times::{Instant, Intv},
 };
 use anyhow::Result;
-use chrono::Local;
+use chrono::{Datelike, Local, TimeDelta};
 use clap::{CommandFactory, Parser};
 use futures::executor::block_on;
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use std::path::Path;
+use stock_importer::Transport;
 
 /// Export all transaction to hledger format
 fn export_hledger(repo: &mut Repository, output: &Path) -> Result<()> {
@@ -59,13 +78,31 @@ hledger -f {} bal --value=end,€ --end=today --tree Asset Liability",
     Ok(())
 }
 
+/// Export all transactions to beancount format
+fn export_beancount(repo: &mut Repository, output: &Path) -> Result<()> {
+    let mut beancount = Beancount {
+        export_reconciliation: true,
+    };
+    beancount.export_file(repo, output, &Formatter::default())?;
+    println!("Run\nbean-check {}", output.display());
+    Ok(())
+}
+
+/// Export the repository to alere's native format
+fn export_alere(repo: &mut Repository, output: &Path) -> Result<()> {
+    AlereFile::default().export_file(repo, output, &Formatter::default())?;
+    println!("Saved {}", output.display());
+    Ok(())
+}
+
 /// Display metrics
 fn metrics(
     repo: &Repository,
     globals: &GlobalSettings,
     periods: Vec<Intv>,
+    benchmark: Option<&String>,
 ) -> Result<()> {
-    let output = metrics_view(repo, globals, periods)?;
+    let output = metrics_view(repo, globals, periods, benchmark)?;
     println!("{}", output);
     Ok(())
 }
@@ -74,9 +111,9 @@ fn metrics(
 fn perfs(
     repo: &Repository,
     globals: &GlobalSettings,
-    columns: Option<Vec<crate::perfs_view::PerfColumn>>,
+    args: crate::perfs_view::PerfArgs,
 ) -> Result<()> {
-    let output = perfs_view(repo, globals, columns)?;
+    let output = perfs_view(repo, globals, args)?;
     println!("{}", output);
     Ok(())
 }
@@ -169,9 +206,11 @@ fn cashflow(
 
 fn run_subcommand(
     repo: &mut Repository,
+    input: &Path,
     command: &Commands,
     settings: &mut GlobalSettings,
 ) -> Result<()> {
+    repo.set_max_price_staleness(settings.max_price_staleness());
     match command {
         Commands::Completions { shell } => {
             shell.generate(&mut Cli::command(), &mut std::io::stdout());
@@ -180,7 +219,108 @@ fn run_subcommand(
             ExportFormat::Hledger { output } => {
                 export_hledger(repo, Path::new(output))?;
             }
+            ExportFormat::Beancount { output } => {
+                export_beancount(repo, Path::new(output))?;
+            }
+            ExportFormat::Alere { output } => {
+                export_alere(repo, Path::new(output))?;
+            }
         },
+        Commands::Import { format } => match format {
+            ImportFormat::Ofx { file, dry_run } => {
+                import_view::import_ofx(repo, settings, input, file, *dry_run)?;
+            }
+            ImportFormat::Csv {
+                file,
+                rules,
+                account,
+                dry_run,
+            } => {
+                import_view::import_csv(
+                    repo,
+                    settings,
+                    input,
+                    file,
+                    rules,
+                    account.as_deref(),
+                    *dry_run,
+                )?;
+            }
+        },
+        Commands::Budget { name, periods } => {
+            let output = budget_view::budget_view(
+                repo,
+                settings,
+                name.as_deref(),
+                periods,
+            )?;
+            println!("{}", output);
+        }
+        Commands::Categorize { suggestions } => {
+            let output =
+                categorize_view::categorize_view(repo, settings, *suggestions)?;
+            println!("{}", output);
+        }
+        Commands::Forecast { days } => {
+            let output = forecast_view::forecast_view(repo, settings, *days)?;
+            println!("{}", output);
+        }
+        Commands::Lots { method, realized } => {
+            let output =
+                lots_view::lots_view(repo, settings, *method, *realized)?;
+            println!("{}", output);
+        }
+        Commands::Tax {
+            year,
+            fiscal_start,
+            long_term_months,
+            method,
+            csv,
+        } => {
+            let output = tax_view::tax_view(
+                repo,
+                settings,
+                &tax_view::TaxArgs {
+                    year: year.unwrap_or_else(|| settings.reftime.year()),
+                    fiscal_start_month: *fiscal_start,
+                    long_term_months: *long_term_months,
+                    method: *method,
+                    csv: *csv,
+                },
+            )?;
+            println!("{}", output);
+        }
+        Commands::Dividends => {
+            let output = dividends_view::dividends_view(repo, settings)?;
+            println!("{}", output);
+        }
+        Commands::Allocation {
+            config,
+            by,
+            new_money,
+            only_new_money,
+        } => {
+            let output = allocation_view::allocation_view(
+                repo,
+                settings,
+                &allocation_view::AllocationArgs {
+                    config: config.clone(),
+                    by: by.clone(),
+                    new_money: *new_money,
+                    only_new_money: *only_new_money,
+                },
+            )?;
+            println!("{}", output);
+        }
+        Commands::Networth {
+            periods,
+            by_currency: true,
+            ..
+        } => {
+            let output =
+                exposure_view::exposure_view(repo, settings, periods.clone())?;
+            println!("{}", output);
+        }
         Commands::Networth {
             periods,
             show_zero,
@@ -191,6 +331,7 @@ fn run_subcommand(
             delta_to_last,
             price,
             percent,
+            by_currency: false,
         } => {
             networth(
                 repo,
@@ -231,11 +372,25 @@ fn run_subcommand(
                 *percent,
             )?;
         }
-        Commands::Metrics { periods } => {
-            metrics(repo, settings, periods.clone())?;
+        Commands::Metrics { periods, benchmark } => {
+            metrics(repo, settings, periods.clone(), benchmark.as_ref())?;
         }
-        Commands::Perf { columns } => {
-            perfs(repo, settings, columns.clone())?;
+        Commands::Perf {
+            columns,
+            group_by,
+            periods,
+            benchmark,
+        } => {
+            perfs(
+                repo,
+                settings,
+                crate::perfs_view::PerfArgs {
+                    columns: columns.clone(),
+                    group_by: *group_by,
+                    periods: periods.clone(),
+                    benchmark: benchmark.clone(),
+                },
+            )?;
         }
         Commands::Ledger {
             account,
@@ -263,9 +418,112 @@ fn run_subcommand(
                 println!("{}", output);
             }
         },
-        Commands::Update => {
+        Commands::Prices { command } => match command {
+            PricesCommand::Explain {
+                commodity,
+                target,
+                at,
+            } => {
+                let output = prices_view::explain_view(
+                    repo,
+                    settings,
+                    commodity,
+                    target,
+                    at.as_ref(),
+                )?;
+                println!("{}", output);
+            }
+            PricesCommand::List { commodity, target } => {
+                let output = prices_view::list_view(
+                    repo,
+                    settings,
+                    commodity,
+                    target.as_deref(),
+                )?;
+                println!("{}", output);
+            }
+            PricesCommand::Add {
+                commodity,
+                price,
+                target,
+                at,
+                source,
+            } => {
+                let timestamp = match at {
+                    None => settings.reftime,
+                    Some(at) => at.to_time(settings.reftime)?,
+                };
+                let output = block_on(prices_view::add_price(
+                    repo, input, commodity, target, *price, timestamp, source,
+                ))?;
+                println!("{}", output);
+            }
+            PricesCommand::Delete {
+                commodity,
+                target,
+                date,
+            } => {
+                let date = date.to_time(settings.reftime)?.date_naive();
+                let output = block_on(prices_view::delete_prices(
+                    repo, input, commodity, target, date,
+                ))?;
+                println!("{}", output);
+            }
+            PricesCommand::Check {
+                max_jump,
+                max_deviation,
+                window,
+                max_age,
+            } => {
+                let output = prices_view::check_view(
+                    repo,
+                    settings,
+                    *max_jump,
+                    *max_deviation,
+                    *window,
+                    *max_age,
+                )?;
+                println!("{}", output);
+            }
+        },
+        Commands::Update {
+            save,
+            dry_run,
+            providers,
+            backfill,
+            cache,
+            cache_ttl,
+            record,
+            replay,
+        } => {
+            let transport = match (cache, record, replay) {
+                (_, _, Some(dir)) => Transport::replay(dir),
+                (_, Some(dir), _) => Transport::record(dir),
+                (Some(dir), _, _) => Transport::cached(
+                    dir,
+                    TimeDelta::hours(i64::from(*cache_ttl)),
+                ),
+                (None, None, None) => Transport::default(),
+            };
+            let args = update_view::UpdateArgs {
+                save: *save,
+                dry_run: *dry_run,
+                providers: providers.clone(),
+                transport,
+            };
             let runtime = tokio::runtime::Runtime::new()?;
-            runtime.block_on(update_view::update_prices(repo, settings))?;
+            match backfill {
+                Some(since) => {
+                    runtime.block_on(update_view::backfill_prices(
+                        repo, settings, input, &args, since,
+                    ))?;
+                }
+                None => {
+                    runtime.block_on(update_view::update_prices(
+                        repo, settings, input, &args,
+                    ))?;
+                }
+            }
         }
         Commands::History {
             account,
@@ -318,10 +576,14 @@ fn run_subcommand(
                         .or(settings.commodity_str.clone()),
                     empty: cli.global.empty || settings.empty,
                     style: settings.style.clone(),
+                    max_staleness: cli
+                        .global
+                        .max_staleness
+                        .or(settings.max_staleness),
                     ..GlobalSettings::default()
                 };
                 global.postprocess(repo);
-                run_subcommand(repo, &cli.command, &mut global)?;
+                run_subcommand(repo, input, &cli.command, &mut global)?;
             }
         }
     }
@@ -350,13 +612,32 @@ fn main() -> Result<()> {
             .with_message("importing kmy"),
     );
 
-    let mut kmy = KmyMoneyImporter::default();
-    let mut repo = block_on(kmy.import_file(&cli.input, |current, max| {
+    let report = |current, max| {
         progress.set_length(max);
         progress.set_position(current);
-    }))?;
+    };
+    let extension = cli.input.extension().and_then(|e| e.to_str());
+    let mut repo = match extension {
+        Some("alere") => {
+            progress.set_message("importing alere");
+            block_on(AlereFile::default().import_file(&cli.input, report))?
+        }
+        Some("gnucash") => {
+            progress.set_message("importing gnucash");
+            block_on(
+                GnucashImporter::default().import_file(&cli.input, report),
+            )?
+        }
+        Some("journal" | "hledger" | "ledger") => {
+            progress.set_message("importing journal");
+            block_on(Hledger::default().import_file(&cli.input, report))?
+        }
+        _ => block_on(
+            KmyMoneyImporter::default().import_file(&cli.input, report),
+        )?,
+    };
     progress.finish_and_clear();
 
     settings.postprocess(&repo);
-    run_subcommand(&mut repo, &cli.command, &mut settings)
+    run_subcommand(&mut repo, &cli.input, &cli.command, &mut settings)
 }
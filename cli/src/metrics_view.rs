@@ -39,17 +39,27 @@ pub fn metrics_view(
     repo: &Repository,
     globals: &GlobalSettings,
     periods: Vec<Intv>,
+    benchmark: Option<&String>,
 ) -> Result<String> {
+    let benchmark = match benchmark {
+        None => None,
+        Some(name) => Some(
+            repo.commodities
+                .find(name)
+                .ok_or_else(|| anyhow::anyhow!("unknown commodity {}", name))?,
+        ),
+    };
     let m = Metrics::load(
         repo,
         alere_lib::metrics::Settings {
             commodity: globals.commodity.clone(),
             intervals: periods,
+            benchmark: benchmark.clone(),
         },
         globals.reftime,
     )?;
 
-    let rows = vec![
+    let mut rows = vec![
         MetricRow::new("networth at end", &m, |s| {
             s.end_networth.display(&globals.format)
         }),
@@ -101,6 +111,20 @@ pub fn metrics_view(
         MetricRow::new("Wealth", &m, |s| duration(&s.wealth)),
         MetricRow::new("Income Tax Rate", &m, |s| percent(&s.income_tax_rate)),
     ];
+    if let Some(bench) = &benchmark {
+        rows.push(MetricRow::new(&bench.get_symbol(), &m, |s| {
+            s.benchmark.as_ref().map_or_else(
+                || percent(&None),
+                |b| b.value.display(&globals.format),
+            )
+        }));
+        rows.push(MetricRow::new("  IRR", &m, |s| {
+            percent(&s.benchmark.as_ref().and_then(|b| b.irr))
+        }));
+        rows.push(MetricRow::new("  Excess", &m, |s| {
+            percent(&s.benchmark.as_ref().and_then(|b| b.excess))
+        }));
+    }
 
     // Build table dynamically
     let mut builder = tabled::builder::Builder::default();
@@ -123,6 +147,8 @@ pub fn metrics_view(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
 
     #[test]
     fn test_metric_row_dynamic_columns() {
@@ -135,4 +161,60 @@ mod tests {
         let row = MetricRow::new("Test", &metrics, |_| "value".to_string());
         assert_eq!(row.values.len(), 0);
     }
+
+    #[test]
+    fn test_metrics_benchmark() -> Result<()> {
+        let repo = load_journal(
+            "metrics",
+            "account Assets:Bank   ; type: C\n\
+             account Income:Salary   ; type: R\n\
+             account Equity:Opening   ; type: E\n\
+             \n\
+             2023-12-01 Opening\n    \
+             Assets:Bank         1000 EUR\n    \
+             Equity:Opening     -1000 EUR\n\
+             \n\
+             2024-07-01 Salary\n    \
+             Assets:Bank         1000 EUR\n    \
+             Income:Salary      -1000 EUR\n\
+             \n\
+             P 2023-12-15 ACME 10 EUR\n\
+             P 2024-06-15 ACME 20 EUR\n",
+        )?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2025, 6, 1, 0, 0, 0).unwrap(),
+        );
+
+        // 100 shares bought with the starting networth, 50 more with the
+        // salary, all worth 20 EUR at the end.
+        let output = metrics_view(
+            &repo,
+            &settings,
+            vec![Intv::SpecificYear(2024)],
+            Some(&"ACME".to_string()),
+        )?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ networth at end │ 2,000 │")),
+            "{output}"
+        );
+        assert!(
+            lines.iter().any(|l| l.contains("│ ACME │ 3,000 │")),
+            "{output}"
+        );
+
+        assert!(
+            metrics_view(
+                &repo,
+                &settings,
+                vec![Intv::SpecificYear(2024)],
+                Some(&"UNKNOWN".to_string()),
+            )
+            .is_err()
+        );
+        Ok(())
+    }
 }
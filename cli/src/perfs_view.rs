@@ -1,7 +1,17 @@
 use crate::global_settings::GlobalSettings;
 use alere_lib::{
-    accounts::AccountNameDepth, multi_values::MultiValue, perf::Performance,
+    accounts::AccountNameDepth,
+    formatters::Formatter,
+    multi_values::MultiValue,
+    networth::GroupBy,
+    perf::{
+        Benchmark, Performance, PeriodPerformance, Portfolio,
+        PortfolioPerformance, Settings,
+    },
     repositories::Repository,
+    times::Intv,
+    tree_keys::Key,
+    trees::NodeData,
 };
 use anyhow::Result;
 use clap::ValueEnum;
@@ -16,6 +26,7 @@ pub enum PerfColumn {
     Return,
     Annualized,
     Irr,
+    Twr,
     Pnl,
     Wavg,
     Avgcost,
@@ -23,6 +34,16 @@ pub enum PerfColumn {
     Shares,
 }
 
+/// How stock accounts are grouped
+#[derive(Clone, Copy, Default, ValueEnum)]
+pub enum PerfGroupBy {
+    None,
+    #[default]
+    Parent,
+    Kind,
+    Institution,
+}
+
 const DEFAULT_COLUMNS: &[PerfColumn] = &[
     PerfColumn::Equity,
     PerfColumn::Invested,
@@ -60,6 +81,8 @@ struct PerfRow {
     annualized: String,
     #[tabled(rename = "IRR")]
     irr: String,
+    #[tabled(rename = "TWR")]
+    twr: String,
     #[tabled(rename = "P&L")]
     pnl: String,
     #[tabled(rename = "WAvg")]
@@ -70,13 +93,14 @@ struct PerfRow {
     price: String,
     #[tabled(rename = "Shares")]
     shares: String,
+    #[tabled(skip)]
+    periods: Vec<PeriodPerformance>,
+    #[tabled(skip)]
+    benchmark: Option<Benchmark>,
 }
 
 impl PerfRow {
-    fn from_perf(
-        perf: &Performance,
-        format: &alere_lib::formatters::Formatter,
-    ) -> Self {
+    fn from_perf(perf: &Performance, format: &Formatter) -> Self {
         let mv = |val: &Option<MultiValue>| {
             val.as_ref().map(|a| a.display(format)).unwrap_or_default()
         };
@@ -88,77 +112,200 @@ impl PerfRow {
             roi: returns(&perf.roi),
             annualized: returns(&perf.annualized_roi),
             irr: rate(&perf.irr),
+            twr: returns(&perf.twr),
             pnl: perf.pnl.display(format),
             weighted_avg: mv(&perf.weighted_average),
             avg_cost: mv(&perf.average_cost),
             price: mv(&perf.price),
             shares: perf.shares.display(format),
+            periods: Vec::new(),
+            benchmark: None,
         }
     }
+
+    /// A row for a group of accounts.  Values that only make sense for a
+    /// single security are left empty.
+    fn from_portfolio(
+        name: String,
+        perf: &PortfolioPerformance,
+        format: &Formatter,
+    ) -> Self {
+        PerfRow {
+            account: name,
+            equity: perf.equity.display(format),
+            invested: perf.invested.display(format),
+            realized: perf.realized.display(format),
+            roi: returns(&perf.roi),
+            annualized: String::new(),
+            irr: rate(&perf.irr),
+            twr: returns(&perf.twr),
+            pnl: perf.pnl.display(format),
+            weighted_avg: String::new(),
+            avg_cost: String::new(),
+            price: String::new(),
+            shares: String::new(),
+            periods: perf.periods.clone(),
+            benchmark: perf.benchmark.clone(),
+        }
+    }
+}
+
+pub struct PerfArgs {
+    pub columns: Option<Vec<PerfColumn>>,
+    pub group_by: PerfGroupBy,
+    pub periods: Vec<Intv>,
+    pub benchmark: Option<String>,
 }
 
 pub fn perfs_view(
     repo: &Repository,
     globals: &GlobalSettings,
-    columns: Option<Vec<PerfColumn>>,
+    args: PerfArgs,
 ) -> Result<String> {
-    let selected_columns = columns.unwrap_or_else(|| DEFAULT_COLUMNS.to_vec());
+    let selected_columns =
+        args.columns.unwrap_or_else(|| DEFAULT_COLUMNS.to_vec());
+    let benchmark = match &args.benchmark {
+        None => None,
+        Some(name) => Some(
+            repo.commodities
+                .find(name)
+                .ok_or_else(|| anyhow::anyhow!("unknown commodity {}", name))?,
+        ),
+    };
 
-    let mut perfs = Performance::load(
-        repo,
-        alere_lib::perf::Settings {
-            commodity: globals.commodity.clone(),
+    let settings = Settings {
+        commodity: globals.commodity.clone(),
+        group_by: match args.group_by {
+            PerfGroupBy::None => GroupBy::None,
+            PerfGroupBy::Parent => GroupBy::ParentAccount,
+            PerfGroupBy::Kind => GroupBy::AccountKind,
+            PerfGroupBy::Institution => GroupBy::Institution,
         },
-        globals.reftime,
-    )?;
+        intervals: args.periods,
+        benchmark,
+    };
+    let perfs = Performance::load(repo, &settings, globals.reftime)?;
+    let mut portfolio =
+        Portfolio::new(repo, &perfs, &settings, globals.reftime)?;
 
-    perfs.sort_by_key(|p| p.account.name(AccountNameDepth::unlimited()));
+    let account_names = if settings.group_by.need_indent() {
+        AccountNameDepth::unlimited()
+    } else {
+        AccountNameDepth::basename()
+    };
+    let node_name = |row: &NodeData<Key, PortfolioPerformance>| match &row.key {
+        Key::Account(a) => a.name(account_names.inc(row.collapse_depth)),
+        Key::Institution(Some(inst)) => inst.get_name(),
+        Key::Institution(None) => "Unknown".to_string(),
+        Key::AccountKind(kind) => kind.get_name(),
+    };
 
-    let rows: Vec<PerfRow> = perfs
-        .iter()
-        .filter(|p| !p.invested.is_zero())
-        .map(|p| PerfRow::from_perf(p, &globals.format))
-        .collect();
+    let mut rows = Vec::new();
+    portfolio.tree.sort(node_name);
+    portfolio.tree.traverse(
+        |node| {
+            let mut row = match &node.data.key {
+                Key::Account(acc) if !node.has_children() => perfs
+                    .iter()
+                    .find(|p| p.account == *acc)
+                    .map(|p| PerfRow::from_perf(p, &globals.format)),
+                Key::Account(_) | Key::Institution(_) | Key::AccountKind(_) => {
+                    None
+                }
+            }
+            .unwrap_or_else(|| {
+                PerfRow::from_portfolio(
+                    String::new(),
+                    &node.data.data,
+                    &globals.format,
+                )
+            });
+            row.periods.clone_from(&node.data.data.periods);
+            row.benchmark.clone_from(&node.data.data.benchmark);
+            row.account = format!(
+                "{}{}",
+                "  ".repeat(node.data.depth),
+                node_name(&node.data)
+            );
+            rows.push(row);
+            Ok(())
+        },
+        true,
+    )?;
+    rows.push(PerfRow::from_portfolio(
+        "Total investments".to_string(),
+        &portfolio.total,
+        &globals.format,
+    ));
 
     // Build table with selected columns only
     let mut builder = Builder::default();
 
+    // Columns that also have a value for each interval
+    let period_columns = selected_columns
+        .iter()
+        .filter(|col| {
+            period_cell(col, &PeriodPerformance::default(), &globals.format)
+                .is_some()
+        })
+        .collect::<Vec<_>>();
+
     // Add header - Account is always first
-    let mut header = vec!["Account"];
+    let mut header = vec!["Account".to_string()];
     for col in &selected_columns {
-        header.push(match col {
-            PerfColumn::Equity => "Equity",
-            PerfColumn::Invested => "Invested",
-            PerfColumn::Realized => "Realized",
-            PerfColumn::Return => "Return",
-            PerfColumn::Annualized => "Annualized",
-            PerfColumn::Irr => "IRR",
-            PerfColumn::Pnl => "P&L",
-            PerfColumn::Wavg => "WAvg",
-            PerfColumn::Avgcost => "Avg Cost",
-            PerfColumn::Price => "Price",
-            PerfColumn::Shares => "Shares",
-        });
+        header.push(column_name(col).to_string());
+    }
+    for intv in &portfolio.intervals {
+        for col in &period_columns {
+            header.push(format!("{} {}", column_name(col), intv.descr));
+        }
+    }
+    if let Some(bench) = &settings.benchmark {
+        header.push(bench.get_symbol().clone());
+        header.push(format!("IRR {}", bench.get_symbol()));
+        header.push("Excess".to_string());
     }
     builder.push_record(header);
 
     // Add data rows - Account is always first
     for row in &rows {
-        let mut record = vec![&row.account];
+        let mut record = vec![row.account.clone()];
         for col in &selected_columns {
-            record.push(match col {
-                PerfColumn::Equity => &row.equity,
-                PerfColumn::Invested => &row.invested,
-                PerfColumn::Realized => &row.realized,
-                PerfColumn::Return => &row.roi,
-                PerfColumn::Annualized => &row.annualized,
-                PerfColumn::Irr => &row.irr,
-                PerfColumn::Pnl => &row.pnl,
-                PerfColumn::Wavg => &row.weighted_avg,
-                PerfColumn::Avgcost => &row.avg_cost,
-                PerfColumn::Price => &row.price,
-                PerfColumn::Shares => &row.shares,
-            });
+            record.push(
+                match col {
+                    PerfColumn::Equity => &row.equity,
+                    PerfColumn::Invested => &row.invested,
+                    PerfColumn::Realized => &row.realized,
+                    PerfColumn::Return => &row.roi,
+                    PerfColumn::Annualized => &row.annualized,
+                    PerfColumn::Irr => &row.irr,
+                    PerfColumn::Twr => &row.twr,
+                    PerfColumn::Pnl => &row.pnl,
+                    PerfColumn::Wavg => &row.weighted_avg,
+                    PerfColumn::Avgcost => &row.avg_cost,
+                    PerfColumn::Price => &row.price,
+                    PerfColumn::Shares => &row.shares,
+                }
+                .clone(),
+            );
+        }
+        for period in &row.periods {
+            for col in &period_columns {
+                record.push(
+                    period_cell(col, period, &globals.format)
+                        .unwrap_or_default(),
+                );
+            }
+        }
+        if settings.benchmark.is_some() {
+            match &row.benchmark {
+                None => record.extend([rate(&None), rate(&None), rate(&None)]),
+                Some(bench) => {
+                    record.push(bench.value.display(&globals.format));
+                    record.push(rate(&bench.irr));
+                    record.push(rate(&bench.excess));
+                }
+            }
         }
         builder.push_record(record);
     }
@@ -166,9 +313,163 @@ pub fn perfs_view(
     Ok(globals.finalize_table(builder, Some(1), true))
 }
 
+fn column_name(col: &PerfColumn) -> &'static str {
+    match col {
+        PerfColumn::Equity => "Equity",
+        PerfColumn::Invested => "Invested",
+        PerfColumn::Realized => "Realized",
+        PerfColumn::Return => "Return",
+        PerfColumn::Annualized => "Annualized",
+        PerfColumn::Irr => "IRR",
+        PerfColumn::Twr => "TWR",
+        PerfColumn::Pnl => "P&L",
+        PerfColumn::Wavg => "WAvg",
+        PerfColumn::Avgcost => "Avg Cost",
+        PerfColumn::Price => "Price",
+        PerfColumn::Shares => "Shares",
+    }
+}
+
+/// The value to display for a column over one interval, or None if the
+/// column only applies since the first transaction.
+fn period_cell(
+    col: &PerfColumn,
+    period: &PeriodPerformance,
+    format: &Formatter,
+) -> Option<String> {
+    match col {
+        PerfColumn::Equity => Some(period.end_value.display(format)),
+        PerfColumn::Invested => Some(period.invested.display(format)),
+        PerfColumn::Return => Some(returns(&period.roi)),
+        PerfColumn::Irr => Some(rate(&period.irr)),
+        PerfColumn::Twr => Some(returns(&period.twr)),
+        PerfColumn::Pnl => Some(period.pnl.display(format)),
+        PerfColumn::Realized
+        | PerfColumn::Annualized
+        | PerfColumn::Wavg
+        | PerfColumn::Avgcost
+        | PerfColumn::Price
+        | PerfColumn::Shares => None,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "perfs",
+            "account Assets:Bank   ; type: C\n\
+             account Assets:Broker:ACME\n\
+             account Assets:Broker:BETA\n\
+             \n\
+             2023-01-10 Buy\n    \
+             Assets:Broker:ACME    10 ACME @@ 1000 EUR\n    \
+             Assets:Bank          -1000 EUR\n\
+             \n\
+             2023-07-10 Buy\n    \
+             Assets:Broker:BETA    10 BETA @@ 500 EUR\n    \
+             Assets:Bank          -500 EUR\n\
+             \n\
+             P 2024-05-01 ACME 120 EUR\n\
+             P 2024-05-01 BETA 40 EUR\n",
+        )
+    }
+
+    #[test]
+    fn test_perfs_view_tree() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        );
+
+        let output = perfs_view(
+            &repo,
+            &settings,
+            PerfArgs {
+                columns: Some(vec![PerfColumn::Equity, PerfColumn::Invested]),
+                group_by: PerfGroupBy::Parent,
+                periods: Vec::new(),
+                benchmark: None,
+            },
+        )?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ Broker │ 1,600 │ 1,500 │")),
+            "{output}"
+        );
+        assert!(
+            lines.iter().any(|l| l.contains("│ ACME │ 1,200 │ 1,000 │")),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ Total investments │ 1,600 │ 1,500 │")),
+            "{output}"
+        );
+
+        let output = perfs_view(
+            &repo,
+            &settings,
+            PerfArgs {
+                columns: Some(vec![PerfColumn::Pnl, PerfColumn::Shares]),
+                group_by: PerfGroupBy::None,
+                periods: vec![Intv::SpecificYear(2024)],
+                benchmark: None,
+            },
+        )?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ P&L │ Shares │ P&L 2024 │")),
+            "{output}"
+        );
+        assert!(
+            lines.iter().any(|l| l
+                .contains("│ Assets:Broker:BETA │ -100 │ 10 BETA │ -100 │")),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ Total investments │ 100 │ │ 100 │")),
+            "{output}"
+        );
+
+        // Compare with investing everything in ACME
+        let output = perfs_view(
+            &repo,
+            &settings,
+            PerfArgs {
+                columns: Some(vec![PerfColumn::Equity]),
+                group_by: PerfGroupBy::None,
+                periods: Vec::new(),
+                benchmark: Some("ACME".to_string()),
+            },
+        )?;
+        let lines = table_lines(&output);
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ Equity │ ACME │ IRR ACME │ Excess │")),
+            "{output}"
+        );
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "│ Assets:Broker:BETA │ 400 │ 600 │ 22.58% │ -44.64% │"
+            )),
+            "{output}"
+        );
+        Ok(())
+    }
 
     #[test]
     fn test_column_order_matches_input() {
@@ -0,0 +1,611 @@
+use crate::{
+    global_settings::{GlobalSettings, format_percent},
+    update_view::{PriceFile, save_prices},
+};
+use alere_lib::{
+    alere_file::AlereFile,
+    commodities::Commodity,
+    errors::AlrError,
+    formatters::Formatter,
+    importers::Exporter,
+    price_check::{Anomaly, Settings, check_prices},
+    price_sources::PriceSourceFrom,
+    prices::Price,
+    repositories::Repository,
+    times::Instant,
+};
+use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
+use itertools::Itertools;
+use rust_decimal::Decimal;
+use std::path::Path;
+use tabled::builder::Builder;
+
+fn find_commodity(repo: &Repository, name: &str) -> Result<Commodity> {
+    Ok(repo
+        .commodities
+        .find(name)
+        .ok_or_else(|| AlrError::Str(format!("unknown commodity {name}")))?)
+}
+
+fn source_name(repo: &Repository, source: PriceSourceFrom) -> String {
+    match source {
+        PriceSourceFrom::Transaction => "transaction".to_string(),
+        PriceSourceFrom::Turnkey => "turnkey".to_string(),
+        PriceSourceFrom::External(id) => repo
+            .get_price_source(id)
+            .map(|s| s.get_name().clone())
+            .unwrap_or_default(),
+    }
+}
+
+fn format_price(price: Decimal) -> String {
+    price.round_dp(6).normalize().to_string()
+}
+
+/// Show how the price of a commodity in another one is computed, with all
+/// the chains of known prices that could be used.  The first one is the one
+/// used for market values, with the same maximum staleness as other reports.
+pub fn explain_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    commodity: &str,
+    target: &str,
+    at: Option<&Instant>,
+) -> Result<String> {
+    let commodity = find_commodity(repo, commodity)?;
+    let target = find_commodity(repo, target)?;
+    let as_of = match at {
+        None => globals.reftime,
+        Some(at) => at.to_time(globals.reftime)?,
+    };
+
+    let mut prices = repo.market_prices(Some(target.clone()));
+    let chains = prices.explain(&commodity, &as_of);
+
+    let source = |s: PriceSourceFrom| source_name(repo, s);
+
+    let mut builder = Builder::default();
+    builder.push_record(["Chain", "From", "To", "Date", "Price", "Source"]);
+    for (idx, chain) in chains.iter().enumerate() {
+        for step in &chain.steps {
+            builder.push_record([
+                (idx + 1).to_string(),
+                step.from.get_symbol().clone(),
+                step.to.get_symbol().clone(),
+                step.price.timestamp.date_naive().to_string(),
+                format_price(step.price.price),
+                if step.inverted {
+                    format!("{} (inverted)", source(step.price.source()))
+                } else {
+                    source(step.price.source())
+                },
+            ]);
+        }
+        if chain.steps.len() > 1 {
+            builder.push_record([
+                (idx + 1).to_string(),
+                commodity.get_symbol().clone(),
+                target.get_symbol().clone(),
+                chain.price.timestamp.date_naive().to_string(),
+                format_price(chain.price.price),
+                std::iter::once(commodity.get_symbol().clone())
+                    .chain(
+                        chain.steps.iter().map(|s| s.to.get_symbol().clone()),
+                    )
+                    .join(" → "),
+            ]);
+        }
+    }
+
+    let summary = match chains.first() {
+        None => format!(
+            "No price for {} in {} as of {}",
+            commodity.get_symbol(),
+            target.get_symbol(),
+            as_of.date_naive(),
+        ),
+        Some(chain) => format!(
+            "1 {} = {} {} as of {} (using chain 1)",
+            commodity.get_symbol(),
+            chain.price.price.round_dp(6).normalize(),
+            target.get_symbol(),
+            chain.price.timestamp.date_naive(),
+        ),
+    };
+    Ok(format!(
+        "{}\n{}",
+        summary,
+        globals.finalize_table(builder, None, false)
+    ))
+}
+
+/// List the known prices of a commodity, in all other commodities or only in
+/// target.  Prices registered the other way around are inverted.
+pub fn list_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    commodity: &str,
+    target: Option<&str>,
+) -> Result<String> {
+    let commodity = find_commodity(repo, commodity)?;
+    let target = target.map(|t| find_commodity(repo, t)).transpose()?;
+
+    let mut rows = Vec::new();
+    for ((origin, other), prices) in repo.prices().iter() {
+        let (currency, inverted) = if *origin == commodity {
+            (other, false)
+        } else if *other == commodity {
+            (origin, true)
+        } else {
+            continue;
+        };
+        if target.as_ref().is_some_and(|t| t != currency) {
+            continue;
+        }
+        for price in prices {
+            if inverted && price.price.is_zero() {
+                continue;
+            }
+            rows.push((currency.get_symbol().clone(), price, inverted));
+        }
+    }
+    rows.sort_by(|a, b| a.0.cmp(&b.0).then(a.1.older_than(b.1)));
+
+    let mut builder = Builder::default();
+    builder.push_record(["Date", "Price", "Currency", "Source"]);
+    for (currency, price, inverted) in rows {
+        let source = source_name(repo, price.source());
+        builder.push_record([
+            price.timestamp.date_naive().to_string(),
+            format_price(if inverted {
+                price.invert().price
+            } else {
+                price.price
+            }),
+            currency,
+            if inverted {
+                format!("{source} (inverted)")
+            } else {
+                source
+            },
+        ]);
+    }
+    Ok(globals.finalize_table(builder, None, false))
+}
+
+/// Register a new price, and save it in the input file
+pub async fn add_price(
+    repo: &mut Repository,
+    input: &Path,
+    commodity: &str,
+    target: &str,
+    price: Decimal,
+    timestamp: DateTime<Local>,
+    source: &str,
+) -> Result<String> {
+    PriceFile::of(input)?;
+    let commodity = find_commodity(repo, commodity)?;
+    let target = find_commodity(repo, target)?;
+    let source = repo.get_or_add_price_source(source);
+    let new_price = Price::new(
+        timestamp,
+        price,
+        PriceSourceFrom::External(source.get_id()),
+    );
+    repo.add_price(&commodity, &target, new_price.clone());
+    save_prices(
+        repo,
+        input,
+        &[(commodity.clone(), target.clone(), new_price)],
+    )
+    .await?;
+    Ok(format!(
+        "Saved 1 {} = {} {} on {} in {}",
+        commodity.get_symbol(),
+        format_price(price),
+        target.get_symbol(),
+        timestamp.date_naive(),
+        input.display(),
+    ))
+}
+
+/// Delete the prices of a commodity in target (or the reverse) on a given
+/// date, and save the input file.  Alere files are rewritten entirely.
+pub async fn delete_prices(
+    repo: &mut Repository,
+    input: &Path,
+    commodity: &str,
+    target: &str,
+    date: NaiveDate,
+) -> Result<String> {
+    let format = PriceFile::of(input)?;
+    let commodity = find_commodity(repo, commodity)?;
+    let target = find_commodity(repo, target)?;
+    let removed = repo.remove_prices(&commodity, &target, date);
+    if removed.is_empty() {
+        return Ok(format!(
+            "No price for {} in {} on {}",
+            commodity.get_symbol(),
+            target.get_symbol(),
+            date
+        ));
+    }
+    match format {
+        PriceFile::Alere => {
+            AlereFile::default().export_file(
+                repo,
+                input,
+                &Formatter::default(),
+            )?;
+        }
+        PriceFile::KmyMoney => {
+            alere_lib::kmymoney::delete_prices(
+                input, &commodity, &target, date,
+            )
+            .await?;
+        }
+    }
+    Ok(format!(
+        "Deleted {} price(s) for {} in {} on {}",
+        removed.len(),
+        commodity.get_symbol(),
+        target.get_symbol(),
+        date
+    ))
+}
+
+/// Report suspicious prices.  Thresholds are given as percentages.
+pub fn check_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    max_jump: Decimal,
+    max_deviation: Decimal,
+    window_days: u32,
+    max_age_days: u32,
+) -> Result<String> {
+    let settings = Settings {
+        max_jump: max_jump / Decimal::ONE_HUNDRED,
+        max_deviation: max_deviation / Decimal::ONE_HUNDRED,
+        window: TimeDelta::days(i64::from(window_days)),
+        max_age: TimeDelta::days(i64::from(max_age_days)),
+        commodity: globals.commodity.clone(),
+    };
+    let anomalies = check_prices(repo, &settings, globals.reftime);
+    if anomalies.is_empty() {
+        return Ok("No suspicious price found".to_string());
+    }
+
+    let pair = |origin: &Commodity, target: &Commodity| {
+        format!("{} in {}", origin.get_symbol(), target.get_symbol())
+    };
+    let dated = |price: &Price| {
+        format!(
+            "{} ({})",
+            format_price(price.price),
+            price.timestamp.date_naive()
+        )
+    };
+
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Issue",
+        "Commodity",
+        "Date",
+        "Price",
+        "Compared to",
+        "Change",
+    ]);
+    for anomaly in &anomalies {
+        let change = format_percent(&anomaly.change());
+        builder.push_record(match anomaly {
+            Anomaly::Jump {
+                origin,
+                target,
+                previous,
+                price,
+            } => [
+                "jump".to_string(),
+                pair(origin, target),
+                price.timestamp.date_naive().to_string(),
+                format_price(price.price),
+                dated(previous),
+                change,
+            ],
+            Anomaly::Contradiction {
+                origin,
+                target,
+                price,
+                transaction,
+            } => [
+                "differs from transaction".to_string(),
+                pair(origin, target),
+                price.timestamp.date_naive().to_string(),
+                format_price(price.price),
+                dated(transaction),
+                change,
+            ],
+            Anomaly::Stale { commodity, latest } => [
+                "stale".to_string(),
+                commodity.get_symbol().clone(),
+                latest.map_or("never".to_string(), |ts| {
+                    ts.date_naive().to_string()
+                }),
+                String::new(),
+                String::new(),
+                String::new(),
+            ],
+        });
+    }
+    Ok(globals.finalize_table(builder, None, false))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use alere_lib::{importers::Importer, kmymoney::KmyMoneyImporter};
+    use chrono::{Local, TimeZone};
+    use futures::executor::block_on;
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "prices",
+            "commodity 1,000.00 EUR\n\
+             commodity 1,000.00 USD\n\
+             \n\
+             P 2024-01-10 AAPL 100 USD\n\
+             P 2024-01-01 EUR 1.25 USD\n\
+             P 2024-02-01 EUR 1.1 USD\n",
+        )
+    }
+
+    #[test]
+    fn test_list_and_check_views() -> Result<()> {
+        let mut repo = load_journal(
+            "prices_check",
+            "commodity 1,000.00 EUR\n\
+             commodity 1,000.00 USD\n\
+             \n\
+             P 2024-01-01 EUR 1.25 USD\n\
+             P 2024-01-14 EUR 1.2 USD\n\
+             P 2023-12-01 MSFT 300 USD\n\
+             P 2024-01-10 AAPL 100 USD\n\
+             P 2024-01-11 AAPL 10300 USD\n\
+             P 2024-01-12 AAPL 104 USD\n\
+             \n\
+             2023-12-01 Buy\n\
+             \x20   assets:stocks   5 MSFT @@ 1500 USD\n\
+             \x20   assets:cash\n\
+             \n\
+             2024-01-11 Buy\n\
+             \x20   assets:stocks   10 AAPL @@ 1030 USD\n\
+             \x20   assets:cash\n",
+        )?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 1, 15, 0, 0, 0).unwrap(),
+        );
+
+        let output = list_view(&repo, &settings, "AAPL", Some("USD"))?;
+        let lines = table_lines(&output);
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "│ 2024-01-11 │ 103 │ USD │ transaction (inverted) │"
+            )),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("│ 2024-01-12 │ 104 │ USD │ hledger │")),
+            "{output}"
+        );
+        assert!(
+            list_view(&repo, &settings, "AAPL", Some("EUR"))?
+                .lines()
+                .all(|l| !l.contains("2024"))
+        );
+
+        // Stale prices are reported even when reports ignore them
+        repo.set_max_price_staleness(Some(TimeDelta::days(30)));
+        let output = check_view(
+            &repo,
+            &settings,
+            Decimal::from(50),
+            Decimal::from(20),
+            5,
+            7,
+        )?;
+        let lines = table_lines(&output);
+        for expected in [
+            "│ jump │ AAPL in USD │ 2024-01-11 │ 10300 │ 100 (2024-01-10) │ \
+             10200.00% │",
+            "│ jump │ AAPL in USD │ 2024-01-12 │ 104 │ 10300 (2024-01-11) │",
+            "│ differs from transaction │ AAPL in USD │ 2024-01-11 │ 10300 │ \
+             103 (2024-01-11) │ 9900.00% │",
+            "│ stale │ MSFT │ 2023-12-01 │",
+        ] {
+            assert!(lines.iter().any(|l| l.contains(expected)), "{output}");
+        }
+        assert_eq!(
+            lines
+                .iter()
+                .filter(|l| l.contains("differs from transaction"))
+                .count(),
+            1,
+            "{output}"
+        );
+        assert!(!output.contains("│ stale │ AAPL"), "{output}");
+        assert!(!output.contains("│ stale │ USD"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_add_and_delete_prices() -> Result<()> {
+        let path = std::env::temp_dir()
+            .join(format!("test_prices_{}.alere", std::process::id()));
+        let repo = load_test_repo()?;
+        AlereFile::default().export_file(
+            &repo,
+            &path,
+            &Formatter::default(),
+        )?;
+        let reload =
+            || block_on(AlereFile::default().import_file(&path, |_, _| {}));
+        let settings = GlobalSettings::default();
+
+        let mut repo = reload()?;
+        let ts = Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        block_on(add_price(
+            &mut repo,
+            &path,
+            "AAPL",
+            "USD",
+            Decimal::from(120),
+            ts,
+            "User",
+        ))?;
+        let mut repo = reload()?;
+        let output = list_view(&repo, &settings, "AAPL", None)?;
+        assert!(
+            table_lines(&output)
+                .iter()
+                .any(|l| l.contains("│ 2024-03-01 │ 120 │ USD │ User │")),
+            "{output}"
+        );
+
+        let output = block_on(delete_prices(
+            &mut repo,
+            &path,
+            "USD",
+            "AAPL",
+            ts.date_naive(),
+        ))?;
+        assert_eq!(output, "Deleted 1 price(s) for USD in AAPL on 2024-03-01");
+        let repo = reload()?;
+        std::fs::remove_file(&path)?;
+        let output = list_view(&repo, &settings, "AAPL", None)?;
+        assert!(!output.contains("2024-03-01"), "{output}");
+        assert!(output.contains("2024-01-10"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_prices_unsupported_format() -> Result<()> {
+        let mut repo = load_test_repo()?;
+        let path = Path::new("prices.journal");
+        let ts = Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let err = block_on(add_price(
+            &mut repo,
+            path,
+            "AAPL",
+            "USD",
+            Decimal::from(120),
+            ts,
+            "User",
+        ))
+        .unwrap_err();
+        assert!(
+            err.to_string().starts_with(
+                "Saving prices is only supported for .kmy/.alere files"
+            ),
+            "{err}"
+        );
+        assert!(
+            block_on(delete_prices(
+                &mut repo,
+                path,
+                "AAPL",
+                "USD",
+                NaiveDate::from_ymd_opt(2024, 1, 10).unwrap(),
+            ))
+            .is_err()
+        );
+
+        // The repository was left unchanged
+        let output =
+            list_view(&repo, &GlobalSettings::default(), "AAPL", None)?;
+        assert!(!output.contains("2024-03-01"), "{output}");
+        assert!(output.contains("2024-01-10"), "{output}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_delete_prices_in_kmy() -> Result<()> {
+        let mut editor = kmy_editor::KmyEditor::new()?;
+        editor.add_currency("EUR", "Euro", "€")?;
+        editor.add_currency("USD", "US Dollar", "$")?;
+        let reload = || {
+            block_on(
+                KmyMoneyImporter::default()
+                    .import_file(editor.path(), |_, _| {}),
+            )
+        };
+
+        let mut repo = reload()?;
+        let ts = Local.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        block_on(add_price(
+            &mut repo,
+            editor.path(),
+            "USD",
+            "EUR",
+            Decimal::new(93, 2),
+            ts,
+            "User",
+        ))?;
+
+        let mut repo = reload()?;
+        assert_eq!(repo.prices().iter().count(), 1);
+        block_on(delete_prices(
+            &mut repo,
+            editor.path(),
+            "EUR",
+            "USD",
+            ts.date_naive(),
+        ))?;
+        assert_eq!(reload()?.prices().iter().count(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_explain_view() -> Result<()> {
+        let mut repo = load_test_repo()?;
+        let mut settings = GlobalSettings {
+            reftime: Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+            ..GlobalSettings::default()
+        };
+        settings.postprocess(&repo);
+
+        let at: Instant = "2024-01-20".parse()?;
+        let output = explain_view(&repo, &settings, "AAPL", "EUR", Some(&at))?;
+        let lines = table_lines(&output);
+        assert_eq!(
+            lines.first().map(String::as_str),
+            Some("1 AAPL = 80 EUR as of 2024-01-01 (using chain 1)"),
+        );
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "│ 1 │ USD │ EUR │ 2024-01-01 │ 0.8 │ hledger (inverted) │"
+            )),
+            "{output}"
+        );
+
+        // Old prices are ignored, as in all other reports
+        repo.set_max_price_staleness(Some(TimeDelta::days(5)));
+        let output = explain_view(&repo, &settings, "AAPL", "EUR", Some(&at))?;
+        assert!(
+            repo.market_prices(Some(find_commodity(&repo, "EUR")?))
+                .get_price(
+                    &find_commodity(&repo, "AAPL")?,
+                    &at.to_time(settings.reftime)?
+                )
+                .is_none()
+        );
+        assert!(
+            output.starts_with("No price for AAPL in EUR as of 2024-01-20"),
+            "{output}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+use crate::global_settings::GlobalSettings;
+use alere_lib::{
+    accounts::AccountNameDepth,
+    lots::CostMethod,
+    multi_values::MultiValue,
+    repositories::Repository,
+    tax_report::{Settings, TaxReport},
+};
+use anyhow::Result;
+use tabled::builder::Builder;
+
+pub struct TaxArgs {
+    pub year: i32,
+    pub fiscal_start_month: u32,
+    pub long_term_months: u32,
+    pub method: Option<CostMethod>,
+    pub csv: bool,
+}
+
+/// Show the capital gains for each sale in a fiscal year, followed by the
+/// totals for each commodity.  Alternatively, output the sales as CSV.
+/// Sales with an unknown cost basis are flagged, and left out of the totals.
+pub fn tax_view(
+    repo: &Repository,
+    globals: &GlobalSettings,
+    args: &TaxArgs,
+) -> Result<String> {
+    let report = TaxReport::new(
+        repo,
+        &Settings {
+            commodity: globals.commodity.clone(),
+            method: args.method,
+            year: args.year,
+            fiscal_start_month: args.fiscal_start_month,
+            long_term_months: args.long_term_months,
+        },
+    )?;
+
+    if args.csv {
+        let mut output = Vec::new();
+        report.write_csv(&mut output)?;
+        return Ok(String::from_utf8(output)?.trim_end().to_string());
+    }
+
+    let display = |v: &MultiValue| v.display(&globals.format);
+
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Account", "Acquired", "Sold", "Term", "Shares", "Proceeds", "Cost",
+        "Gain",
+    ]);
+    for row in &report.rows {
+        // Sales with an unknown cost basis are not in the totals
+        let known = |text: String| {
+            if row.unknown_basis {
+                "?".to_string()
+            } else {
+                text
+            }
+        };
+        builder.push_record([
+            row.account.name(AccountNameDepth::unlimited()),
+            known(row.acquired.date_naive().to_string()),
+            row.sold_on.date_naive().to_string(),
+            known(row.term.to_string()),
+            row.shares.display(&globals.format),
+            display(&row.proceeds),
+            known(display(&row.cost)),
+            known(display(&row.gain)),
+        ]);
+    }
+    let sales = globals.finalize_table(builder, Some(4), true);
+
+    let mut builder = Builder::default();
+    builder.push_record([
+        "Commodity",
+        "Proceeds",
+        "Cost",
+        "Short term",
+        "Long term",
+    ]);
+    let mut short_term = MultiValue::zero();
+    let mut long_term = MultiValue::zero();
+    for t in &report.totals {
+        builder.push_record([
+            t.commodity.get_symbol().clone(),
+            display(&t.proceeds),
+            display(&t.cost),
+            display(&t.short_term),
+            display(&t.long_term),
+        ]);
+        short_term += &t.short_term;
+        long_term += &t.long_term;
+    }
+    builder.push_record([
+        "Total".to_string(),
+        String::new(),
+        String::new(),
+        display(&short_term),
+        display(&long_term),
+    ]);
+    let totals = globals.finalize_table(builder, Some(1), true);
+
+    let warning = if report.unknown_basis == 0 {
+        String::new()
+    } else {
+        format!(
+            "\n{} sale(s) of shares that were never bought have an unknown \
+             cost basis (shown as ?), and are not included in the totals",
+            report.unknown_basis
+        )
+    };
+
+    Ok(format!(
+        "Fiscal year {} to {}\n{}\n{}{}",
+        report.start.date_naive(),
+        report.end.date_naive().pred_opt().unwrap_or_default(),
+        sales,
+        totals,
+        warning,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur, table_lines};
+    use chrono::{Local, TimeZone};
+
+    fn load_test_repo() -> Result<Repository> {
+        load_journal(
+            "tax",
+            "account Assets:Bank   ; type: C\n\
+             account Assets:Broker:ACME\n\
+             \n\
+             2022-01-10 Buy\n    \
+             Assets:Broker:ACME    10 ACME @@ 1000 EUR\n    \
+             Assets:Bank          -1000 EUR\n\
+             \n\
+             2024-02-10 Buy\n    \
+             Assets:Broker:ACME    5 ACME @@ 600 EUR\n    \
+             Assets:Bank          -600 EUR\n\
+             \n\
+             2024-03-10 Sell\n    \
+             Assets:Broker:ACME    -12 ACME @@ 1560 EUR\n    \
+             Assets:Bank          1560 EUR\n",
+        )
+    }
+
+    #[test]
+    fn test_tax_view() -> Result<()> {
+        let repo = load_test_repo()?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap(),
+        );
+        let mut args = TaxArgs {
+            year: 2024,
+            fiscal_start_month: 1,
+            long_term_months: 12,
+            method: None,
+            csv: false,
+        };
+
+        let output = tax_view(&repo, &settings, &args)?;
+        let lines = table_lines(&output);
+        assert!(
+            lines.iter().any(|l| l.contains(
+                "2022-01-10 │ 2024-03-10 │ long │ 10 ACME │ 1,300 │ 1,000 │ 300"
+            )),
+            "{output}"
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.contains("ACME │ 1,560 │ 1,240 │ 20 │ 300")),
+            "{output}"
+        );
+
+        args.csv = true;
+        let output = tax_view(&repo, &settings, &args)?;
+        assert_eq!(
+            output.lines().nth(2),
+            Some(
+                "Assets:Broker:ACME,ACME,2,2024-02-10,2024-03-10,short,\
+                 260,240,20,EUR"
+            )
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,43 @@
+//! Helpers shared by the tests of the views
+
+use crate::global_settings::GlobalSettings;
+use alere_lib::{
+    hledger::Hledger, importers::Importer, repositories::Repository,
+};
+use anyhow::Result;
+use chrono::{DateTime, Local};
+use futures::executor::block_on;
+
+/// Load a repository from the text of an hledger journal.  The name is used
+/// for the temporary file, so must be unique among tests.
+pub fn load_journal(name: &str, journal: &str) -> Result<Repository> {
+    let path = std::env::temp_dir()
+        .join(format!("test_{name}_{}.journal", std::process::id()));
+    std::fs::write(&path, journal)?;
+    let repo = block_on(Hledger::default().import_file(&path, |_, _| {}));
+    std::fs::remove_file(&path)?;
+    repo
+}
+
+/// Settings to display values in EUR, as of the given time
+pub fn settings_in_eur(
+    repo: &Repository,
+    reftime: DateTime<Local>,
+) -> GlobalSettings {
+    let mut settings = GlobalSettings {
+        reftime,
+        commodity_str: Some("EUR".to_string()),
+        ..GlobalSettings::default()
+    };
+    settings.postprocess(repo);
+    settings
+}
+
+/// The lines of a table, where each sequence of whitespaces is replaced with
+/// a single space, so that tests do not depend on the width of columns.
+pub fn table_lines(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(|l| l.split_whitespace().collect::<Vec<_>>().join(" "))
+        .collect()
+}
@@ -1,18 +1,76 @@
-use alere_lib::repositories::Repository;
+use alere_lib::{
+    alere_file::AlereFile, formatters::Formatter, importers::Exporter,
+    price_sources::PriceSourceFrom, prices::Price, repositories::Repository,
+};
+use alere_lib::{commodities::Commodity, times::Instant};
 use anyhow::Result;
+use chrono::{DateTime, Local, NaiveDate, TimeDelta};
 use futures::future::join_all;
-use rust_decimal::prelude::ToPrimitive;
-use stock_importer::{StockSource, YahooFinance};
+use rust_decimal::{Decimal, prelude::ToPrimitive};
+use std::{
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+use stock_importer::{QuoteProviders, StockPrice, StockSource, Transport};
 use tabled::builder::Builder;
 
 use crate::global_settings::GlobalSettings;
 
-type PriceUpdate = (
+/// Name of the price source used when saving prices that were not
+/// downloaded from a provider.
+const PRICE_SOURCE: &str = "Yahoo Finance";
+
+pub struct UpdateArgs {
+    pub save: bool,
+    pub dry_run: bool,
+
+    // JSON file describing additional providers
+    pub providers: Option<PathBuf>,
+
+    // How requests are sent to the providers
+    pub transport: Transport,
+}
+
+/// A commodity to fetch: its symbol, the number of shares we own, and the
+/// name of the price source.
+type Fetchable = (
     alere_lib::commodities::Commodity,
+    String,
     rust_decimal::Decimal,
-    chrono::DateTime<chrono::Local>,
+    String,
 );
 
+/// A range of days without prices for a commodity: its symbol, the name of
+/// the price source, and the missing days.
+type MissingRange = (Commodity, String, String, RangeInclusive<NaiveDate>);
+
+/// Number of consecutive days without a price that are accepted when
+/// looking for gaps in the history (markets are closed on week-ends and
+/// bank holidays).
+const MAX_GAP_DAYS: i64 = 4;
+
+/// Number of requests sent in parallel, and the pause between batches, to
+/// respect the rate limits of the providers.
+const BATCH_SIZE: usize = 10;
+const BATCH_PAUSE_MS: u64 = 500;
+
+/// A new price, to be registered in the repository
+struct PriceUpdate {
+    commodity: Commodity,
+
+    // The commodity the price is expressed in
+    currency: Commodity,
+
+    // Whether the currency was reported by the provider, rather than guessed
+    reported_currency: bool,
+
+    price: Decimal,
+    timestamp: DateTime<Local>,
+    source: String,
+}
+
 fn show_current_networth(
     repo: &mut Repository,
     settings: &GlobalSettings,
@@ -28,30 +86,46 @@ fn show_current_networth(
 pub async fn update_prices(
     repo: &mut Repository,
     settings: &GlobalSettings,
+    input: &Path,
+    args: &UpdateArgs,
 ) -> Result<()> {
+    if args.save {
+        PriceFile::of(input)?;
+    }
+
     println!("Current Networth:");
     show_current_networth(repo, settings)?;
     println!();
 
-    let source = YahooFinance::new()?;
+    let providers = load_providers(args)?;
+
     #[allow(clippy::mutable_key_type)]
     let commodity_shares = repo.compute_commodity_balances();
 
-    let mut fetchable: Vec<_> = repo
+    // Currencies can be fetched too (e.g. from the ECB), except the one
+    // prices are expressed in.
+    let mut fetchable: Vec<Fetchable> = repo
         .commodities
         .iter_commodities()
-        .filter(|c| !c.is_currency())
+        .filter(|c| settings.commodity.as_ref() != Some(*c))
         .filter_map(|c| {
-            if let (Some(symbol), Some(_source)) =
+            if let (Some(symbol), Some(source)) =
                 (c.get_quote_symbol(), c.get_quote_source())
             {
                 let shares = commodity_shares
                     .get(c)
                     .copied()
                     .unwrap_or(rust_decimal::Decimal::ZERO);
-                if shares > rust_decimal::Decimal::ZERO {
-                    Some((c.clone(), symbol, shares))
+                if shares <= rust_decimal::Decimal::ZERO {
+                    None
+                } else if let Some((name, _)) = providers.get(&source) {
+                    Some((c.clone(), symbol, shares, name.to_string()))
                 } else {
+                    eprintln!(
+                        "No quote provider for {} (source {})",
+                        c.get_name(),
+                        source
+                    );
                     None
                 }
             } else {
@@ -70,53 +144,215 @@ pub async fn update_prices(
     fetchable.sort_by(|a, b| a.0.get_name().cmp(&b.0.get_name()));
     println!("Fetching {} stock prices...\n", fetchable.len());
 
-    let all_results = fetch_prices_in_batches(&source, &fetchable).await;
-    display_and_update_prices(repo, settings, fetchable, all_results)?;
+    let all_results = fetch_prices_in_batches(&providers, &fetchable).await;
+    let new_prices =
+        display_and_update_prices(repo, settings, fetchable, all_results)?;
+
+    if args.dry_run {
+        println!("\nDry run, prices were not updated");
+        return Ok(());
+    }
+
+    let saved = update_prices_in_repo(repo, new_prices);
 
     println!("\nUpdated Networth:");
     show_current_networth(repo, settings)?;
 
+    if args.save {
+        let count = save_prices(repo, input, &saved).await?;
+        println!("\nSaved {} prices in {}", count, input.display());
+    }
+
     Ok(())
 }
 
-async fn fetch_prices_in_batches(
-    source: &YahooFinance,
-    fetchable: &[(
-        alere_lib::commodities::Commodity,
-        String,
-        rust_decimal::Decimal,
-    )],
-) -> Vec<Result<stock_importer::StockPrice, anyhow::Error>> {
-    let batch_size = 10;
-    let mut all_results = Vec::new();
+/// Fill the gaps in the price history since a given date, downloading the
+/// daily prices from each commodity's provider.
+pub async fn backfill_prices(
+    repo: &mut Repository,
+    settings: &GlobalSettings,
+    input: &Path,
+    args: &UpdateArgs,
+    since: &Instant,
+) -> Result<()> {
+    let Some(target) = settings.commodity.clone() else {
+        anyhow::bail!("Backfilling prices requires a --currency");
+    };
+    if args.save {
+        PriceFile::of(input)?;
+    }
+    let providers = &load_providers(args)?;
+    let since = since.to_time(settings.reftime)?;
+    let until = settings.reftime.min(Local::now());
+
+    let mut commodities = repo
+        .commodities
+        .iter_commodities()
+        .filter(|c| **c != target)
+        .collect::<Vec<_>>();
+    commodities.sort_by(|a, b| a.get_name().cmp(&b.get_name()));
+
+    let mut missing: Vec<MissingRange> = Vec::new();
+    for c in commodities {
+        let (Some(symbol), Some(source)) =
+            (c.get_quote_symbol(), c.get_quote_source())
+        else {
+            continue;
+        };
+        let Some((name, _)) = providers.get(&source) else {
+            eprintln!(
+                "No quote provider for {} (source {})",
+                c.get_name(),
+                source
+            );
+            continue;
+        };
+        let currency = c.get_quote_currency().unwrap_or_else(|| target.clone());
+        for range in repo.prices().missing_ranges(
+            c,
+            &currency,
+            since,
+            until,
+            TimeDelta::days(MAX_GAP_DAYS),
+        ) {
+            missing.push((c.clone(), symbol.clone(), name.to_string(), range));
+        }
+    }
+
+    if missing.is_empty() {
+        println!("No missing prices since {}", since.date_naive());
+        return Ok(());
+    }
+    println!("Fetching {} missing price ranges...\n", missing.len());
+
+    let all_results =
+        in_batches(&missing, |(_, symbol, source, range)| async move {
+            match providers.get(source) {
+                Some((_, provider)) => {
+                    provider.fetch_history(symbol, range).await
+                }
+                None => Err(anyhow::anyhow!("Unknown source {}", source)),
+            }
+        })
+        .await;
+
+    let mut builder = Builder::default();
+    builder.push_record(["Name", "Symbol", "Source", "Missing", "Fetched"]);
+    let mut new_prices = Vec::new();
+    for ((commodity, symbol, source, range), result) in
+        missing.iter().zip(all_results)
+    {
+        let history = match result {
+            Ok(history) => history,
+            Err(e) => {
+                eprintln!("Failed to fetch history for {}: {}", symbol, e);
+                continue;
+            }
+        };
+        let (currency, reported_currency) = match quote_currency(
+            repo,
+            settings,
+            commodity,
+            history.currency.as_deref(),
+        ) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Cannot store prices for {}: {}", symbol, e);
+                continue;
+            }
+        };
+        builder.push_record([
+            commodity.get_name().to_string(),
+            symbol.clone(),
+            source.clone(),
+            format!("{} - {}", range.start(), range.end()),
+            history.prices.len().to_string(),
+        ]);
+        new_prices.extend(history.prices.iter().filter_map(|p| {
+            Some(PriceUpdate {
+                commodity: commodity.clone(),
+                currency: currency.clone(),
+                reported_currency,
+                price: p.close,
+                timestamp: p
+                    .date
+                    .and_hms_opt(0, 0, 0)?
+                    .and_local_timezone(Local)
+                    .earliest()?,
+                source: source.clone(),
+            })
+        }));
+    }
+    let mut table = builder.build();
+    settings.style.apply(&mut table);
+    println!("{}", table);
+
+    if args.dry_run {
+        println!("\nDry run, prices were not updated");
+        return Ok(());
+    }
+
+    let saved = update_prices_in_repo(repo, new_prices);
+    if args.save {
+        let count = save_prices(repo, input, &saved).await?;
+        println!("\nSaved {} prices in {}", count, input.display());
+    }
+    Ok(())
+}
 
-    for (i, chunk) in fetchable.chunks(batch_size).enumerate() {
-        let fetch_tasks: Vec<_> = chunk
-            .iter()
-            .map(|(_, symbol, _)| source.fetch_price(symbol))
-            .collect();
+/// The builtin providers, and the user-defined ones
+fn load_providers(args: &UpdateArgs) -> Result<QuoteProviders> {
+    let mut providers = QuoteProviders::new()?;
+    if let Some(path) = &args.providers {
+        providers.load_templates(path)?;
+    }
+    providers.set_transport(&args.transport);
+    Ok(providers)
+}
 
-        let results = join_all(fetch_tasks).await;
+/// Run the requests in batches, pausing between batches to respect the rate
+/// limits of the providers.
+async fn in_batches<'a, T, F, Fut>(items: &'a [T], fetch: F) -> Vec<Fut::Output>
+where
+    F: Fn(&'a T) -> Fut,
+    Fut: Future,
+{
+    let mut all_results = Vec::new();
+
+    for (i, chunk) in items.chunks(BATCH_SIZE).enumerate() {
+        let results = join_all(chunk.iter().map(&fetch)).await;
         all_results.extend(results);
 
-        if i < fetchable.len().div_ceil(batch_size) - 1 {
-            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+        if i < items.len().div_ceil(BATCH_SIZE) - 1 {
+            tokio::time::sleep(tokio::time::Duration::from_millis(
+                BATCH_PAUSE_MS,
+            ))
+            .await;
         }
     }
 
     all_results
 }
 
+async fn fetch_prices_in_batches(
+    providers: &QuoteProviders,
+    fetchable: &[Fetchable],
+) -> Vec<Result<stock_importer::StockPrice, anyhow::Error>> {
+    in_batches(fetchable, |(_, symbol, _, source)| async move {
+        match providers.get(source) {
+            Some((_, provider)) => provider.fetch_price(symbol).await,
+            None => Err(anyhow::anyhow!("Unknown source {}", source)),
+        }
+    })
+    .await
+}
+
 fn display_and_update_prices(
     repo: &mut Repository,
     settings: &GlobalSettings,
-    fetchable: Vec<(
-        alere_lib::commodities::Commodity,
-        String,
-        rust_decimal::Decimal,
-    )>,
-    all_results: Vec<Result<stock_importer::StockPrice, anyhow::Error>>,
-) -> Result<()> {
+    fetchable: Vec<Fetchable>,
+    all_results: Vec<Result<StockPrice, anyhow::Error>>,
+) -> Result<Vec<PriceUpdate>> {
     let mut builder = Builder::default();
     builder.push_record([
         "Name",
@@ -125,30 +361,56 @@ fn display_and_update_prices(
         "Old Price",
         "Old Date",
         "New Price",
+        "Currency",
         "New Date",
         "Change",
+        "Day Change",
+        "Market",
     ]);
 
-    let mut prices = repo.market_prices(settings.commodity.clone());
+    // Prices are compared in the currency of the quote
+    #[allow(clippy::mutable_key_type)]
+    let mut prices = HashMap::new();
     let mut new_prices = Vec::new();
 
-    for ((commodity, symbol, shares), result) in
+    for ((commodity, symbol, shares, source), result) in
         fetchable.iter().zip(all_results.iter())
     {
-        if let Ok(price_data) = result {
-            if let Some((row, price_update)) = build_price_row(
-                commodity,
-                symbol,
-                shares,
-                price_data,
-                &mut prices,
-                settings,
-            ) {
-                builder.push_record(row);
-                new_prices.push(price_update);
+        let price_data = match result {
+            Ok(p) => p,
+            Err(e) => {
+                eprintln!("Failed to fetch {}: {}", symbol, e);
+                continue;
             }
-        } else if let Err(e) = result {
-            eprintln!("Failed to fetch {}: {}", symbol, e);
+        };
+        let (currency, reported_currency) = match quote_currency(
+            repo,
+            settings,
+            commodity,
+            price_data.currency.as_deref(),
+        ) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("Cannot store price for {}: {}", symbol, e);
+                continue;
+            }
+        };
+        let market = prices
+            .entry(currency.clone())
+            .or_insert_with(|| repo.market_prices(Some(currency.clone())));
+        let update = PriceUpdate {
+            commodity: commodity.clone(),
+            currency,
+            reported_currency,
+            price: price_data.price,
+            timestamp: price_data.timestamp,
+            source: source.clone(),
+        };
+        if let Some(row) = build_price_row(
+            symbol, shares, price_data, &update, market, settings,
+        ) {
+            builder.push_record(row);
+            new_prices.push(update);
         }
     }
 
@@ -157,21 +419,42 @@ fn display_and_update_prices(
     println!("\nStock Price Updates:");
     println!("{}", table);
 
-    update_prices_in_repo(repo, settings, new_prices);
+    Ok(new_prices)
+}
 
-    Ok(())
+/// The commodity a quote is expressed in: the currency reported by the
+/// provider, or else the known quote currency of the commodity, or else the
+/// reporting currency.  Also return whether the provider reported it.
+fn quote_currency(
+    repo: &Repository,
+    settings: &GlobalSettings,
+    commodity: &Commodity,
+    reported: Option<&str>,
+) -> Result<(Commodity, bool)> {
+    match reported {
+        Some(code) => repo
+            .commodities
+            .find(code)
+            .map(|c| (c, true))
+            .ok_or_else(|| anyhow::anyhow!("unknown currency {}", code)),
+        None => commodity
+            .get_quote_currency()
+            .or_else(|| settings.commodity.clone())
+            .map(|c| (c, false))
+            .ok_or_else(|| anyhow::anyhow!("unknown quote currency")),
+    }
 }
 
 fn build_price_row(
-    commodity: &alere_lib::commodities::Commodity,
     symbol: &str,
-    shares: &rust_decimal::Decimal,
-    price_data: &stock_importer::StockPrice,
+    shares: &Decimal,
+    price_data: &StockPrice,
+    update: &PriceUpdate,
     prices: &mut alere_lib::market_prices::MarketPrices,
     settings: &GlobalSettings,
-) -> Option<([String; 8], PriceUpdate)> {
+) -> Option<[String; 11]> {
     let old_price_opt =
-        prices.get_price_with_date(commodity, &settings.reftime);
+        prices.get_price_with_date(&update.commodity, &settings.reftime);
 
     let should_update = old_price_opt
         .as_ref()
@@ -192,56 +475,247 @@ fn build_price_row(
         })
         .unwrap_or_else(|| ("N/A".to_string(), "N/A".to_string()));
 
-    let new_price = format!("{:.2}", price_data.price);
+    let new_price = format!("{:.2}", price_data.price.to_f64().unwrap_or(0.0));
     let new_date = price_data.timestamp.format("%Y-%m-%d").to_string();
     let shares_str = format!("{:.2}", shares.to_f64().unwrap_or(0.0));
 
-    let change = if let Some(ref old) = old_price_opt {
-        let old_f64 = old.price.to_f64().unwrap_or(0.0);
-        if old_f64 > 0.0 {
-            let diff = (price_data.price - old_f64) / old_f64 * 100.0;
-            format!("{:+.2}%", diff)
-        } else {
+    let percent = |diff: Decimal, base: Decimal| {
+        if base.is_zero() {
             "N/A".to_string()
+        } else {
+            format!(
+                "{:+.2}%",
+                (diff / base * Decimal::ONE_HUNDRED).to_f64().unwrap_or(0.0)
+            )
         }
-    } else {
-        "N/A".to_string()
+    };
+    let change = match &old_price_opt {
+        Some(old) => percent(price_data.price - old.price, old.price),
+        None => "N/A".to_string(),
+    };
+    let day_change = match (price_data.change, price_data.previous_close) {
+        (Some(c), Some(p)) => percent(c, p),
+        _ => String::new(),
     };
 
-    let row = [
-        commodity.get_name().to_string(),
+    Some([
+        update.commodity.get_name().to_string(),
         symbol.to_string(),
         shares_str,
         old_price_str,
         old_date_str,
         new_price,
+        update.currency.get_symbol().to_string(),
         new_date,
         change,
-    ];
-
-    let price_update = (
-        commodity.clone(),
-        rust_decimal::Decimal::from_f64_retain(price_data.price)
-            .unwrap_or_default(),
-        price_data.timestamp,
-    );
-
-    Some((row, price_update))
+        day_change,
+        price_data.market_state.to_string(),
+    ])
 }
 
+/// Register the new prices in the repository, and return them.
+/// The quote currency reported by the provider is remembered for the
+/// commodity.
 fn update_prices_in_repo(
     repo: &mut Repository,
-    settings: &GlobalSettings,
     new_prices: Vec<PriceUpdate>,
-) {
-    if let Some(ref target_commodity) = settings.commodity {
-        for (commodity, price_value, timestamp) in new_prices {
-            let price = alere_lib::prices::Price::new(
-                timestamp,
-                price_value,
-                alere_lib::price_sources::PriceSourceFrom::Transaction,
+) -> Vec<(Commodity, Commodity, Price)> {
+    let mut result = Vec::new();
+    for update in new_prices {
+        let source = repo.get_or_add_price_source(&update.source);
+        let price = Price::new(
+            update.timestamp,
+            update.price,
+            PriceSourceFrom::External(source.get_id()),
+        );
+        if update.reported_currency
+            && update.commodity.get_quote_currency().as_ref()
+                != Some(&update.currency)
+        {
+            update
+                .commodity
+                .clone()
+                .set_quote_currency(&update.currency);
+        }
+        repo.add_price(&update.commodity, &update.currency, price.clone());
+        result.push((update.commodity, update.currency, price));
+    }
+    result
+}
+
+/// The formats of input files in which prices can be saved
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum PriceFile {
+    Alere,
+    KmyMoney,
+}
+
+impl PriceFile {
+    /// Find how to save prices in the input file.  This should be checked
+    /// before fetching or changing any price, so that we do not fail after
+    /// the work has been done.
+    pub(crate) fn of(input: &Path) -> Result<Self> {
+        match input.extension().and_then(|e| e.to_str()) {
+            Some("alere") => Ok(PriceFile::Alere),
+            Some("gnucash" | "journal" | "hledger" | "ledger") => {
+                anyhow::bail!(
+                    "Saving prices is only supported for .kmy/.alere files, \
+                     not {}",
+                    input.display()
+                )
+            }
+            _ => Ok(PriceFile::KmyMoney),
+        }
+    }
+}
+
+/// Save the new prices in the input file.  For kmymoney files, only the new
+/// prices are inserted (grouped by price source), while alere files are
+/// rewritten entirely.
+pub(crate) async fn save_prices(
+    repo: &Repository,
+    input: &Path,
+    prices: &[(
+        alere_lib::commodities::Commodity,
+        alere_lib::commodities::Commodity,
+        Price,
+    )],
+) -> Result<usize> {
+    match PriceFile::of(input)? {
+        PriceFile::Alere => {
+            AlereFile::default().export_file(
+                repo,
+                input,
+                &Formatter::default(),
+            )?;
+            Ok(prices.len())
+        }
+        PriceFile::KmyMoney => {
+            let mut per_source: BTreeMap<String, Vec<_>> = BTreeMap::new();
+            for p in prices {
+                let source = match p.2.source() {
+                    PriceSourceFrom::External(id) => {
+                        repo.get_price_source(id).map(|s| s.get_name().clone())
+                    }
+                    PriceSourceFrom::Transaction | PriceSourceFrom::Turnkey => {
+                        None
+                    }
+                };
+                per_source
+                    .entry(source.unwrap_or_else(|| PRICE_SOURCE.to_string()))
+                    .or_default()
+                    .push(p.clone());
+            }
+            let mut count = 0;
+            for (source, prices) in per_source {
+                count +=
+                    alere_lib::kmymoney::save_prices(input, &source, &prices)
+                        .await?;
+            }
+            Ok(count)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{load_journal, settings_in_eur};
+    use alere_lib::{importers::Importer, kmymoney::KmyMoneyImporter};
+    use chrono::{Local, TimeZone};
+    use futures::executor::block_on;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_price_file() -> Result<()> {
+        assert_eq!(PriceFile::of(Path::new("a.alere"))?, PriceFile::Alere);
+        assert_eq!(PriceFile::of(Path::new("a.kmy"))?, PriceFile::KmyMoney);
+        for input in ["a.journal", "a.hledger", "a.ledger", "a.gnucash"] {
+            let err = PriceFile::of(Path::new(input)).unwrap_err();
+            assert!(
+                err.to_string().starts_with(
+                    "Saving prices is only supported for .kmy/.alere files"
+                ),
+                "{err}"
             );
-            repo.add_price(&commodity, target_commodity, price);
         }
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_prices_in_kmy() -> Result<()> {
+        let mut editor = kmy_editor::KmyEditor::new()?;
+        editor.add_currency("EUR", "Euro", "€")?;
+        editor.add_currency("USD", "US Dollar", "$")?;
+
+        let repo = block_on(
+            KmyMoneyImporter::default().import_file(editor.path(), |_, _| {}),
+        )?;
+        let eur = repo.commodities.find("EUR").unwrap();
+        let usd = repo.commodities.find("USD").unwrap();
+        let ts = Local.with_ymd_and_hms(2024, 5, 2, 0, 0, 0).unwrap();
+        let prices = vec![(
+            usd.clone(),
+            eur.clone(),
+            Price::new(ts, Decimal::new(93456, 5), PriceSourceFrom::Turnkey),
+        )];
+        let count = block_on(save_prices(&repo, editor.path(), &prices))?;
+        assert_eq!(count, 1);
+
+        // Reload and check the price (truncated to the price precision)
+        let repo = block_on(
+            KmyMoneyImporter::default().import_file(editor.path(), |_, _| {}),
+        )?;
+        let usd = repo.commodities.find("USD").unwrap();
+        let eur = repo.commodities.find("EUR").unwrap();
+        let mut market = repo.market_prices(Some(eur));
+        let price = market.get_price_with_date(&usd, &Local::now()).unwrap();
+        assert_eq!(price.price, Decimal::new(9345, 4));
+        assert_eq!(price.timestamp, ts);
+        Ok(())
+    }
+
+    #[test]
+    fn test_price_in_quote_currency() -> Result<()> {
+        let mut repo = load_journal(
+            "update",
+            "P 2024-01-01 USD 0.9 EUR\n\
+             P 2024-01-01 AAPL 150 USD\n",
+        )?;
+        let settings = settings_in_eur(
+            &repo,
+            Local.with_ymd_and_hms(2024, 3, 2, 0, 0, 0).unwrap(),
+        );
+
+        let aapl = repo.commodities.find("AAPL").unwrap();
+        let ts = Local.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap();
+        let mut quote = StockPrice::new("AAPL", Decimal::from(180), ts);
+        quote.currency = Some("USD".to_string());
+        let updates = display_and_update_prices(
+            &mut repo,
+            &settings,
+            vec![(
+                aapl.clone(),
+                "AAPL".to_string(),
+                Decimal::from(10),
+                "Yahoo Finance".to_string(),
+            )],
+            vec![Ok(quote)],
+        )?;
+        let saved = update_prices_in_repo(&mut repo, updates);
+        let [(_, currency, _)] = saved.as_slice() else {
+            panic!("expected a single price");
+        };
+        assert_eq!(*currency.get_symbol(), "USD");
+        assert_eq!(*aapl.get_quote_currency().unwrap().get_symbol(), "USD");
+
+        // Converted to the reporting currency via USD
+        let mut market = repo.market_prices(settings.commodity.clone());
+        let price = market.get_price(&aapl, &settings.reftime).unwrap();
+        assert_eq!(price, Decimal::from(162));
+
+        // A currency that is not in the repository
+        assert!(quote_currency(&repo, &settings, &aapl, Some("XYZ")).is_err());
+        Ok(())
     }
 }
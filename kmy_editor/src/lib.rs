@@ -11,6 +11,8 @@ pub struct KmyEditor {
     account_counter: u32,
     transaction_counter: u32,
     payee_counter: u32,
+    budget_counter: u32,
+    schedule_counter: u32,
 }
 
 impl KmyEditor {
@@ -36,6 +38,8 @@ impl KmyEditor {
             account_counter: 1,
             transaction_counter: 1,
             payee_counter: 1,
+            budget_counter: 1,
+            schedule_counter: 1,
         })
     }
 
@@ -175,6 +179,85 @@ impl KmyEditor {
         Self::exec_sql(&self.path, &sql)
     }
 
+    pub fn add_budget(
+        &mut self,
+        name: &str,
+        start: &str,
+        xml: &str,
+    ) -> Result<String> {
+        let id = format!("B{:06}", self.budget_counter);
+        self.budget_counter += 1;
+
+        let sql = format!(
+            "INSERT INTO kmmBudgetConfig VALUES ('{}', '{}', '{}', '{}'); \
+             UPDATE kmmFileInfo SET budgets = budgets + 1;",
+            id, name, start, xml
+        );
+        Self::exec_sql(&self.path, &sql)?;
+        Ok(id)
+    }
+
+    /// Add a schedule, with its template transaction.  `occurence` and
+    /// `weekend_option` use the kmymoney codes (e.g. 32 for monthly, 2 to
+    /// ignore week-ends).
+    #[allow(clippy::too_many_arguments)]
+    pub fn add_schedule(
+        &mut self,
+        name: &str,
+        occurence: i32,
+        multiplier: i32,
+        start: &str,
+        end: Option<&str>,
+        last_payment: Option<&str>,
+        weekend_option: i32,
+        currency: &str,
+    ) -> Result<String> {
+        let id = format!("SCH{:06}", self.schedule_counter);
+        self.schedule_counter += 1;
+
+        let opt = |v: Option<&str>| {
+            v.map(|d| format!("'{}'", d))
+                .unwrap_or_else(|| "NULL".to_string())
+        };
+        let sql = format!(
+            "INSERT INTO kmmSchedules VALUES ('{}', '{}', 1, 'Bill', {}, {}, NULL, 1, NULL, '{}', {}, 'Y', 'N', 'N', {}, NULL, {}, NULL); \
+             INSERT INTO kmmTransactions VALUES ('{}', 'S', '{}', '{}', NULL, '{}', NULL); \
+             UPDATE kmmFileInfo SET schedules = schedules + 1;",
+            id,
+            name,
+            occurence,
+            multiplier,
+            start,
+            opt(end),
+            opt(last_payment),
+            weekend_option,
+            id,
+            start,
+            name,
+            currency
+        );
+        Self::exec_sql(&self.path, &sql)?;
+        Ok(id)
+    }
+
+    pub fn add_schedule_split(
+        &mut self,
+        schedule_id: &str,
+        split_id: i32,
+        account_id: &str,
+        amount: &str,
+        payee_id: Option<&str>,
+    ) -> Result<()> {
+        let payee_sql = payee_id
+            .map(|p| format!("'{}'", p))
+            .unwrap_or_else(|| "NULL".to_string());
+        let sql = format!(
+            "INSERT INTO kmmSplits VALUES ('{}', 'S', {}, {}, NULL, NULL, 'N', '{}', NULL, '{}', NULL, NULL, NULL, NULL, '{}', NULL, NULL, NULL, NULL);",
+            schedule_id, split_id, payee_sql, amount, amount, account_id
+        );
+        Self::exec_sql(&self.path, &sql)
+    }
+
     pub fn path(&self) -> &PathBuf {
         &self.path
     }
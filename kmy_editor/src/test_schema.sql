@@ -8,3 +8,5 @@ CREATE TABLE kmmPayees (id VARCHAR(32) NOT NULL PRIMARY KEY, name TEXT);
 CREATE TABLE kmmSecurities (id VARCHAR(32) NOT NULL PRIMARY KEY, name TEXT, symbol TEXT, type SMALLINT, typeString TEXT, smallestAccountFraction VARCHAR(24), pricePrecision SMALLINT, tradingCurrency CHAR(3));
 CREATE TABLE kmmPrices (fromId VARCHAR(32) NOT NULL, toId VARCHAR(32) NOT NULL, priceDate DATE NOT NULL, price TEXT NOT NULL, priceSource VARCHAR(255), PRIMARY KEY (fromId, toId, priceDate));
 CREATE TABLE kmmKeyValuePairs (kvpType VARCHAR(16) NOT NULL, kvpId VARCHAR(32), kvpKey VARCHAR(255) NOT NULL, kvpData TEXT);
+CREATE TABLE kmmBudgetConfig (id VARCHAR(32) NOT NULL PRIMARY KEY, name TEXT NOT NULL, start DATE NOT NULL, XML TEXT);
+CREATE TABLE kmmSchedules (id VARCHAR(32) NOT NULL PRIMARY KEY, name TEXT NOT NULL, type SMALLINT NOT NULL, typeString TEXT, occurence SMALLINT NOT NULL, occurenceMultiplier SMALLINT NOT NULL, occurenceString TEXT, paymentType SMALLINT, paymentTypeString TEXT, startDate DATE NOT NULL, endDate DATE, fixed CHAR(1) NOT NULL, lastDayInMonth CHAR(1) NOT NULL DEFAULT 'N', autoEnter CHAR(1) NOT NULL, lastPayment DATE, nextPaymentDue DATE, weekendOption SMALLINT NOT NULL, weekendOptionString TEXT);
@@ -0,0 +1,173 @@
+use crate::{
+    DailyPrice, History, StockPrice, StockSource, Transport, parse_date,
+    parse_decimal,
+};
+use anyhow::{Result, anyhow};
+use chrono::NaiveDate;
+use std::ops::RangeInclusive;
+
+/// Quotes from alphavantage.co.  The API key is read from the
+/// ALPHAVANTAGE_API_KEY environment variable.
+pub struct AlphaVantage {
+    api_key: Option<String>,
+    client: reqwest::Client,
+    transport: Transport,
+}
+
+impl AlphaVantage {
+    pub fn new(api_key: Option<String>) -> Result<Self> {
+        Ok(Self {
+            api_key,
+            client: reqwest::Client::new(),
+            transport: Transport::default(),
+        })
+    }
+
+    pub fn from_env() -> Result<Self> {
+        Self::new(std::env::var("ALPHAVANTAGE_API_KEY").ok())
+    }
+
+    fn api_key(&self) -> Result<&str> {
+        self.api_key.as_deref().ok_or_else(|| {
+            anyhow!("Alpha Vantage: ALPHAVANTAGE_API_KEY is not set")
+        })
+    }
+
+    /// Parse the response.  Errors and rate limits are reported with a 200
+    /// status code.
+    fn parse_json(symbol: &str, body: &str) -> Result<serde_json::Value> {
+        let json: serde_json::Value = serde_json::from_str(body)?;
+        for key in ["Error Message", "Note", "Information"] {
+            if let Some(msg) = json.get(key).and_then(|m| m.as_str()) {
+                anyhow::bail!("Alpha Vantage: {} ({})", msg, symbol);
+            }
+        }
+        Ok(json)
+    }
+}
+
+impl StockSource for AlphaVantage {
+    fn name(&self) -> &str {
+        "alpha_vantage"
+    }
+
+    fn url(&self, symbol: &str) -> Result<String> {
+        Ok(format!(
+            "https://www.alphavantage.co/query?function=GLOBAL_QUOTE&symbol={}&apikey={}",
+            symbol,
+            self.api_key()?
+        ))
+    }
+
+    /// Request the full output, since the compact one only has the last
+    /// 100 days.
+    fn history_url(
+        &self,
+        symbol: &str,
+        _range: &RangeInclusive<NaiveDate>,
+    ) -> Result<String> {
+        Ok(format!(
+            "https://www.alphavantage.co/query?function=TIME_SERIES_DAILY&outputsize=full&symbol={}&apikey={}",
+            symbol,
+            self.api_key()?
+        ))
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice> {
+        let json = AlphaVantage::parse_json(symbol, body)?;
+        let quote = json
+            .get("Global Quote")
+            .ok_or_else(|| anyhow!("No quote for {}", symbol))?;
+        let field = |name: &str| quote.get(name).and_then(|v| v.as_str());
+        let price = field("05. price")
+            .and_then(parse_decimal)
+            .ok_or_else(|| anyhow!("Failed to extract price for {}", symbol))?;
+        let timestamp = field("07. latest trading day")
+            .and_then(parse_date)
+            .ok_or_else(|| anyhow!("Failed to extract date for {}", symbol))?;
+
+        Ok(
+            StockPrice::new(symbol, price, timestamp).with_previous_close(
+                field("08. previous close").and_then(parse_decimal),
+            ),
+        )
+    }
+
+    fn parse_history(&self, symbol: &str, body: &str) -> Result<History> {
+        let json = AlphaVantage::parse_json(symbol, body)?;
+        let series = json
+            .get("Time Series (Daily)")
+            .and_then(|s| s.as_object())
+            .ok_or_else(|| anyhow!("No price history for {}", symbol))?;
+        let prices = series
+            .iter()
+            .filter_map(|(date, day)| {
+                let num = |name: &str| {
+                    day.get(name)
+                        .and_then(|v| v.as_str())
+                        .and_then(parse_decimal)
+                };
+                Some(DailyPrice {
+                    date: NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?,
+                    open: num("1. open"),
+                    high: num("2. high"),
+                    low: num("3. low"),
+                    close: num("4. close")?,
+                })
+            })
+            .collect();
+        Ok(History {
+            currency: None,
+            prices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let av = AlphaVantage::new(None)?;
+        assert!(av.url("IBM").is_err());
+        let price =
+            av.parse("IBM", include_str!("../fixtures/alpha_vantage.json"))?;
+        assert_eq!(price.price, dec!(187.14));
+        assert_eq!(price.change, Some(dec!(2.11)));
+        assert_eq!(price.timestamp.date_naive().to_string(), "2024-03-01");
+        assert!(av.parse("IBM", r#"{"Note": "Rate limit"}"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_history() -> Result<()> {
+        let av = AlphaVantage::new(None)?;
+        let mut prices = av
+            .parse_history(
+                "IBM",
+                include_str!("../fixtures/alpha_vantage_history.json"),
+            )?
+            .prices;
+        prices.sort_by_key(|p| p.date);
+        assert_eq!(prices.len(), 2);
+        let first = prices.first().unwrap();
+        assert_eq!(first.date.to_string(), "2024-02-29");
+        assert_eq!(first.close, dec!(185.03));
+        assert_eq!(first.high, Some(dec!(186.8495)));
+        Ok(())
+    }
+}
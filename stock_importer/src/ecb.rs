@@ -0,0 +1,169 @@
+use crate::{
+    DailyPrice, History, StockPrice, StockSource, Transport, parse_date,
+    parse_decimal,
+};
+use anyhow::{Result, anyhow};
+use chrono::{Local, NaiveDate, TimeDelta};
+use regex::Regex;
+use rust_decimal::Decimal;
+use std::ops::RangeInclusive;
+
+/// Daily reference exchange rates from the European Central Bank.
+/// The symbol is the ISO code of a currency, and the price is the value of
+/// one unit of that currency in EUR.
+pub struct Ecb {
+    time_regex: Regex,
+    client: reqwest::Client,
+    transport: Transport,
+}
+
+impl Ecb {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            time_regex: Regex::new(r#"<Cube\s+time=['"]([0-9-]+)['"]"#)?,
+            client: reqwest::Client::new(),
+            transport: Transport::default(),
+        })
+    }
+
+    fn rate_regex(symbol: &str) -> Result<Regex> {
+        Ok(Regex::new(&format!(
+            r#"<Cube\s+currency=['"]{}['"]\s+rate=['"]([0-9.]+)['"]"#,
+            regex::escape(&symbol.to_uppercase())
+        ))?)
+    }
+}
+
+impl StockSource for Ecb {
+    fn name(&self) -> &str {
+        "ecb"
+    }
+
+    fn url(&self, _symbol: &str) -> Result<String> {
+        Ok(
+            "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-daily.xml"
+                .to_string(),
+        )
+    }
+
+    /// The ECB only provides the last 90 days, or the whole history since
+    /// 1999.
+    fn history_url(
+        &self,
+        _symbol: &str,
+        range: &RangeInclusive<NaiveDate>,
+    ) -> Result<String> {
+        let recent = Local::now().date_naive() - TimeDelta::days(85);
+        Ok(format!(
+            "https://www.ecb.europa.eu/stats/eurofxref/eurofxref-hist{}.xml",
+            if *range.start() >= recent { "-90d" } else { "" }
+        ))
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice> {
+        let timestamp = self
+            .time_regex
+            .captures(body)
+            .and_then(|c| c.get(1))
+            .and_then(|m| parse_date(m.as_str()))
+            .ok_or_else(|| anyhow!("Failed to extract date for {}", symbol))?;
+
+        let rate = Ecb::rate_regex(symbol)?
+            .captures(body)
+            .and_then(|c| c.get(1))
+            .and_then(|m| parse_decimal(m.as_str()))
+            .filter(|r| !r.is_zero())
+            .ok_or_else(|| anyhow!("No exchange rate for {}", symbol))?;
+
+        let mut result =
+            StockPrice::new(symbol, Decimal::ONE / rate, timestamp);
+        result.currency = Some("EUR".to_string());
+        Ok(result)
+    }
+
+    fn parse_history(&self, symbol: &str, body: &str) -> Result<History> {
+        let rate_regex = Ecb::rate_regex(symbol)?;
+        let days = self.time_regex.captures_iter(body).collect::<Vec<_>>();
+        let mut result = Vec::new();
+        for (idx, day) in days.iter().enumerate() {
+            let (Some(all), Some(date)) = (day.get(0), day.get(1)) else {
+                continue;
+            };
+            let end = days
+                .get(idx + 1)
+                .and_then(|next| next.get(0))
+                .map_or(body.len(), |m| m.start());
+            let rate = body
+                .get(all.end()..end)
+                .and_then(|cube| rate_regex.captures(cube))
+                .and_then(|c| c.get(1))
+                .and_then(|m| parse_decimal(m.as_str()))
+                .filter(|r| !r.is_zero());
+            if let (Some(rate), Ok(date)) =
+                (rate, NaiveDate::parse_from_str(date.as_str(), "%Y-%m-%d"))
+            {
+                result.push(DailyPrice {
+                    date,
+                    open: None,
+                    high: None,
+                    low: None,
+                    close: Decimal::ONE / rate,
+                });
+            }
+        }
+        if result.is_empty() {
+            anyhow::bail!("No exchange rate history for {}", symbol);
+        }
+        Ok(History {
+            currency: Some("EUR".to_string()),
+            prices: result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let ecb = Ecb::new()?;
+        let body = include_str!("../fixtures/ecb.xml");
+        let price = ecb.parse("gbp", body)?;
+        assert_eq!(price.price, Decimal::ONE / dec!(0.85553));
+        assert_eq!(price.currency.as_deref(), Some("EUR"));
+        assert_eq!(price.timestamp.date_naive().to_string(), "2024-03-01");
+        assert!(ecb.parse("XYZ", body).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_history() -> Result<()> {
+        let ecb = Ecb::new()?;
+        let body = include_str!("../fixtures/ecb_history.xml");
+        let history = ecb.parse_history("GBP", body)?;
+        assert_eq!(history.currency.as_deref(), Some("EUR"));
+        let dates = history
+            .prices
+            .iter()
+            .map(|p| p.date.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(dates, vec!["2024-03-01", "2024-02-29"]);
+        assert_eq!(ecb.parse_history("USD", body)?.prices.len(), 3);
+        assert!(ecb.parse_history("XYZ", body).is_err());
+        Ok(())
+    }
+}
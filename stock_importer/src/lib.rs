@@ -1,91 +1,244 @@
-use anyhow::Result;
-use chrono::{DateTime, Local, TimeZone};
-use regex::Regex;
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use rust_decimal::Decimal;
+use std::{ops::RangeInclusive, str::FromStr};
 
-/// Stock price data
+mod alpha_vantage;
+mod ecb;
+mod registry;
+mod stooq;
+mod template;
+mod transport;
+mod yahoo;
+
+pub use alpha_vantage::AlphaVantage;
+pub use ecb::Ecb;
+pub use registry::{Provider, QuoteProviders};
+pub use stooq::Stooq;
+pub use template::{Extractor, UrlTemplate};
+pub use transport::{CacheKey, Mode, Transport};
+pub use yahoo::YahooFinance;
+
+/// Whether the market is currently open
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MarketState {
+    Pre,
+    Regular,
+    Post,
+    Closed,
+
+    #[default]
+    Unknown,
+}
+
+impl std::fmt::Display for MarketState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            MarketState::Pre => "pre",
+            MarketState::Regular => "regular",
+            MarketState::Post => "post",
+            MarketState::Closed => "closed",
+            MarketState::Unknown => "",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+/// Stock price data.  Providers only report part of the metadata.
 #[derive(Debug, Clone)]
 pub struct StockPrice {
     pub symbol: String,
-    pub price: f64,
+    pub price: Decimal,
     pub timestamp: DateTime<Local>,
+
+    // ISO code of the currency the price is expressed in
+    pub currency: Option<String>,
+
+    pub exchange: Option<String>,
+    pub market_state: MarketState,
+    pub previous_close: Option<Decimal>,
+
+    // Change since the previous close
+    pub change: Option<Decimal>,
+}
+
+impl StockPrice {
+    #[must_use]
+    pub fn new(
+        symbol: &str,
+        price: Decimal,
+        timestamp: DateTime<Local>,
+    ) -> Self {
+        StockPrice {
+            symbol: symbol.to_string(),
+            price,
+            timestamp,
+            currency: None,
+            exchange: None,
+            market_state: MarketState::Unknown,
+            previous_close: None,
+            change: None,
+        }
+    }
+
+    /// Set the previous close, and the change since then
+    #[must_use]
+    pub fn with_previous_close(mut self, previous: Option<Decimal>) -> Self {
+        self.previous_close = previous;
+        self.change = previous.map(|p| self.price - p);
+        self
+    }
+}
+
+/// The price of a commodity for one day.  Not all providers report the
+/// opening, highest and lowest prices.
+#[derive(Debug, Clone)]
+pub struct DailyPrice {
+    pub date: NaiveDate,
+    pub open: Option<Decimal>,
+    pub high: Option<Decimal>,
+    pub low: Option<Decimal>,
+    pub close: Decimal,
+}
+
+/// The daily prices of a commodity
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    // ISO code of the currency the prices are expressed in
+    pub currency: Option<String>,
+
+    pub prices: Vec<DailyPrice>,
 }
 
-/// Trait for stock data sources
+/// Trait for stock data sources.
+/// Downloading and parsing are separate, so that parsers can be tested
+/// against saved responses.
 #[allow(async_fn_in_trait)]
 pub trait StockSource {
-    async fn fetch_price(&self, symbol: &str) -> Result<StockPrice>;
+    /// Short name of the provider, used as the key in the cache
+    fn name(&self) -> &str;
+
+    /// The URL to query for the latest price of symbol
+    fn url(&self, symbol: &str) -> Result<String>;
+
+    /// Extract the price from the body of the response
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice>;
+
+    /// The URL to query for the daily prices of symbol.  Providers might
+    /// return more days than requested.
+    fn history_url(
+        &self,
+        _symbol: &str,
+        _range: &RangeInclusive<NaiveDate>,
+    ) -> Result<String> {
+        Err(anyhow!("This provider has no price history"))
+    }
+
+    /// Extract the daily prices from the body of the response
+    fn parse_history(&self, _symbol: &str, _body: &str) -> Result<History> {
+        Err(anyhow!("This provider has no price history"))
+    }
+
+    fn client(&self) -> &reqwest::Client;
+
+    fn transport(&self) -> &Transport;
+
+    fn set_transport(&mut self, transport: Transport);
+
+    /// Prepare the request for a given URL
+    fn request(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client().get(url)
+    }
+
+    async fn fetch_price(&self, symbol: &str) -> Result<StockPrice> {
+        let transport = self.transport();
+        let body = transport
+            .get(
+                &CacheKey::latest(
+                    self.name(),
+                    symbol,
+                    Local::now().date_naive(),
+                ),
+                self.request(&transport.rewrite(&self.url(symbol)?)?),
+            )
+            .await?;
+        self.parse(symbol, &body)
+    }
+
+    /// The daily prices for symbol within range, sorted chronologically
+    async fn fetch_history(
+        &self,
+        symbol: &str,
+        range: &RangeInclusive<NaiveDate>,
+    ) -> Result<History> {
+        let transport = self.transport();
+        let body = transport
+            .get(
+                &CacheKey::history(self.name(), symbol, range),
+                self.request(
+                    &transport.rewrite(&self.history_url(symbol, range)?)?,
+                ),
+            )
+            .await?;
+        let mut history = self.parse_history(symbol, &body)?;
+        history.prices.retain(|p| range.contains(&p.date));
+        history.prices.sort_by_key(|p| p.date);
+        Ok(history)
+    }
 }
 
-/// Yahoo Finance data source
-pub struct YahooFinance {
-    price_regex: Regex,
-    time_regex: Regex,
-    client: reqwest::Client,
+/// Parse a date as returned by the providers, at midnight local time
+fn parse_date(date: &str) -> Option<DateTime<Local>> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .ok()?
+        .and_hms_opt(0, 0, 0)?
+        .and_local_timezone(Local)
+        .earliest()
 }
 
-impl YahooFinance {
-    pub fn new() -> Result<Self> {
-        Ok(Self {
-            price_regex: Regex::new(r#""regularMarketPrice":([0-9.]+)"#)?,
-            time_regex: Regex::new(r#""regularMarketTime":([0-9]+)"#)?,
-            client: reqwest::Client::builder().cookie_store(true).build()?,
+/// Split a CSV response into the (lower-cased) header and the rows.
+/// Missing values ("N/D") are returned as None.
+fn parse_csv(body: &str) -> (Vec<String>, Vec<Vec<Option<&str>>>) {
+    let mut lines = body.lines().filter(|l| !l.trim().is_empty());
+    let header = lines
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(|h| h.trim().to_lowercase())
+        .collect();
+    let rows = lines
+        .map(|l| {
+            l.split(',')
+                .map(str::trim)
+                .map(|v| if v == "N/D" { None } else { Some(v) })
+                .collect()
         })
-    }
+        .collect();
+    (header, rows)
 }
 
-impl StockSource for YahooFinance {
-    async fn fetch_price(&self, symbol: &str) -> Result<StockPrice> {
-        let url = format!(
-            "https://query1.finance.yahoo.com/v8/finance/chart/{}",
-            symbol
-        );
-
-        let response = self.client.get(&url)
-            .header("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7")
-            .header("accept-language", "en-US,en;q=0.9,fr;q=0.8")
-            .header("cache-control", "max-age=0")
-            .header("dnt", "1")
-            .header("sec-ch-ua", r#""Not(A:Brand";v="8", "Chromium";v="144", "Google Chrome";v="144""#)
-            .header("sec-ch-ua-mobile", "?0")
-            .header("sec-ch-ua-platform", r#""Windows""#)
-            .header("sec-fetch-dest", "document")
-            .header("sec-fetch-mode", "navigate")
-            .header("sec-fetch-site", "none")
-            .header("sec-fetch-user", "?1")
-            .header("upgrade-insecure-requests", "1")
-            .header("user-agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/144.0.0.0 Safari/537.36")
-            .send().await?
-            .text().await?;
-
-        let price = self
-            .price_regex
-            .captures(&response)
-            .and_then(|c| c.get(1))
-            .and_then(|m| m.as_str().parse::<f64>().ok())
-            .ok_or_else(|| {
-                anyhow::anyhow!("Failed to extract price for {}", symbol)
-            })?;
-
-        let timestamp = self
-            .time_regex
-            .captures(&response)
-            .and_then(|c| c.get(1))
-            .and_then(|m| m.as_str().parse::<i64>().ok())
-            .and_then(|ts| Local.timestamp_opt(ts, 0).single())
-            .ok_or_else(|| {
-                anyhow::anyhow!("Failed to extract timestamp for {}", symbol)
-            })?;
-
-        Ok(StockPrice {
-            symbol: symbol.to_string(),
-            price,
-            timestamp,
-        })
-    }
+/// Parse a price, as found in the responses
+fn parse_decimal(value: &str) -> Option<Decimal> {
+    let value = value.trim();
+    Decimal::from_str(value)
+        .or_else(|_| Decimal::from_scientific(value))
+        .ok()
+        .map(|d| d.normalize())
 }
 
-impl Default for YahooFinance {
-    fn default() -> Self {
-        Self::new().unwrap()
+/// A price found in a JSON response, either as a number or a string
+fn json_decimal(value: &serde_json::Value) -> Option<Decimal> {
+    match value {
+        serde_json::Value::Number(n) => parse_decimal(&n.to_string()),
+        serde_json::Value::String(s) => parse_decimal(s),
+        serde_json::Value::Null
+        | serde_json::Value::Bool(_)
+        | serde_json::Value::Array(_)
+        | serde_json::Value::Object(_) => None,
     }
 }
+
+/// Convert a unix timestamp (in seconds)
+fn from_timestamp(ts: i64) -> Option<DateTime<Local>> {
+    Local.timestamp_opt(ts, 0).single()
+}
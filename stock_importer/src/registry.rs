@@ -0,0 +1,221 @@
+use crate::{
+    AlphaVantage, Ecb, Extractor, History, StockPrice, StockSource, Stooq,
+    Transport, UrlTemplate, YahooFinance,
+};
+use anyhow::Result;
+use chrono::NaiveDate;
+use serde::Deserialize;
+use std::{ops::RangeInclusive, path::Path};
+
+/// Any of the supported providers
+pub enum Provider {
+    Yahoo(YahooFinance),
+    Ecb(Ecb),
+    Stooq(Stooq),
+    AlphaVantage(AlphaVantage),
+    Template(UrlTemplate),
+}
+
+impl StockSource for Provider {
+    fn name(&self) -> &str {
+        match self {
+            Provider::Yahoo(p) => p.name(),
+            Provider::Ecb(p) => p.name(),
+            Provider::Stooq(p) => p.name(),
+            Provider::AlphaVantage(p) => p.name(),
+            Provider::Template(p) => p.name(),
+        }
+    }
+
+    fn url(&self, symbol: &str) -> Result<String> {
+        match self {
+            Provider::Yahoo(p) => p.url(symbol),
+            Provider::Ecb(p) => p.url(symbol),
+            Provider::Stooq(p) => p.url(symbol),
+            Provider::AlphaVantage(p) => p.url(symbol),
+            Provider::Template(p) => p.url(symbol),
+        }
+    }
+
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice> {
+        match self {
+            Provider::Yahoo(p) => p.parse(symbol, body),
+            Provider::Ecb(p) => p.parse(symbol, body),
+            Provider::Stooq(p) => p.parse(symbol, body),
+            Provider::AlphaVantage(p) => p.parse(symbol, body),
+            Provider::Template(p) => p.parse(symbol, body),
+        }
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        match self {
+            Provider::Yahoo(p) => p.client(),
+            Provider::Ecb(p) => p.client(),
+            Provider::Stooq(p) => p.client(),
+            Provider::AlphaVantage(p) => p.client(),
+            Provider::Template(p) => p.client(),
+        }
+    }
+
+    fn history_url(
+        &self,
+        symbol: &str,
+        range: &RangeInclusive<NaiveDate>,
+    ) -> Result<String> {
+        match self {
+            Provider::Yahoo(p) => p.history_url(symbol, range),
+            Provider::Ecb(p) => p.history_url(symbol, range),
+            Provider::Stooq(p) => p.history_url(symbol, range),
+            Provider::AlphaVantage(p) => p.history_url(symbol, range),
+            Provider::Template(p) => p.history_url(symbol, range),
+        }
+    }
+
+    fn parse_history(&self, symbol: &str, body: &str) -> Result<History> {
+        match self {
+            Provider::Yahoo(p) => p.parse_history(symbol, body),
+            Provider::Ecb(p) => p.parse_history(symbol, body),
+            Provider::Stooq(p) => p.parse_history(symbol, body),
+            Provider::AlphaVantage(p) => p.parse_history(symbol, body),
+            Provider::Template(p) => p.parse_history(symbol, body),
+        }
+    }
+
+    fn transport(&self) -> &Transport {
+        match self {
+            Provider::Yahoo(p) => p.transport(),
+            Provider::Ecb(p) => p.transport(),
+            Provider::Stooq(p) => p.transport(),
+            Provider::AlphaVantage(p) => p.transport(),
+            Provider::Template(p) => p.transport(),
+        }
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        match self {
+            Provider::Yahoo(p) => p.set_transport(transport),
+            Provider::Ecb(p) => p.set_transport(transport),
+            Provider::Stooq(p) => p.set_transport(transport),
+            Provider::AlphaVantage(p) => p.set_transport(transport),
+            Provider::Template(p) => p.set_transport(transport),
+        }
+    }
+
+    fn request(&self, url: &str) -> reqwest::RequestBuilder {
+        match self {
+            Provider::Yahoo(p) => p.request(url),
+            Provider::Ecb(p) => p.request(url),
+            Provider::Stooq(p) => p.request(url),
+            Provider::AlphaVantage(p) => p.request(url),
+            Provider::Template(p) => p.request(url),
+        }
+    }
+}
+
+/// One user-defined provider, in the configuration file
+#[derive(Deserialize)]
+struct TemplateConfig {
+    name: String,
+    url: String,
+    price: Extractor,
+    #[serde(default)]
+    time: Option<Extractor>,
+}
+
+/// The providers, indexed by the name of the price source (as found in
+/// kmymoney files for instance).  Names are case-insensitive.
+pub struct QuoteProviders {
+    providers: Vec<(String, Provider)>,
+}
+
+impl QuoteProviders {
+    /// The builtin providers
+    pub fn new() -> Result<Self> {
+        let mut result = QuoteProviders {
+            providers: Vec::new(),
+        };
+        result.register("Yahoo Finance", Provider::Yahoo(YahooFinance::new()?));
+        result.register("ECB", Provider::Ecb(Ecb::new()?));
+        result.register("Stooq", Provider::Stooq(Stooq::new()?));
+        result.register(
+            "Alpha Vantage",
+            Provider::AlphaVantage(AlphaVantage::from_env()?),
+        );
+        Ok(result)
+    }
+
+    /// Register a new provider, replacing any existing one with the same name
+    pub fn register(&mut self, name: &str, provider: Provider) {
+        self.providers
+            .retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+        self.providers.push((name.to_string(), provider));
+    }
+
+    /// Load user-defined providers from a JSON file, for instance:
+    ///    [{"name": "My bank",
+    ///      "url": "https://example.com/quote?s={symbol}",
+    ///      "price": {"json": "$.quote.last"},
+    ///      "time": {"regex": "\"date\":\"([0-9-]+)\""}}]
+    pub fn load_templates(&mut self, path: &Path) -> Result<()> {
+        let configs: Vec<TemplateConfig> =
+            serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        for c in configs {
+            let provider =
+                UrlTemplate::new(&c.name, &c.url, &c.price, c.time.as_ref())?;
+            self.register(&c.name, Provider::Template(provider));
+        }
+        Ok(())
+    }
+
+    /// Change how all registered providers send their requests
+    pub fn set_transport(&mut self, transport: &Transport) {
+        for (_, p) in &mut self.providers {
+            p.set_transport(transport.clone());
+        }
+    }
+
+    /// The provider to use for a given price source, and its canonical name
+    pub fn get(&self, source: &str) -> Option<(&str, &Provider)> {
+        self.providers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(source.trim()))
+            .map(|(n, p)| (n.as_str(), p))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal::Decimal;
+
+    #[test]
+    fn test_registry() -> Result<()> {
+        let mut providers = QuoteProviders::new()?;
+        assert!(matches!(
+            providers.get("yahoo finance"),
+            Some((_, Provider::Yahoo(_)))
+        ));
+        assert!(matches!(providers.get("ecb"), Some((_, Provider::Ecb(_)))));
+        assert!(providers.get("Unknown").is_none());
+
+        let path = std::env::temp_dir().join("alere_test_providers.json");
+        std::fs::write(
+            &path,
+            r#"[{"name": "Stooq",
+                 "url": "https://example.com/{symbol}",
+                 "price": {"json": "$.price"}}]"#,
+        )?;
+        providers.load_templates(&path)?;
+        std::fs::remove_file(&path)?;
+        let Some((name, provider)) = providers.get("STOOQ") else {
+            anyhow::bail!("template not registered");
+        };
+        assert_eq!(name, "Stooq");
+        assert_eq!(provider.url("X")?, "https://example.com/X");
+        assert_eq!(
+            provider.parse("X", r#"{"price": 12.5}"#)?.price,
+            Decimal::new(125, 1)
+        );
+        Ok(())
+    }
+}
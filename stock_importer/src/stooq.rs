@@ -0,0 +1,171 @@
+use crate::{
+    DailyPrice, History, StockPrice, StockSource, Transport, parse_csv,
+    parse_date, parse_decimal,
+};
+use anyhow::{Result, anyhow};
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use std::ops::RangeInclusive;
+
+/// Quotes from stooq.com, as CSV.  Symbols include the market, for instance
+/// "aapl.us".
+pub struct Stooq {
+    client: reqwest::Client,
+    transport: Transport,
+}
+
+impl Stooq {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::new(),
+            transport: Transport::default(),
+        })
+    }
+}
+
+impl StockSource for Stooq {
+    fn name(&self) -> &str {
+        "stooq"
+    }
+
+    fn url(&self, symbol: &str) -> Result<String> {
+        Ok(format!(
+            "https://stooq.com/q/l/?s={}&f=sd2t2ohlcv&h&e=csv",
+            symbol.to_lowercase()
+        ))
+    }
+
+    fn history_url(
+        &self,
+        symbol: &str,
+        range: &RangeInclusive<NaiveDate>,
+    ) -> Result<String> {
+        Ok(format!(
+            "https://stooq.com/q/d/l/?s={}&d1={}&d2={}&i=d",
+            symbol.to_lowercase(),
+            range.start().format("%Y%m%d"),
+            range.end().format("%Y%m%d"),
+        ))
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice> {
+        let (header, rows) = parse_csv(body);
+        let values = rows
+            .first()
+            .ok_or_else(|| anyhow!("No quote for {}", symbol))?;
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|h| h == name)
+                .and_then(|idx| values.get(idx))
+                .copied()
+                .flatten()
+        };
+
+        let price = column("close")
+            .and_then(parse_decimal)
+            .ok_or_else(|| anyhow!("Failed to extract price for {}", symbol))?;
+        let date = column("date")
+            .ok_or_else(|| anyhow!("Failed to extract date for {}", symbol))?;
+        let timestamp = column("time")
+            .and_then(|time| {
+                NaiveDateTime::parse_from_str(
+                    &format!("{date} {time}"),
+                    "%Y-%m-%d %H:%M:%S",
+                )
+                .ok()
+            })
+            .and_then(|d| d.and_local_timezone(Local).earliest())
+            .or_else(|| parse_date(date))
+            .ok_or_else(|| anyhow!("Invalid date {} for {}", date, symbol))?;
+
+        Ok(StockPrice::new(symbol, price, timestamp))
+    }
+
+    fn parse_history(&self, symbol: &str, body: &str) -> Result<History> {
+        let (header, rows) = parse_csv(body);
+        let idx = |name: &str| header.iter().position(|h| h == name);
+        let (Some(date), Some(close)) = (idx("date"), idx("close")) else {
+            anyhow::bail!("No price history for {}", symbol);
+        };
+        let (open, high, low) = (idx("open"), idx("high"), idx("low"));
+        let prices = rows
+            .iter()
+            .filter_map(|row| {
+                let get = |col: Option<usize>| {
+                    col.and_then(|c| row.get(c).copied().flatten())
+                };
+                let num = |col| get(col).and_then(parse_decimal);
+                Some(DailyPrice {
+                    date: NaiveDate::parse_from_str(
+                        get(Some(date))?,
+                        "%Y-%m-%d",
+                    )
+                    .ok()?,
+                    open: num(open),
+                    high: num(high),
+                    low: num(low),
+                    close: num(Some(close))?,
+                })
+            })
+            .collect();
+        Ok(History {
+            currency: None,
+            prices,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let stooq = Stooq::new()?;
+        let price =
+            stooq.parse("aapl.us", include_str!("../fixtures/stooq.csv"))?;
+        assert_eq!(price.price, dec!(179.66));
+        assert_eq!(price.timestamp.to_string()[..19], *"2024-03-01 22:00:09");
+        assert!(
+            stooq
+                .parse(
+                    "xyz.us",
+                    "Symbol,Date,Time,Open,High,Low,Close,Volume\n\
+                     XYZ.US,N/D,N/D,N/D,N/D,N/D,N/D,N/D\n"
+                )
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_history() -> Result<()> {
+        let stooq = Stooq::new()?;
+        let prices = stooq
+            .parse_history(
+                "aapl.us",
+                include_str!("../fixtures/stooq_history.csv"),
+            )?
+            .prices;
+        assert_eq!(prices.len(), 4);
+        let last = prices.last().unwrap();
+        assert_eq!(last.date.to_string(), "2024-03-01");
+        assert_eq!(last.close, dec!(179.66));
+        assert_eq!(last.low, Some(dec!(177.38)));
+        assert!(stooq.parse_history("xyz.us", "No data").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,261 @@
+//! A generic provider, configured by the user: the URL contains a {symbol}
+//! placeholder, and the price (and optionally the date) are extracted from
+//! the response with either a JSON path or a regular expression.
+
+use crate::{
+    StockPrice, StockSource, Transport, from_timestamp, parse_date,
+    parse_decimal,
+};
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, Local};
+use regex::Regex;
+use serde::Deserialize;
+
+/// How to find a value in the response, as found in the configuration file.
+/// JSON paths only support a subset of the syntax: `$.a.b[0]["c d"]`.
+/// Regular expressions must have exactly one capture group.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Extractor {
+    Json(String),
+    Regex(String),
+}
+
+enum PathItem {
+    Key(String),
+    Index(usize),
+}
+
+enum Compiled {
+    Json(Vec<PathItem>),
+    Regex(Regex),
+}
+
+impl Compiled {
+    fn new(extractor: &Extractor) -> Result<Self> {
+        Ok(match extractor {
+            Extractor::Json(path) => Compiled::Json(parse_path(path)?),
+            Extractor::Regex(re) => {
+                let re = Regex::new(re)?;
+                if re.captures_len() != 2 {
+                    anyhow::bail!(
+                        "Regex {} must have exactly one capture group",
+                        re
+                    );
+                }
+                Compiled::Regex(re)
+            }
+        })
+    }
+
+    /// Extract the matching text (numbers are returned as text)
+    fn extract(&self, body: &str) -> Option<String> {
+        match self {
+            Compiled::Regex(re) => {
+                re.captures(body)?.get(1).map(|m| m.as_str().to_string())
+            }
+            Compiled::Json(path) => {
+                let json: serde_json::Value =
+                    serde_json::from_str(body).ok()?;
+                let mut current = &json;
+                for item in path {
+                    current = match item {
+                        PathItem::Key(k) => current.get(k)?,
+                        PathItem::Index(i) => current.get(i)?,
+                    };
+                }
+                match current {
+                    serde_json::Value::String(s) => Some(s.clone()),
+                    serde_json::Value::Number(n) => Some(n.to_string()),
+                    serde_json::Value::Null
+                    | serde_json::Value::Bool(_)
+                    | serde_json::Value::Array(_)
+                    | serde_json::Value::Object(_) => None,
+                }
+            }
+        }
+    }
+}
+
+/// Split a JSON path into its components
+fn parse_path(path: &str) -> Result<Vec<PathItem>> {
+    let invalid = || anyhow!("Invalid JSON path {}", path);
+    let mut rest = path.strip_prefix('$').ok_or_else(invalid)?;
+    let mut items = Vec::new();
+    while !rest.is_empty() {
+        if let Some(r) = rest.strip_prefix('.') {
+            let end = r.find(['.', '[']).unwrap_or(r.len());
+            let (key, r) = r.split_at(end);
+            if key.is_empty() {
+                return Err(invalid());
+            }
+            items.push(PathItem::Key(key.to_string()));
+            rest = r;
+        } else if let Some(r) = rest.strip_prefix('[') {
+            let end = r.find(']').ok_or_else(invalid)?;
+            let (inner, r) = r.split_at(end);
+            rest = r.get(1..).unwrap_or_default();
+            let quoted = inner
+                .strip_prefix('"')
+                .and_then(|k| k.strip_suffix('"'))
+                .or_else(|| {
+                    inner.strip_prefix('\'').and_then(|k| k.strip_suffix('\''))
+                });
+            items.push(match quoted {
+                Some(key) => PathItem::Key(key.to_string()),
+                None => PathItem::Index(inner.parse().map_err(|_| invalid())?),
+            });
+        } else {
+            return Err(invalid());
+        }
+    }
+    Ok(items)
+}
+
+/// A provider described by a URL template
+pub struct UrlTemplate {
+    name: String,
+    url: String,
+    price: Compiled,
+    time: Option<Compiled>,
+    client: reqwest::Client,
+    transport: Transport,
+}
+
+impl UrlTemplate {
+    pub fn new(
+        name: &str,
+        url: &str,
+        price: &Extractor,
+        time: Option<&Extractor>,
+    ) -> Result<Self> {
+        Ok(Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            price: Compiled::new(price)?,
+            time: time.map(Compiled::new).transpose()?,
+            client: reqwest::Client::new(),
+            transport: Transport::default(),
+        })
+    }
+}
+
+/// Dates are either unix timestamps or YYYY-MM-DD
+fn parse_time(time: &str) -> Option<DateTime<Local>> {
+    match time.parse::<i64>() {
+        Ok(ts) => from_timestamp(ts),
+        Err(_) => DateTime::parse_from_rfc3339(time)
+            .ok()
+            .map(|d| d.with_timezone(&Local))
+            .or_else(|| parse_date(time)),
+    }
+}
+
+impl StockSource for UrlTemplate {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn url(&self, symbol: &str) -> Result<String> {
+        Ok(self.url.replace("{symbol}", symbol))
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice> {
+        let price = self
+            .price
+            .extract(body)
+            .and_then(|p| parse_decimal(&p))
+            .ok_or_else(|| anyhow!("Failed to extract price for {}", symbol))?;
+        let timestamp = match &self.time {
+            None => Local::now(),
+            Some(t) => t
+                .extract(body)
+                .and_then(|t| parse_time(t.trim()))
+                .ok_or_else(|| {
+                    anyhow!("Failed to extract date for {}", symbol)
+                })?,
+        };
+        Ok(StockPrice::new(symbol, price, timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_json_path() -> Result<()> {
+        let t = UrlTemplate::new(
+            "test",
+            "https://example.com/{symbol}/quote",
+            &Extractor::Json(
+                "$.chart.result[0].meta.regularMarketPrice".into(),
+            ),
+            Some(&Extractor::Json(
+                "$['chart'].result[0].meta[\"regularMarketTime\"]".into(),
+            )),
+        )?;
+        assert_eq!(t.url("AAPL")?, "https://example.com/AAPL/quote");
+        let price = t.parse("AAPL", include_str!("../fixtures/yahoo.json"))?;
+        assert_eq!(price.price, dec!(179.66));
+        assert_eq!(price.timestamp.timestamp(), 1709326809);
+
+        let t = UrlTemplate::new(
+            "test",
+            "",
+            &Extractor::Json("$[\"Global Quote\"][\"05. price\"]".into()),
+            Some(&Extractor::Json(
+                "$[\"Global Quote\"][\"07. latest trading day\"]".into(),
+            )),
+        )?;
+        let price =
+            t.parse("IBM", include_str!("../fixtures/alpha_vantage.json"))?;
+        assert_eq!(price.price, dec!(187.14));
+        assert_eq!(price.timestamp.date_naive().to_string(), "2024-03-01");
+
+        assert!(
+            UrlTemplate::new("test", "", &Extractor::Json("a.b".into()), None)
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_regex() -> Result<()> {
+        let t = UrlTemplate::new(
+            "test",
+            "",
+            &Extractor::Regex(
+                r"(?m)^AAPL\.US,[^,]*,[^,]*,[^,]*,[^,]*,[^,]*,([0-9.]+)".into(),
+            ),
+            Some(&Extractor::Regex(r"AAPL\.US,([0-9-]+),".into())),
+        )?;
+        let price = t.parse("AAPL", include_str!("../fixtures/stooq.csv"))?;
+        assert_eq!(price.price, dec!(179.66));
+        assert_eq!(price.timestamp.date_naive().to_string(), "2024-03-01");
+
+        assert!(
+            UrlTemplate::new(
+                "test",
+                "",
+                &Extractor::Regex("[0-9]+".into()),
+                None
+            )
+            .is_err()
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,289 @@
+//! How requests reach the providers.
+//!
+//! By default, requests are sent directly over the network.  Responses can
+//! also be kept in an on-disk cache, so that running the same update again
+//! does not query the providers until the cache expires, or recorded once and
+//! replayed later without any network access (in CI, or on a plane).
+//! Finally, all requests can be redirected to a local server that stands in
+//! for the real endpoints.
+
+use anyhow::{Result, anyhow};
+use chrono::{NaiveDate, TimeDelta};
+use std::{
+    ops::RangeInclusive,
+    path::{Path, PathBuf},
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Mode {
+    /// Query the providers, unless the cache (if any) is fresh enough
+    #[default]
+    Live,
+
+    /// Query the providers, and store all responses
+    Record,
+
+    /// Never access the network, only use stored responses
+    Replay,
+}
+
+/// What a response is for, used as the key in the cache
+pub struct CacheKey {
+    provider: String,
+    symbol: String,
+    name: String,
+}
+
+impl CacheKey {
+    /// The latest quote, as of date
+    pub fn latest(provider: &str, symbol: &str, date: NaiveDate) -> Self {
+        CacheKey {
+            provider: provider.to_string(),
+            symbol: symbol.to_string(),
+            name: format!("latest-{date}"),
+        }
+    }
+
+    /// The daily prices over a range of dates
+    pub fn history(
+        provider: &str,
+        symbol: &str,
+        range: &RangeInclusive<NaiveDate>,
+    ) -> Self {
+        CacheKey {
+            provider: provider.to_string(),
+            symbol: symbol.to_string(),
+            name: format!("history-{}-{}", range.start(), range.end()),
+        }
+    }
+
+    fn dir(&self, root: &Path) -> PathBuf {
+        let clean = |s: &str| s.replace(['/', '\\', ':'], "_");
+        root.join(clean(&self.provider)).join(clean(&self.symbol))
+    }
+
+    fn path(&self, root: &Path) -> PathBuf {
+        self.dir(root).join(&self.name)
+    }
+
+    /// When replaying, the latest quote is the most recent one recorded
+    fn recorded(&self, root: &Path) -> Option<PathBuf> {
+        let path = self.path(root);
+        if path.is_file() || !self.name.starts_with("latest-") {
+            return Some(path);
+        }
+        std::fs::read_dir(self.dir(root))
+            .ok()?
+            .filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .filter(|p| {
+                p.file_name()
+                    .and_then(|n| n.to_str())
+                    .is_some_and(|n| n.starts_with("latest-"))
+            })
+            .max()
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Transport {
+    mode: Mode,
+
+    // Where responses are cached or recorded
+    dir: Option<PathBuf>,
+
+    // How long cached responses remain valid in live mode
+    ttl: TimeDelta,
+
+    // A server that replaces the scheme, host and port of all requests
+    base_url: Option<String>,
+}
+
+impl Transport {
+    /// Keep responses in dir, and reuse them for ttl
+    #[must_use]
+    pub fn cached(dir: &Path, ttl: TimeDelta) -> Self {
+        Transport {
+            mode: Mode::Live,
+            dir: Some(dir.to_path_buf()),
+            ttl,
+            base_url: None,
+        }
+    }
+
+    /// Query the providers, and store all responses in dir
+    #[must_use]
+    pub fn record(dir: &Path) -> Self {
+        Transport {
+            mode: Mode::Record,
+            dir: Some(dir.to_path_buf()),
+            ttl: TimeDelta::zero(),
+            base_url: None,
+        }
+    }
+
+    /// Only use the responses previously recorded in dir
+    #[must_use]
+    pub fn replay(dir: &Path) -> Self {
+        Transport {
+            mode: Mode::Replay,
+            dir: Some(dir.to_path_buf()),
+            ttl: TimeDelta::zero(),
+            base_url: None,
+        }
+    }
+
+    /// Send all requests to a stand-in server, for instance
+    /// "http://127.0.0.1:8080"
+    #[must_use]
+    pub fn with_base_url(mut self, base_url: &str) -> Self {
+        self.base_url = Some(base_url.to_string());
+        self
+    }
+
+    #[must_use]
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    /// The URL to actually query
+    pub fn rewrite(&self, url: &str) -> Result<String> {
+        let Some(base) = &self.base_url else {
+            return Ok(url.to_string());
+        };
+        let base = reqwest::Url::parse(base)?;
+        let mut result = reqwest::Url::parse(url)?;
+        let invalid = |_| anyhow!("Cannot redirect {} to {}", url, base);
+        result.set_scheme(base.scheme()).map_err(invalid)?;
+        result.set_host(base.host_str())?;
+        result.set_port(base.port()).map_err(invalid)?;
+        Ok(result.to_string())
+    }
+
+    /// The body of the response for key, either read from the disk or
+    /// obtained by sending the request.
+    pub async fn get(
+        &self,
+        key: &CacheKey,
+        request: reqwest::RequestBuilder,
+    ) -> Result<String> {
+        match (self.mode, &self.dir) {
+            (Mode::Replay, None) => {
+                anyhow::bail!("No directory to replay responses from")
+            }
+            (Mode::Replay, Some(dir)) => {
+                return key
+                    .recorded(dir)
+                    .and_then(|p| std::fs::read_to_string(p).ok())
+                    .ok_or_else(|| {
+                        anyhow!(
+                            "No recorded response for {} ({})",
+                            key.symbol,
+                            key.provider
+                        )
+                    });
+            }
+            (Mode::Live, Some(dir)) => {
+                let path = key.path(dir);
+                let fresh = std::fs::metadata(&path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|m| m.elapsed().ok())
+                    .zip(self.ttl.to_std().ok())
+                    .is_some_and(|(age, ttl)| age < ttl);
+                if fresh {
+                    return Ok(std::fs::read_to_string(path)?);
+                }
+            }
+            (Mode::Live, None) | (Mode::Record, _) => {}
+        }
+
+        // The URL might include an API key, which must not be shown in
+        // error messages.
+        let body = request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(reqwest::Error::without_url)?
+            .text()
+            .await
+            .map_err(reqwest::Error::without_url)?;
+        if let Some(dir) = &self.dir {
+            let path = key.path(dir);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(path, &body)?;
+        }
+        Ok(body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{StockSource, Stooq};
+    use rust_decimal_macros::dec;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    /// A local server answering a single request with body
+    fn serve_once(body: &'static str) -> Result<String> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let url = format!("http://{}", listener.local_addr()?);
+        std::thread::spawn(move || {
+            if let Ok((mut stream, _)) = listener.accept() {
+                let mut buf = [0; 4096];
+                let _ = stream.read(&mut buf);
+                let _ = write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\
+                     Connection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+            }
+        });
+        Ok(url)
+    }
+
+    #[test]
+    fn test_rewrite() -> Result<()> {
+        let t = Transport::default().with_base_url("http://127.0.0.1:8080");
+        assert_eq!(
+            t.rewrite("https://stooq.com/q/l/?s=aapl.us&e=csv")?,
+            "http://127.0.0.1:8080/q/l/?s=aapl.us&e=csv"
+        );
+        assert_eq!(
+            Transport::default().rewrite("https://a.b/c")?,
+            "https://a.b/c"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_record_replay() -> Result<()> {
+        let dir = std::env::temp_dir()
+            .join(format!("alere_test_transport_{}", std::process::id()));
+        let server = serve_once(include_str!("../fixtures/stooq.csv"))?;
+
+        let mut stooq = Stooq::new()?;
+        stooq.set_transport(Transport::record(&dir).with_base_url(&server));
+        assert_eq!(stooq.fetch_price("aapl.us").await?.price, dec!(179.66));
+
+        // The server is gone: only the recorded response can be used
+        stooq.set_transport(Transport::replay(&dir).with_base_url(&server));
+        assert_eq!(stooq.fetch_price("aapl.us").await?.price, dec!(179.66));
+        assert!(stooq.fetch_price("msft.us").await.is_err());
+
+        stooq.set_transport(
+            Transport::cached(&dir, TimeDelta::hours(1)).with_base_url(&server),
+        );
+        assert_eq!(stooq.fetch_price("aapl.us").await?.price, dec!(179.66));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
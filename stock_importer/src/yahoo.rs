@@ -0,0 +1,263 @@
+use crate::{
+    DailyPrice, History, MarketState, StockPrice, StockSource, Transport,
+    from_timestamp, json_decimal,
+};
+use anyhow::{Result, anyhow};
+use chrono::{Local, NaiveDate, TimeDelta};
+use rust_decimal::Decimal;
+use serde_json::Value;
+use std::ops::RangeInclusive;
+
+/// Yahoo Finance data source
+pub struct YahooFinance {
+    client: reqwest::Client,
+    transport: Transport,
+}
+
+impl YahooFinance {
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder().cookie_store(true).build()?,
+            transport: Transport::default(),
+        })
+    }
+}
+
+/// Some exchanges quote prices in a fraction of the currency, for instance
+/// in pence on the London Stock Exchange.  Return the actual currency, and
+/// the number of units in the quote currency.
+fn minor_unit(currency: &str) -> (&str, Decimal) {
+    match currency {
+        "GBp" | "GBX" => ("GBP", Decimal::ONE_HUNDRED),
+        "ZAc" => ("ZAR", Decimal::ONE_HUNDRED),
+        "ILA" => ("ILS", Decimal::ONE_HUNDRED),
+        _ => (currency, Decimal::ONE),
+    }
+}
+
+/// Whether the market is open at the given time, according to the trading
+/// periods of the day
+fn market_state(meta: &Value, now: i64) -> MarketState {
+    let within = |period: &str| {
+        let bound = |b: &str| {
+            meta.pointer(&format!("/currentTradingPeriod/{period}/{b}"))
+                .and_then(|v| v.as_i64())
+        };
+        match (bound("start"), bound("end")) {
+            (Some(start), Some(end)) => Some(start <= now && now < end),
+            _ => None,
+        }
+    };
+    match (within("pre"), within("regular"), within("post")) {
+        (None, None, None) => MarketState::Unknown,
+        (Some(true), _, _) => MarketState::Pre,
+        (_, Some(true), _) => MarketState::Regular,
+        (_, _, Some(true)) => MarketState::Post,
+        _ => MarketState::Closed,
+    }
+}
+
+/// The metadata of the response, and the currency and unit of prices
+fn parse_meta<'a>(
+    symbol: &str,
+    json: &'a Value,
+) -> Result<(&'a Value, Option<String>, Decimal)> {
+    let meta = json
+        .pointer("/chart/result/0/meta")
+        .ok_or_else(|| anyhow!("No quote for {}", symbol))?;
+    let (currency, unit) = match meta.get("currency").and_then(|c| c.as_str()) {
+        Some(c) => {
+            let (currency, unit) = minor_unit(c);
+            (Some(currency.to_string()), unit)
+        }
+        None => (None, Decimal::ONE),
+    };
+    Ok((meta, currency, unit))
+}
+
+impl StockSource for YahooFinance {
+    fn name(&self) -> &str {
+        "yahoo"
+    }
+
+    fn url(&self, symbol: &str) -> Result<String> {
+        Ok(format!(
+            "https://query1.finance.yahoo.com/v8/finance/chart/{}",
+            symbol
+        ))
+    }
+
+    fn history_url(
+        &self,
+        symbol: &str,
+        range: &RangeInclusive<NaiveDate>,
+    ) -> Result<String> {
+        let ts = |d: &NaiveDate| {
+            d.and_hms_opt(0, 0, 0)
+                .map_or(0, |d| d.and_utc().timestamp())
+        };
+        Ok(format!(
+            "https://query1.finance.yahoo.com/v8/finance/chart/{}?period1={}&period2={}&interval=1d",
+            symbol,
+            ts(range.start()),
+            ts(&(*range.end() + TimeDelta::days(1))),
+        ))
+    }
+
+    fn client(&self) -> &reqwest::Client {
+        &self.client
+    }
+
+    fn transport(&self) -> &Transport {
+        &self.transport
+    }
+
+    fn set_transport(&mut self, transport: Transport) {
+        self.transport = transport;
+    }
+
+    fn parse(&self, symbol: &str, body: &str) -> Result<StockPrice> {
+        let json: Value = serde_json::from_str(body)?;
+        let (meta, currency, unit) = parse_meta(symbol, &json)?;
+        let field = |name: &str| meta.get(name).and_then(json_decimal);
+
+        let price = field("regularMarketPrice")
+            .ok_or_else(|| anyhow!("Failed to extract price for {}", symbol))?;
+        let timestamp = meta
+            .get("regularMarketTime")
+            .and_then(|t| t.as_i64())
+            .and_then(from_timestamp)
+            .ok_or_else(|| {
+                anyhow!("Failed to extract timestamp for {}", symbol)
+            })?;
+        let previous_close = field("previousClose")
+            .or_else(|| field("chartPreviousClose"))
+            .map(|p| p / unit);
+
+        let mut result = StockPrice::new(symbol, price / unit, timestamp)
+            .with_previous_close(previous_close);
+        result.currency = currency;
+        result.exchange = ["fullExchangeName", "exchangeName"]
+            .iter()
+            .find_map(|name| meta.get(*name).and_then(|e| e.as_str()))
+            .map(str::to_string);
+        result.market_state = market_state(meta, Local::now().timestamp());
+        Ok(result)
+    }
+
+    fn parse_history(&self, symbol: &str, body: &str) -> Result<History> {
+        let json: Value = serde_json::from_str(body)?;
+        let (_, currency, unit) = parse_meta(symbol, &json)?;
+        let result = json
+            .pointer("/chart/result/0")
+            .ok_or_else(|| anyhow!("No price history for {}", symbol))?;
+        let series = |name: &str| {
+            result
+                .pointer(&format!("/indicators/quote/0/{name}"))
+                .and_then(|v| v.as_array())
+        };
+        let timestamps = result
+            .get("timestamp")
+            .and_then(|t| t.as_array())
+            .ok_or_else(|| anyhow!("No price history for {}", symbol))?;
+        let (open, high, low, close) = (
+            series("open"),
+            series("high"),
+            series("low"),
+            series("close"),
+        );
+        let at = |s: Option<&Vec<Value>>, idx: usize| {
+            s.and_then(|s| s.get(idx))
+                .and_then(json_decimal)
+                .map(|p| p / unit)
+        };
+
+        // Days without trading have null prices
+        let prices = timestamps
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, ts)| {
+                Some(DailyPrice {
+                    date: from_timestamp(ts.as_i64()?)?.date_naive(),
+                    open: at(open, idx),
+                    high: at(high, idx),
+                    low: at(low, idx),
+                    close: at(close, idx)?,
+                })
+            })
+            .collect();
+        Ok(History { currency, prices })
+    }
+
+    /// Yahoo rejects requests that do not look like they come from a browser
+    fn request(&self, url: &str) -> reqwest::RequestBuilder {
+        self.client.get(url)
+            .header("accept", "text/html,application/xhtml+xml,application/xml;q=0.9,image/avif,image/webp,image/apng,*/*;q=0.8,application/signed-exchange;v=b3;q=0.7")
+            .header("accept-language", "en-US,en;q=0.9,fr;q=0.8")
+            .header("cache-control", "max-age=0")
+            .header("dnt", "1")
+            .header("sec-ch-ua", r#""Not(A:Brand";v="8", "Chromium";v="144", "Google Chrome";v="144""#)
+            .header("sec-ch-ua-mobile", "?0")
+            .header("sec-ch-ua-platform", r#""Windows""#)
+            .header("sec-fetch-dest", "document")
+            .header("sec-fetch-mode", "navigate")
+            .header("sec-fetch-site", "none")
+            .header("sec-fetch-user", "?1")
+            .header("upgrade-insecure-requests", "1")
+            .header("user-agent", "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/144.0.0.0 Safari/537.36")
+    }
+}
+
+impl Default for YahooFinance {
+    fn default() -> Self {
+        Self::new().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_decimal_macros::dec;
+
+    #[test]
+    fn test_parse() -> Result<()> {
+        let body = include_str!("../fixtures/yahoo.json");
+        let price = YahooFinance::new()?.parse("AAPL", body)?;
+        assert_eq!(price.price, dec!(179.66));
+        assert_eq!(price.timestamp.timestamp(), 1709326809);
+        assert_eq!(price.currency.as_deref(), Some("USD"));
+        assert_eq!(price.exchange.as_deref(), Some("NasdaqGS"));
+        assert_eq!(price.previous_close, Some(dec!(180.75)));
+        assert_eq!(price.change, Some(dec!(-1.09)));
+
+        let json: Value = serde_json::from_str(body)?;
+        let (meta, _, _) = parse_meta("AAPL", &json)?;
+        assert_eq!(market_state(meta, 1709290000), MarketState::Pre);
+        assert_eq!(market_state(meta, 1709310000), MarketState::Regular);
+        assert_eq!(market_state(meta, 1709326809), MarketState::Post);
+        assert_eq!(market_state(meta, 1709350000), MarketState::Closed);
+
+        // Prices in pence
+        let price = YahooFinance::new()?
+            .parse("VOD.L", include_str!("../fixtures/yahoo_lse.json"))?;
+        assert_eq!(price.price, dec!(0.694));
+        assert_eq!(price.currency.as_deref(), Some("GBP"));
+        assert_eq!(price.market_state, MarketState::Unknown);
+        Ok(())
+    }
+
+    #[test]
+    fn test_parse_history() -> Result<()> {
+        let history = YahooFinance::new()?.parse_history(
+            "AAPL",
+            include_str!("../fixtures/yahoo_history.json"),
+        )?;
+        assert_eq!(history.currency.as_deref(), Some("USD"));
+        assert_eq!(history.prices.len(), 4);
+        let first = history.prices.first().unwrap();
+        assert_eq!(first.date.to_string(), "2024-02-27");
+        assert_eq!(first.open, Some(dec!(182.1)));
+        assert_eq!(first.close, dec!(182.63));
+        Ok(())
+    }
+}